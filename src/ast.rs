@@ -1,23 +1,62 @@
+pub use crate::lexer::Span;
+
 #[derive(Debug, Clone)]
 pub struct Program {
     #[allow(dead_code)]
     pub package: String,
+    /// The file the program was parsed from.
+    pub file: String,
     pub imports: Vec<Import>,
+    pub structs: Vec<StructDecl>,
     pub functions: Vec<Function>,
-    pub modules: std::collections::HashMap<String, Module>,
+    pub modules: std::collections::BTreeMap<String, Module>,
+}
+
+impl Program {
+    /// The function a call refers to; `module` is `None` for the program's
+    /// own functions.
+    pub fn callee(&self, module: Option<&str>, function: &str) -> Option<&Function> {
+        let functions = match module {
+            Some(module) => &self.modules.get(module)?.functions,
+            None => &self.functions,
+        };
+        functions.iter().find(|f| f.name == function)
+    }
+}
+
+/// Functions of the `string` module that every backend implements with its
+/// string runtime instead of compiling the stub in `string.per`.
+pub fn is_string_builtin(module: &str, function: &str) -> bool {
+    module == "string" && matches!(function, "len" | "compare" | "concat" | "slice")
+}
+
+#[derive(Debug, Clone)]
+pub struct StructDecl {
+    pub name: String,
+    pub fields: Vec<StructField>,
+    pub is_exported: bool,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct StructField {
+    pub name: String,
+    pub field_type: String,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub struct Import {
     pub path: String,
-    #[allow(dead_code)]
     pub alias: Option<String>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub struct Module {
-    #[allow(dead_code)]
     pub name: String,
+    pub file: String,
+    pub imports: Vec<Import>,
     pub functions: Vec<Function>,
 }
 
@@ -29,6 +68,18 @@ pub struct Function {
     pub return_type: Option<String>,
     pub body: Vec<Statement>,
     pub is_exported: bool,
+    /// Set for `extern` declarations, which have no body.
+    pub external: Option<External>,
+    pub span: Span,
+}
+
+/// Where the body of an `extern fn` lives.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum External {
+    /// `extern "C"`: a C function, called through the PLT on ELF.
+    C,
+    /// `extern "novaria" ... = n`: NVM syscall `n`.
+    Syscall(u8),
 }
 
 #[derive(Debug, Clone)]
@@ -36,10 +87,23 @@ pub struct Parameter {
     pub name: String,
     #[allow(dead_code)]
     pub param_type: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+impl Statement {
+    pub fn new(kind: StatementKind, span: Span) -> Self {
+        Statement { kind, span }
+    }
 }
 
 #[derive(Debug, Clone)]
-pub enum Statement {
+pub enum StatementKind {
     VarDecl {
         name: String,
         #[allow(dead_code)]
@@ -51,6 +115,9 @@ pub enum Statement {
         #[allow(dead_code)]
         element_type: String,
         size: usize,
+        /// The `eval(...)` the size was written as, until `fold_constants`
+        /// sets `size` from it.
+        size_eval: Option<Expression>,
     },
     Assignment {
         name: String,
@@ -65,28 +132,52 @@ pub enum Statement {
         target: Expression,
         value: Expression,
     },
+    FieldAssignment {
+        object: Expression,
+        field: String,
+        value: Expression,
+    },
     If {
         condition: Expression,
         then_body: Vec<Statement>,
         else_body: Option<Vec<Statement>>,
     },
     For {
-        #[allow(dead_code)]
         init: Option<Box<Statement>>,
         condition: Option<Expression>,
-        #[allow(dead_code)]
         post: Option<Box<Statement>>,
         body: Vec<Statement>,
     },
     Return(Option<Expression>),
+    Break,
+    Continue,
     Expression(Expression),
+    /// `asm` code with the GCC-style operand lists that may follow it:
+    /// `: outputs : inputs : clobbers`.
     InlineAsm {
         parts: Vec<AsmPart>,
+        outputs: Vec<AsmOperand>,
+        inputs: Vec<AsmOperand>,
+        clobbers: Vec<String>,
     },
 }
 
+/// An expression and the position of its first token (the operator for
+/// binary expressions).
+#[derive(Debug, Clone)]
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub span: Span,
+}
+
+impl Expression {
+    pub fn new(kind: ExpressionKind, span: Span) -> Self {
+        Expression { kind, span }
+    }
+}
+
 #[derive(Debug, Clone)]
-pub enum Expression {
+pub enum ExpressionKind {
     Number(i64),
     String(String),
     TemplateString {
@@ -111,6 +202,10 @@ pub enum Expression {
         function: String,
         args: Vec<Expression>,
     },
+    FieldAccess {
+        object: Box<Expression>,
+        field: String,
+    },
     ArrayAccess {
         name: String,
         index: Box<Expression>,
@@ -125,6 +220,10 @@ pub enum Expression {
     Deref {
         operand: Box<Expression>,
     },
+    Cast {
+        operand: Box<Expression>,
+        target_type: String,
+    },
     Eval {
         instruction: Box<Expression>,
     },
@@ -145,6 +244,14 @@ pub enum AsmPart {
     Variable(String),
 }
 
+/// A `"constraint"(variable)` operand of inline assembly.
+#[derive(Debug, Clone)]
+pub struct AsmOperand {
+    pub constraint: String,
+    pub variable: String,
+    pub span: Span,
+}
+
 #[derive(Debug, Clone)]
 pub struct FormatSpec {
     pub width: Option<usize>,
@@ -186,3 +293,96 @@ pub enum UnaryOp {
     Neg,
     Not,
 }
+
+/// Calls `f` with every statement in `body`, including the ones nested in
+/// `if` and `for`.
+pub fn visit_statements<'a>(body: &'a [Statement], f: &mut impl FnMut(&'a Statement)) {
+    for stmt in body {
+        f(stmt);
+        match &stmt.kind {
+            StatementKind::If { then_body, else_body, .. } => {
+                visit_statements(then_body, f);
+                if let Some(body) = else_body {
+                    visit_statements(body, f);
+                }
+            }
+            StatementKind::For { init, post, body, .. } => {
+                for s in init.iter().chain(post) {
+                    visit_statements(std::slice::from_ref(&**s), f);
+                }
+                visit_statements(body, f);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Calls `f` with every `Call` and `ModuleCall` expression in `body`,
+/// including calls nested in arguments.
+pub fn visit_calls<'a>(body: &'a [Statement], f: &mut impl FnMut(&'a Expression)) {
+    for stmt in body {
+        visit_statement_calls(stmt, f);
+    }
+}
+
+fn visit_statement_calls<'a>(stmt: &'a Statement, f: &mut impl FnMut(&'a Expression)) {
+    match &stmt.kind {
+        StatementKind::VarDecl { value, .. } | StatementKind::Return(value) => {
+            if let Some(expr) = value {
+                visit_expression_calls(expr, f);
+            }
+        }
+        StatementKind::Assignment { value, .. } | StatementKind::Expression(value) => visit_expression_calls(value, f),
+        StatementKind::ArrayAssignment { index: first, value, .. }
+        | StatementKind::PointerAssignment { target: first, value }
+        | StatementKind::FieldAssignment { object: first, value, .. } => {
+            visit_expression_calls(first, f);
+            visit_expression_calls(value, f);
+        }
+        StatementKind::If { condition, then_body, else_body } => {
+            visit_expression_calls(condition, f);
+            visit_calls(then_body, f);
+            if let Some(body) = else_body {
+                visit_calls(body, f);
+            }
+        }
+        StatementKind::For { init, condition, post, body } => {
+            for s in init.iter().chain(post) {
+                visit_statement_calls(s, f);
+            }
+            if let Some(expr) = condition {
+                visit_expression_calls(expr, f);
+            }
+            visit_calls(body, f);
+        }
+        StatementKind::ArrayDecl { .. } | StatementKind::Break | StatementKind::Continue | StatementKind::InlineAsm { .. } => {}
+    }
+}
+
+fn visit_expression_calls<'a>(expr: &'a Expression, f: &mut impl FnMut(&'a Expression)) {
+    match &expr.kind {
+        ExpressionKind::Call { args, .. } | ExpressionKind::ModuleCall { args, .. } => {
+            f(expr);
+            args.iter().for_each(|arg| visit_expression_calls(arg, f));
+        }
+        ExpressionKind::TemplateString { parts } => {
+            for part in parts {
+                if let TemplateStringPart::Expression { expr, .. } = part {
+                    visit_expression_calls(expr, f);
+                }
+            }
+        }
+        ExpressionKind::Binary { left, right, .. } | ExpressionKind::StringIndex { string: left, index: right } => {
+            visit_expression_calls(left, f);
+            visit_expression_calls(right, f);
+        }
+        ExpressionKind::ArrayAccess { index: operand, .. }
+        | ExpressionKind::Unary { operand, .. }
+        | ExpressionKind::FieldAccess { object: operand, .. }
+        | ExpressionKind::AddressOf { operand }
+        | ExpressionKind::Deref { operand }
+        | ExpressionKind::Cast { operand, .. }
+        | ExpressionKind::Eval { instruction: operand } => visit_expression_calls(operand, f),
+        ExpressionKind::Number(_) | ExpressionKind::String(_) | ExpressionKind::Identifier(_) => {}
+    }
+}
@@ -0,0 +1,260 @@
+use std::path::{Path, PathBuf};
+
+/// Exit codes of the `perano` binary. `run` exits with the program's own
+/// code once the program has started.
+pub const EXIT_COMPILE_ERROR: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+/// An input could not be read, an output could not be written or an
+/// external tool (gcc, cl.exe) failed.
+pub const EXIT_IO_ERROR: i32 = 3;
+pub const EXIT_VM_ERROR: i32 = 101;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Subcommand {
+    Build,
+    Check,
+    Emit,
+    Run,
+    Fmt,
+    Repl,
+    Lsp,
+    Help,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    Elf,
+    ElfGcc,
+    Novaria,
+    Pe,
+    PeAsm,
+}
+
+impl Target {
+    fn parse(name: &str) -> Option<Target> {
+        match name {
+            "elf" => Some(Target::Elf),
+            "elf-gcc" => Some(Target::ElfGcc),
+            "novaria" | "nvm" => Some(Target::Novaria),
+            "pe" => Some(Target::Pe),
+            "pe-asm" => Some(Target::PeAsm),
+            _ => None,
+        }
+    }
+
+    fn host() -> Target {
+        if cfg!(target_os = "windows") {
+            Target::Pe
+        } else {
+            Target::Elf
+        }
+    }
+
+    /// Where `build` writes when no `-o` is given: next to the input.
+    pub fn default_output(self, input: &Path) -> PathBuf {
+        match self {
+            Target::Elf | Target::ElfGcc => {
+                let output = input.with_extension("");
+                if output == input {
+                    input.with_extension("out")
+                } else {
+                    output
+                }
+            }
+            Target::Novaria => input.with_extension("bin"),
+            Target::Pe | Target::PeAsm => input.with_extension("exe"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    Tokens,
+    Ast,
+    TypedAst,
+    Asm,
+    NvmAsm,
+    Bytecode,
+    C,
+}
+
+impl Stage {
+    fn parse(name: &str) -> Option<Stage> {
+        match name {
+            "tokens" => Some(Stage::Tokens),
+            "ast" => Some(Stage::Ast),
+            "typed-ast" => Some(Stage::TypedAst),
+            "asm" => Some(Stage::Asm),
+            "nvm-asm" => Some(Stage::NvmAsm),
+            "bytecode" => Some(Stage::Bytecode),
+            "c" => Some(Stage::C),
+            _ => None,
+        }
+    }
+
+    /// Extension of the file `build --emit` writes next to the input.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Stage::Tokens => "tokens",
+            Stage::Ast => "ast",
+            Stage::TypedAst => "typed-ast",
+            Stage::Asm => "s",
+            Stage::NvmAsm => "asm",
+            Stage::Bytecode => "bin",
+            Stage::C => "c",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub command: Subcommand,
+    pub inputs: Vec<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub target: Target,
+    pub emit: Option<Stage>,
+    pub sandbox: PathBuf,
+    /// `fmt --check`: report unformatted files instead of rewriting them.
+    pub check: bool,
+}
+
+pub fn usage(program: &str) -> String {
+    format!(
+        "Usage: {0} build <source.per>... [-o <path>] [--target <target>] [--emit=<stage>]
+       {0} check <source.per>...
+       {0} emit --emit=<stage> <source.per>... [-o <path>] [--target <target>]
+       {0} run <source.per|program.asm|program.bin> [--target novaria|elf] [--sandbox <dir>]
+       {0} fmt <source.per>... [--check]
+       {0} repl
+       {0} lsp
+
+Targets: elf (default on Linux), elf-gcc, novaria (or nvm), pe (default on Windows), pe-asm
+Stages:  tokens, ast, typed-ast, asm, nvm-asm, bytecode, c
+
+`build` writes next to each input unless -o is given; `emit` writes to
+stdout unless -o is given. Inputs ending in .asm are NVM assembly, as
+written by --emit=nvm-asm, and build to .bin bytecode. The flags --elf, --elf-gcc, --novaria, --pe-asm
+and --nvm-code of earlier versions are still accepted, as is leaving out
+`build`. `fmt` rewrites files in the canonical style; with --check it only
+lists the files that would change. `repl` reads declarations and
+statements interactively and interprets them. `lsp` serves the language
+server protocol on stdin/stdout.
+
+Exit codes: 0 success, 1 compile errors (or unformatted files for
+`fmt --check`), 2 usage errors, 3 I/O or tool failures; `run` exits with
+the program's code, or 101 on an NVM error.
+",
+        program
+    )
+}
+
+/// Parses the arguments after the program name.
+pub fn parse_args(args: &[String]) -> Result<Options, String> {
+    let (command, rest) = match args.first().map(String::as_str) {
+        Some("build") => (Subcommand::Build, &args[1..]),
+        Some("check") => (Subcommand::Check, &args[1..]),
+        Some("emit") => (Subcommand::Emit, &args[1..]),
+        Some("run") => (Subcommand::Run, &args[1..]),
+        Some("fmt") => (Subcommand::Fmt, &args[1..]),
+        Some("repl") => (Subcommand::Repl, &args[1..]),
+        Some("lsp") => (Subcommand::Lsp, &args[1..]),
+        Some("help" | "-h" | "--help") => (Subcommand::Help, &args[1..]),
+        _ => (Subcommand::Build, args),
+    };
+
+    let mut options = Options {
+        command,
+        inputs: Vec::new(),
+        output: None,
+        target: if command == Subcommand::Run { Target::Novaria } else { Target::host() },
+        emit: None,
+        sandbox: PathBuf::from("."),
+        check: false,
+    };
+    let mut sandbox_given = false;
+
+    let mut i = 0;
+    while i < rest.len() {
+        let arg = rest[i].as_str();
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value)),
+            _ => (arg, None),
+        };
+
+        let mut value = |name: &str| -> Result<String, String> {
+            if let Some(v) = inline_value {
+                return Ok(v.to_string());
+            }
+            i += 1;
+            rest.get(i).cloned().ok_or_else(|| format!("{} expects a value", name))
+        };
+
+        match flag {
+            "-o" | "--output" => options.output = Some(PathBuf::from(value(flag)?)),
+            "--target" => {
+                let name = value(flag)?;
+                options.target = Target::parse(&name).ok_or_else(|| format!("unknown target '{}'", name))?;
+            }
+            "--emit" => {
+                let name = value(flag)?;
+                options.emit = Some(Stage::parse(&name).ok_or_else(|| format!("unknown emit stage '{}'", name))?);
+            }
+            "--sandbox" => {
+                options.sandbox = PathBuf::from(value(flag)?);
+                sandbox_given = true;
+            }
+            "--check" if command == Subcommand::Fmt => options.check = true,
+            "--elf" => options.target = Target::Elf,
+            "--elf-gcc" => options.target = Target::ElfGcc,
+            "--novaria" | "--nvm" => options.target = Target::Novaria,
+            "--pe-asm" => options.target = Target::PeAsm,
+            "--nvm-code" => options.emit = Some(Stage::NvmAsm),
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ => options.inputs.push(PathBuf::from(arg)),
+        }
+        i += 1;
+    }
+
+    match options.command {
+        Subcommand::Help => return Ok(options),
+        Subcommand::Lsp if !rest.is_empty() => return Err("'lsp' takes no arguments".to_string()),
+        Subcommand::Repl if !rest.is_empty() => return Err("'repl' takes no arguments".to_string()),
+        Subcommand::Lsp | Subcommand::Repl => return Ok(options),
+        _ => {}
+    }
+    if options.inputs.is_empty() {
+        return Err("no input files".to_string());
+    }
+    if options.output.is_some() && options.inputs.len() > 1 {
+        return Err("-o cannot be used with more than one input file".to_string());
+    }
+    if sandbox_given && options.command != Subcommand::Run {
+        return Err("--sandbox is only valid for 'run'".to_string());
+    }
+
+    match options.command {
+        Subcommand::Emit if options.emit.is_none() => {
+            return Err("'emit' needs --emit=<stage>".to_string());
+        }
+        Subcommand::Check if options.emit.is_some() || options.output.is_some() => {
+            return Err("'check' does not write any output".to_string());
+        }
+        Subcommand::Fmt if options.emit.is_some() || options.output.is_some() => {
+            return Err("'fmt' rewrites its inputs in place".to_string());
+        }
+        Subcommand::Run => {
+            if options.inputs.len() > 1 {
+                return Err("'run' takes exactly one program".to_string());
+            }
+            if options.emit.is_some() || options.output.is_some() {
+                return Err("'run' does not write any output".to_string());
+            }
+            if !matches!(options.target, Target::Novaria | Target::Elf) {
+                return Err("'run' supports the novaria and elf targets".to_string());
+            }
+        }
+        _ => {}
+    }
+
+    Ok(options)
+}
@@ -0,0 +1,367 @@
+use crate::ast::{self, Program};
+use crate::elf;
+use crate::error::{CompileError, ErrorKind};
+use crate::lexer::{Lexer, Span, Token};
+use crate::nvm;
+use crate::parser::Parser;
+use crate::pe;
+use crate::resolver;
+use crate::typechecker::{self, TypeChecker};
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub type Diagnostics = Vec<CompileError>;
+
+/// Everything `Compiler::analyze` learned about a file, even when it has
+/// errors.
+pub struct Analysis {
+    /// `None` when the file itself does not parse.
+    pub program: Option<Program>,
+    /// The checker that ran over `program`, for signature lookups. `None` if
+    /// an import could not be loaded.
+    pub checker: Option<TypeChecker>,
+    pub diagnostics: Diagnostics,
+}
+
+/// A source file handed to the compiler, either directly or by a resolver.
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    /// The name diagnostics refer to, usually a path.
+    pub name: String,
+    pub text: String,
+}
+
+type Resolver = Box<dyn Fn(&str) -> Option<SourceFile>>;
+
+/// A compilation session over in-memory sources.
+///
+/// Imports are looked up among the added sources as `<module>.per` first,
+/// then through the resolver callback. Every error comes back as a
+/// `CompileError` with its source line attached, ready to `display`.
+pub struct Compiler {
+    sources: BTreeMap<String, String>,
+    resolver: Resolver,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Compiler {
+            sources: BTreeMap::new(),
+            resolver: Box::new(|_| None),
+        }
+    }
+
+    /// Sets the callback that finds imported modules which were not added
+    /// with `add_source`. It receives the import path, e.g. `"stdio"`.
+    pub fn with_resolver(mut self, resolver: impl Fn(&str) -> Option<SourceFile> + 'static) -> Self {
+        self.resolver = Box::new(resolver);
+        self
+    }
+
+    pub fn add_source(&mut self, name: &str, text: &str) {
+        self.sources.insert(name.to_string(), text.to_string());
+    }
+
+    pub fn source(&self, name: &str) -> Option<&str> {
+        self.sources.get(name).map(String::as_str)
+    }
+
+    pub fn tokens(&self, name: &str) -> Result<Vec<(Token, Span)>, Diagnostics> {
+        let (tokens, spans) = self.lex(name)?;
+        Ok(tokens.into_iter().zip(spans).collect())
+    }
+
+    /// Parses a single file without loading its imports.
+    pub fn parse(&self, name: &str) -> Result<Program, Diagnostics> {
+        let (tokens, spans) = self.lex(name)?;
+        Parser::new_with_spans(tokens, spans, name)
+            .parse()
+            .map_err(|errors| self.attach_sources(errors))
+    }
+
+    /// Parses `name`, loads its imports, resolves calls and type checks the
+    /// whole program. The result is ready for any backend.
+    pub fn check(&mut self, name: &str) -> Result<Program, Diagnostics> {
+        let analysis = self.analyze(name);
+        match analysis.program {
+            Some(program) if analysis.diagnostics.is_empty() => Ok(program),
+            _ => Err(analysis.diagnostics),
+        }
+    }
+
+    /// Runs the same steps as `check` but keeps whatever was built, so
+    /// editors can work with a program that does not compile yet.
+    pub fn analyze(&mut self, name: &str) -> Analysis {
+        let mut program = match self.parse(name) {
+            Ok(program) => program,
+            Err(diagnostics) => return Analysis { program: None, checker: None, diagnostics },
+        };
+
+        if let Err(errors) = self.load_modules(&mut program, name, &mut HashSet::new()) {
+            let diagnostics = self.attach_sources(errors);
+            return Analysis { program: Some(program), checker: None, diagnostics };
+        }
+
+        // Type errors are only worth reporting once calls resolve, but the
+        // checker still runs so its signatures are available.
+        let resolved = resolver::resolve_program(&mut program, name);
+        let folded = match resolved {
+            Ok(()) => typechecker::fold_constants(&mut program),
+            Err(_) => Ok(()),
+        };
+        let mut checker = TypeChecker::new(name);
+        let checked = checker.check_program(&program);
+        let diagnostics = match (resolved, folded, checked) {
+            (Err(e), ..) => vec![e],
+            (Ok(()), folded, checked) => {
+                folded.err().into_iter().chain(checked.err()).flatten().collect()
+            }
+        };
+
+        Analysis {
+            program: Some(program),
+            checker: Some(checker),
+            diagnostics: self.attach_sources(diagnostics),
+        }
+    }
+
+    fn lex(&self, name: &str) -> Result<(Vec<Token>, Vec<Span>), Diagnostics> {
+        let text = self.require(name)?;
+        let mut lexer = Lexer::new_with_file(text, name);
+        let tokens = lexer.tokenize_with_spans();
+        let errors = lexer.take_errors();
+        if errors.is_empty() {
+            Ok(tokens)
+        } else {
+            Err(self.attach_sources(errors))
+        }
+    }
+
+    fn require(&self, name: &str) -> Result<&str, Diagnostics> {
+        self.source(name).ok_or_else(|| {
+            vec![CompileError::new(
+                ErrorKind::ModuleError,
+                format!("no source named '{}'", name),
+                name.to_string(),
+                1,
+                1,
+            )]
+        })
+    }
+
+    fn attach_sources(&self, errors: Diagnostics) -> Diagnostics {
+        errors
+            .into_iter()
+            .map(|e| match self.sources.get(&e.file) {
+                Some(text) if e.source_line.is_none() => e.with_source(text),
+                _ => e,
+            })
+            .collect()
+    }
+
+    fn load_modules(&mut self, program: &mut Program, importer: &str, loaded: &mut HashSet<String>) -> Result<(), Diagnostics> {
+        for import in program.imports.clone() {
+            if !loaded.insert(import.path.clone()) {
+                continue;
+            }
+
+            let in_memory = format!("{}.per", import.path);
+            let file = if self.sources.contains_key(&in_memory) {
+                in_memory
+            } else if let Some(found) = (self.resolver)(&import.path) {
+                self.sources.insert(found.name.clone(), found.text);
+                found.name
+            } else {
+                return Err(vec![CompileError::new(
+                    ErrorKind::ModuleError,
+                    format!("could not find module '{}'", import.path),
+                    importer.to_string(),
+                    import.span.line,
+                    import.span.column,
+                )]);
+            };
+
+            let mut module_ast = self.parse(&file)?;
+            self.load_modules(&mut module_ast, &file, loaded)?;
+
+            program.modules.append(&mut module_ast.modules);
+            program.structs.extend(module_ast.structs);
+            program.modules.insert(import.path.clone(), ast::Module {
+                name: import.path,
+                file,
+                imports: module_ast.imports,
+                functions: module_ast.functions,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Where the `perano-lang` binary looks for the imports of `source`: next to
+/// it, in `stdlib/` under the working directory, then in `stdlib/` next to
+/// the running executable.
+pub fn search_dirs(source: &Path) -> Vec<PathBuf> {
+    let mut dirs = vec![source.parent().unwrap_or(Path::new(".")).to_path_buf(), PathBuf::from("stdlib")];
+    if let Some(exe_dir) = env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf)) {
+        dirs.push(exe_dir.join("stdlib"));
+    }
+    dirs
+}
+
+/// A resolver that looks for `<module>.per` in each directory in turn.
+pub fn directory_resolver(dirs: Vec<PathBuf>) -> impl Fn(&str) -> Option<SourceFile> {
+    move |module| {
+        dirs.iter().find_map(|dir| {
+            let path = dir.join(format!("{}.per", module));
+            let text = fs::read_to_string(&path).ok()?;
+            Some(SourceFile { name: path.to_string_lossy().to_string(), text })
+        })
+    }
+}
+
+/// x86-64 assembly; freestanding code uses raw syscalls, the other variant
+/// links against libc.
+pub fn elf_assembly(program: &Program, freestanding: bool) -> Result<String, CompileError> {
+    check_inline_asm(program, true)?;
+    let mut asm_gen = if freestanding {
+        check_extern_calls(program, "elf", |_| false)?;
+        elf::AsmGenerator::new_freestanding()
+    } else {
+        check_extern_calls(program, "elf-gcc", |external| external == ast::External::C)?;
+        elf::AsmGenerator::new()
+    };
+    asm_gen.generate(program)
+}
+
+/// A static, freestanding ELF executable image.
+pub fn elf_executable(program: &Program) -> Result<Vec<u8>, CompileError> {
+    let asm_code = elf_assembly(program, true)?;
+
+    let mut writer = elf::ELFWriter::new();
+    let mut assembler = elf::Assembler::new();
+    assembler.assemble(&asm_code)?;
+    let text_size = assembler.text_size();
+    let machine_code = assembler.link(writer.code_address(), writer.data_address(text_size), "_start")?;
+    Ok(writer.image(&machine_code))
+}
+
+pub fn nvm_assembly(program: &Program) -> Result<String, CompileError> {
+    check_extern_calls(program, "novaria", |external| matches!(external, ast::External::Syscall(_)))?;
+    check_inline_asm(program, false)?;
+    check_syscall_bindings(program)?;
+    nvm::NVMAssemblyGenerator::new().generate(program)
+}
+
+pub fn nvm_bytecode(program: &Program) -> Result<Vec<u8>, CompileError> {
+    check_extern_calls(program, "novaria", |external| matches!(external, ast::External::Syscall(_)))?;
+    check_inline_asm(program, false)?;
+    check_syscall_bindings(program)?;
+    nvm::NVMCodeGen::new().generate(program)
+}
+
+pub fn c_source(program: &Program) -> Result<String, CompileError> {
+    check_extern_calls(program, "pe", |external| external == ast::External::C)?;
+    check_inline_asm(program, false)?;
+    pe::c_codegen::CCodeGen::new().generate(program)
+}
+
+/// Rejects the first call to an `extern` function that `target` cannot
+/// reach: C functions need libc and syscalls only exist on NVM.
+fn check_extern_calls(program: &Program, target: &str, callable: impl Fn(ast::External) -> bool) -> Result<(), CompileError> {
+    check_functions(program, |func| {
+        let mut rejected = None;
+        ast::visit_calls(&func.body, &mut |call| {
+            let (module, function) = match &call.kind {
+                ast::ExpressionKind::Call { function, .. } => (None, function),
+                ast::ExpressionKind::ModuleCall { module, function, .. } => (Some(module.as_str()), function),
+                _ => return,
+            };
+            match program.callee(module, function).and_then(|f| f.external) {
+                Some(external) if rejected.is_none() && !callable(external) => rejected = Some((call, function, external)),
+                _ => {}
+            }
+        });
+        let (call, function, external) = rejected?;
+        let message = match external {
+            ast::External::C if target == "elf" => format!(
+                "extern \"C\" function '{}' needs libc, which target 'elf' does not link; use 'elf-gcc'", function
+            ),
+            ast::External::C => format!("extern \"C\" function '{}' cannot be called on target '{}'", function, target),
+            ast::External::Syscall(_) => format!(
+                "'{}' is bound to a NovariaOS syscall and cannot be called on target '{}'", function, target
+            ),
+        };
+        Some((call.span, message))
+    })
+}
+
+/// Binds the operands of every `asm` statement the way the x86-64 backend
+/// will, or rejects operands and clobbers on targets without them.
+fn check_inline_asm(program: &Program, x86_64: bool) -> Result<(), CompileError> {
+    check_functions(program, |func| {
+        let mut rejected = None;
+        ast::visit_statements(&func.body, &mut |stmt| {
+            let ast::StatementKind::InlineAsm { outputs, inputs, clobbers, .. } = &stmt.kind else {
+                return;
+            };
+            if rejected.is_some() {
+                return;
+            }
+            if x86_64 {
+                rejected = elf::inline_asm::bind(outputs, inputs, clobbers, stmt.span).err();
+            } else if !(outputs.is_empty() && inputs.is_empty() && clobbers.is_empty()) {
+                rejected = Some((stmt.span, "asm operands and clobbers need an x86-64 target, 'elf' or 'elf-gcc'".to_string()));
+            }
+        });
+        rejected
+    })
+}
+
+/// Holds `extern "novaria"` declarations to the syscall table: the number
+/// must exist, the parameters match what it pops and a return type is
+/// declared exactly when it pushes a result.
+fn check_syscall_bindings(program: &Program) -> Result<(), CompileError> {
+    check_functions(program, |func| {
+        let Some(ast::External::Syscall(number)) = func.external else {
+            return None;
+        };
+        let message = match nvm::syscalls::by_number(number) {
+            None => format!("'{}' is bound to syscall {}, which NovariaOS does not have", func.name, number),
+            Some(syscall) if syscall.args != func.params.len() => format!(
+                "'{}' takes {} arguments, but syscall {} pops {}", func.name, func.params.len(), syscall.name, syscall.args
+            ),
+            Some(syscall) if syscall.returns != func.return_type.is_some() => format!(
+                "'{}' {} a return type: syscall {} {}",
+                func.name,
+                if syscall.returns { "needs" } else { "cannot have" },
+                syscall.name,
+                if syscall.returns { "pushes a result" } else { "pushes nothing" },
+            ),
+            Some(_) => return None,
+        };
+        Some((func.span, message))
+    })
+}
+
+/// Runs `check` over the functions of `program` and of its modules, and
+/// reports the first problem it finds as a codegen error in that
+/// function's file.
+fn check_functions(program: &Program, mut check: impl FnMut(&ast::Function) -> Option<(Span, String)>) -> Result<(), CompileError> {
+    let functions = program.functions.iter().map(|f| (program.file.as_str(), f))
+        .chain(program.modules.values().flat_map(|m| m.functions.iter().map(move |f| (m.file.as_str(), f))));
+    for (file, func) in functions {
+        if let Some((span, message)) = check(func) {
+            return Err(CompileError::new(ErrorKind::CodeGenError, message, file.to_string(), span.line, span.column));
+        }
+    }
+    Ok(())
+}
@@ -1,12 +1,46 @@
 use crate::ast::*;
+use crate::error::{CompileError, ErrorKind};
+use crate::literal;
+use super::inline_asm::{self, Place, Register, RAX, RCX};
+use crate::typechecker::{compute_struct_layouts, expression_type, operand_type, FieldLayout, StructLayout, Type, EVAL_NOT_FOLDED};
 use std::collections::HashMap;
 
+/// The largest stack frame a function may reserve.
+const MAX_FRAME_SIZE: usize = 1 << 20;
+/// Room a template string reserves for a formatted integer beyond its
+/// width: 20 digits and a sign at most, plus the NUL.
+const FORMATTED_INT: usize = 24;
+const ARG_REGS: [&str; 6] = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
+
 pub struct AsmGenerator {
     output: String,
     label_counter: usize,
     string_literals: Vec<String>,
     variables: HashMap<String, i32>,
+    var_types: HashMap<String, Type>,
+    structs: HashMap<String, StructLayout>,
     stack_offset: i32,
+    loop_stack: Vec<(String, String)>,
+    /// Values pushed by the expression being generated, to keep %rsp 16-byte
+    /// aligned at calls.
+    pushed: usize,
+    freestanding: bool,
+    /// The C name and return type of each `extern "C"` function, by the
+    /// symbol a call would otherwise use.
+    externs: HashMap<String, (String, Type)>,
+    /// The return type of every function, by symbol.
+    returns: HashMap<String, Type>,
+    /// Frame slots holding template strings to free when the current
+    /// statement ends.
+    temporaries: Vec<i32>,
+    /// Source file of the function being generated, for errors.
+    file: String,
+}
+
+impl Default for AsmGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl AsmGenerator {
@@ -16,7 +50,25 @@ impl AsmGenerator {
             label_counter: 0,
             string_literals: Vec::new(),
             variables: HashMap::new(),
+            var_types: HashMap::new(),
+            structs: HashMap::new(),
             stack_offset: 0,
+            loop_stack: Vec::new(),
+            pushed: 0,
+            freestanding: false,
+            externs: HashMap::new(),
+            returns: HashMap::new(),
+            temporaries: Vec::new(),
+            file: String::new(),
+        }
+    }
+
+    /// Targets a static executable: no libc, stdio goes through raw Linux
+    /// syscalls and `_start` is emitted as the entry point.
+    pub fn new_freestanding() -> Self {
+        AsmGenerator {
+            freestanding: true,
+            ..AsmGenerator::new()
         }
     }
 
@@ -26,27 +78,44 @@ impl AsmGenerator {
         label
     }
 
-    pub fn generate(&mut self, program: &Program) -> String {
+    pub fn generate(&mut self, program: &Program) -> Result<String, CompileError> {
+        self.structs = compute_struct_layouts(&program.structs).unwrap_or_default();
         self.output.push_str("    .text\n");
 
+        let module_functions = program.modules.iter()
+            .flat_map(|(name, module)| module.functions.iter().map(move |f| (format!("{}_{}", name, f.name), f)));
+        for (symbol, func) in program.functions.iter().map(|f| (f.name.clone(), f)).chain(module_functions) {
+            let return_type = func.return_type.as_ref().map_or(Type::Void, |t| Type::resolve(t, &self.structs));
+            if func.external.is_some() {
+                self.externs.insert(symbol.clone(), (func.name.clone(), return_type.clone()));
+            }
+            self.returns.insert(symbol, return_type);
+        }
+
         for (module_name, module) in &program.modules {
             if module_name == "stdio" {
                 continue;
             }
-            for func in &module.functions {
-                if func.is_exported {
-                    self.generate_module_function(module_name, func);
-                }
+            let compiled = module.functions.iter()
+                .filter(|f| f.external.is_none() && !is_string_builtin(module_name, &f.name));
+            for func in compiled {
+                self.generate_module_function(module_name, &module.file, func)?;
             }
         }
 
-        for func in &program.functions {
+        for func in program.functions.iter().filter(|f| f.external.is_none()) {
             if func.name != "main" {
-                self.generate_user_function(func);
+                self.generate_user_function(&program.file, func)?;
             }
         }
 
-        if program.modules.contains_key("stdio") {
+        self.generate_string_runtime(program.modules.contains_key("string"));
+        if self.freestanding {
+            self.generate_runtime_functions();
+            if program.modules.contains_key("stdio") {
+                self.generate_stdio_syscalls();
+            }
+        } else if program.modules.contains_key("stdio") {
             self.generate_stdio_functions();
         }
 
@@ -55,11 +124,12 @@ impl AsmGenerator {
 
         self.output.push_str("    pushq   %rbp\n");
         self.output.push_str("    movq    %rsp, %rbp\n");
-        self.output.push_str("    subq    $64, %rsp\n");
 
         if let Some(main_func) = program.functions.iter().find(|f| f.name == "main") {
+            self.reserve_frame(&program.file, main_func)?;
+            self.file = program.file.clone();
             for stmt in &main_func.body {
-                self.generate_statement(stmt);
+                self.generate_statement(stmt)?;
             }
         }
 
@@ -67,15 +137,93 @@ impl AsmGenerator {
         self.output.push_str("    leave\n");
         self.output.push_str("    ret\n");
 
+        if self.freestanding {
+            self.output.push_str("\n    .globl _start\n");
+            self.output.push_str("_start:\n");
+            self.output.push_str("    xorl    %ebp, %ebp\n");
+            self.output.push_str("    call    main\n");
+            self.output.push_str("    movq    %rax, %rdi\n");
+            self.output.push_str("    movq    $60, %rax\n");
+            self.output.push_str("    syscall\n");
+        }
+
         if !self.string_literals.is_empty() {
             self.output.push_str("\n    .section .rodata\n");
             for (i, s) in self.string_literals.iter().enumerate() {
+                self.output.push_str(&format!("    .quad   {}\n", s.len()));
                 self.output.push_str(&format!(".LS{}:\n", i));
-                self.output.push_str(&format!("    .string \"{}\"\n", s));
+                self.output.push_str(&format!("    .string {}\n", literal::gas(s.as_bytes())));
             }
         }
 
-        self.output.clone()
+        Ok(self.output.clone())
+    }
+
+    /// Emits the `subq` that reserves `func`'s frame: its parameters, every
+    /// declaration in its body and a slot for each of its template strings,
+    /// rounded up so %rsp stays 16-byte aligned.
+    fn reserve_frame(&mut self, file: &str, func: &Function) -> Result<(), CompileError> {
+        let params = func.params.len().min(ARG_REGS.len()) * 8;
+        let size = (params + self.block_frame_size(&func.body)).next_multiple_of(16);
+        if size > MAX_FRAME_SIZE {
+            return Err(CompileError::new(
+                ErrorKind::CodeGenError,
+                format!("stack frame of '{}' needs {} bytes, more than the {} allowed", func.name, size, MAX_FRAME_SIZE),
+                file.to_string(),
+                func.span.line,
+                func.span.column,
+            ));
+        }
+        if size > 0 {
+            self.output.push_str(&format!("    subq    ${}, %rsp\n", size));
+        }
+        Ok(())
+    }
+
+    fn block_frame_size(&self, body: &[Statement]) -> usize {
+        body.iter().map(|stmt| self.statement_frame_size(stmt)).sum()
+    }
+
+    fn statement_frame_size(&self, stmt: &Statement) -> usize {
+        match &stmt.kind {
+            StatementKind::VarDecl { value, .. } => {
+                self.declaration_size(&stmt.kind) + template_slots(value)
+            }
+            StatementKind::ArrayDecl { .. } => self.declaration_size(&stmt.kind),
+            StatementKind::Assignment { value, .. } => template_slots([value]),
+            StatementKind::ArrayAssignment { index, value, .. } => template_slots([index, value]),
+            StatementKind::PointerAssignment { target, value } => template_slots([target, value]),
+            StatementKind::FieldAssignment { object, value, .. } => template_slots([object, value]),
+            StatementKind::If { condition, then_body, else_body } => {
+                template_slots([condition])
+                    + self.block_frame_size(then_body)
+                    + else_body.as_ref().map_or(0, |body| self.block_frame_size(body))
+            }
+            StatementKind::For { init, condition, post, body } => {
+                init.as_ref().map_or(0, |s| self.statement_frame_size(s))
+                    + template_slots(condition)
+                    + post.as_ref().map_or(0, |s| self.statement_frame_size(s))
+                    + self.block_frame_size(body)
+            }
+            StatementKind::Return(value) => template_slots(value),
+            StatementKind::Expression(expr) => template_slots([expr]),
+            StatementKind::Break | StatementKind::Continue | StatementKind::InlineAsm { .. } => 0,
+        }
+    }
+
+    /// Bytes of frame a `var` or array declaration takes.
+    fn declaration_size(&self, kind: &StatementKind) -> usize {
+        match kind {
+            StatementKind::VarDecl { value: Some(_), .. } => 8,
+            StatementKind::VarDecl { var_type, value: None, .. } => match var_type.as_ref().map(|t| Type::resolve(t, &self.structs)) {
+                Some(Type::Struct(name)) => self.structs.get(&name).map(|s| s.size).unwrap_or(0).max(1).next_multiple_of(8),
+                _ => 8,
+            },
+            StatementKind::ArrayDecl { element_type, size, .. } => {
+                (Type::resolve(element_type, &self.structs).size() * size).max(1).next_multiple_of(8)
+            }
+            _ => 0,
+        }
     }
 
     fn generate_stdio_functions(&mut self) {
@@ -86,7 +234,7 @@ impl AsmGenerator {
         self.output.push_str("    movq    %rsp, %rbp\n");
         self.output.push_str("    movq    %rdi, %rsi\n");
         let idx1 = self.string_literals.len();
-        self.string_literals.push("%ld\\n".to_string());
+        self.string_literals.push("%ld\n".to_string());
         self.output.push_str(&format!("    leaq    .LS{}(%rip), %rdi\n", idx1));
         self.output.push_str("    xorl    %eax, %eax\n");
         self.output.push_str("    call    printf@PLT\n");
@@ -128,7 +276,7 @@ impl AsmGenerator {
         self.output.push_str("    movq    %rsp, %rbp\n");
         self.output.push_str("    movq    %rdi, %rsi\n");
         let idx4 = self.string_literals.len();
-        self.string_literals.push("%s\\n".to_string());
+        self.string_literals.push("%s\n".to_string());
         self.output.push_str(&format!("    leaq    .LS{}(%rip), %rdi\n", idx4));
         self.output.push_str("    xorl    %eax, %eax\n");
         self.output.push_str("    call    printf@PLT\n");
@@ -205,162 +353,912 @@ impl AsmGenerator {
         self.output.push_str("    ret\n\n");
     }
 
-    fn generate_user_function(&mut self, func: &Function) {
-        self.output.push_str(&format!("    .globl {}\n", func.name));
-        self.output.push_str(&format!("{}:\n", func.name));
+    fn generate_runtime_functions(&mut self) {
+        self.output.push_str("__per_strlen:\n");
+        self.output.push_str("    movq    %rdi, %rax\n");
+        self.output.push_str(".Lrt_strlen_loop:\n");
+        self.output.push_str("    cmpb    $0, (%rax)\n");
+        self.output.push_str("    je      .Lrt_strlen_done\n");
+        self.output.push_str("    addq    $1, %rax\n");
+        self.output.push_str("    jmp     .Lrt_strlen_loop\n");
+        self.output.push_str(".Lrt_strlen_done:\n");
+        self.output.push_str("    subq    %rdi, %rax\n");
+        self.output.push_str("    ret\n");
+        self.output.push('\n');
 
+        self.output.push_str("__per_write:\n");
+        self.output.push_str("    movq    %rsi, %rdx\n");
+        self.output.push_str("    movq    %rdi, %rsi\n");
+        self.output.push_str("    movq    $1, %rdi\n");
+        self.output.push_str("    movq    $1, %rax\n");
+        self.output.push_str("    syscall\n");
+        self.output.push_str("    ret\n");
+        self.output.push('\n');
+
+        self.output.push_str("__per_puts:\n");
         self.output.push_str("    pushq   %rbp\n");
         self.output.push_str("    movq    %rsp, %rbp\n");
-        self.output.push_str("    subq    $64, %rsp\n");
+        self.output.push_str("    pushq   %rbx\n");
+        self.output.push_str("    subq    $8, %rsp\n");
+        self.output.push_str("    movq    %rdi, %rbx\n");
+        self.output.push_str("    call    __per_strlen\n");
+        self.output.push_str("    movq    %rbx, %rdi\n");
+        self.output.push_str("    movq    %rax, %rsi\n");
+        self.output.push_str("    call    __per_write\n");
+        self.output.push_str("    addq    $8, %rsp\n");
+        self.output.push_str("    popq    %rbx\n");
+        self.output.push_str("    leave\n");
+        self.output.push_str("    ret\n");
+        self.output.push('\n');
 
-        let arg_regs = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
-        let mut local_vars = HashMap::new();
-        let mut local_offset = 0i32;
+        // __per_fmt_int(dst, value, width, pad char, base, uppercase)
+        self.output.push_str("__per_fmt_int:\n");
+        self.output.push_str("    pushq   %rbp\n");
+        self.output.push_str("    movq    %rsp, %rbp\n");
+        self.output.push_str("    subq    $32, %rsp\n");
+        self.output.push_str("    movq    %rdx, %r10\n");
+        self.output.push_str("    movq    %rcx, %r11\n");
+        self.output.push_str("    xorl    %ecx, %ecx\n");
+        self.output.push_str("    movq    %rsi, %rax\n");
+        self.output.push_str("    cmpq    $10, %r8\n");
+        self.output.push_str("    jne     .Lrt_fmt_digits\n");
+        self.output.push_str("    testq   %rax, %rax\n");
+        self.output.push_str("    jns     .Lrt_fmt_digits\n");
+        self.output.push_str("    negq    %rax\n");
+        self.output.push_str("    movq    $1, %rcx\n");
+        self.output.push_str(".Lrt_fmt_digits:\n");
+        self.output.push_str("    movq    %rbp, %rsi\n");
+        self.output.push_str(".Lrt_fmt_loop:\n");
+        self.output.push_str("    xorl    %edx, %edx\n");
+        self.output.push_str("    divq    %r8\n");
+        self.output.push_str("    cmpq    $10, %rdx\n");
+        self.output.push_str("    jb      .Lrt_fmt_digit\n");
+        self.output.push_str("    addq    $39, %rdx\n");
+        self.output.push_str("    testq   %r9, %r9\n");
+        self.output.push_str("    je      .Lrt_fmt_digit\n");
+        self.output.push_str("    subq    $32, %rdx\n");
+        self.output.push_str(".Lrt_fmt_digit:\n");
+        self.output.push_str("    addq    $48, %rdx\n");
+        self.output.push_str("    subq    $1, %rsi\n");
+        self.output.push_str("    movb    %dl, (%rsi)\n");
+        self.output.push_str("    testq   %rax, %rax\n");
+        self.output.push_str("    jne     .Lrt_fmt_loop\n");
+        self.output.push_str("    movq    %rbp, %rax\n");
+        self.output.push_str("    subq    %rsi, %rax\n");
+        self.output.push_str("    addq    %rcx, %rax\n");
+        self.output.push_str("    subq    %rax, %r10\n");
+        self.output.push_str("    cmpq    $48, %r11\n");
+        self.output.push_str("    jne     .Lrt_fmt_pad\n");
+        self.output.push_str("    testq   %rcx, %rcx\n");
+        self.output.push_str("    je      .Lrt_fmt_pad\n");
+        self.output.push_str("    movb    $45, (%rdi)\n");
+        self.output.push_str("    addq    $1, %rdi\n");
+        self.output.push_str("    xorl    %ecx, %ecx\n");
+        self.output.push_str(".Lrt_fmt_pad:\n");
+        self.output.push_str("    testq   %r10, %r10\n");
+        self.output.push_str("    jle     .Lrt_fmt_sign\n");
+        self.output.push_str("    movb    %r11b, (%rdi)\n");
+        self.output.push_str("    addq    $1, %rdi\n");
+        self.output.push_str("    subq    $1, %r10\n");
+        self.output.push_str("    jmp     .Lrt_fmt_pad\n");
+        self.output.push_str(".Lrt_fmt_sign:\n");
+        self.output.push_str("    testq   %rcx, %rcx\n");
+        self.output.push_str("    je      .Lrt_fmt_copy\n");
+        self.output.push_str("    movb    $45, (%rdi)\n");
+        self.output.push_str("    addq    $1, %rdi\n");
+        self.output.push_str(".Lrt_fmt_copy:\n");
+        self.output.push_str("    cmpq    %rbp, %rsi\n");
+        self.output.push_str("    je      .Lrt_fmt_done\n");
+        self.output.push_str("    movb    (%rsi), %al\n");
+        self.output.push_str("    movb    %al, (%rdi)\n");
+        self.output.push_str("    addq    $1, %rsi\n");
+        self.output.push_str("    addq    $1, %rdi\n");
+        self.output.push_str("    jmp     .Lrt_fmt_copy\n");
+        self.output.push_str(".Lrt_fmt_done:\n");
+        self.output.push_str("    movb    $0, (%rdi)\n");
+        self.output.push_str("    leave\n");
+        self.output.push_str("    ret\n");
+        self.output.push('\n');
 
-        for (i, param) in func.params.iter().enumerate() {
-            if i < arg_regs.len() {
-                local_offset -= 8;
-                local_vars.insert(param.name.clone(), local_offset);
-                self.output.push_str(&format!("    movq    {}, {}(%rbp)\n", arg_regs[i], local_offset));
-            }
+        self.output.push_str("__per_print_int:\n");
+        self.output.push_str("    pushq   %rbp\n");
+        self.output.push_str("    movq    %rsp, %rbp\n");
+        self.output.push_str("    subq    $32, %rsp\n");
+        self.output.push_str("    movq    %rdi, %rsi\n");
+        self.output.push_str("    leaq    -32(%rbp), %rdi\n");
+        self.output.push_str("    xorl    %edx, %edx\n");
+        self.output.push_str("    movq    $32, %rcx\n");
+        self.output.push_str("    movq    $10, %r8\n");
+        self.output.push_str("    xorq    %r9, %r9\n");
+        self.output.push_str("    call    __per_fmt_int\n");
+        self.output.push_str("    leaq    -32(%rbp), %rdi\n");
+        self.output.push_str("    call    __per_puts\n");
+        self.output.push_str("    leave\n");
+        self.output.push_str("    ret\n");
+        self.output.push('\n');
+    }
+
+    /// The string runtime. A string is a pointer to its bytes, which end in a
+    /// NUL and follow their length as a quad at -8, so literals and heap
+    /// strings look the same. Concatenation and slicing allocate: from
+    /// `malloc` when linking libc, otherwise from a bump heap of mmap'd chunks
+    /// that is never freed.
+    fn generate_string_runtime(&mut self, string_module: bool) {
+        if self.freestanding {
+            self.output.push_str("__per_alloc:\n");
+            self.output.push_str("    addq    $7, %rdi\n");
+            self.output.push_str("    andq    $-8, %rdi\n");
+            self.output.push_str("    movq    __per_heap(%rip), %rax\n");
+            self.output.push_str("    leaq    (%rax,%rdi), %rdx\n");
+            self.output.push_str("    cmpq    __per_heap_end(%rip), %rdx\n");
+            self.output.push_str("    jbe     .Lrt_alloc_done\n");
+            self.output.push_str("    movq    %rdi, %rsi\n");
+            self.output.push_str("    cmpq    $1048576, %rsi\n");
+            self.output.push_str("    jae     .Lrt_alloc_map\n");
+            self.output.push_str("    movq    $1048576, %rsi\n");
+            self.output.push_str(".Lrt_alloc_map:\n");
+            self.output.push_str("    pushq   %rdi\n");
+            self.output.push_str("    pushq   %rsi\n");
+            self.output.push_str("    movq    $9, %rax\n");
+            self.output.push_str("    xorl    %edi, %edi\n");
+            self.output.push_str("    movq    $3, %rdx\n");
+            self.output.push_str("    movq    $34, %r10\n");
+            self.output.push_str("    movq    $-1, %r8\n");
+            self.output.push_str("    xorq    %r9, %r9\n");
+            self.output.push_str("    syscall\n");
+            self.output.push_str("    popq    %rsi\n");
+            self.output.push_str("    popq    %rdi\n");
+            self.output.push_str("    cmpq    $-4096, %rax\n");
+            self.output.push_str("    ja      .Lrt_alloc_fail\n");
+            self.output.push_str("    leaq    (%rax,%rsi), %rdx\n");
+            self.output.push_str("    movq    %rdx, __per_heap_end(%rip)\n");
+            self.output.push_str("    leaq    (%rax,%rdi), %rdx\n");
+            self.output.push_str(".Lrt_alloc_done:\n");
+            self.output.push_str("    movq    %rdx, __per_heap(%rip)\n");
+            self.output.push_str("    ret\n");
+            self.output.push_str(".Lrt_alloc_fail:\n");
+            self.output.push_str("    movq    $60, %rax\n");
+            self.output.push_str("    movq    $1, %rdi\n");
+            self.output.push_str("    syscall\n");
+            self.output.push('\n');
+
+            self.output.push_str("    .data\n");
+            self.output.push_str("__per_heap:\n");
+            self.output.push_str("    .quad   0\n");
+            self.output.push_str("__per_heap_end:\n");
+            self.output.push_str("    .quad   0\n");
+            self.output.push_str("    .text\n");
+            self.output.push('\n');
+        } else {
+            self.output.push_str("__per_alloc:\n");
+            self.output.push_str("    jmp     malloc@PLT\n");
+            self.output.push('\n');
         }
 
-        let saved_vars = self.variables.clone();
-        let saved_offset = self.stack_offset;
-        self.variables = local_vars;
-        self.stack_offset = local_offset;
+        // __per_str_new(len): room for len bytes, with the length and NUL set
+        self.output.push_str("__per_str_new:\n");
+        self.output.push_str("    pushq   %rbx\n");
+        self.output.push_str("    movq    %rdi, %rbx\n");
+        self.output.push_str("    addq    $9, %rdi\n");
+        self.output.push_str("    call    __per_alloc\n");
+        self.output.push_str("    movq    %rbx, (%rax)\n");
+        self.output.push_str("    addq    $8, %rax\n");
+        self.output.push_str("    movb    $0, (%rax,%rbx)\n");
+        self.output.push_str("    popq    %rbx\n");
+        self.output.push_str("    ret\n");
+        self.output.push('\n');
 
-        for stmt in &func.body {
-            self.generate_statement(stmt);
+        // __per_copy(dst, src, len): returns dst + len
+        self.output.push_str("__per_copy:\n");
+        self.output.push_str("    testq   %rdx, %rdx\n");
+        self.output.push_str("    je      .Lrt_copy_done\n");
+        self.output.push_str(".Lrt_copy_loop:\n");
+        self.output.push_str("    movb    (%rsi), %cl\n");
+        self.output.push_str("    movb    %cl, (%rdi)\n");
+        self.output.push_str("    addq    $1, %rsi\n");
+        self.output.push_str("    addq    $1, %rdi\n");
+        self.output.push_str("    subq    $1, %rdx\n");
+        self.output.push_str("    jne     .Lrt_copy_loop\n");
+        self.output.push_str(".Lrt_copy_done:\n");
+        self.output.push_str("    movq    %rdi, %rax\n");
+        self.output.push_str("    ret\n");
+        self.output.push('\n');
+
+        self.output.push_str("__per_str_concat:\n");
+        self.output.push_str("    pushq   %rbx\n");
+        self.output.push_str("    pushq   %r12\n");
+        self.output.push_str("    pushq   %r13\n");
+        self.output.push_str("    movq    %rdi, %rbx\n");
+        self.output.push_str("    movq    %rsi, %r12\n");
+        self.output.push_str("    movq    -8(%rbx), %rdi\n");
+        self.output.push_str("    addq    -8(%r12), %rdi\n");
+        self.output.push_str("    call    __per_str_new\n");
+        self.output.push_str("    movq    %rax, %r13\n");
+        self.output.push_str("    movq    %rax, %rdi\n");
+        self.output.push_str("    movq    %rbx, %rsi\n");
+        self.output.push_str("    movq    -8(%rbx), %rdx\n");
+        self.output.push_str("    call    __per_copy\n");
+        self.output.push_str("    movq    %rax, %rdi\n");
+        self.output.push_str("    movq    %r12, %rsi\n");
+        self.output.push_str("    movq    -8(%r12), %rdx\n");
+        self.output.push_str("    call    __per_copy\n");
+        self.output.push_str("    movq    %r13, %rax\n");
+        self.output.push_str("    popq    %r13\n");
+        self.output.push_str("    popq    %r12\n");
+        self.output.push_str("    popq    %rbx\n");
+        self.output.push_str("    ret\n");
+        self.output.push('\n');
+
+        // __per_str_compare(a, b): -1, 0 or 1, comparing unsigned bytes
+        self.output.push_str("__per_str_compare:\n");
+        self.output.push_str("    movq    -8(%rdi), %rcx\n");
+        self.output.push_str("    movq    -8(%rsi), %rdx\n");
+        self.output.push_str(".Lrt_compare_loop:\n");
+        self.output.push_str("    testq   %rcx, %rcx\n");
+        self.output.push_str("    je      .Lrt_compare_end\n");
+        self.output.push_str("    testq   %rdx, %rdx\n");
+        self.output.push_str("    je      .Lrt_compare_greater\n");
+        self.output.push_str("    movzbq  (%rdi), %rax\n");
+        self.output.push_str("    movzbq  (%rsi), %r8\n");
+        self.output.push_str("    cmpq    %r8, %rax\n");
+        self.output.push_str("    jb      .Lrt_compare_less\n");
+        self.output.push_str("    ja      .Lrt_compare_greater\n");
+        self.output.push_str("    addq    $1, %rdi\n");
+        self.output.push_str("    addq    $1, %rsi\n");
+        self.output.push_str("    subq    $1, %rcx\n");
+        self.output.push_str("    subq    $1, %rdx\n");
+        self.output.push_str("    jmp     .Lrt_compare_loop\n");
+        self.output.push_str(".Lrt_compare_end:\n");
+        self.output.push_str("    xorl    %eax, %eax\n");
+        self.output.push_str("    testq   %rdx, %rdx\n");
+        self.output.push_str("    jne     .Lrt_compare_less\n");
+        self.output.push_str("    ret\n");
+        self.output.push_str(".Lrt_compare_less:\n");
+        self.output.push_str("    movq    $-1, %rax\n");
+        self.output.push_str("    ret\n");
+        self.output.push_str(".Lrt_compare_greater:\n");
+        self.output.push_str("    movq    $1, %rax\n");
+        self.output.push_str("    ret\n");
+        self.output.push('\n');
+
+        // __per_str_slice(s, start, end), both clamped to 0..=len(s)
+        self.output.push_str("__per_str_slice:\n");
+        self.output.push_str("    movq    -8(%rdi), %rax\n");
+        self.output.push_str("    cmpq    %rax, %rdx\n");
+        self.output.push_str("    jle     .Lrt_slice_end_low\n");
+        self.output.push_str("    movq    %rax, %rdx\n");
+        self.output.push_str(".Lrt_slice_end_low:\n");
+        self.output.push_str("    testq   %rdx, %rdx\n");
+        self.output.push_str("    jns     .Lrt_slice_start\n");
+        self.output.push_str("    xorl    %edx, %edx\n");
+        self.output.push_str(".Lrt_slice_start:\n");
+        self.output.push_str("    testq   %rsi, %rsi\n");
+        self.output.push_str("    jns     .Lrt_slice_start_high\n");
+        self.output.push_str("    xorl    %esi, %esi\n");
+        self.output.push_str(".Lrt_slice_start_high:\n");
+        self.output.push_str("    cmpq    %rdx, %rsi\n");
+        self.output.push_str("    jle     .Lrt_slice_copy\n");
+        self.output.push_str("    movq    %rdx, %rsi\n");
+        self.output.push_str(".Lrt_slice_copy:\n");
+        self.output.push_str("    pushq   %rbx\n");
+        self.output.push_str("    pushq   %r12\n");
+        self.output.push_str("    pushq   %r13\n");
+        self.output.push_str("    leaq    (%rdi,%rsi), %rbx\n");
+        self.output.push_str("    movq    %rdx, %r12\n");
+        self.output.push_str("    subq    %rsi, %r12\n");
+        self.output.push_str("    movq    %r12, %rdi\n");
+        self.output.push_str("    call    __per_str_new\n");
+        self.output.push_str("    movq    %rax, %r13\n");
+        self.output.push_str("    movq    %rax, %rdi\n");
+        self.output.push_str("    movq    %rbx, %rsi\n");
+        self.output.push_str("    movq    %r12, %rdx\n");
+        self.output.push_str("    call    __per_copy\n");
+        self.output.push_str("    movq    %r13, %rax\n");
+        self.output.push_str("    popq    %r13\n");
+        self.output.push_str("    popq    %r12\n");
+        self.output.push_str("    popq    %rbx\n");
+        self.output.push_str("    ret\n");
+        self.output.push('\n');
+
+        // __per_str_from_c(p): a copy of a NUL-terminated C string, "" for NULL
+        self.output.push_str("__per_str_from_c:\n");
+        self.output.push_str("    pushq   %rbx\n");
+        self.output.push_str("    pushq   %r12\n");
+        self.output.push_str("    subq    $8, %rsp\n");
+        self.output.push_str("    movq    %rdi, %rbx\n");
+        self.output.push_str("    movq    %rdi, %rax\n");
+        self.output.push_str("    testq   %rax, %rax\n");
+        self.output.push_str("    je      .Lrt_from_c_len\n");
+        self.output.push_str(".Lrt_from_c_loop:\n");
+        self.output.push_str("    cmpb    $0, (%rax)\n");
+        self.output.push_str("    je      .Lrt_from_c_len\n");
+        self.output.push_str("    addq    $1, %rax\n");
+        self.output.push_str("    jmp     .Lrt_from_c_loop\n");
+        self.output.push_str(".Lrt_from_c_len:\n");
+        self.output.push_str("    subq    %rbx, %rax\n");
+        self.output.push_str("    movq    %rax, %r12\n");
+        self.output.push_str("    movq    %rax, %rdi\n");
+        self.output.push_str("    call    __per_str_new\n");
+        self.output.push_str("    movq    %rax, %rdi\n");
+        self.output.push_str("    movq    %rbx, %rsi\n");
+        self.output.push_str("    movq    %r12, %rdx\n");
+        self.output.push_str("    call    __per_copy\n");
+        self.output.push_str("    subq    %r12, %rax\n");
+        self.output.push_str("    addq    $8, %rsp\n");
+        self.output.push_str("    popq    %r12\n");
+        self.output.push_str("    popq    %rbx\n");
+        self.output.push_str("    ret\n");
+        self.output.push('\n');
+
+        // Template strings are built in a block with the capacity before the
+        // length, so a finished builder is a string too. Appends grow it by
+        // doubling and always leave room for the NUL.
+
+        // __per_tpl_new(): an empty builder with room for 63 bytes
+        self.output.push_str("__per_tpl_new:\n");
+        self.output.push_str("    subq    $8, %rsp\n");
+        self.output.push_str("    movq    $80, %rdi\n");
+        self.output.push_str("    call    __per_alloc\n");
+        self.output.push_str("    addq    $8, %rsp\n");
+        self.output.push_str("    movq    $64, (%rax)\n");
+        self.output.push_str("    movq    $0, 8(%rax)\n");
+        self.output.push_str("    addq    $16, %rax\n");
+        self.output.push_str("    movb    $0, (%rax)\n");
+        self.output.push_str("    ret\n");
+        self.output.push('\n');
+
+        // __per_tpl_reserve(builder, n): the builder, moved if it had no
+        // room for n more bytes and the NUL
+        self.output.push_str("__per_tpl_reserve:\n");
+        self.output.push_str("    movq    -8(%rdi), %rdx\n");
+        self.output.push_str("    addq    %rsi, %rdx\n");
+        self.output.push_str("    addq    $1, %rdx\n");
+        self.output.push_str("    cmpq    -16(%rdi), %rdx\n");
+        self.output.push_str("    ja      .Lrt_reserve_grow\n");
+        self.output.push_str("    movq    %rdi, %rax\n");
+        self.output.push_str("    ret\n");
+        self.output.push_str(".Lrt_reserve_grow:\n");
+        self.output.push_str("    pushq   %rbx\n");
+        self.output.push_str("    pushq   %r12\n");
+        self.output.push_str("    pushq   %r13\n");
+        self.output.push_str("    movq    %rdi, %rbx\n");
+        self.output.push_str("    movq    -16(%rdi), %r12\n");
+        self.output.push_str(".Lrt_reserve_double:\n");
+        self.output.push_str("    addq    %r12, %r12\n");
+        self.output.push_str("    cmpq    %rdx, %r12\n");
+        self.output.push_str("    jb      .Lrt_reserve_double\n");
+        self.output.push_str("    leaq    16(%r12), %rdi\n");
+        self.output.push_str("    call    __per_alloc\n");
+        self.output.push_str("    movq    %r12, (%rax)\n");
+        self.output.push_str("    movq    -8(%rbx), %rdx\n");
+        self.output.push_str("    movq    %rdx, 8(%rax)\n");
+        self.output.push_str("    leaq    16(%rax), %r13\n");
+        self.output.push_str("    movq    %r13, %rdi\n");
+        self.output.push_str("    movq    %rbx, %rsi\n");
+        self.output.push_str("    addq    $1, %rdx\n");
+        self.output.push_str("    call    __per_copy\n");
+        self.output.push_str("    movq    %rbx, %rdi\n");
+        self.output.push_str("    call    __per_tpl_free\n");
+        self.output.push_str("    movq    %r13, %rax\n");
+        self.output.push_str("    popq    %r13\n");
+        self.output.push_str("    popq    %r12\n");
+        self.output.push_str("    popq    %rbx\n");
+        self.output.push_str("    ret\n");
+        self.output.push('\n');
+
+        // __per_tpl_append(builder, bytes, n): the builder
+        self.output.push_str("__per_tpl_append:\n");
+        self.output.push_str("    pushq   %rbx\n");
+        self.output.push_str("    pushq   %r12\n");
+        self.output.push_str("    pushq   %r13\n");
+        self.output.push_str("    movq    %rsi, %r12\n");
+        self.output.push_str("    movq    %rdx, %r13\n");
+        self.output.push_str("    movq    %rdx, %rsi\n");
+        self.output.push_str("    call    __per_tpl_reserve\n");
+        self.output.push_str("    movq    %rax, %rbx\n");
+        self.output.push_str("    movq    -8(%rax), %rdi\n");
+        self.output.push_str("    addq    %rax, %rdi\n");
+        self.output.push_str("    movq    %r12, %rsi\n");
+        self.output.push_str("    movq    %r13, %rdx\n");
+        self.output.push_str("    call    __per_copy\n");
+        self.output.push_str("    movb    $0, (%rax)\n");
+        self.output.push_str("    movq    -8(%rbx), %rax\n");
+        self.output.push_str("    addq    %r13, %rax\n");
+        self.output.push_str("    movq    %rax, -8(%rbx)\n");
+        self.output.push_str("    movq    %rbx, %rax\n");
+        self.output.push_str("    popq    %r13\n");
+        self.output.push_str("    popq    %r12\n");
+        self.output.push_str("    popq    %rbx\n");
+        self.output.push_str("    ret\n");
+        self.output.push('\n');
+
+        if self.freestanding {
+            // __per_tpl_free(builder): gives the block back if it was the
+            // last one allocated
+            self.output.push_str("__per_tpl_free:\n");
+            self.output.push_str("    movq    -16(%rdi), %rax\n");
+            self.output.push_str("    addq    %rdi, %rax\n");
+            self.output.push_str("    cmpq    __per_heap(%rip), %rax\n");
+            self.output.push_str("    jne     .Lrt_free_done\n");
+            self.output.push_str("    subq    $16, %rdi\n");
+            self.output.push_str("    movq    %rdi, __per_heap(%rip)\n");
+            self.output.push_str(".Lrt_free_done:\n");
+            self.output.push_str("    ret\n");
+            self.output.push('\n');
+
+            // __per_tpl_int(builder, value, width, pad char, base, uppercase)
+            self.output.push_str("__per_tpl_int:\n");
+            self.output.push_str("    pushq   %rbp\n");
+            self.output.push_str("    movq    %rsp, %rbp\n");
+            self.output.push_str("    subq    $48, %rsp\n");
+            self.output.push_str("    movq    %rsi, -8(%rbp)\n");
+            self.output.push_str("    movq    %rdx, -16(%rbp)\n");
+            self.output.push_str("    movq    %rcx, -24(%rbp)\n");
+            self.output.push_str("    movq    %r8, -32(%rbp)\n");
+            self.output.push_str("    movq    %r9, -40(%rbp)\n");
+            self.output.push_str("    movq    %rdx, %rsi\n");
+            self.output.push_str(&format!("    addq    ${}, %rsi\n", FORMATTED_INT));
+            self.output.push_str("    call    __per_tpl_reserve\n");
+            self.output.push_str("    movq    %rax, -48(%rbp)\n");
+            self.output.push_str("    movq    -8(%rax), %rdi\n");
+            self.output.push_str("    addq    %rax, %rdi\n");
+            self.output.push_str("    movq    -8(%rbp), %rsi\n");
+            self.output.push_str("    movq    -16(%rbp), %rdx\n");
+            self.output.push_str("    movq    -24(%rbp), %rcx\n");
+            self.output.push_str("    movq    -32(%rbp), %r8\n");
+            self.output.push_str("    movq    -40(%rbp), %r9\n");
+            self.output.push_str("    call    __per_fmt_int\n");
+            self.output.push_str("    movq    -48(%rbp), %rdi\n");
+            self.output.push_str("    addq    -8(%rdi), %rdi\n");
+            self.output.push_str("    call    __per_strlen\n");
+            self.output.push_str("    movq    -48(%rbp), %rdi\n");
+            self.output.push_str("    addq    -8(%rdi), %rax\n");
+            self.output.push_str("    movq    %rax, -8(%rdi)\n");
+            self.output.push_str("    movq    %rdi, %rax\n");
+            self.output.push_str("    leave\n");
+            self.output.push_str("    ret\n");
+            self.output.push('\n');
+        } else {
+            self.output.push_str("__per_tpl_free:\n");
+            self.output.push_str("    subq    $16, %rdi\n");
+            self.output.push_str("    jmp     free@PLT\n");
+            self.output.push('\n');
+
+            // __per_tpl_format(builder, format, value, room): snprintf's one
+            // value into the builder, cut short if it needs more than room
+            self.output.push_str("__per_tpl_format:\n");
+            self.output.push_str("    pushq   %rbp\n");
+            self.output.push_str("    movq    %rsp, %rbp\n");
+            self.output.push_str("    subq    $32, %rsp\n");
+            self.output.push_str("    movq    %rsi, -8(%rbp)\n");
+            self.output.push_str("    movq    %rdx, -16(%rbp)\n");
+            self.output.push_str("    movq    %rcx, %rsi\n");
+            self.output.push_str("    call    __per_tpl_reserve\n");
+            self.output.push_str("    movq    %rax, -24(%rbp)\n");
+            self.output.push_str("    movq    -16(%rax), %rsi\n");
+            self.output.push_str("    subq    -8(%rax), %rsi\n");
+            self.output.push_str("    movq    %rsi, -32(%rbp)\n");
+            self.output.push_str("    movq    -8(%rax), %rdi\n");
+            self.output.push_str("    addq    %rax, %rdi\n");
+            self.output.push_str("    movq    -8(%rbp), %rdx\n");
+            self.output.push_str("    movq    -16(%rbp), %rcx\n");
+            self.output.push_str("    xorl    %eax, %eax\n");
+            self.output.push_str("    call    snprintf@PLT\n");
+            self.output.push_str("    movslq  %eax, %rax\n");
+            self.output.push_str("    movq    -32(%rbp), %rcx\n");
+            self.output.push_str("    subq    $1, %rcx\n");
+            self.output.push_str("    cmpq    %rcx, %rax\n");
+            self.output.push_str("    jbe     .Lrt_format_fits\n");
+            self.output.push_str("    movq    %rcx, %rax\n");
+            self.output.push_str(".Lrt_format_fits:\n");
+            self.output.push_str("    movq    -24(%rbp), %rdi\n");
+            self.output.push_str("    addq    -8(%rdi), %rax\n");
+            self.output.push_str("    movq    %rax, -8(%rdi)\n");
+            self.output.push_str("    movq    %rdi, %rax\n");
+            self.output.push_str("    leave\n");
+            self.output.push_str("    ret\n");
+            self.output.push('\n');
         }
 
-        self.variables = saved_vars;
-        self.stack_offset = saved_offset;
+        if string_module {
+            self.output.push_str("    .globl  string_len\n");
+            self.output.push_str("string_len:\n");
+            self.output.push_str("    movq    -8(%rdi), %rax\n");
+            self.output.push_str("    ret\n");
+            self.output.push('\n');
 
-        self.output.push_str("    movl    $0, %eax\n");
+            for (function, runtime) in [("compare", "__per_str_compare"), ("concat", "__per_str_concat"), ("slice", "__per_str_slice")] {
+                self.output.push_str(&format!("    .globl  string_{}\n", function));
+                self.output.push_str(&format!("string_{}:\n", function));
+                self.output.push_str(&format!("    jmp     {}\n", runtime));
+                self.output.push('\n');
+            }
+        }
+    }
+
+    fn generate_stdio_syscalls(&mut self) {
+        self.output.push_str("    .globl  stdio_Println\n");
+        self.output.push_str("stdio_Println:\n");
+        self.output.push_str("    pushq   %rbp\n");
+        self.output.push_str("    movq    %rsp, %rbp\n");
+        self.output.push_str("    call    __per_print_int\n");
+        self.output.push_str("    movq    $10, %rdi\n");
+        self.output.push_str("    call    stdio_PrintChar\n");
+        self.output.push_str("    xorl    %eax, %eax\n");
         self.output.push_str("    leave\n");
-        self.output.push_str("    ret\n\n");
+        self.output.push_str("    ret\n");
+        self.output.push('\n');
+
+        self.output.push_str("    .globl  stdio_Print\n");
+        self.output.push_str("stdio_Print:\n");
+        self.output.push_str("    pushq   %rbp\n");
+        self.output.push_str("    movq    %rsp, %rbp\n");
+        self.output.push_str("    call    __per_print_int\n");
+        self.output.push_str("    xorl    %eax, %eax\n");
+        self.output.push_str("    leave\n");
+        self.output.push_str("    ret\n");
+        self.output.push('\n');
+
+        self.output.push_str("    .globl  stdio_PrintStr\n");
+        self.output.push_str("stdio_PrintStr:\n");
+        self.output.push_str("    pushq   %rbp\n");
+        self.output.push_str("    movq    %rsp, %rbp\n");
+        self.output.push_str("    movq    -8(%rdi), %rsi\n");
+        self.output.push_str("    call    __per_write\n");
+        self.output.push_str("    xorl    %eax, %eax\n");
+        self.output.push_str("    leave\n");
+        self.output.push_str("    ret\n");
+        self.output.push('\n');
+
+        self.output.push_str("    .globl  stdio_PrintlnStr\n");
+        self.output.push_str("stdio_PrintlnStr:\n");
+        self.output.push_str("    pushq   %rbp\n");
+        self.output.push_str("    movq    %rsp, %rbp\n");
+        self.output.push_str("    movq    -8(%rdi), %rsi\n");
+        self.output.push_str("    call    __per_write\n");
+        self.output.push_str("    movq    $10, %rdi\n");
+        self.output.push_str("    call    stdio_PrintChar\n");
+        self.output.push_str("    xorl    %eax, %eax\n");
+        self.output.push_str("    leave\n");
+        self.output.push_str("    ret\n");
+        self.output.push('\n');
+
+        self.output.push_str("    .globl  stdio_PrintChar\n");
+        self.output.push_str("stdio_PrintChar:\n");
+        self.output.push_str("    pushq   %rbp\n");
+        self.output.push_str("    movq    %rsp, %rbp\n");
+        self.output.push_str("    subq    $16, %rsp\n");
+        self.output.push_str("    movb    %dil, -1(%rbp)\n");
+        self.output.push_str("    leaq    -1(%rbp), %rdi\n");
+        self.output.push_str("    movq    $1, %rsi\n");
+        self.output.push_str("    call    __per_write\n");
+        self.output.push_str("    xorl    %eax, %eax\n");
+        self.output.push_str("    leave\n");
+        self.output.push_str("    ret\n");
+        self.output.push('\n');
+
+        self.output.push_str("    .globl  stdio_ReadChar\n");
+        self.output.push_str("stdio_ReadChar:\n");
+        self.output.push_str("    pushq   %rbp\n");
+        self.output.push_str("    movq    %rsp, %rbp\n");
+        self.output.push_str("    subq    $16, %rsp\n");
+        self.output.push_str("    xorl    %eax, %eax\n");
+        self.output.push_str("    xorl    %edi, %edi\n");
+        self.output.push_str("    leaq    -1(%rbp), %rsi\n");
+        self.output.push_str("    movq    $1, %rdx\n");
+        self.output.push_str("    syscall\n");
+        self.output.push_str("    cmpq    $1, %rax\n");
+        self.output.push_str("    jne     .Lrt_readchar_eof\n");
+        self.output.push_str("    movzbq  -1(%rbp), %rax\n");
+        self.output.push_str("    leave\n");
+        self.output.push_str("    ret\n");
+        self.output.push_str(".Lrt_readchar_eof:\n");
+        self.output.push_str("    movq    $-1, %rax\n");
+        self.output.push_str("    leave\n");
+        self.output.push_str("    ret\n");
+        self.output.push('\n');
+
+        self.output.push_str("    .globl  stdio_ReadInt\n");
+        self.output.push_str("stdio_ReadInt:\n");
+        self.output.push_str("    pushq   %rbp\n");
+        self.output.push_str("    movq    %rsp, %rbp\n");
+        self.output.push_str("    pushq   %rbx\n");
+        self.output.push_str("    pushq   %r12\n");
+        self.output.push_str("    xorl    %ebx, %ebx\n");
+        self.output.push_str("    xorq    %r12, %r12\n");
+        self.output.push_str(".Lrt_readint_skip:\n");
+        self.output.push_str("    call    stdio_ReadChar\n");
+        self.output.push_str("    cmpq    $32, %rax\n");
+        self.output.push_str("    je      .Lrt_readint_skip\n");
+        self.output.push_str("    cmpq    $9, %rax\n");
+        self.output.push_str("    je      .Lrt_readint_skip\n");
+        self.output.push_str("    cmpq    $10, %rax\n");
+        self.output.push_str("    je      .Lrt_readint_skip\n");
+        self.output.push_str("    cmpq    $13, %rax\n");
+        self.output.push_str("    je      .Lrt_readint_skip\n");
+        self.output.push_str("    cmpq    $45, %rax\n");
+        self.output.push_str("    jne     .Lrt_readint_digit\n");
+        self.output.push_str("    movq    $1, %r12\n");
+        self.output.push_str("    call    stdio_ReadChar\n");
+        self.output.push_str(".Lrt_readint_digit:\n");
+        self.output.push_str("    cmpq    $48, %rax\n");
+        self.output.push_str("    jl      .Lrt_readint_done\n");
+        self.output.push_str("    cmpq    $57, %rax\n");
+        self.output.push_str("    jg      .Lrt_readint_done\n");
+        self.output.push_str("    imulq   $10, %rbx\n");
+        self.output.push_str("    subq    $48, %rax\n");
+        self.output.push_str("    addq    %rax, %rbx\n");
+        self.output.push_str("    call    stdio_ReadChar\n");
+        self.output.push_str("    jmp     .Lrt_readint_digit\n");
+        self.output.push_str(".Lrt_readint_done:\n");
+        self.output.push_str("    movq    %rbx, %rax\n");
+        self.output.push_str("    testq   %r12, %r12\n");
+        self.output.push_str("    je      .Lrt_readint_end\n");
+        self.output.push_str("    negq    %rax\n");
+        self.output.push_str(".Lrt_readint_end:\n");
+        self.output.push_str("    popq    %r12\n");
+        self.output.push_str("    popq    %rbx\n");
+        self.output.push_str("    leave\n");
+        self.output.push_str("    ret\n");
+        self.output.push('\n');
+
+        self.output.push_str("    .globl  stdio_ReadLine\n");
+        self.output.push_str("stdio_ReadLine:\n");
+        self.output.push_str("    pushq   %rbp\n");
+        self.output.push_str("    movq    %rsp, %rbp\n");
+        self.output.push_str("    pushq   %rbx\n");
+        self.output.push_str("    pushq   %r12\n");
+        self.output.push_str("    pushq   %r13\n");
+        self.output.push_str("    subq    $8, %rsp\n");
+        self.output.push_str("    movq    %rdi, %rbx\n");
+        self.output.push_str("    movq    %rsi, %r12\n");
+        self.output.push_str("    subq    $1, %r12\n");
+        self.output.push_str("    xorq    %r13, %r13\n");
+        self.output.push_str(".Lrt_readline_loop:\n");
+        self.output.push_str("    cmpq    %r12, %r13\n");
+        self.output.push_str("    jge     .Lrt_readline_done\n");
+        self.output.push_str("    call    stdio_ReadChar\n");
+        self.output.push_str("    cmpq    $0, %rax\n");
+        self.output.push_str("    jl      .Lrt_readline_done\n");
+        self.output.push_str("    movb    %al, (%rbx,%r13)\n");
+        self.output.push_str("    addq    $1, %r13\n");
+        self.output.push_str("    cmpq    $10, %rax\n");
+        self.output.push_str("    jne     .Lrt_readline_loop\n");
+        self.output.push_str(".Lrt_readline_done:\n");
+        self.output.push_str("    cmpq    $0, %r12\n");
+        self.output.push_str("    jl      .Lrt_readline_end\n");
+        self.output.push_str("    movb    $0, (%rbx,%r13)\n");
+        self.output.push_str(".Lrt_readline_end:\n");
+        self.output.push_str("    movq    %r13, %rax\n");
+        self.output.push_str("    addq    $8, %rsp\n");
+        self.output.push_str("    popq    %r13\n");
+        self.output.push_str("    popq    %r12\n");
+        self.output.push_str("    popq    %rbx\n");
+        self.output.push_str("    leave\n");
+        self.output.push_str("    ret\n");
+        self.output.push('\n');
+
+        self.output.push_str("    .globl  stdio_Flush\n");
+        self.output.push_str("stdio_Flush:\n");
+        self.output.push_str("    xorl    %eax, %eax\n");
+        self.output.push_str("    ret\n");
+        self.output.push('\n');
+    }
+
+    fn generate_user_function(&mut self, file: &str, func: &Function) -> Result<(), CompileError> {
+        self.generate_function(&func.name, file, func)
+    }
+
+    fn generate_module_function(&mut self, module_name: &str, file: &str, func: &Function) -> Result<(), CompileError> {
+        self.generate_function(&format!("{}_{}", module_name, func.name), file, func)
     }
 
-    fn generate_module_function(&mut self, module_name: &str, func: &Function) {
-        self.output.push_str(&format!("    .globl {}_{}\n", module_name, func.name));
-        self.output.push_str(&format!("{}_{}", module_name, func.name));
-        self.output.push_str(":\n");
+    /// Register parameters are spilled below %rbp; the rest stay where the
+    /// caller pushed them, above the return address.
+    fn generate_function(&mut self, symbol: &str, file: &str, func: &Function) -> Result<(), CompileError> {
+        self.output.push_str(&format!("    .globl {}\n", symbol));
+        self.output.push_str(&format!("{}:\n", symbol));
 
         self.output.push_str("    pushq   %rbp\n");
         self.output.push_str("    movq    %rsp, %rbp\n");
-        self.output.push_str("    subq    $64, %rsp\n");
+        self.reserve_frame(file, func)?;
+        self.file = file.to_string();
 
-        let arg_regs = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
         let mut local_vars = HashMap::new();
         let mut local_offset = 0i32;
 
+        let mut local_types = HashMap::new();
+
         for (i, param) in func.params.iter().enumerate() {
-            if i < arg_regs.len() {
+            local_types.insert(param.name.clone(), Type::resolve(&param.param_type, &self.structs));
+            if i < ARG_REGS.len() {
                 local_offset -= 8;
                 local_vars.insert(param.name.clone(), local_offset);
-                self.output.push_str(&format!("    movq    {}, {}(%rbp)\n", arg_regs[i], local_offset));
+                self.output.push_str(&format!("    movq    {}, {}(%rbp)\n", ARG_REGS[i], local_offset));
+            } else {
+                local_vars.insert(param.name.clone(), 16 + 8 * (i - ARG_REGS.len()) as i32);
             }
         }
 
         let saved_vars = self.variables.clone();
+        let saved_types = std::mem::replace(&mut self.var_types, local_types);
         let saved_offset = self.stack_offset;
         self.variables = local_vars;
         self.stack_offset = local_offset;
 
         for stmt in &func.body {
-            self.generate_statement(stmt);
+            self.generate_statement(stmt)?;
         }
 
         self.variables = saved_vars;
+        self.var_types = saved_types;
         self.stack_offset = saved_offset;
 
         self.output.push_str("    movl    $0, %eax\n");
         self.output.push_str("    leave\n");
         self.output.push_str("    ret\n\n");
+        Ok(())
     }
 
-    fn generate_statement(&mut self, stmt: &Statement) {
-        match stmt {
-            Statement::VarDecl { name, var_type: _, value } => {
+    fn generate_statement(&mut self, stmt: &Statement) -> Result<(), CompileError> {
+        let pending = self.temporaries.len();
+        match &stmt.kind {
+            StatementKind::VarDecl { name, var_type, value } => {
+                let ty = match var_type {
+                    Some(t) => Type::resolve(t, &self.structs),
+                    None => value.as_ref().and_then(|v| self.static_type(v)).unwrap_or(Type::Unknown),
+                };
+
                 if let Some(expr) = value {
-                    self.generate_expression(expr);
+                    self.generate_expression(expr)?;
                     self.stack_offset -= 8;
                     self.variables.insert(name.clone(), self.stack_offset);
-                    self.output.push_str(&format!("    movq    %rax, {}(%rbp)\n", self.stack_offset));
+                    let slot = format!("{}(%rbp)", self.stack_offset);
+                    self.output.push_str(&format!("    {}\n", store_instruction(&ty, 'a', &slot)));
+                } else {
+                    let size = self.declaration_size(&stmt.kind) as i32;
+                    self.stack_offset -= size;
+                    self.variables.insert(name.clone(), self.stack_offset);
+                    for i in (0..size).step_by(8) {
+                        self.output.push_str(&format!("    movq    $0, {}(%rbp)\n", self.stack_offset + i));
+                    }
                 }
+
+                self.var_types.insert(name.clone(), ty);
             }
-            Statement::ArrayDecl { name, element_type: _, size } => {
-                let array_size = (*size as i32) * 8;
+            StatementKind::ArrayDecl { name, element_type, size, .. } => {
+                let elem = Type::resolve(element_type, &self.structs);
+                let array_size = self.declaration_size(&stmt.kind) as i32;
                 self.stack_offset -= array_size;
                 self.variables.insert(name.clone(), self.stack_offset);
-                for i in 0..*size {
-                    let offset = self.stack_offset + (i as i32 * 8);
-                    self.output.push_str(&format!("    movq    $0, {}(%rbp)\n", offset));
+                for i in (0..array_size).step_by(8) {
+                    self.output.push_str(&format!("    movq    $0, {}(%rbp)\n", self.stack_offset + i));
                 }
+                self.var_types.insert(name.clone(), Type::Array(Box::new(elem), *size));
             }
-            Statement::Assignment { name, value } => {
-                self.generate_expression(value);
+            StatementKind::Assignment { name, value } => {
+                self.generate_expression(value)?;
                 if let Some(&offset) = self.variables.get(name) {
-                    self.output.push_str(&format!("    movq    %rax, {}(%rbp)\n", offset));
+                    let ty = self.var_types.get(name).cloned().unwrap_or(Type::Unknown);
+                    let slot = format!("{}(%rbp)", offset);
+                    self.output.push_str(&format!("    {}\n", store_instruction(&ty, 'a', &slot)));
                 }
             }
-            Statement::PointerAssignment { target, value } => {
-                self.generate_expression(value);
-                self.output.push_str("    pushq   %rax\n");
+            StatementKind::PointerAssignment { target, value } => {
+                self.generate_expression(value)?;
+                self.push("%rax");
                 
-                self.generate_expression(target);
+                self.generate_expression(target)?;
                 
-                self.output.push_str("    popq    %rcx\n");
-                self.output.push_str("    movq    %rcx, (%rax)\n");
+                let pointee = match self.static_type(target) {
+                    Some(Type::Ptr(inner)) => *inner,
+                    _ => Type::Unknown,
+                };
+                self.pop("%rcx");
+                self.output.push_str(&format!("    {}\n", store_instruction(&pointee, 'c', "(%rax)")));
+            }
+
+            StatementKind::FieldAssignment { object, field, value } => {
+                self.generate_expression(value)?;
+                self.push("%rax");
+
+                let layout = self.generate_field_address(object, field)?;
+
+                self.pop("%rcx");
+                let ty = layout.map(|f| f.ty).unwrap_or(Type::Unknown);
+                self.output.push_str(&format!("    {}\n", store_instruction(&ty, 'c', "(%rax)")));
             }
 
-            Statement::InlineAsm { parts } => {
+            StatementKind::InlineAsm { parts, outputs, inputs, clobbers } => {
                 use crate::ast::AsmPart;
-                
+
                 self.output.push_str("    # inline asm\n");
-                
+                // `compiler::elf_assembly` has bound the operands already.
+                let bindings = inline_asm::bind(outputs, inputs, clobbers, stmt.span).unwrap_or_default();
+                for r in &bindings.saved {
+                    self.push(r.names[0]);
+                }
+                for binding in bindings.operands.iter().filter(|b| b.read) {
+                    if let (Place::Register(r), Some(&offset)) = (binding.place, self.variables.get(&binding.variable)) {
+                        let ty = self.var_types.get(&binding.variable).cloned().unwrap_or(Type::Unknown);
+                        let slot = format!("{}(%rbp)", offset);
+                        self.output.push_str(&format!("    {}\n", load_register(&ty, &slot, &r)));
+                    }
+                }
+
+                let mut code = String::new();
                 for part in parts {
                     match part {
                         AsmPart::Literal(s) => {
-                            self.output.push_str(s);
+                            code.push_str(s);
                         }
                         AsmPart::Variable(var_name) => {
-                            self.output.push_str(&format!("    # Variable: {}\n", var_name));
+                            let ty = self.var_types.get(var_name).cloned().unwrap_or(Type::Unknown);
+                            match (bindings.get(var_name).map(|b| b.place), self.variables.get(var_name)) {
+                                (Some(Place::Register(r)), _) => code.push_str(r.sized(ty.size())),
+                                (_, Some(&offset)) => code.push_str(&format!("{}(%rbp)", offset)),
+                                (_, None) => code.push_str(&format!("\n# Variable: {}\n", var_name)),
+                            }
                         }
                     }
                 }
-                self.output.push_str("\n");
+                for line in code.lines().map(str::trim).filter(|line| !line.is_empty()) {
+                    self.output.push_str(&format!("    {}\n", line));
+                }
+
+                for binding in bindings.operands.iter().filter(|b| b.written) {
+                    if let (Place::Register(r), Some(&offset)) = (binding.place, self.variables.get(&binding.variable)) {
+                        let ty = self.var_types.get(&binding.variable).cloned().unwrap_or(Type::Unknown);
+                        let slot = format!("{}(%rbp)", offset);
+                        self.output.push_str(&format!("    {}\n", store_register(&ty, &r, &slot)));
+                    }
+                }
+                for r in bindings.saved.iter().rev() {
+                    self.pop(r.names[0]);
+                }
             }
-            Statement::ArrayAssignment { name, index, value } => {
-                self.generate_expression(value);
-                self.output.push_str("    pushq   %rax\n");
+            StatementKind::ArrayAssignment { name, index, value } => {
+                self.generate_expression(value)?;
+                self.push("%rax");
 
-                self.generate_expression(index);
+                self.generate_expression(index)?;
 
                 if let Some(&base_offset) = self.variables.get(name) {
-                    self.output.push_str("    imulq   $8, %rax\n");
+                    let elem = self.element_type(name);
+                    self.output.push_str(&format!("    imulq   ${}, %rax\n", elem.size()));
                     self.output.push_str(&format!("    addq    ${}, %rax\n", base_offset));
                     self.output.push_str("    addq    %rbp, %rax\n");
 
-                    self.output.push_str("    popq    %rcx\n");
-                    self.output.push_str("    movq    %rcx, (%rax)\n");
+                    self.pop("%rcx");
+                    self.output.push_str(&format!("    {}\n", store_instruction(&elem, 'c', "(%rax)")));
                 }
             }
-            Statement::Return(expr) => {
+            StatementKind::Return(expr) => {
                 if let Some(e) = expr {
-                    self.generate_expression(e);
+                    self.generate_expression(e)?;
+                    self.free_temporaries(pending);
                 } else {
                     self.output.push_str("    movl    $0, %eax\n");
                 }
                 self.output.push_str("    leave\n");
                 self.output.push_str("    ret\n");
             }
-            Statement::Expression(expr) => {
-                self.generate_expression(expr);
+            StatementKind::Break => {
+                if let Some((end_label, _)) = self.loop_stack.last() {
+                    self.output.push_str(&format!("    jmp     {}\n", end_label));
+                }
+            }
+            StatementKind::Continue => {
+                if let Some((_, continue_label)) = self.loop_stack.last() {
+                    self.output.push_str(&format!("    jmp     {}\n", continue_label));
+                }
+            }
+            StatementKind::Expression(expr) => {
+                self.generate_expression(expr)?;
             }
-            Statement::If { condition, then_body, else_body } => {
-                self.generate_expression(condition);
+            StatementKind::If { condition, then_body, else_body } => {
+                self.generate_expression(condition)?;
+                self.free_temporaries(pending);
                 let else_label = self.next_label();
                 let end_label = self.next_label();
 
@@ -368,55 +1266,119 @@ impl AsmGenerator {
                 self.output.push_str(&format!("    je      {}\n", else_label));
 
                 for stmt in then_body {
-                    self.generate_statement(stmt);
+                    self.generate_statement(stmt)?;
                 }
                 self.output.push_str(&format!("    jmp     {}\n", end_label));
 
                 self.output.push_str(&format!("{}:\n", else_label));
                 if let Some(body) = else_body {
                     for stmt in body {
-                        self.generate_statement(stmt);
+                        self.generate_statement(stmt)?;
                     }
                 }
                 self.output.push_str(&format!("{}:\n", end_label));
             }
-            Statement::For { init: _, condition, post: _, body } => {
+            StatementKind::For { init, condition, post, body } => {
+                if let Some(init_stmt) = init {
+                    self.generate_statement(init_stmt)?;
+                }
+
                 let loop_label = self.next_label();
+                let continue_label = self.next_label();
                 let end_label = self.next_label();
 
                 self.output.push_str(&format!("{}:\n", loop_label));
 
                 if let Some(cond) = condition {
-                    self.generate_expression(cond);
+                    self.generate_expression(cond)?;
+                    self.free_temporaries(pending);
                     self.output.push_str("    testq   %rax, %rax\n");
                     self.output.push_str(&format!("    je      {}\n", end_label));
                 }
 
+                self.loop_stack.push((end_label.clone(), continue_label.clone()));
                 for stmt in body {
-                    self.generate_statement(stmt);
+                    self.generate_statement(stmt)?;
+                }
+                self.loop_stack.pop();
+
+                self.output.push_str(&format!("{}:\n", continue_label));
+                if let Some(post_stmt) = post {
+                    self.generate_statement(post_stmt)?;
                 }
 
                 self.output.push_str(&format!("    jmp     {}\n", loop_label));
                 self.output.push_str(&format!("{}:\n", end_label));
             }
         }
+        self.free_temporaries(pending);
+        Ok(())
     }
 
-    fn generate_expression(&mut self, expr: &Expression) {
-        match expr {
-            Expression::Number(n) => {
+    /// Generates a string operand that is only read, so a template string
+    /// there can be freed once the statement is done with it.
+    fn generate_borrowed(&mut self, expr: &Expression) -> Result<(), CompileError> {
+        self.generate_expression(expr)?;
+        if let ExpressionKind::TemplateString { .. } = expr.kind {
+            self.stack_offset -= 8;
+            self.output.push_str(&format!("    movq    %rax, {}(%rbp)\n", self.stack_offset));
+            self.temporaries.push(self.stack_offset);
+        }
+        Ok(())
+    }
+
+    /// Frees the template strings registered since `from`, keeping %rax.
+    fn free_temporaries(&mut self, from: usize) {
+        if self.temporaries.len() == from {
+            return;
+        }
+        self.push("%rax");
+        for offset in self.temporaries.split_off(from).into_iter().rev() {
+            self.output.push_str(&format!("    movq    {}(%rbp), %rdi\n", offset));
+            self.call("__per_tpl_free");
+        }
+        self.pop("%rax");
+    }
+
+    fn generate_expression(&mut self, expr: &Expression) -> Result<(), CompileError> {
+        match &expr.kind {
+            ExpressionKind::Number(n) => {
                 self.output.push_str(&format!("    movq    ${}, %rax\n", n));
             }
-            Expression::Identifier(name) => {
+            ExpressionKind::Identifier(name) => {
                 if let Some(&offset) = self.variables.get(name) {
-                    self.output.push_str(&format!("    movq    {}(%rbp), %rax\n", offset));
+                    let ty = self.var_types.get(name).cloned().unwrap_or(Type::Unknown);
+                    let slot = format!("{}(%rbp)", offset);
+                    self.output.push_str(&format!("    {}\n", load_instruction(&ty, &slot)));
                 }
             }
-            Expression::Binary { op, left, right } => {
-                self.generate_expression(right);
-                self.output.push_str("    pushq   %rax\n");
-                self.generate_expression(left);
-                self.output.push_str("    popq    %rcx\n");
+            ExpressionKind::Binary { op, left, right } => {
+                let strings = [left, right].iter().any(|side| self.static_type(side) == Some(Type::String));
+                if strings {
+                    self.generate_borrowed(right)?;
+                    self.push("%rax");
+                    self.generate_borrowed(left)?;
+                } else {
+                    self.generate_expression(right)?;
+                    self.push("%rax");
+                    self.generate_expression(left)?;
+                }
+                self.pop("%rcx");
+
+                if strings && matches!(op, BinaryOp::Equal | BinaryOp::NotEqual) {
+                    self.output.push_str("    movq    %rax, %rdi\n");
+                    self.output.push_str("    movq    %rcx, %rsi\n");
+                    self.call("__per_str_compare");
+                    self.output.push_str("    testq   %rax, %rax\n");
+                    let set = if matches!(op, BinaryOp::Equal) { "sete" } else { "setne" };
+                    self.output.push_str(&format!("    {:<8}%al\n", set));
+                    self.output.push_str("    movzbq  %al, %rax\n");
+                    return Ok(());
+                }
+
+                let ty = operand_type(left, right, &self.var_types, &self.structs).unwrap_or(Type::I64);
+                let unsigned = ty.is_unsigned();
+                let cc = |signed: &'static str, unsigned_cc: &'static str| if unsigned { unsigned_cc } else { signed };
 
                 match op {
                     BinaryOp::Add => {
@@ -428,14 +1390,17 @@ impl AsmGenerator {
                     BinaryOp::Mul => {
                         self.output.push_str("    imulq   %rcx, %rax\n");
                     }
-                    BinaryOp::Div => {
-                        self.output.push_str("    cqto\n");
-                        self.output.push_str("    idivq   %rcx\n");
-                    }
-                    BinaryOp::Mod => {
-                        self.output.push_str("    cqto\n");
-                        self.output.push_str("    idivq   %rcx\n");
-                        self.output.push_str("    movq    %rdx, %rax\n");
+                    BinaryOp::Div | BinaryOp::Mod => {
+                        if unsigned {
+                            self.output.push_str("    xorl    %edx, %edx\n");
+                            self.output.push_str("    divq    %rcx\n");
+                        } else {
+                            self.output.push_str("    cqto\n");
+                            self.output.push_str("    idivq   %rcx\n");
+                        }
+                        if matches!(op, BinaryOp::Mod) {
+                            self.output.push_str("    movq    %rdx, %rax\n");
+                        }
                     }
                     BinaryOp::Equal => {
                         self.output.push_str("    cmpq    %rcx, %rax\n");
@@ -449,34 +1414,44 @@ impl AsmGenerator {
                     }
                     BinaryOp::Less => {
                         self.output.push_str("    cmpq    %rcx, %rax\n");
-                        self.output.push_str("    setl    %al\n");
+                        self.output.push_str(&format!("    {:<8}%al\n", cc("setl", "setb")));
                         self.output.push_str("    movzbq  %al, %rax\n");
                     }
                     BinaryOp::LessEqual => {
                         self.output.push_str("    cmpq    %rcx, %rax\n");
-                        self.output.push_str("    setle   %al\n");
+                        self.output.push_str(&format!("    {:<8}%al\n", cc("setle", "setbe")));
                         self.output.push_str("    movzbq  %al, %rax\n");
                     }
                     BinaryOp::Greater => {
                         self.output.push_str("    cmpq    %rcx, %rax\n");
-                        self.output.push_str("    setg    %al\n");
+                        self.output.push_str(&format!("    {:<8}%al\n", cc("setg", "seta")));
                         self.output.push_str("    movzbq  %al, %rax\n");
                     }
                     BinaryOp::GreaterEqual => {
                         self.output.push_str("    cmpq    %rcx, %rax\n");
-                        self.output.push_str("    setge   %al\n");
+                        self.output.push_str(&format!("    {:<8}%al\n", cc("setge", "setae")));
                         self.output.push_str("    movzbq  %al, %rax\n");
                     }
                     BinaryOp::Concat => {
+                        self.output.push_str("    movq    %rax, %rdi\n");
+                        self.output.push_str("    movq    %rcx, %rsi\n");
+                        self.call("__per_str_concat");
                     }
                     _ => {}
                 }
+
+                if matches!(op, BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod) {
+                    self.emit_wrap(&ty);
+                }
             }
-            Expression::Unary { op, operand } => {
-                self.generate_expression(operand);
+            ExpressionKind::Unary { op, operand } => {
+                self.generate_expression(operand)?;
                 match op {
                     UnaryOp::Neg => {
                         self.output.push_str("    negq    %rax\n");
+                        if let Some(ty) = self.static_type(operand) {
+                            self.emit_wrap(&ty);
+                        }
                     }
                     UnaryOp::Not => {
                         self.output.push_str("    testq   %rax, %rax\n");
@@ -485,177 +1460,391 @@ impl AsmGenerator {
                     }
                 }
             }
-            Expression::Call { function, args } => {
-                let arg_regs = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
-                
-                for arg in args.iter().rev() {
-                    self.generate_expression(arg);
-                    self.output.push_str("    pushq   %rax\n");
-                }
-                
-                for (i, _) in args.iter().enumerate() {
-                    if i < arg_regs.len() {
-                        self.output.push_str(&format!("    popq    {}\n", arg_regs[i]));
-                    }
-                }
-                
-                self.output.push_str(&format!("    call    {}\n", function));
+            ExpressionKind::Call { function, args } => {
+                self.generate_call(function, args, false)?;
             }
-            Expression::ArrayAccess { name, index } => {
-                self.generate_expression(index);
+            ExpressionKind::ArrayAccess { name, index } => {
+                self.generate_expression(index)?;
 
                 if let Some(&base_offset) = self.variables.get(name) {
-                    self.output.push_str("    imulq   $8, %rax\n");
+                    let elem = self.element_type(name);
+                    self.output.push_str(&format!("    imulq   ${}, %rax\n", elem.size()));
                     self.output.push_str(&format!("    addq    ${}, %rax\n", base_offset));
                     self.output.push_str("    addq    %rbp, %rax\n");
 
-                    self.output.push_str("    movq    (%rax), %rax\n");
+                    self.output.push_str(&format!("    {}\n", load_instruction(&elem, "(%rax)")));
                 }
             }
-            Expression::ModuleCall { module, function, args } => {
-                let arg_regs = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
-
-                for arg in args.iter().rev() {
-                    self.generate_expression(arg);
-                    self.output.push_str("    pushq   %rax\n");
-                }
-
-                for (i, _) in args.iter().enumerate() {
-                    if i < arg_regs.len() {
-                        self.output.push_str(&format!("    popq    {}\n", arg_regs[i]));
-                    }
-                }
-
-                self.output.push_str(&format!("    call    {}_{}\n", module, function));
+            ExpressionKind::ModuleCall { module, function, args } => {
+                let borrowed = module == "stdio" || is_string_builtin(module, function);
+                self.generate_call(&format!("{}_{}", module, function), args, borrowed)?;
             }
-            Expression::String(s) => {
+            ExpressionKind::String(s) => {
                 let idx = self.string_literals.len();
                 self.string_literals.push(s.clone());
                 self.output.push_str(&format!("    leaq    .LS{}(%rip), %rax\n", idx));
             }
-            Expression::TemplateString { parts } => {
+            ExpressionKind::TemplateString { parts } => {
                 use crate::ast::{TemplateStringPart, FormatType};
-                
-                self.output.push_str("    subq    $1024, %rsp\n");
-                self.output.push_str("    movq    %rsp, %r15\n");
-                
-                self.output.push_str("    movb    $0, (%r15)\n");
-                
-                self.output.push_str("    subq    $128, %rsp\n");
-                self.output.push_str("    movq    %rsp, %r14\n");
-                
+
+                // The builder is kept on the stack while the parts are
+                // generated; every append may move it.
+                self.call("__per_tpl_new");
+                self.push("%rax");
+
                 for part in parts {
                     match part {
                         TemplateStringPart::Literal(lit) => {
                             let idx = self.string_literals.len();
                             self.string_literals.push(lit.clone());
-                            
-                            self.output.push_str("    movq    %r15, %rdi\n");
+
+                            self.pop("%rdi");
                             self.output.push_str(&format!("    leaq    .LS{}(%rip), %rsi\n", idx));
-                            self.output.push_str("    call    strcat@PLT\n");
+                            self.output.push_str(&format!("    movq    ${}, %rdx\n", lit.len()));
+                            self.call("__per_tpl_append");
+                            self.push("%rax");
                         }
                         TemplateStringPart::Expression { expr, format } => {
-                            let is_string_expr = matches!(
-                                expr.as_ref(),
-                                Expression::String(_) | Expression::Identifier(_)
+                            let is_string_expr = self.static_type(expr) == Some(Type::String);
+                            let is_string_format = matches!(
+                                format,
+                                Some(spec) if matches!(spec.format_type, FormatType::String)
                             );
-                            
-                            self.generate_expression(expr);
-                            
-                            if is_string_expr && format.is_none() {
-                                self.output.push_str("    movq    %r15, %rdi\n");
+
+                            self.generate_expression(expr)?;
+
+                            if is_string_expr || is_string_format {
                                 self.output.push_str("    movq    %rax, %rsi\n");
-                                self.output.push_str("    call    strcat@PLT\n");
-                            } else {
-                                let format_str = if let Some(spec) = format {
-                                    match spec.format_type {
-                                        FormatType::Hex => {
-                                            if let Some(width) = spec.width {
-                                                if spec.padding == '0' {
-                                                    format!("%0{}lx", width)
-                                                } else {
-                                                    format!("%{}lx", width)
-                                                }
-                                            } else {
-                                                "%lx".to_string()
-                                            }
-                                        }
-                                        FormatType::HexUpper => {
-                                            if let Some(width) = spec.width {
-                                                if spec.padding == '0' {
-                                                    format!("%0{}lX", width)
-                                                } else {
-                                                    format!("%{}lX", width)
-                                                }
-                                            } else {
-                                                "%lX".to_string()
-                                            }
-                                        }
-                                        FormatType::Decimal => {
-                                            if let Some(width) = spec.width {
-                                                if spec.padding == '0' {
-                                                    format!("%0{}ld", width)
-                                                } else {
-                                                    format!("%{}ld", width)
-                                                }
-                                            } else {
-                                                "%ld".to_string()
-                                            }
-                                        }
-                                        FormatType::String => "%s".to_string(),
-                                        FormatType::Auto => "%ld".to_string(),
-                                    }
+                                if is_string_expr {
+                                    self.output.push_str("    movq    -8(%rsi), %rdx\n");
                                 } else {
-                                    "%ld".to_string()
+                                    self.push("%rax");
+                                    self.output.push_str("    movq    %rax, %rdi\n");
+                                    self.call(if self.freestanding { "__per_strlen" } else { "strlen@PLT" });
+                                    self.output.push_str("    movq    %rax, %rdx\n");
+                                    self.pop("%rsi");
+                                }
+                                self.pop("%rdi");
+                                self.call("__per_tpl_append");
+                            } else if self.freestanding {
+                                let (base, upper) = match format.as_ref().map(|spec| &spec.format_type) {
+                                    Some(FormatType::Hex) => (16, 0),
+                                    Some(FormatType::HexUpper) => (16, 1),
+                                    _ => (10, 0),
                                 };
-                                
+                                let width = format.as_ref().and_then(|spec| spec.width).unwrap_or(0);
+                                let padding = format.as_ref().map(|spec| spec.padding).unwrap_or(' ');
+
+                                self.output.push_str("    movq    %rax, %rsi\n");
+                                self.pop("%rdi");
+                                self.output.push_str(&format!("    movq    ${}, %rdx\n", width));
+                                self.output.push_str(&format!("    movq    ${}, %rcx\n", padding as u32));
+                                self.output.push_str(&format!("    movq    ${}, %r8\n", base));
+                                self.output.push_str(&format!("    movq    ${}, %r9\n", upper));
+                                self.call("__per_tpl_int");
+                            } else {
+                                let unsigned = self.static_type(expr).is_some_and(|t| t.is_unsigned());
                                 let fmt_idx = self.string_literals.len();
-                                self.string_literals.push(format_str);
-                                
+                                self.string_literals.push(printf_format(format.as_ref(), unsigned));
+                                let width = format.as_ref().and_then(|spec| spec.width).unwrap_or(0);
+
                                 self.output.push_str("    movq    %rax, %rdx\n");
-                                self.output.push_str("    movq    %r14, %rdi\n");
+                                self.pop("%rdi");
                                 self.output.push_str(&format!("    leaq    .LS{}(%rip), %rsi\n", fmt_idx));
-                                self.output.push_str("    xorl    %eax, %eax\n");
-                                self.output.push_str("    call    sprintf@PLT\n");
-                                
-                                self.output.push_str("    movq    %r15, %rdi\n");
-                                self.output.push_str("    movq    %r14, %rsi\n");
-                                self.output.push_str("    call    strcat@PLT\n");
+                                self.output.push_str(&format!("    movq    ${}, %rcx\n", width + FORMATTED_INT));
+                                self.call("__per_tpl_format");
                             }
+                            self.push("%rax");
                         }
                     }
                 }
-                
-                self.output.push_str("    movq    %r15, %rax\n");
-                
+
+                self.pop("%rax");
+            }
+            ExpressionKind::StringIndex { string, index } => {
+                self.generate_expression(index)?;
+                self.push("%rax");
+                self.generate_expression(string)?;
+                self.pop("%rcx");
+                self.output.push_str("    addq    %rax, %rcx\n");
+                self.output.push_str("    movzbq  (%rcx), %rax\n");
+            }
+            ExpressionKind::FieldAccess { object, field } => {
+                let layout = self.generate_field_address(object, field)?;
+                let ty = layout.map(|f| f.ty).unwrap_or(Type::Unknown);
+                if !matches!(ty, Type::Struct(_)) {
+                    self.output.push_str(&format!("    {}\n", load_instruction(&ty, "(%rax)")));
+                }
+            }
+            ExpressionKind::AddressOf { operand } => {
+                match &operand.kind {
+                    ExpressionKind::Identifier(name) => {
+                        if let Some(&offset) = self.variables.get(name) {
+                            self.output.push_str(&format!("    leaq    {}(%rbp), %rax\n", offset));
+                        }
+                    }
+                    ExpressionKind::FieldAccess { object, field } => {
+                        self.generate_field_address(object, field)?;
+                    }
+                    _ => {}
+                }
             }
-            Expression::StringIndex { string, index } => {
-                if let Expression::String(s) = string.as_ref() {
-                    let idx = self.string_literals.len();
-                    self.string_literals.push(s.clone());
+            ExpressionKind::Deref { operand } => {
+                self.generate_expression(operand)?;
+                let pointee = match self.static_type(operand) {
+                    Some(Type::Ptr(inner)) => *inner,
+                    _ => Type::Unknown,
+                };
+                self.output.push_str(&format!("    {}\n", load_instruction(&pointee, "(%rax)")));
+            }
+            ExpressionKind::Cast { operand, target_type } => {
+                self.generate_expression(operand)?;
+                match Type::resolve(target_type, &self.structs) {
+                    Type::Bool => {
+                        self.output.push_str("    testq   %rax, %rax\n");
+                        self.output.push_str("    setne   %al\n");
+                        self.output.push_str("    movzbq  %al, %rax\n");
+                    }
+                    ty => self.emit_wrap(&ty),
+                }
+            }
+            ExpressionKind::Eval { .. } => {
+                return Err(CompileError::new(
+                    ErrorKind::CodeGenError,
+                    EVAL_NOT_FOLDED.to_string(),
+                    self.file.clone(),
+                    expr.span.line,
+                    expr.span.column,
+                ));
+            }
+        }
+        Ok(())
+    }
 
-                    self.generate_expression(index);
+    /// System V call: the first six arguments in registers, the rest pushed
+    /// right to left, padded so %rsp is 16-byte aligned at the call, and
+    /// popped again by the caller. `extern "C"` functions are called through
+    /// the PLT by their own name, with %al cleared in case they are variadic,
+    /// and their narrow results extended. `borrowed` arguments are only read
+    /// by the callee, see `generate_borrowed`.
+    fn generate_call(&mut self, target: &str, args: &[Expression], borrowed: bool) -> Result<(), CompileError> {
+        let external = self.externs.get(target).cloned();
+        let stacked = args.len().saturating_sub(ARG_REGS.len());
+        let padding = (self.pushed + stacked) % 2;
+        if stacked > 0 && padding == 1 {
+            self.output.push_str("    subq    $8, %rsp\n");
+            self.pushed += 1;
+        }
 
-                    self.output.push_str(&format!("    leaq    .LS{}(%rip), %rcx\n", idx));
-                    self.output.push_str("    addq    %rax, %rcx\n");
+        for arg in args.iter().rev() {
+            if borrowed {
+                self.generate_borrowed(arg)?;
+            } else {
+                self.generate_expression(arg)?;
+            }
+            self.push("%rax");
+        }
+        for reg in ARG_REGS.iter().take(args.len()) {
+            self.pop(reg);
+        }
 
-                    self.output.push_str("    movzbq  (%rcx), %rax\n");
-                }
+        match &external {
+            Some((name, _)) => {
+                self.output.push_str("    xorl    %eax, %eax\n");
+                self.call(&format!("{}@PLT", name));
             }
-            Expression::AddressOf { operand } => {
-                if let Expression::Identifier(name) = operand.as_ref() {
-                    if let Some(&offset) = self.variables.get(name) {
-                        self.output.push_str(&format!("    leaq    {}(%rbp), %rax\n", offset));
-                    }
+            None => self.call(target),
+        }
+
+        if stacked > 0 {
+            let cleanup = stacked + padding;
+            self.output.push_str(&format!("    addq    ${}, %rsp\n", 8 * cleanup));
+            self.pushed -= cleanup;
+        }
+
+        match external {
+            Some((_, Type::Bool)) => self.output.push_str("    movzbq  %al, %rax\n"),
+            Some((_, Type::String)) => {
+                self.output.push_str("    movq    %rax, %rdi\n");
+                self.call("__per_str_from_c");
+            }
+            Some((_, return_type)) => self.emit_wrap(&return_type),
+            None => {}
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, reg: &str) {
+        self.output.push_str(&format!("    pushq   {}\n", reg));
+        self.pushed += 1;
+    }
+
+    fn pop(&mut self, reg: &str) {
+        self.output.push_str(&format!("    popq    {}\n", reg));
+        self.pushed -= 1;
+    }
+
+    /// Calls `target` with %rsp 16-byte aligned, padding it if an odd number
+    /// of values is pushed.
+    fn call(&mut self, target: &str) {
+        if self.pushed % 2 == 1 {
+            self.output.push_str("    subq    $8, %rsp\n");
+            self.output.push_str(&format!("    call    {}\n", target));
+            self.output.push_str("    addq    $8, %rsp\n");
+        } else {
+            self.output.push_str(&format!("    call    {}\n", target));
+        }
+    }
+
+    fn static_type(&self, expr: &Expression) -> Option<Type> {
+        let symbol = match &expr.kind {
+            ExpressionKind::Call { function, .. } => function.clone(),
+            ExpressionKind::ModuleCall { module, function, .. } if !is_string_builtin(module, function) => {
+                format!("{}_{}", module, function)
+            }
+            _ => return expression_type(expr, &self.var_types, &self.structs),
+        };
+        self.returns.get(&symbol).cloned().filter(|t| !matches!(t, Type::Void | Type::Unknown))
+    }
+
+    fn element_type(&self, array: &str) -> Type {
+        match self.var_types.get(array) {
+            Some(Type::Array(elem, _)) => (**elem).clone(),
+            _ => Type::Unknown,
+        }
+    }
+
+    /// Truncates %rax to `ty` and extends it back to 64 bits, so narrow
+    /// values wrap the way they would in memory.
+    fn emit_wrap(&mut self, ty: &Type) {
+        let wrap = match ty {
+            Type::I32 => "movslq  %eax, %rax",
+            Type::U32 => "movl    %eax, %eax",
+            Type::I8 => "movsbq  %al, %rax",
+            Type::U8 => "movzbq  %al, %rax",
+            _ => return,
+        };
+        self.output.push_str(&format!("    {}\n", wrap));
+    }
+
+    /// Leaves the address of `object.field` in %rax. Struct values are
+    /// addressed in place; pointers to structs are loaded and offset.
+    fn generate_field_address(&mut self, object: &Expression, field: &str) -> Result<Option<FieldLayout>, CompileError> {
+        let Some(object_type) = self.static_type(object) else {
+            return Ok(None);
+        };
+        let layout = object_type.struct_name()
+            .and_then(|name| self.structs.get(name))
+            .and_then(|s| s.field(field))
+            .cloned();
+        let Some(layout) = layout else {
+            return Ok(None);
+        };
+
+        match (&object_type, &object.kind) {
+            (Type::Struct(_), ExpressionKind::Identifier(name)) => {
+                let Some(&offset) = self.variables.get(name) else {
+                    return Ok(None);
+                };
+                self.output.push_str(&format!("    leaq    {}(%rbp), %rax\n", offset));
+            }
+            (Type::Struct(_), ExpressionKind::FieldAccess { object: inner, field: inner_field }) => {
+                if self.generate_field_address(inner, inner_field)?.is_none() {
+                    return Ok(None);
                 }
             }
-            Expression::Deref { operand } => {
-                self.generate_expression(operand);
-                self.output.push_str("    movq    (%rax), %rax\n");
+            (Type::Struct(_), ExpressionKind::Deref { operand }) => {
+                self.generate_expression(operand)?;
             }
-            Expression::Eval { instruction: _ } => {
-                self.output.push_str("    movq    $0, %rax\n");
+            _ => {
+                self.generate_expression(object)?;
             }
         }
+
+        if layout.offset != 0 {
+            self.output.push_str(&format!("    addq    ${}, %rax\n", layout.offset));
+        }
+
+        Ok(Some(layout))
     }
 }
+
+/// Loads a `ty` from `src` into %rax, sign- or zero-extended to 64 bits.
+fn load_instruction(ty: &Type, src: &str) -> String {
+    load_register(ty, src, &RAX)
+}
+
+/// Loads a `ty` from `src` into `reg`, sign- or zero-extended to 64 bits.
+fn load_register(ty: &Type, src: &str, reg: &Register) -> String {
+    match ty {
+        Type::I32 => format!("movslq  {}, {}", src, reg.names[0]),
+        Type::U32 => format!("movl    {}, {}", src, reg.names[1]),
+        Type::I8 => format!("movsbq  {}, {}", src, reg.names[0]),
+        Type::U8 | Type::Bool => format!("movzbq  {}, {}", src, reg.names[0]),
+        _ => format!("movq    {}, {}", src, reg.names[0]),
+    }
+}
+
+/// Stores the low `ty.size()` bytes of %rax (`reg` 'a') or %rcx ('c') to `dst`.
+fn store_instruction(ty: &Type, reg: char, dst: &str) -> String {
+    store_register(ty, if reg == 'a' { &RAX } else { &RCX }, dst)
+}
+
+/// Stores the low `ty.size()` bytes of `reg` to `dst`.
+fn store_register(ty: &Type, reg: &Register, dst: &str) -> String {
+    let suffix = match ty.size() {
+        4 => 'l',
+        1 => 'b',
+        _ => 'q',
+    };
+    format!("mov{}    {}, {}", suffix, reg.sized(ty.size()), dst)
+}
+
+/// Bytes of frame the template strings in `exprs` need: a slot each, in
+/// case they are freed at the end of the statement.
+fn template_slots<'a>(exprs: impl IntoIterator<Item = &'a Expression>) -> usize {
+    exprs.into_iter().map(template_strings).sum::<usize>() * 8
+}
+
+/// How many template strings `expr` builds, each needing a buffer in the
+/// frame.
+fn template_strings(expr: &Expression) -> usize {
+    match &expr.kind {
+        ExpressionKind::TemplateString { parts } => 1 + parts.iter().map(|part| match part {
+            TemplateStringPart::Expression { expr, .. } => template_strings(expr),
+            TemplateStringPart::Literal(_) => 0,
+        }).sum::<usize>(),
+        ExpressionKind::Binary { left, right, .. } => template_strings(left) + template_strings(right),
+        ExpressionKind::Call { args, .. } | ExpressionKind::ModuleCall { args, .. } => args.iter().map(template_strings).sum(),
+        ExpressionKind::StringIndex { string, index } => template_strings(string) + template_strings(index),
+        ExpressionKind::ArrayAccess { index: operand, .. }
+        | ExpressionKind::Unary { operand, .. }
+        | ExpressionKind::FieldAccess { object: operand, .. }
+        | ExpressionKind::AddressOf { operand }
+        | ExpressionKind::Deref { operand }
+        | ExpressionKind::Cast { operand, .. }
+        | ExpressionKind::Eval { instruction: operand } => template_strings(operand),
+        ExpressionKind::Number(_) | ExpressionKind::String(_) | ExpressionKind::Identifier(_) => 0,
+    }
+}
+
+/// The `printf` conversion for an interpolated integer.
+fn printf_format(format: Option<&FormatSpec>, unsigned: bool) -> String {
+    let decimal = if unsigned { "lu" } else { "ld" };
+    let Some(spec) = format else {
+        return format!("%{}", decimal);
+    };
+    let conversion = match spec.format_type {
+        FormatType::Hex => "lx",
+        FormatType::HexUpper => "lX",
+        FormatType::String => "s",
+        FormatType::Decimal | FormatType::Auto => decimal,
+    };
+    match spec.width {
+        Some(width) if !matches!(spec.format_type, FormatType::Auto | FormatType::String) => {
+            format!("%{}{}{}", if spec.padding == '0' { "0" } else { "" }, width, conversion)
+        }
+        _ => format!("%{}", conversion),
+    }
+}
+
@@ -0,0 +1,958 @@
+use crate::error::{CompileError, ErrorKind, Result};
+use crate::pe::MachineCode;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Section {
+    Text,
+    Data,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Reg {
+    num: u8,
+    size: u8,
+}
+
+#[derive(Debug, Clone)]
+struct Mem {
+    base: Option<u8>,
+    index: Option<(u8, u8)>,
+    disp: i64,
+    symbol: Option<String>,
+    rip: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Reg(Reg),
+    Imm(i64),
+    Mem(Mem),
+    Label(String),
+    Indirect(Box<Operand>),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum FixupKind {
+    Rel32,
+    Abs32,
+    Abs64,
+}
+
+struct Fixup {
+    section: Section,
+    offset: usize,
+    symbol: String,
+    addend: i64,
+    kind: FixupKind,
+    line: usize,
+}
+
+const ALU_OPS: [&str; 8] = ["add", "or", "adc", "sbb", "and", "sub", "xor", "cmp"];
+const UNARY_OPS: [(&str, u8); 6] = [("not", 2), ("neg", 3), ("mul", 4), ("div", 6), ("idiv", 7), ("imul", 5)];
+const SHIFT_OPS: [(&str, u8); 8] = [
+    ("rol", 0), ("ror", 1), ("rcl", 2), ("rcr", 3), ("shl", 4), ("sal", 4), ("shr", 5), ("sar", 7),
+];
+const SIZED_OPS: [&str; 19] = [
+    "add", "or", "adc", "sbb", "and", "sub", "xor", "cmp", "mov", "test", "lea", "not", "neg", "mul", "div",
+    "idiv", "imul", "inc", "dec",
+];
+
+const CONDITIONS: [(&str, u8); 30] = [
+    ("o", 0), ("no", 1), ("b", 2), ("c", 2), ("nae", 2), ("ae", 3), ("nb", 3), ("nc", 3),
+    ("e", 4), ("z", 4), ("ne", 5), ("nz", 5), ("be", 6), ("na", 6), ("a", 7), ("nbe", 7),
+    ("s", 8), ("ns", 9), ("p", 10), ("pe", 10), ("np", 11), ("po", 11), ("l", 12), ("nge", 12),
+    ("ge", 13), ("nl", 13), ("le", 14), ("ng", 14), ("g", 15), ("nle", 15),
+];
+
+/// Assembles the AT&T syntax emitted by `AsmGenerator` into machine code that
+/// `ELFWriter` can place in a static executable, without an external toolchain.
+pub struct Assembler {
+    text: Vec<u8>,
+    data: Vec<u8>,
+    section: Section,
+    symbols: HashMap<String, (Section, usize)>,
+    fixups: Vec<Fixup>,
+    line: usize,
+    source_line: String,
+}
+
+impl Default for Assembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Assembler {
+    pub fn new() -> Self {
+        Assembler {
+            text: Vec::new(),
+            data: Vec::new(),
+            section: Section::Text,
+            symbols: HashMap::new(),
+            fixups: Vec::new(),
+            line: 0,
+            source_line: String::new(),
+        }
+    }
+
+    pub fn assemble(&mut self, source: &str) -> Result<()> {
+        for (i, line) in source.lines().enumerate() {
+            self.line = i + 1;
+            self.source_line = line.to_string();
+            for statement in split_statements(line) {
+                self.assemble_statement(statement.trim())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn text_size(&self) -> usize {
+        self.text.len()
+    }
+
+    /// Resolves every symbol reference once the final load addresses are known.
+    pub fn link(&mut self, text_address: u64, data_address: u64, entry: &str) -> Result<MachineCode> {
+        let fixups = std::mem::take(&mut self.fixups);
+        for fixup in &fixups {
+            self.line = fixup.line;
+            let target = match self.symbols.get(&fixup.symbol) {
+                Some(&(Section::Text, offset)) => text_address + offset as u64,
+                Some(&(Section::Data, offset)) => data_address + offset as u64,
+                None => return Err(self.error(format!("undefined symbol '{}'", fixup.symbol))),
+            };
+            let place = match fixup.section {
+                Section::Text => text_address,
+                Section::Data => data_address,
+            } + fixup.offset as u64;
+            let value = (target as i64).wrapping_add(fixup.addend);
+
+            let bytes = match fixup.kind {
+                FixupKind::Rel32 => {
+                    let rel = value - place as i64;
+                    if rel < i32::MIN as i64 || rel > i32::MAX as i64 {
+                        return Err(self.error(format!("relocation to '{}' out of range", fixup.symbol)));
+                    }
+                    (rel as i32).to_le_bytes().to_vec()
+                }
+                FixupKind::Abs32 => (value as i32).to_le_bytes().to_vec(),
+                FixupKind::Abs64 => value.to_le_bytes().to_vec(),
+            };
+            let buffer = match fixup.section {
+                Section::Text => &mut self.text,
+                Section::Data => &mut self.data,
+            };
+            buffer[fixup.offset..fixup.offset + bytes.len()].copy_from_slice(&bytes);
+        }
+
+        let entry_point = match self.symbols.get(entry) {
+            Some(&(Section::Text, offset)) => offset,
+            _ => return Err(self.error(format!("entry symbol '{}' not defined in .text", entry))),
+        };
+
+        Ok(MachineCode {
+            code: self.text.clone(),
+            data: self.data.clone(),
+            entry_point,
+        })
+    }
+
+    fn error(&self, message: String) -> CompileError {
+        CompileError::new(ErrorKind::CodeGenError, message, "<assembly>".to_string(), self.line, 1)
+            .with_source_line(self.source_line.trim_end().to_string())
+    }
+
+    fn buffer(&mut self) -> &mut Vec<u8> {
+        match self.section {
+            Section::Text => &mut self.text,
+            Section::Data => &mut self.data,
+        }
+    }
+
+    fn assemble_statement(&mut self, mut statement: &str) -> Result<()> {
+        while let Some(colon) = label_end(statement) {
+            let label = statement[..colon].trim();
+            let offset = self.buffer().len();
+            if self.symbols.insert(label.to_string(), (self.section, offset)).is_some() {
+                return Err(self.error(format!("symbol '{}' is already defined", label)));
+            }
+            statement = statement[colon + 1..].trim();
+        }
+
+        if statement.is_empty() {
+            return Ok(());
+        }
+
+        let (mnemonic, rest) = match statement.find(char::is_whitespace) {
+            Some(pos) => (&statement[..pos], statement[pos..].trim()),
+            None => (statement, ""),
+        };
+
+        if mnemonic.starts_with('.') {
+            return self.assemble_directive(mnemonic, rest);
+        }
+
+        if self.section != Section::Text {
+            return Err(self.error("instructions are only allowed in .text".to_string()));
+        }
+
+        let operands = split_operands(rest)
+            .iter()
+            .map(|op| self.parse_operand(op))
+            .collect::<Result<Vec<_>>>()?;
+
+        self.assemble_instruction(&mnemonic.to_lowercase(), &operands)
+    }
+
+    fn assemble_directive(&mut self, directive: &str, args: &str) -> Result<()> {
+        match directive {
+            ".text" => self.section = Section::Text,
+            ".data" | ".rodata" | ".bss" => self.section = Section::Data,
+            ".section" => {
+                let name = args.split(',').next().unwrap_or("").trim();
+                self.section = if name.starts_with(".text") { Section::Text } else { Section::Data };
+            }
+            ".globl" | ".global" | ".type" | ".size" | ".file" | ".ident" | ".local" => {}
+            ".string" | ".asciz" | ".ascii" => {
+                let mut bytes = self.parse_string(args)?;
+                if directive != ".ascii" {
+                    bytes.push(0);
+                }
+                self.buffer().extend_from_slice(&bytes);
+            }
+            ".byte" | ".word" | ".short" | ".long" | ".int" | ".quad" => {
+                let width = match directive {
+                    ".byte" => 1,
+                    ".word" | ".short" => 2,
+                    ".long" | ".int" => 4,
+                    _ => 8,
+                };
+                for value in split_operands(args) {
+                    match parse_number(&value) {
+                        Some(n) => {
+                            let bytes = n.to_le_bytes();
+                            self.buffer().extend_from_slice(&bytes[..width]);
+                        }
+                        None if width >= 4 => {
+                            let (symbol, addend) = self.parse_symbol(&value)?;
+                            let offset = self.buffer().len();
+                            let kind = if width == 8 { FixupKind::Abs64 } else { FixupKind::Abs32 };
+                            self.add_fixup(offset, symbol, addend, kind);
+                            self.buffer().extend_from_slice(&vec![0; width]);
+                        }
+                        None => return Err(self.error(format!("invalid constant '{}'", value))),
+                    }
+                }
+            }
+            ".zero" | ".space" | ".skip" => {
+                let count = parse_number(args)
+                    .filter(|&n| n >= 0)
+                    .ok_or_else(|| self.error(format!("invalid size '{}'", args)))?;
+                let fill = if self.section == Section::Text { 0x90 } else { 0 };
+                self.buffer().extend(std::iter::repeat_n(fill, count as usize));
+            }
+            ".align" | ".p2align" | ".balign" => {
+                let value = parse_number(args.split(',').next().unwrap_or(""))
+                    .filter(|&n| n >= 0)
+                    .ok_or_else(|| self.error(format!("invalid alignment '{}'", args)))?;
+                let align = if directive == ".p2align" { 1usize << value } else { value.max(1) as usize };
+                let fill = if self.section == Section::Text { 0x90 } else { 0 };
+                while !self.buffer().len().is_multiple_of(align) {
+                    self.buffer().push(fill);
+                }
+            }
+            _ => return Err(self.error(format!("unsupported directive '{}'", directive))),
+        }
+        Ok(())
+    }
+
+    fn parse_string(&self, args: &str) -> Result<Vec<u8>> {
+        let args = args.trim();
+        if args.len() < 2 || !args.starts_with('"') || !args.ends_with('"') {
+            return Err(self.error(format!("expected string literal, found '{}'", args)));
+        }
+        let mut bytes = Vec::new();
+        let mut chars = args[1..args.len() - 1].chars().peekable();
+        while let Some(ch) = chars.next() {
+            if ch != '\\' {
+                let mut buf = [0; 4];
+                bytes.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+                continue;
+            }
+            match chars.next() {
+                Some('n') => bytes.push(b'\n'),
+                Some('t') => bytes.push(b'\t'),
+                Some('r') => bytes.push(b'\r'),
+                Some('b') => bytes.push(8),
+                Some('f') => bytes.push(12),
+                Some('\\') => bytes.push(b'\\'),
+                Some('"') => bytes.push(b'"'),
+                Some('x') => {
+                    let mut value = 0u32;
+                    while let Some(digit) = chars.peek().and_then(|c| c.to_digit(16)) {
+                        value = value * 16 + digit;
+                        chars.next();
+                    }
+                    bytes.push(value as u8);
+                }
+                Some(c) if c.is_digit(8) => {
+                    let mut value = c.to_digit(8).unwrap();
+                    for _ in 0..2 {
+                        match chars.peek().and_then(|c| c.to_digit(8)) {
+                            Some(digit) => {
+                                value = value * 8 + digit;
+                                chars.next();
+                            }
+                            None => break,
+                        }
+                    }
+                    bytes.push(value as u8);
+                }
+                Some(c) => return Err(self.error(format!("unknown escape '\\{}' in string", c))),
+                None => return Err(self.error("unterminated escape in string".to_string())),
+            }
+        }
+        Ok(bytes)
+    }
+
+    fn parse_symbol(&self, text: &str) -> Result<(String, i64)> {
+        let text = text.trim();
+        let split = text.char_indices().skip(1).find(|(_, c)| *c == '+' || *c == '-').map(|(pos, _)| pos);
+        let (name, addend) = match split {
+            Some(pos) => {
+                let addend = parse_number(&text[pos..])
+                    .ok_or_else(|| self.error(format!("invalid symbol expression '{}'", text)))?;
+                (text[..pos].trim(), addend)
+            }
+            None => (text, 0),
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$') {
+            return Err(self.error(format!("invalid symbol '{}'", text)));
+        }
+        Ok((name.to_string(), addend))
+    }
+
+    fn parse_operand(&self, text: &str) -> Result<Operand> {
+        let text = text.trim();
+        if let Some(inner) = text.strip_prefix('*') {
+            return Ok(Operand::Indirect(Box::new(self.parse_operand(inner)?)));
+        }
+        if let Some(name) = text.strip_prefix('%') {
+            return parse_register(name)
+                .map(Operand::Reg)
+                .ok_or_else(|| self.error(format!("unknown register '%{}'", name)));
+        }
+        if let Some(value) = text.strip_prefix('$') {
+            return parse_number(value)
+                .map(Operand::Imm)
+                .ok_or_else(|| self.error(format!("unsupported immediate '{}'", text)));
+        }
+        if let Some(open) = text.find('(') {
+            if !text.ends_with(')') {
+                return Err(self.error(format!("malformed memory operand '{}'", text)));
+            }
+            let disp_text = text[..open].trim();
+            let (disp, symbol) = if disp_text.is_empty() {
+                (0, None)
+            } else if let Some(n) = parse_number(disp_text) {
+                (n, None)
+            } else {
+                let (name, addend) = self.parse_symbol(disp_text)?;
+                (addend, Some(name))
+            };
+
+            let parts: Vec<&str> = text[open + 1..text.len() - 1].split(',').map(|p| p.trim()).collect();
+            let mut mem = Mem { base: None, index: None, disp, symbol, rip: false };
+
+            if let Some(base) = parts.first().filter(|p| !p.is_empty()) {
+                if *base == "%rip" {
+                    mem.rip = true;
+                } else {
+                    mem.base = Some(self.address_register(base)?);
+                }
+            }
+            if let Some(index) = parts.get(1).filter(|p| !p.is_empty()) {
+                let index = self.address_register(index)?;
+                if index == 4 {
+                    return Err(self.error("%rsp cannot be used as an index register".to_string()));
+                }
+                let scale = match parts.get(2) {
+                    Some(s) => parse_number(s).unwrap_or(0),
+                    None => 1,
+                };
+                let scale_bits = match scale {
+                    1 => 0,
+                    2 => 1,
+                    4 => 2,
+                    8 => 3,
+                    _ => return Err(self.error(format!("invalid scale in '{}'", text))),
+                };
+                mem.index = Some((index, scale_bits));
+            }
+            if mem.rip && mem.index.is_some() {
+                return Err(self.error("%rip-relative operands cannot use an index".to_string()));
+            }
+            return Ok(Operand::Mem(mem));
+        }
+        if let Some(n) = parse_number(text) {
+            return Ok(Operand::Mem(Mem { base: None, index: None, disp: n, symbol: None, rip: false }));
+        }
+        let (name, addend) = self.parse_symbol(text)?;
+        if addend != 0 {
+            return Ok(Operand::Mem(Mem { base: None, index: None, disp: addend, symbol: Some(name), rip: false }));
+        }
+        Ok(Operand::Label(name))
+    }
+
+    fn address_register(&self, text: &str) -> Result<u8> {
+        match text.strip_prefix('%').and_then(parse_register) {
+            Some(reg) if reg.size == 8 => Ok(reg.num),
+            _ => Err(self.error(format!("invalid address register '{}'", text))),
+        }
+    }
+
+    fn add_fixup(&mut self, offset: usize, symbol: String, addend: i64, kind: FixupKind) {
+        self.fixups.push(Fixup { section: self.section, offset, symbol, addend, kind, line: self.line });
+    }
+
+    fn assemble_instruction(&mut self, mnemonic: &str, ops: &[Operand]) -> Result<()> {
+        match (mnemonic, ops) {
+            ("ret" | "retq", []) => return self.emit(&[0xC3]),
+            ("leave" | "leaveq", []) => return self.emit(&[0xC9]),
+            ("syscall", []) => return self.emit(&[0x0F, 0x05]),
+            ("cqto" | "cqo", []) => return self.emit(&[0x48, 0x99]),
+            ("cltq" | "cdqe", []) => return self.emit(&[0x48, 0x98]),
+            ("cltd" | "cdq", []) => return self.emit(&[0x99]),
+            ("nop", []) => return self.emit(&[0x90]),
+            ("hlt", []) => return self.emit(&[0xF4]),
+            ("int3", []) => return self.emit(&[0xCC]),
+            ("ud2", []) => return self.emit(&[0x0F, 0x0B]),
+            ("int", [Operand::Imm(n)]) => return self.emit(&[0xCD, *n as u8]),
+            ("jmp" | "jmpq" | "call" | "callq", [Operand::Label(target)]) => {
+                let opcode = if mnemonic.starts_with('j') { 0xE9 } else { 0xE8 };
+                return self.emit_branch(&[opcode], target);
+            }
+            ("jmp" | "jmpq" | "call" | "callq", [Operand::Indirect(target)]) => {
+                let ext = if mnemonic.starts_with('j') { 4 } else { 2 };
+                return self.emit_modrm(0, &[0xFF], ext, target, &[], false);
+            }
+            ("push" | "pushq", [op]) => return self.assemble_push_pop(true, op),
+            ("pop" | "popq", [op]) => return self.assemble_push_pop(false, op),
+            _ => {}
+        }
+
+        if let Some(cc) = mnemonic.strip_prefix('j').and_then(condition_code) {
+            return match ops {
+                [Operand::Label(target)] => self.emit_branch(&[0x0F, 0x80 + cc], target),
+                _ => Err(self.error(format!("'{}' expects a label", mnemonic))),
+            };
+        }
+        if let Some(cc) = mnemonic.strip_prefix("set").and_then(condition_code) {
+            return match ops {
+                [op] if operand_size(op).unwrap_or(1) == 1 => {
+                    self.emit_modrm(0, &[0x0F, 0x90 + cc], 0, op, &[], needs_rex8(op))
+                }
+                _ => Err(self.error(format!("'{}' expects an 8-bit operand", mnemonic))),
+            };
+        }
+        if let Some(rest) = mnemonic.strip_prefix("cmov") {
+            let cc = condition_code(rest).or_else(|| rest.strip_suffix(['w', 'l', 'q']).and_then(condition_code));
+            if let Some(cc) = cc {
+                return match ops {
+                    [src, Operand::Reg(dst)] if dst.size > 1 => {
+                        self.emit_modrm(dst.size, &[0x0F, 0x40 + cc], dst.num, src, &[], false)
+                    }
+                    _ => Err(self.error(format!("'{}' expects a register destination", mnemonic))),
+                };
+            }
+        }
+        if let Some(result) = self.assemble_extend(mnemonic, ops) {
+            return result;
+        }
+
+        let (base, suffix_size) = if SIZED_OPS.contains(&mnemonic)
+            || SHIFT_OPS.iter().any(|(name, _)| *name == mnemonic)
+            || mnemonic == "xchg"
+        {
+            (mnemonic, None)
+        } else {
+            let size = match mnemonic.chars().last() {
+                Some('b') => 1,
+                Some('w') => 2,
+                Some('l') => 4,
+                Some('q') => 8,
+                _ => 0,
+            };
+            let base = &mnemonic[..mnemonic.len().saturating_sub(1)];
+            if size == 0 || base.is_empty() {
+                return Err(self.error(format!("unknown instruction '{}'", mnemonic)));
+            }
+            let base = if base == "movabs" { "mov" } else { base };
+            (base, Some(size))
+        };
+
+        let size = match suffix_size {
+            Some(size) => size,
+            None => match ops.iter().rev().find_map(operand_size) {
+                Some(size) => size,
+                None => return Err(self.error(format!("operand size of '{}' is ambiguous", mnemonic))),
+            },
+        };
+        let shift = SHIFT_OPS.iter().find(|(name, _)| *name == base).map(|(_, ext)| *ext);
+        if let Some(inferred) = ops.iter().rev().find_map(operand_size) {
+            if base != "lea" && shift.is_none() && inferred != size {
+                return Err(self.error(format!("operand size mismatch for '{}'", mnemonic)));
+            }
+        }
+        let rex8 = size == 1 && ops.iter().any(needs_rex8);
+
+        if let Some(n) = ALU_OPS.iter().position(|op| *op == base) {
+            let n = n as u8;
+            let byte_op = if size == 1 { 0 } else { 1 };
+            return match ops {
+                [Operand::Imm(imm), dst] if is_rm(dst) => {
+                    if size == 1 {
+                        self.emit_modrm(size, &[0x80], n, dst, &[*imm as u8], rex8)
+                    } else if fits_i8(*imm) {
+                        self.emit_modrm(size, &[0x83], n, dst, &[*imm as u8], rex8)
+                    } else {
+                        let imm = self.imm_bytes(*imm, size)?;
+                        self.emit_modrm(size, &[0x81], n, dst, &imm, rex8)
+                    }
+                }
+                [Operand::Reg(src), dst] if is_rm(dst) => {
+                    self.emit_modrm(size, &[n * 8 + byte_op], src.num, dst, &[], rex8)
+                }
+                [src @ Operand::Mem(_), Operand::Reg(dst)] => {
+                    self.emit_modrm(size, &[n * 8 + 2 + byte_op], dst.num, src, &[], rex8)
+                }
+                _ => Err(self.error(format!("invalid operands for '{}'", mnemonic))),
+            };
+        }
+
+        match (base, ops) {
+            ("mov", [Operand::Imm(imm), Operand::Reg(dst)]) => {
+                if size == 8 && !fits_i32(*imm) {
+                    let rex = 0x48 | (dst.num >> 3);
+                    let mut bytes = vec![rex, 0xB8 + (dst.num & 7)];
+                    bytes.extend_from_slice(&imm.to_le_bytes());
+                    self.emit(&bytes)
+                } else if size == 8 {
+                    let imm = self.imm_bytes(*imm, 8)?;
+                    self.emit_modrm(8, &[0xC7], 0, &Operand::Reg(*dst), &imm, false)
+                } else {
+                    let imm = self.imm_bytes(*imm, size)?;
+                    let mut bytes = Vec::new();
+                    if size == 2 {
+                        bytes.push(0x66);
+                    }
+                    if dst.num >= 8 || rex8 {
+                        bytes.push(0x40 | (dst.num >> 3));
+                    }
+                    bytes.push(if size == 1 { 0xB0 } else { 0xB8 } + (dst.num & 7));
+                    bytes.extend_from_slice(&imm);
+                    self.emit(&bytes)
+                }
+            }
+            ("mov", [Operand::Imm(imm), dst @ Operand::Mem(_)]) => {
+                let imm = self.imm_bytes(*imm, size)?;
+                self.emit_modrm(size, &[if size == 1 { 0xC6 } else { 0xC7 }], 0, dst, &imm, rex8)
+            }
+            ("mov", [Operand::Reg(src), dst]) if is_rm(dst) => {
+                self.emit_modrm(size, &[if size == 1 { 0x88 } else { 0x89 }], src.num, dst, &[], rex8)
+            }
+            ("mov", [src @ Operand::Mem(_), Operand::Reg(dst)]) => {
+                self.emit_modrm(size, &[if size == 1 { 0x8A } else { 0x8B }], dst.num, src, &[], rex8)
+            }
+            ("test", [Operand::Imm(imm), dst]) if is_rm(dst) => {
+                let imm = self.imm_bytes(*imm, size)?;
+                self.emit_modrm(size, &[if size == 1 { 0xF6 } else { 0xF7 }], 0, dst, &imm, rex8)
+            }
+            ("test", [Operand::Reg(src), dst]) if is_rm(dst) => {
+                self.emit_modrm(size, &[if size == 1 { 0x84 } else { 0x85 }], src.num, dst, &[], rex8)
+            }
+            ("xchg", [Operand::Reg(src), dst]) if is_rm(dst) => {
+                self.emit_modrm(size, &[if size == 1 { 0x86 } else { 0x87 }], src.num, dst, &[], rex8)
+            }
+            ("lea", [src @ Operand::Mem(_), Operand::Reg(dst)]) if size > 1 => {
+                self.emit_modrm(size, &[0x8D], dst.num, src, &[], false)
+            }
+            ("imul", [src, Operand::Reg(dst)]) if is_rm(src) && size > 1 => {
+                self.emit_modrm(size, &[0x0F, 0xAF], dst.num, src, &[], false)
+            }
+            ("imul", [Operand::Imm(imm), Operand::Reg(dst)]) if size > 1 => {
+                self.assemble_imul_imm(size, *imm, &Operand::Reg(*dst), dst)
+            }
+            ("imul", [Operand::Imm(imm), src, Operand::Reg(dst)]) if is_rm(src) && size > 1 => {
+                self.assemble_imul_imm(size, *imm, src, dst)
+            }
+            ("inc" | "dec", [dst]) if is_rm(dst) => {
+                let ext = if base == "inc" { 0 } else { 1 };
+                self.emit_modrm(size, &[if size == 1 { 0xFE } else { 0xFF }], ext, dst, &[], rex8)
+            }
+            (_, [dst]) if is_rm(dst) && UNARY_OPS.iter().any(|(n, _)| *n == base) => {
+                let ext = UNARY_OPS.iter().find(|(n, _)| *n == base).unwrap().1;
+                self.emit_modrm(size, &[if size == 1 { 0xF6 } else { 0xF7 }], ext, dst, &[], rex8)
+            }
+            (_, _) if shift.is_some() => {
+                let ext = shift.unwrap();
+                let byte_op = size == 1;
+                match ops {
+                    [Operand::Imm(count), dst] if is_rm(dst) => {
+                        self.emit_modrm(size, &[if byte_op { 0xC0 } else { 0xC1 }], ext, dst, &[*count as u8], rex8)
+                    }
+                    [Operand::Reg(Reg { num: 1, size: 1 }), dst] if is_rm(dst) => {
+                        self.emit_modrm(size, &[if byte_op { 0xD2 } else { 0xD3 }], ext, dst, &[], rex8)
+                    }
+                    [dst] if is_rm(dst) => {
+                        self.emit_modrm(size, &[if byte_op { 0xD0 } else { 0xD1 }], ext, dst, &[], rex8)
+                    }
+                    _ => Err(self.error(format!("invalid operands for '{}'", mnemonic))),
+                }
+            }
+            _ => Err(self.error(format!("unsupported instruction '{}' with these operands", mnemonic))),
+        }
+    }
+
+    fn assemble_imul_imm(&mut self, size: u8, imm: i64, src: &Operand, dst: &Reg) -> Result<()> {
+        if fits_i8(imm) {
+            self.emit_modrm(size, &[0x6B], dst.num, src, &[imm as u8], false)
+        } else {
+            let imm = self.imm_bytes(imm, size)?;
+            self.emit_modrm(size, &[0x69], dst.num, src, &imm, false)
+        }
+    }
+
+    fn assemble_push_pop(&mut self, push: bool, op: &Operand) -> Result<()> {
+        match op {
+            Operand::Reg(reg) if reg.size == 8 => {
+                let mut bytes = Vec::new();
+                if reg.num >= 8 {
+                    bytes.push(0x41);
+                }
+                bytes.push(if push { 0x50 } else { 0x58 } + (reg.num & 7));
+                self.emit(&bytes)
+            }
+            Operand::Imm(imm) if push => {
+                if fits_i8(*imm) {
+                    self.emit(&[0x6A, *imm as u8])
+                } else {
+                    let imm = self.imm_bytes(*imm, 4)?;
+                    let mut bytes = vec![0x68];
+                    bytes.extend_from_slice(&imm);
+                    self.emit(&bytes)
+                }
+            }
+            Operand::Mem(_) => {
+                if push {
+                    self.emit_modrm(0, &[0xFF], 6, op, &[], false)
+                } else {
+                    self.emit_modrm(0, &[0x8F], 0, op, &[], false)
+                }
+            }
+            _ => Err(self.error(format!("invalid operand for '{}'", if push { "push" } else { "pop" }))),
+        }
+    }
+
+    fn assemble_extend(&mut self, mnemonic: &str, ops: &[Operand]) -> Option<Result<()>> {
+        let (signed, rest) = if let Some(rest) = mnemonic.strip_prefix("movz") {
+            (false, rest)
+        } else if let Some(rest) = mnemonic.strip_prefix("movs") {
+            (true, rest)
+        } else {
+            return None;
+        };
+
+        let (src, dst) = match ops {
+            [src, Operand::Reg(dst)] if is_rm(src) => (src, *dst),
+            _ => return Some(Err(self.error(format!("invalid operands for '{}'", mnemonic)))),
+        };
+        let size_of = |c: char| match c {
+            'b' => Some(1),
+            'w' => Some(2),
+            'l' => Some(4),
+            'q' => Some(8),
+            _ => None,
+        };
+        let sizes: Vec<Option<u8>> = rest.chars().map(size_of).collect();
+        let src_size = match sizes.as_slice() {
+            _ if rest == "x" || rest == "xd" => operand_size(src),
+            [Some(from), Some(to)] if *to == dst.size => Some(*from),
+            _ => return Some(Err(self.error(format!("unknown instruction '{}'", mnemonic)))),
+        };
+        let src_size = match src_size {
+            Some(size) => size,
+            None => return Some(Err(self.error(format!("operand size of '{}' is ambiguous", mnemonic)))),
+        };
+
+        let opcode: &[u8] = match (signed, src_size) {
+            (false, 1) => &[0x0F, 0xB6],
+            (false, 2) => &[0x0F, 0xB7],
+            (true, 1) => &[0x0F, 0xBE],
+            (true, 2) => &[0x0F, 0xBF],
+            (true, 4) if dst.size == 8 => &[0x63],
+            _ => return Some(Err(self.error(format!("invalid operand sizes for '{}'", mnemonic)))),
+        };
+        if src_size >= dst.size {
+            return Some(Err(self.error(format!("invalid operand sizes for '{}'", mnemonic))));
+        }
+        let rex8 = src_size == 1 && needs_rex8(src);
+        Some(self.emit_modrm(dst.size, opcode, dst.num, src, &[], rex8))
+    }
+
+    fn imm_bytes(&self, imm: i64, size: u8) -> Result<Vec<u8>> {
+        let ok = match size {
+            1 => (-128..=255).contains(&imm),
+            2 => (-32768..=65535).contains(&imm),
+            4 => (i32::MIN as i64..=u32::MAX as i64).contains(&imm),
+            _ => fits_i32(imm),
+        };
+        if !ok {
+            return Err(self.error(format!("immediate {} does not fit in {} bytes", imm, size)));
+        }
+        let width = if size == 8 { 4 } else { size as usize };
+        Ok(imm.to_le_bytes()[..width].to_vec())
+    }
+
+    fn emit(&mut self, bytes: &[u8]) -> Result<()> {
+        self.buffer().extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn emit_branch(&mut self, opcode: &[u8], target: &str) -> Result<()> {
+        self.emit(opcode)?;
+        let offset = self.buffer().len();
+        self.add_fixup(offset, target.to_string(), -4, FixupKind::Rel32);
+        self.emit(&[0; 4])
+    }
+
+    /// Emits `[66] [REX] opcode ModRM [SIB] [disp] [imm]`. A size of 0 means the
+    /// instruction defaults to 64-bit operands and needs no REX.W.
+    fn emit_modrm(&mut self, size: u8, opcode: &[u8], reg: u8, rm: &Operand, imm: &[u8], force_rex: bool) -> Result<()> {
+        let mut rex = if size == 8 { 0x48 } else { 0x40 };
+        rex |= (reg >> 3) << 2;
+
+        let mut tail = Vec::new();
+        let mut reloc = None;
+        match rm {
+            Operand::Reg(r) => {
+                rex |= r.num >> 3;
+                tail.push(0xC0 | ((reg & 7) << 3) | (r.num & 7));
+            }
+            Operand::Mem(mem) => {
+                if mem.rip {
+                    tail.push(((reg & 7) << 3) | 5);
+                    reloc = Some((tail.len(), mem.symbol.clone(), mem.disp, true));
+                    tail.extend_from_slice(&[0; 4]);
+                } else {
+                    if let Some(base) = mem.base {
+                        rex |= base >> 3;
+                    }
+                    if let Some((index, _)) = mem.index {
+                        rex |= (index >> 3) << 1;
+                    }
+                    let base_low = mem.base.map(|b| b & 7);
+                    let (mode, disp_size) = if mem.base.is_none() || mem.symbol.is_some() {
+                        (if mem.base.is_none() { 0 } else { 2 }, 4)
+                    } else if mem.disp == 0 && base_low != Some(5) {
+                        (0, 0)
+                    } else if fits_i8(mem.disp) {
+                        (1, 1)
+                    } else if fits_i32(mem.disp) {
+                        (2, 4)
+                    } else {
+                        return Err(self.error("displacement out of range".to_string()));
+                    };
+
+                    if mem.index.is_some() || base_low == Some(4) || mem.base.is_none() {
+                        tail.push((mode << 6) | ((reg & 7) << 3) | 4);
+                        let (index, scale) = mem.index.map(|(i, s)| (i & 7, s)).unwrap_or((4, 0));
+                        tail.push((scale << 6) | (index << 3) | base_low.unwrap_or(5));
+                    } else {
+                        tail.push((mode << 6) | ((reg & 7) << 3) | base_low.unwrap());
+                    }
+
+                    if disp_size == 1 {
+                        tail.push(mem.disp as u8);
+                    } else if disp_size == 4 {
+                        if mem.symbol.is_some() {
+                            reloc = Some((tail.len(), mem.symbol.clone(), mem.disp, false));
+                            tail.extend_from_slice(&[0; 4]);
+                        } else {
+                            tail.extend_from_slice(&(mem.disp as i32).to_le_bytes());
+                        }
+                    }
+                }
+            }
+            _ => return Err(self.error("expected a register or memory operand".to_string())),
+        }
+        tail.extend_from_slice(imm);
+
+        let mut bytes = Vec::new();
+        if size == 2 {
+            bytes.push(0x66);
+        }
+        if rex != 0x40 || force_rex {
+            bytes.push(rex);
+        }
+        bytes.extend_from_slice(opcode);
+        let tail_start = bytes.len();
+        bytes.extend_from_slice(&tail);
+
+        let start = self.buffer().len();
+        self.emit(&bytes)?;
+        match reloc {
+            Some((pos, Some(symbol), disp, true)) => {
+                let field = start + tail_start + pos;
+                let end = start + bytes.len();
+                self.add_fixup(field, symbol, disp - (end - field) as i64, FixupKind::Rel32);
+            }
+            Some((pos, None, disp, true)) => {
+                let field = start + tail_start + pos;
+                self.buffer()[field..field + 4].copy_from_slice(&(disp as i32).to_le_bytes());
+            }
+            Some((pos, Some(symbol), disp, false)) => {
+                self.add_fixup(start + tail_start + pos, symbol, disp, FixupKind::Abs32);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+fn fits_i8(n: i64) -> bool {
+    (-128..128).contains(&n)
+}
+
+fn fits_i32(n: i64) -> bool {
+    (i32::MIN as i64..=i32::MAX as i64).contains(&n)
+}
+
+fn is_rm(op: &Operand) -> bool {
+    matches!(op, Operand::Reg(_) | Operand::Mem(_))
+}
+
+fn operand_size(op: &Operand) -> Option<u8> {
+    match op {
+        Operand::Reg(reg) => Some(reg.size),
+        _ => None,
+    }
+}
+
+fn needs_rex8(op: &Operand) -> bool {
+    matches!(op, Operand::Reg(Reg { num: 4..=7, size: 1 }))
+}
+
+fn condition_code(name: &str) -> Option<u8> {
+    CONDITIONS.iter().find(|(cc, _)| *cc == name).map(|(_, code)| *code)
+}
+
+fn parse_register(name: &str) -> Option<Reg> {
+    const REGS64: [&str; 8] = ["rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi"];
+    const REGS32: [&str; 8] = ["eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi"];
+    const REGS16: [&str; 8] = ["ax", "cx", "dx", "bx", "sp", "bp", "si", "di"];
+    const REGS8: [&str; 8] = ["al", "cl", "dl", "bl", "spl", "bpl", "sil", "dil"];
+
+    let name = name.to_lowercase();
+    for (size, table) in [(8, REGS64), (4, REGS32), (2, REGS16), (1, REGS8)] {
+        if let Some(num) = table.iter().position(|r| *r == name) {
+            return Some(Reg { num: num as u8, size });
+        }
+    }
+
+    let rest = name.strip_prefix('r')?;
+    let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+    let num: u8 = digits.parse().ok().filter(|n| (8..16).contains(n))?;
+    let size = match &rest[digits.len()..] {
+        "" => 8,
+        "d" => 4,
+        "w" => 2,
+        "b" | "l" => 1,
+        _ => return None,
+    };
+    Some(Reg { num, size })
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let text = text.trim();
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest.trim()),
+        None => (false, text.strip_prefix('+').unwrap_or(text).trim()),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16).ok()? as i64
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        u64::from_str_radix(bin, 2).ok()? as i64
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse::<u64>().ok()? as i64
+    } else {
+        return None;
+    };
+    Some(if negative { value.wrapping_neg() } else { value })
+}
+
+fn label_end(statement: &str) -> Option<usize> {
+    let colon = statement.find(':')?;
+    let label = &statement[..colon];
+    let valid = !label.is_empty()
+        && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$');
+    if valid { Some(colon) } else { None }
+}
+
+fn split_statements(line: &str) -> Vec<&str> {
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, ch) in line.char_indices() {
+        if in_string {
+            match ch {
+                '\\' if !escaped => escaped = true,
+                '"' if !escaped => in_string = false,
+                _ => escaped = false,
+            }
+            continue;
+        }
+        match ch {
+            '"' => in_string = true,
+            '#' => {
+                statements.push(&line[start..i]);
+                return statements;
+            }
+            ';' => {
+                statements.push(&line[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    statements.push(&line[start..]);
+    statements
+}
+
+fn split_operands(text: &str) -> Vec<String> {
+    let mut operands = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    let mut in_string = false;
+
+    for ch in text.chars() {
+        match ch {
+            '"' => in_string = !in_string,
+            '(' if !in_string => depth += 1,
+            ')' if !in_string => depth -= 1,
+            ',' if !in_string && depth == 0 => {
+                operands.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+        current.push(ch);
+    }
+    if !current.trim().is_empty() {
+        operands.push(current.trim().to_string());
+    }
+    operands
+}
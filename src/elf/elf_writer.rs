@@ -13,11 +13,23 @@ const EM_X86_64: u16 = 0x3E;
 
 const PT_LOAD: u32 = 1;
 
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+const PAGE_SIZE: u64 = 0x1000;
+
 pub struct ELFWriter {
     entry_point: u64,
     load_address: u64,
 }
 
+impl Default for ELFWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ELFWriter {
     pub fn new() -> Self {
         ELFWriter {
@@ -26,20 +38,20 @@ impl ELFWriter {
         }
     }
 
-    pub fn write(&mut self, filename: &str, machine_code: &MachineCode) -> io::Result<()> {
-        let mut buffer = Vec::new();
-
-        self.write_elf_header(&mut buffer);
+    pub fn code_address(&self) -> u64 {
+        self.load_address + PAGE_SIZE
+    }
 
-        let code_size = machine_code.code.len() as u64;
-        let file_size = 0x1000 + code_size;
-        self.write_program_header(&mut buffer, file_size, code_size);
+    pub fn data_address(&self, code_size: usize) -> u64 {
+        self.load_address + self.data_offset(code_size)
+    }
 
-        while buffer.len() < 0x1000 {
-            buffer.push(0);
-        }
+    fn data_offset(&self, code_size: usize) -> u64 {
+        (PAGE_SIZE + code_size as u64).div_ceil(PAGE_SIZE) * PAGE_SIZE
+    }
 
-        buffer.extend_from_slice(&machine_code.code);
+    pub fn write(&mut self, filename: &str, machine_code: &MachineCode) -> io::Result<()> {
+        let buffer = self.image(machine_code);
 
         let mut file = File::create(filename)?;
         file.write_all(&buffer)?;
@@ -55,7 +67,39 @@ impl ELFWriter {
         Ok(())
     }
 
-    fn write_elf_header(&self, buffer: &mut Vec<u8>) {
+    /// The complete file contents for `machine_code`.
+    pub fn image(&mut self, machine_code: &MachineCode) -> Vec<u8> {
+        let mut buffer = Vec::new();
+
+        self.entry_point = self.code_address() + machine_code.entry_point as u64;
+        let has_data = !machine_code.data.is_empty();
+        self.write_elf_header(&mut buffer, if has_data { 2 } else { 1 });
+
+        let code_size = machine_code.code.len() as u64;
+        let file_size = PAGE_SIZE + code_size;
+        self.write_program_header(&mut buffer, 0, file_size, file_size, PF_R | PF_X);
+
+        let data_offset = self.data_offset(machine_code.code.len());
+        if has_data {
+            let data_size = machine_code.data.len() as u64;
+            self.write_program_header(&mut buffer, data_offset, data_size, data_size, PF_R | PF_W);
+        }
+
+        while buffer.len() < PAGE_SIZE as usize {
+            buffer.push(0);
+        }
+
+        buffer.extend_from_slice(&machine_code.code);
+
+        if has_data {
+            buffer.resize(data_offset as usize, 0);
+            buffer.extend_from_slice(&machine_code.data);
+        }
+
+        buffer
+    }
+
+    fn write_elf_header(&self, buffer: &mut Vec<u8>, program_headers: u16) {
         buffer.extend_from_slice(&ELF_MAGIC);
         buffer.push(ELF_CLASS_64);
         buffer.push(ELF_DATA_LSB);
@@ -81,7 +125,7 @@ impl ELFWriter {
 
         buffer.extend_from_slice(&56u16.to_le_bytes());
 
-        buffer.extend_from_slice(&1u16.to_le_bytes());
+        buffer.extend_from_slice(&program_headers.to_le_bytes());
 
         buffer.extend_from_slice(&0u16.to_le_bytes());
 
@@ -90,21 +134,23 @@ impl ELFWriter {
         buffer.extend_from_slice(&0u16.to_le_bytes());
     }
 
-    fn write_program_header(&self, buffer: &mut Vec<u8>, file_size: u64, _mem_size: u64) {
+    fn write_program_header(&self, buffer: &mut Vec<u8>, offset: u64, file_size: u64, mem_size: u64, flags: u32) {
+        let address = self.load_address + offset;
+
         buffer.extend_from_slice(&PT_LOAD.to_le_bytes());
 
-        buffer.extend_from_slice(&5u32.to_le_bytes());
+        buffer.extend_from_slice(&flags.to_le_bytes());
 
-        buffer.extend_from_slice(&0u64.to_le_bytes());
+        buffer.extend_from_slice(&offset.to_le_bytes());
 
-        buffer.extend_from_slice(&self.load_address.to_le_bytes());
+        buffer.extend_from_slice(&address.to_le_bytes());
 
-        buffer.extend_from_slice(&self.load_address.to_le_bytes());
+        buffer.extend_from_slice(&address.to_le_bytes());
 
         buffer.extend_from_slice(&file_size.to_le_bytes());
 
-        buffer.extend_from_slice(&file_size.to_le_bytes());
+        buffer.extend_from_slice(&mem_size.to_le_bytes());
 
-        buffer.extend_from_slice(&0x1000u64.to_le_bytes());
+        buffer.extend_from_slice(&PAGE_SIZE.to_le_bytes());
     }
 }
@@ -0,0 +1,209 @@
+//! GCC-style operands for `asm` on x86-64.
+//!
+//! ```perano
+//! asm "movq $(a), $(sum)\n    addq $(b), $(sum)" : "=r"(sum) : "r"(a), "m"(b) : "cc"
+//! ```
+//!
+//! `$(name)` in the code stands for the operand bound to `name`, written at
+//! the width of the variable (`%rax`, `%eax` or `%al`). A variable without an
+//! operand is used in place, as its frame slot.
+//!
+//! | constraint              | operand                                          |
+//! |-------------------------|--------------------------------------------------|
+//! | `r`                     | a free one of %rax, %rcx, %rdx, %rsi, %rdi, %r8 to %r11 |
+//! | `a` `b` `c` `d` `S` `D` | %rax, %rbx, %rcx, %rdx, %rsi, %rdi               |
+//! | `m`                     | the variable's frame slot                        |
+//!
+//! Outputs start with `=` (written) or `+` (read and written). Letters can
+//! be combined, as in `rm`, to take the first that is free; `&` is accepted
+//! and changes nothing, as operands never share a register. Registers are
+//! loaded before the code and outputs stored back after it.
+//!
+//! Clobbers name registers (`rbx` or `%rbx`), `cc` or `memory`. Clobbered
+//! registers, and callee-saved ones bound to operands, are pushed before the
+//! block and popped after it.
+
+use crate::ast::{AsmOperand, Span};
+
+/// A general-purpose register by its 64-, 32- and 8-bit names.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Register {
+    pub names: [&'static str; 3],
+    pub callee_saved: bool,
+}
+
+impl Register {
+    /// The name that accesses `size` bytes of the register.
+    pub fn sized(&self, size: usize) -> &'static str {
+        match size {
+            4 => self.names[1],
+            1 => self.names[2],
+            _ => self.names[0],
+        }
+    }
+}
+
+const fn register(names: [&'static str; 3], callee_saved: bool) -> Register {
+    Register { names, callee_saved }
+}
+
+pub const RAX: Register = register(["%rax", "%eax", "%al"], false);
+pub const RBX: Register = register(["%rbx", "%ebx", "%bl"], true);
+pub const RCX: Register = register(["%rcx", "%ecx", "%cl"], false);
+pub const RDX: Register = register(["%rdx", "%edx", "%dl"], false);
+pub const RSI: Register = register(["%rsi", "%esi", "%sil"], false);
+pub const RDI: Register = register(["%rdi", "%edi", "%dil"], false);
+
+pub const REGISTERS: &[Register] = &[
+    RAX,
+    RBX,
+    RCX,
+    RDX,
+    RSI,
+    RDI,
+    register(["%rbp", "%ebp", "%bpl"], true),
+    register(["%rsp", "%esp", "%spl"], true),
+    register(["%r8", "%r8d", "%r8b"], false),
+    register(["%r9", "%r9d", "%r9b"], false),
+    register(["%r10", "%r10d", "%r10b"], false),
+    register(["%r11", "%r11d", "%r11b"], false),
+    register(["%r12", "%r12d", "%r12b"], true),
+    register(["%r13", "%r13d", "%r13b"], true),
+    register(["%r14", "%r14d", "%r14b"], true),
+    register(["%r15", "%r15d", "%r15b"], true),
+];
+
+/// Registers `r` picks from, in order.
+const GENERAL: [&str; 9] = ["%rax", "%rcx", "%rdx", "%rsi", "%rdi", "%r8", "%r9", "%r10", "%r11"];
+
+/// Looks up a register by any of its names, with or without the `%`.
+pub fn by_name(name: &str) -> Option<Register> {
+    let name = name.trim_start_matches('%');
+    REGISTERS.iter().copied().find(|r| r.names.iter().any(|n| n[1..].eq_ignore_ascii_case(name)))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Place {
+    Register(Register),
+    /// The variable's frame slot.
+    Memory,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binding {
+    pub variable: String,
+    pub place: Place,
+    /// Loaded before the code.
+    pub read: bool,
+    /// Stored after the code.
+    pub written: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Bindings {
+    pub operands: Vec<Binding>,
+    /// Registers to push before the block and pop after it.
+    pub saved: Vec<Register>,
+}
+
+impl Bindings {
+    pub fn get(&self, variable: &str) -> Option<&Binding> {
+        self.operands.iter().find(|b| b.variable == variable)
+    }
+}
+
+/// Places the operands of an `asm` statement at `span`. Errors point at the
+/// operand at fault, or at the statement for clobbers.
+pub fn bind(outputs: &[AsmOperand], inputs: &[AsmOperand], clobbers: &[String], span: Span) -> Result<Bindings, (Span, String)> {
+    let mut clobbered = Vec::new();
+    for clobber in clobbers {
+        match clobber.as_str() {
+            "cc" | "memory" => {}
+            name => match by_name(name) {
+                Some(r) if matches!(r.names[0], "%rsp" | "%rbp") => {
+                    return Err((span, format!("asm cannot clobber {}, which holds the frame", r.names[0])));
+                }
+                Some(r) if clobbered.contains(&r) => {}
+                Some(r) => clobbered.push(r),
+                None => return Err((span, format!("unknown clobber '{}'", clobber))),
+            },
+        }
+    }
+
+    let mut parsed: Vec<(&AsmOperand, bool, bool)> = Vec::new();
+    for (operand, output) in outputs.iter().map(|o| (o, true)).chain(inputs.iter().map(|i| (i, false))) {
+        let error = |message: String| Err((operand.span, message));
+        let letters = operand.constraint.trim_start_matches(['=', '+', '&']);
+        let modifiers = &operand.constraint[..operand.constraint.len() - letters.len()];
+        let read = !output || modifiers.contains('+');
+        if output && !modifiers.contains(['=', '+']) {
+            return error(format!("output constraint \"{}\" needs '=' or '+'", operand.constraint));
+        }
+        if !output && modifiers.contains(['=', '+']) {
+            return error(format!("input constraint \"{}\" cannot have '=' or '+'", operand.constraint));
+        }
+        if letters.is_empty() {
+            return error(format!("constraint \"{}\" names no register or memory", operand.constraint));
+        }
+        if let Some(c) = letters.chars().find(|c| !"rabcdSDm".contains(*c)) {
+            return error(format!("unknown constraint '{}' in \"{}\"", c, operand.constraint));
+        }
+        if parsed.iter().any(|(other, ..)| other.variable == operand.variable) {
+            return error(format!(
+                "'{}' is bound twice; use '+' for an operand that is read and written",
+                operand.variable
+            ));
+        }
+        parsed.push((operand, read, output));
+    }
+
+    // Operands that may take a specific register go first, so `r` does not
+    // take it from them.
+    let mut taken = clobbered.clone();
+    let mut places = vec![None; parsed.len()];
+    let specific = |operand: &AsmOperand| operand.constraint.contains(['a', 'b', 'c', 'd', 'S', 'D']);
+    let order = (0..parsed.len()).filter(|&i| specific(parsed[i].0))
+        .chain((0..parsed.len()).filter(|&i| !specific(parsed[i].0)))
+        .collect::<Vec<_>>();
+    for i in order {
+        let operand = parsed[i].0;
+        let free = |r: &Register| !taken.contains(r);
+        let place = operand.constraint.chars().find_map(|c| match c {
+            'm' => Some(Place::Memory),
+            'r' => GENERAL.iter().filter_map(|name| by_name(name)).find(free).map(Place::Register),
+            'a' | 'b' | 'c' | 'd' | 'S' | 'D' => {
+                let r = [RAX, RBX, RCX, RDX, RSI, RDI]["abcdSD".find(c).unwrap()];
+                free(&r).then_some(Place::Register(r))
+            }
+            _ => None,
+        });
+        match place {
+            Some(place) => {
+                if let Place::Register(r) = place {
+                    taken.push(r);
+                }
+                places[i] = Some(place);
+            }
+            None => return Err((operand.span, format!(
+                "no register left for '{}' with constraint \"{}\"",
+                operand.variable, operand.constraint
+            ))),
+        }
+    }
+
+    let operands: Vec<Binding> = parsed.iter().zip(places).map(|(&(operand, read, written), place)| Binding {
+        variable: operand.variable.clone(),
+        place: place.unwrap(),
+        read,
+        written,
+    }).collect();
+    let mut saved = clobbered;
+    for binding in &operands {
+        if let Place::Register(r) = binding.place {
+            if r.callee_saved && !saved.contains(&r) {
+                saved.push(r);
+            }
+        }
+    }
+    Ok(Bindings { operands, saved })
+}
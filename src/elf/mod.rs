@@ -1,5 +1,8 @@
 pub mod asm_generator;
-#[allow(dead_code)]
-mod elf_writer;
+pub mod assembler;
+pub mod elf_writer;
+pub mod inline_asm;
 
 pub use asm_generator::AsmGenerator;
+pub use assembler::Assembler;
+pub use elf_writer::ELFWriter;
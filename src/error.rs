@@ -11,13 +11,12 @@ pub struct CompileError {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::enum_variant_names)]
 pub enum ErrorKind {
     LexerError,
     ParserError,
-    #[allow(dead_code)]
     TypeError,
     ModuleError,
-    #[allow(dead_code)]
     CodeGenError,
 }
 
@@ -38,6 +37,14 @@ impl CompileError {
         self
     }
 
+    /// Attaches line `self.line` of `source` as the snippet shown by `display`.
+    pub fn with_source(self, source: &str) -> Self {
+        match source.lines().nth(self.line.saturating_sub(1)) {
+            Some(line) => self.with_source_line(line.to_string()),
+            None => self,
+        }
+    }
+
     pub fn display(&self) {
         let kind_str = match self.kind {
             ErrorKind::LexerError => "lexer error",
@@ -0,0 +1,513 @@
+use crate::ast::*;
+use crate::compiler::Diagnostics;
+use crate::lexer::{Comment, Lexer, Span, Token};
+use crate::literal;
+use crate::parser::Parser;
+
+const INDENT: &str = "    ";
+
+/// Pretty-prints a source file in the canonical style: `fn`, `var`,
+/// `import` and `for` spellings, `name: type` annotations, `->` before return
+/// types and four-space indentation. Comments are kept where they were,
+/// as are single blank lines between statements.
+pub fn format_source(text: &str, file: &str) -> Result<String, Diagnostics> {
+    let mut lexer = Lexer::new_with_file(text, file);
+    let (tokens, spans, comments) = lexer.tokenize_with_trivia();
+    let errors = lexer.take_errors();
+    if !errors.is_empty() {
+        return Err(errors.into_iter().map(|e| e.with_source(text)).collect());
+    }
+    let program = Parser::new_with_spans(tokens.clone(), spans.clone(), file)
+        .parse()
+        .map_err(|errors| errors.into_iter().map(|e| e.with_source(text)).collect::<Diagnostics>())?;
+
+    let mut formatter = Formatter {
+        lines: text.lines().collect(),
+        tokens,
+        spans,
+        comments,
+        next_comment: 0,
+        out: String::new(),
+        indent: 0,
+        opened_at: 0,
+        written_through: 0,
+    };
+    formatter.program(&program);
+    Ok(formatter.out)
+}
+
+enum Item<'a> {
+    Struct(&'a StructDecl),
+    Function(&'a Function),
+}
+
+struct Formatter<'a> {
+    lines: Vec<&'a str>,
+    tokens: Vec<Token>,
+    spans: Vec<Span>,
+    comments: Vec<Comment>,
+    next_comment: usize,
+    out: String,
+    indent: usize,
+    /// Length of `out` right after the last `{` was written.
+    opened_at: usize,
+    /// The last source line whose code or comments have been written.
+    written_through: usize,
+}
+
+impl Formatter<'_> {
+    fn program(&mut self, program: &Program) {
+        let package = self.tokens.iter().position(|t| *t == Token::Package).unwrap_or(0);
+        let package_line = self.spans[package].line;
+        self.comments_before(package_line);
+        self.line(&format!("package {}", program.package));
+        self.trailing(package_line);
+
+        if !program.imports.is_empty() {
+            self.separate();
+        }
+        for import in &program.imports {
+            self.comments_before(import.span.line);
+            match &import.alias {
+                Some(alias) => self.line(&format!("import {} as {}", literal::perano(&import.path), alias)),
+                None => self.line(&format!("import {}", literal::perano(&import.path))),
+            }
+            self.trailing(import.span.line);
+        }
+
+        let mut items: Vec<(Span, Item)> = program.structs.iter().map(|s| (s.span, Item::Struct(s)))
+            .chain(program.functions.iter().map(|f| (f.span, Item::Function(f))))
+            .collect();
+        items.sort_by_key(|(span, _)| (span.line, span.column));
+
+        let mut previous_extern = false;
+        for (span, item) in items {
+            // Consecutive extern declarations stay together, like imports.
+            let is_extern = matches!(item, Item::Function(f) if f.external.is_some());
+            if previous_extern && is_extern {
+                self.blank_line_above(span.line);
+            } else {
+                self.separate();
+            }
+            previous_extern = is_extern;
+            match item {
+                Item::Struct(decl) => self.struct_decl(decl),
+                Item::Function(func) => self.function(func),
+            }
+        }
+
+        self.comments_before(usize::MAX);
+    }
+
+    fn struct_decl(&mut self, decl: &StructDecl) {
+        let visibility = if decl.is_exported { "pub " } else { "" };
+        let (_, close) = self.braces_after(decl.span);
+        self.comments_before(decl.span.line);
+        self.open(&format!("{}struct {}", visibility, decl.name), decl.span.line);
+        self.indent += 1;
+        for field in &decl.fields {
+            self.comments_before(field.span.line);
+            self.line(&format!("{}: {}", field.name, field.field_type));
+            self.trailing(field.span.line);
+        }
+        self.indent -= 1;
+        self.close(close);
+    }
+
+    fn function(&mut self, func: &Function) {
+        let visibility = if func.is_exported { "pub " } else { "" };
+        let abi = match func.external {
+            Some(External::C) => "extern \"C\" ",
+            Some(External::Syscall(_)) => "extern \"novaria\" ",
+            None => "",
+        };
+        let params: Vec<String> = func.params.iter().map(|p| format!("{}: {}", p.name, p.param_type)).collect();
+        let mut header = format!("{}{}fn {}({})", visibility, abi, func.name, params.join(", "));
+        if let Some(return_type) = &func.return_type {
+            header.push_str(&format!(" -> {}", return_type));
+        }
+        if let Some(external) = func.external {
+            if let External::Syscall(number) = external {
+                header.push_str(&format!(" = {}", number));
+            }
+            self.comments_before(func.span.line);
+            self.line(&header);
+            self.trailing(func.span.line);
+            return;
+        }
+
+        let (_, close) = self.braces_after(func.span);
+        self.comments_before(func.span.line);
+        self.block(&header, func.span.line, &func.body, close);
+        self.close(close);
+    }
+
+    /// Writes `header {`, then `body` and the comments before its closing
+    /// brace. The caller writes the `}` so an `else` can follow it.
+    fn block(&mut self, header: &str, line: usize, body: &[Statement], close: usize) {
+        self.open(header, line);
+        self.indent += 1;
+        for statement in body {
+            self.statement(statement);
+        }
+        self.comments_before(self.spans[close].line);
+        self.indent -= 1;
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        let line = statement.span.line;
+        self.comments_before(line);
+
+        match &statement.kind {
+            StatementKind::If { condition, then_body, else_body } => {
+                let (_, close) = self.braces_after(statement.span);
+                self.block(&format!("if {}", expr(condition)), line, then_body, close);
+                match else_body {
+                    Some(else_body) => {
+                        let (_, else_close) = self.braces_after(self.spans[close + 1]);
+                        self.block("} else", self.spans[close].line, else_body, else_close);
+                        self.close(else_close);
+                    }
+                    None => self.close(close),
+                }
+            }
+            StatementKind::For { init, condition, post, body } => {
+                let condition = condition.as_ref().map(expr).unwrap_or_default();
+                let header = if init.is_some() || post.is_some() {
+                    let init = init.as_deref().map(simple_statement).unwrap_or_default();
+                    let post = post.as_deref().map(simple_statement).unwrap_or_default();
+                    format!("for {}; {}; {}", init, condition, post).trim_end().to_string()
+                } else {
+                    format!("for {}", condition).trim_end().to_string()
+                };
+                let (_, close) = self.braces_after(statement.span);
+                self.block(&header, line, body, close);
+                self.close(close);
+            }
+            StatementKind::InlineAsm { outputs, inputs, clobbers, .. } => {
+                self.inline_asm(statement.span, &asm_operands(outputs, inputs, clobbers));
+            }
+            _ => {
+                self.line(&simple_statement(statement));
+                self.trailing(line);
+            }
+        }
+    }
+
+    /// Assembly is copied as written, re-indented; the parsed form drops
+    /// punctuation and `;` comments.
+    /// An `asm` statement, with `operands` the lists that follow its code.
+    fn inline_asm(&mut self, span: Span, operands: &str) {
+        let start = self.token_index(span);
+        if let Some(Token::String(code)) = self.tokens.get(start + 1) {
+            let code = literal::perano(code);
+            self.line(&format!("asm {}{}", code, operands));
+            self.trailing(span.line);
+            return;
+        }
+
+        let (open, close) = self.braces_after(span);
+        let (open, close) = (self.spans[open], self.spans[close]);
+        let mut body = Vec::new();
+        for line in open.line..=close.line {
+            let text: Vec<char> = self.lines.get(line - 1).copied().unwrap_or("").chars().collect();
+            let from = if line == open.line { open.column.min(text.len()) } else { 0 };
+            let to = if line == close.line { (close.column - 1).min(text.len()) } else { text.len() };
+            body.push(text[from..to.max(from)].iter().collect::<String>().trim().to_string());
+        }
+        while body.first().is_some_and(String::is_empty) {
+            body.remove(0);
+        }
+        while body.last().is_some_and(String::is_empty) {
+            body.pop();
+        }
+
+        // Comments inside the block are part of the copied text.
+        while self.comments.get(self.next_comment).is_some_and(|c| c.span.line <= close.line) {
+            self.next_comment += 1;
+        }
+
+        self.line("asm {");
+        self.indent += 1;
+        for text in body {
+            if text.is_empty() {
+                self.out.push('\n');
+            } else {
+                self.line(&text);
+            }
+        }
+        self.indent -= 1;
+        self.line(&format!("}}{}", operands));
+    }
+
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn open(&mut self, header: &str, line: usize) {
+        self.line(&format!("{} {{", header));
+        self.opened_at = self.out.len();
+        self.trailing(line);
+    }
+
+    /// Writes the `}` at token `close`; a block with nothing in it stays on
+    /// its header line.
+    fn close(&mut self, close: usize) {
+        if self.out.len() == self.opened_at && !self.out.ends_with("} else {\n") {
+            self.out.pop();
+            self.out.push_str("}\n");
+        } else {
+            self.line("}");
+        }
+        self.trailing(self.spans[close].line);
+    }
+
+    /// Ensures one blank line before the next declaration.
+    fn separate(&mut self) {
+        if !self.out.is_empty() && !self.out.ends_with("\n\n") {
+            self.out.push('\n');
+        }
+    }
+
+    /// Keeps a blank line the source had above `line`, except at the start
+    /// of a block.
+    fn blank_line_above(&mut self, line: usize) {
+        let blank = line >= 2
+            && line - 1 > self.written_through
+            && self.lines.get(line - 2).is_some_and(|l| l.trim().is_empty());
+        if blank && !self.out.is_empty() && !self.out.ends_with("{\n") && !self.out.ends_with("\n\n") {
+            self.out.push('\n');
+        }
+    }
+
+    /// Writes the comments that come before `line` on lines of their own.
+    fn comments_before(&mut self, line: usize) {
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.span.line >= line {
+                break;
+            }
+            let (comment_line, text) = (comment.span.line, comment.text.clone());
+            self.blank_line_above(comment_line);
+            self.line(&text);
+            self.next_comment += 1;
+            self.written_through = comment_line;
+        }
+        if line != usize::MAX {
+            self.blank_line_above(line);
+            self.written_through = line;
+        }
+    }
+
+    /// Appends a comment that ends source line `line` to the line just written.
+    fn trailing(&mut self, line: usize) {
+        if let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.span.line == line {
+                self.out.pop();
+                self.out.push(' ');
+                self.out.push_str(&comment.text);
+                self.out.push('\n');
+                self.next_comment += 1;
+            }
+        }
+    }
+
+    fn token_index(&self, span: Span) -> usize {
+        self.spans.iter().position(|s| *s == span).unwrap_or(0)
+    }
+
+    /// The first `{` at or after `span` and its matching `}`.
+    fn braces_after(&self, span: Span) -> (usize, usize) {
+        let start = self.token_index(span);
+        let open = (start..self.tokens.len()).find(|&i| self.tokens[i] == Token::LeftBrace).unwrap_or(start);
+        let mut depth = 0;
+        for i in open..self.tokens.len() {
+            match self.tokens[i] {
+                Token::LeftBrace => depth += 1,
+                Token::RightBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return (open, i);
+                    }
+                }
+                _ => {}
+            }
+        }
+        (open, self.tokens.len() - 1)
+    }
+}
+
+/// A statement that fits on one line, also used for `for` clauses.
+fn simple_statement(statement: &Statement) -> String {
+    match &statement.kind {
+        StatementKind::VarDecl { name, var_type, value } => {
+            let mut text = format!("var {}", name);
+            if let Some(var_type) = var_type {
+                text.push_str(&format!(": {}", var_type));
+            }
+            if let Some(value) = value {
+                text.push_str(&format!(" = {}", expr(value)));
+            }
+            text
+        }
+        StatementKind::ArrayDecl { name, element_type, size, size_eval } => match size_eval {
+            Some(size) => format!("var {}: [{}; eval({})]", name, element_type, expr(size)),
+            None => format!("var {}: [{}; {}]", name, element_type, size),
+        },
+        StatementKind::Assignment { name, value } => format!("{} = {}", name, expr(value)),
+        StatementKind::ArrayAssignment { name, index, value } => format!("{}[{}] = {}", name, expr(index), expr(value)),
+        StatementKind::PointerAssignment { target, value } => {
+            format!("*{} = {}", operand(target, PRIMARY), expr(value))
+        }
+        StatementKind::FieldAssignment { object, field, value } => {
+            format!("{}.{} = {}", expr(object), field, expr(value))
+        }
+        StatementKind::Return(Some(value)) => format!("return {}", expr(value)),
+        StatementKind::Return(None) => "return".to_string(),
+        StatementKind::Break => "break".to_string(),
+        StatementKind::Continue => "continue".to_string(),
+        StatementKind::Expression(e) => expr(e),
+        StatementKind::If { .. } | StatementKind::For { .. } | StatementKind::InlineAsm { .. } => String::new(),
+    }
+}
+
+const CAST: u8 = 7;
+const UNARY: u8 = 8;
+const PRIMARY: u8 = 9;
+
+fn binding(op: &BinaryOp) -> u8 {
+    match op {
+        BinaryOp::Or => 1,
+        BinaryOp::And => 2,
+        BinaryOp::Equal | BinaryOp::NotEqual => 3,
+        BinaryOp::Less | BinaryOp::LessEqual | BinaryOp::Greater | BinaryOp::GreaterEqual => 4,
+        BinaryOp::Add | BinaryOp::Sub | BinaryOp::Concat => 5,
+        BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => 6,
+    }
+}
+
+fn precedence(e: &Expression) -> u8 {
+    match &e.kind {
+        ExpressionKind::Binary { op, .. } => binding(op),
+        ExpressionKind::Cast { .. } => CAST,
+        ExpressionKind::Unary { .. } | ExpressionKind::AddressOf { .. } | ExpressionKind::Deref { .. } => UNARY,
+        _ => PRIMARY,
+    }
+}
+
+/// `e` parenthesized unless it binds at least as tightly as `min`.
+fn operand(e: &Expression, min: u8) -> String {
+    if precedence(e) < min {
+        format!("({})", expr(e))
+    } else {
+        expr(e)
+    }
+}
+
+fn prefix(symbol: &str, e: &Expression) -> String {
+    let inner = operand(e, UNARY);
+    // `& &x` must not become `&&x`.
+    if symbol == "&" && inner.starts_with('&') {
+        format!("&({})", inner)
+    } else {
+        format!("{}{}", symbol, inner)
+    }
+}
+
+fn expr(e: &Expression) -> String {
+    match &e.kind {
+        ExpressionKind::Number(n) => n.to_string(),
+        ExpressionKind::String(s) => literal::perano(s),
+        ExpressionKind::TemplateString { parts } => {
+            let mut raw = String::new();
+            for part in parts {
+                match part {
+                    TemplateStringPart::Literal(text) => raw.push_str(text),
+                    TemplateStringPart::Expression { expr: inner, format } => {
+                        raw.push_str("$(");
+                        raw.push_str(&expr(inner));
+                        if let Some(spec) = format {
+                            raw.push_str(&format_spec(spec));
+                        }
+                        raw.push(')');
+                    }
+                }
+            }
+            literal::perano(&raw)
+        }
+        ExpressionKind::Identifier(name) => name.clone(),
+        ExpressionKind::Binary { op, left, right } => {
+            let p = binding(op);
+            format!("{} {} {}", operand(left, p), symbol(op), operand(right, p + 1))
+        }
+        ExpressionKind::Unary { op: UnaryOp::Neg, operand } => prefix("-", operand),
+        ExpressionKind::Unary { op: UnaryOp::Not, operand } => prefix("!", operand),
+        ExpressionKind::AddressOf { operand } => prefix("&", operand),
+        ExpressionKind::Deref { operand } => prefix("*", operand),
+        ExpressionKind::Call { function, args } => format!("{}({})", function, arguments(args)),
+        ExpressionKind::ModuleCall { module, function, args } => {
+            format!("{}.{}({})", module, function, arguments(args))
+        }
+        ExpressionKind::FieldAccess { object, field } => format!("{}.{}", expr(object), field),
+        ExpressionKind::ArrayAccess { name, index } => format!("{}[{}]", name, expr(index)),
+        ExpressionKind::StringIndex { string, index } => format!("{}[{}]", expr(string), expr(index)),
+        ExpressionKind::Cast { operand: inner, target_type } => {
+            format!("{} as {}", operand(inner, CAST), target_type)
+        }
+        ExpressionKind::Eval { instruction } => format!("eval({})", expr(instruction)),
+    }
+}
+
+fn arguments(args: &[Expression]) -> String {
+    args.iter().map(expr).collect::<Vec<_>>().join(", ")
+}
+
+fn symbol(op: &BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "+",
+        BinaryOp::Sub => "-",
+        BinaryOp::Mul => "*",
+        BinaryOp::Div => "/",
+        BinaryOp::Mod => "%",
+        BinaryOp::Equal => "==",
+        BinaryOp::NotEqual => "!=",
+        BinaryOp::Less => "<",
+        BinaryOp::LessEqual => "<=",
+        BinaryOp::Greater => ">",
+        BinaryOp::GreaterEqual => ">=",
+        BinaryOp::And => "&&",
+        BinaryOp::Or => "||",
+        BinaryOp::Concat => "++",
+    }
+}
+
+fn format_spec(spec: &FormatSpec) -> String {
+    let mut text = String::from(":");
+    if spec.padding == '0' {
+        text.push('0');
+    }
+    if let Some(width) = spec.width {
+        text.push_str(&width.to_string());
+    }
+    match spec.format_type {
+        FormatType::Decimal => text.push('d'),
+        FormatType::Hex => text.push('x'),
+        FormatType::HexUpper => text.push('X'),
+        FormatType::String => text.push('s'),
+        FormatType::Auto => {}
+    }
+    text
+}
+
+/// ` : outputs : inputs : clobbers`, leaving out empty lists at the end.
+fn asm_operands(outputs: &[AsmOperand], inputs: &[AsmOperand], clobbers: &[String]) -> String {
+    let operands = |list: &[AsmOperand]| {
+        list.iter().map(|o| format!("{}({})", literal::perano(&o.constraint), o.variable)).collect::<Vec<_>>().join(", ")
+    };
+    let sections = [operands(outputs), operands(inputs), clobbers.iter().map(|c| literal::perano(c)).collect::<Vec<_>>().join(", ")];
+    let used = sections.iter().rposition(|s| !s.is_empty()).map_or(0, |i| i + 1);
+    sections[..used].iter().map(|s| format!(" : {}", s).trim_end().to_string()).collect()
+}
@@ -1,3 +1,5 @@
+use crate::error::{CompileError, ErrorKind};
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     Package,
@@ -8,7 +10,11 @@ pub enum Token {
     Else,
     For,
     Return,
+    Break,
+    Continue,
+    Struct,
     Asm,
+    Extern,
 
     Identifier(String),
     Number(i64),
@@ -49,6 +55,21 @@ pub enum Token {
     Eof,
 }
 
+/// Position of the first character of a token, 1-based.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A `//` or `#` comment, kept aside as trivia so the formatter can put it
+/// back. `text` includes the comment marker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    pub text: String,
+    pub span: Span,
+}
+
 pub struct Lexer {
     input: Vec<char>,
     position: usize,
@@ -56,6 +77,8 @@ pub struct Lexer {
     line: usize,
     column: usize,
     file: String,
+    comments: Vec<Comment>,
+    errors: Vec<CompileError>,
 }
 
 impl Lexer {
@@ -75,6 +98,8 @@ impl Lexer {
             line: 1,
             column: 1,
             file: file.to_string(),
+            comments: Vec::new(),
+            errors: Vec::new(),
         }
     }
 
@@ -117,13 +142,26 @@ impl Lexer {
 
     fn skip_comment(&mut self) {
         if self.current_char == Some('/') && self.peek(1) == Some('/') {
-            while self.current_char.is_some() && self.current_char != Some('\n') {
-                self.advance();
+            self.read_comment();
+        }
+    }
+
+    /// Consumes the rest of the line as a comment.
+    fn read_comment(&mut self) {
+        let span = Span { line: self.line, column: self.column };
+        let mut text = String::new();
+        while let Some(ch) = self.current_char {
+            if ch == '\n' {
+                break;
             }
+            text.push(ch);
+            self.advance();
         }
+        self.comments.push(Comment { text: text.trim_end().to_string(), span });
     }
 
     fn read_number(&mut self) -> Token {
+        let span = Span { line: self.line, column: self.column };
         let mut num_str = String::new();
 
         while let Some(ch) = self.current_char {
@@ -138,7 +176,13 @@ impl Lexer {
         match num_str.parse::<i64>() {
             Ok(num) => Token::Number(num),
             Err(_) => {
-                eprintln!("Warning: Number '{}' is too large, using i64::MAX ({})", num_str, i64::MAX);
+                self.errors.push(CompileError::new(
+                    ErrorKind::LexerError,
+                    format!("number '{}' is too large; the largest is {}", num_str, i64::MAX),
+                    self.file.clone(),
+                    span.line,
+                    span.column,
+                ));
                 Token::Number(i64::MAX)
             }
         }
@@ -170,7 +214,11 @@ impl Lexer {
             "while" => Token::For,
             "loop" => Token::For,
             "return" => Token::Return,
+            "break" => Token::Break,
+            "continue" => Token::Continue,
+            "struct" => Token::Struct,
             "asm" => Token::Asm,
+            "extern" => Token::Extern,
             "pub" => Token::Identifier(id),
             _ => Token::Identifier(id),
         }
@@ -185,17 +233,17 @@ impl Lexer {
                 self.advance();
                 break;
             } else if ch == '\\' {
+                let span = Span { line: self.line, column: self.column };
                 self.advance();
-                if let Some(escape_ch) = self.current_char {
-                    match escape_ch {
-                        'n' => string.push('\n'),
-                        't' => string.push('\t'),
-                        'r' => string.push('\r'),
-                        '\\' => string.push('\\'),
-                        '"' => string.push('"'),
-                        _ => string.push(escape_ch),
-                    }
-                    self.advance();
+                match self.read_escape() {
+                    Ok(c) => string.push(c),
+                    Err(message) => self.errors.push(CompileError::new(
+                        ErrorKind::LexerError,
+                        message,
+                        self.file.clone(),
+                        span.line,
+                        span.column,
+                    )),
                 }
             } else {
                 string.push(ch);
@@ -206,16 +254,77 @@ impl Lexer {
         Token::String(string)
     }
 
+    /// The character an escape stands for, read from just after its
+    /// backslash: `\n`, `\t`, `\r`, `\0`, `\\`, `\"`, `\xNN` up to `\x7f` and
+    /// `\u{...}` with one to six hex digits.
+    fn read_escape(&mut self) -> Result<char, String> {
+        let Some(escape_ch) = self.current_char else {
+            return Err("unterminated escape in string".to_string());
+        };
+        if escape_ch != '\n' {
+            self.advance();
+        }
+        match escape_ch {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            '0' => Ok('\0'),
+            '\\' => Ok('\\'),
+            '"' => Ok('"'),
+            'x' => {
+                let digits = self.read_hex_digits(2);
+                match u8::from_str_radix(&digits, 16) {
+                    Ok(byte) if digits.len() == 2 && byte.is_ascii() => Ok(byte as char),
+                    Ok(_) if digits.len() == 2 => Err(format!("'\\x{}' is not ASCII; use '\\u{{...}}' above \\x7f", digits)),
+                    _ => Err("'\\x' needs two hex digits".to_string()),
+                }
+            }
+            'u' => {
+                if self.current_char != Some('{') {
+                    return Err("'\\u' needs hex digits in braces, as in '\\u{e9}'".to_string());
+                }
+                self.advance();
+                let digits = self.read_hex_digits(6);
+                if self.current_char != Some('}') {
+                    return Err("'\\u{' needs one to six hex digits and a '}'".to_string());
+                }
+                self.advance();
+                u32::from_str_radix(&digits, 16).ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| format!("'\\u{{{}}}' is not a Unicode character", digits))
+            }
+            c => Err(format!("unknown escape '\\{}' in string", c.escape_default())),
+        }
+    }
+
+    fn read_hex_digits(&mut self, max: usize) -> String {
+        let mut digits = String::new();
+        while let Some(ch) = self.current_char.filter(|c| c.is_ascii_hexdigit() && digits.len() < max) {
+            digits.push(ch);
+            self.advance();
+        }
+        digits
+    }
+
     pub fn tokenize(&mut self) -> Vec<Token> {
+        self.tokenize_with_spans().0
+    }
+
+    /// Like `tokenize`, but also returns the span of every token.
+    pub fn tokenize_with_spans(&mut self) -> (Vec<Token>, Vec<Span>) {
         let mut tokens = Vec::new();
+        let mut spans = Vec::new();
 
         loop {
             self.skip_whitespace();
             self.skip_comment();
 
+            let start = Span { line: self.line, column: self.column };
+
             match self.current_char {
                 None => {
                     tokens.push(Token::Eof);
+                    spans.push(start);
                     break;
                 }
                 Some('\n') => {
@@ -349,13 +458,7 @@ impl Lexer {
                     self.advance();
                 }
                 Some('#') => {
-                    self.advance();
-                    while let Some(ch) = self.current_char {
-                        if ch == '\n' {
-                            break;
-                        }
-                        self.advance();
-                    }
+                    self.read_comment();
                 }
                 Some('"') => {
                     tokens.push(self.read_string());
@@ -367,20 +470,32 @@ impl Lexer {
                     tokens.push(self.read_identifier());
                 }
                 Some(ch) => {
-                    use crate::error::{CompileError, ErrorKind};
-                    let err = CompileError::new(
+                    self.errors.push(CompileError::new(
                         ErrorKind::LexerError,
                         format!("unexpected character: '{}'", ch),
                         self.file.clone(),
                         self.line,
                         self.column,
-                    );
-                    err.display();
-                    std::process::exit(1);
+                    ));
+                    self.advance();
                 }
             }
+
+            spans.resize(tokens.len(), start);
         }
 
-        tokens
+        (tokens, spans)
+    }
+
+    /// Like `tokenize_with_spans`, but also returns the comments that were
+    /// skipped, in source order.
+    pub fn tokenize_with_trivia(&mut self) -> (Vec<Token>, Vec<Span>, Vec<Comment>) {
+        let (tokens, spans) = self.tokenize_with_spans();
+        (tokens, spans, std::mem::take(&mut self.comments))
+    }
+
+    /// Characters that start no token; they are skipped while tokenizing.
+    pub fn take_errors(&mut self) -> Vec<CompileError> {
+        std::mem::take(&mut self.errors)
     }
 }
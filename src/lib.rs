@@ -0,0 +1,34 @@
+//! The Perano compiler as a library.
+//!
+//! ```no_run
+//! use perano_lang::{compiler, Compiler};
+//!
+//! let mut session = Compiler::new()
+//!     .with_resolver(compiler::directory_resolver(vec!["stdlib".into()]));
+//! session.add_source("main.per", "package main\n\nfunc main() i64 {\n    return 0\n}\n");
+//! match session.check("main.per") {
+//!     Ok(program) => match compiler::nvm_bytecode(&program) {
+//!         Ok(bytecode) => std::fs::write("main.bin", bytecode).unwrap(),
+//!         Err(e) => e.display(),
+//!     },
+//!     Err(errors) => errors.iter().for_each(|e| e.display()),
+//! }
+//! ```
+
+pub mod lexer;
+pub mod literal;
+pub mod parser;
+pub mod ast;
+pub mod elf;
+pub mod pe;
+pub mod nvm;
+pub mod error;
+pub mod typechecker;
+pub mod resolver;
+pub mod compiler;
+pub mod formatter;
+pub mod lsp;
+pub mod repl;
+
+pub use compiler::{Analysis, Compiler, Diagnostics, SourceFile};
+pub use error::{CompileError, ErrorKind};
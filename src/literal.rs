@@ -0,0 +1,72 @@
+//! Quoted string literals for the languages the compiler writes: Perano
+//! source, GNU assembler and C, and NVM assembly. Each form reads back as
+//! exactly the text or bytes it was made from.
+//!
+//! `\n`, `\t`, `\r`, `\\` and `\"` are used everywhere; other control
+//! characters and bytes that are not UTF-8 get the numeric escape of the
+//! language, and the rest of the text is kept as it is.
+
+/// A Perano string literal that lexes back to `s`.
+pub fn perano(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match common_escape(c) {
+            Some(escape) => quoted.push_str(escape),
+            None if c == '\0' => quoted.push_str("\\0"),
+            None if c.is_ascii_control() => quoted.push_str(&format!("\\x{:02x}", c as u32)),
+            None if c.is_control() => quoted.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            None => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// A `.string` operand for the GNU assembler and `elf::assembler`.
+pub fn gas(bytes: &[u8]) -> String {
+    quote(bytes, |byte| format!("\\{:03o}", byte), false)
+}
+
+/// A C string literal. Octal escapes always take three digits, so a digit
+/// after one is not read into it, and `??` is broken up so it never starts
+/// a trigraph.
+pub fn c(bytes: &[u8]) -> String {
+    quote(bytes, |byte| format!("\\{:03o}", byte), true)
+}
+
+/// A `.string` operand for `nvm::assembler`, which only knows `\xNN`.
+pub fn nvm(bytes: &[u8]) -> String {
+    quote(bytes, |byte| format!("\\x{:02x}", byte), false)
+}
+
+fn common_escape(c: char) -> Option<&'static str> {
+    match c {
+        '\n' => Some("\\n"),
+        '\t' => Some("\\t"),
+        '\r' => Some("\\r"),
+        '\\' => Some("\\\\"),
+        '"' => Some("\\\""),
+        _ => None,
+    }
+}
+
+/// `bytes` between double quotes, spelling each byte of a control character
+/// and each byte that is not UTF-8 with `escape`.
+fn quote(bytes: &[u8], escape: fn(u8) -> String, trigraphs: bool) -> String {
+    let mut quoted = String::from("\"");
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match common_escape(c) {
+                Some(escape) => quoted.push_str(escape),
+                None if c.is_control() => {
+                    c.encode_utf8(&mut [0; 4]).bytes().for_each(|byte| quoted.push_str(&escape(byte)));
+                }
+                None if c == '?' && trigraphs && quoted.ends_with('?') => quoted.push_str("\\?"),
+                None => quoted.push(c),
+            }
+        }
+        chunk.invalid().iter().for_each(|&byte| quoted.push_str(&escape(byte)));
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,480 @@
+//! A language server for `.per` files, speaking JSON-RPC over stdio.
+//!
+//! Supports diagnostics on open and change, go-to-definition, hover with
+//! function signatures, and completion of module members after `alias.`.
+
+mod json;
+
+pub use json::Json;
+
+use crate::ast::{External, Function, Program};
+use crate::compiler::{self, Analysis, Compiler};
+use crate::error::CompileError;
+use crate::lexer::{Lexer, Span, Token};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+const COMPLETION_FUNCTION: usize = 3;
+const COMPLETION_MODULE: usize = 9;
+const COMPLETION_STRUCT: usize = 22;
+
+struct Document {
+    /// The path the document is analysed under.
+    name: String,
+    text: String,
+    /// The last version that parsed, so navigation and completion keep
+    /// working while the user types.
+    last_good: Option<Snapshot>,
+}
+
+struct Snapshot {
+    session: Compiler,
+    analysis: Analysis,
+    /// How many of `program.structs` the document declares itself; the rest
+    /// come from imported modules.
+    local_structs: usize,
+}
+
+impl Snapshot {
+    fn program(&self) -> &Program {
+        self.analysis.program.as_ref().unwrap()
+    }
+
+    fn local_structs(&self) -> &[crate::ast::StructDecl] {
+        &self.program().structs[..self.local_structs]
+    }
+}
+
+/// What the identifier under the cursor refers to.
+enum Symbol {
+    /// `alias.Name`, with the alias resolved to the module path.
+    Member { module: String, name: String },
+    Module(String),
+    Local(String),
+}
+
+struct Server<W: Write> {
+    out: W,
+    documents: HashMap<String, Document>,
+    shutdown_requested: bool,
+}
+
+/// Serves requests from `input` until the client sends `exit` or closes the
+/// stream. Returns the exit code the process should end with.
+pub fn serve(mut input: impl BufRead, output: impl Write) -> io::Result<i32> {
+    let mut server = Server { out: output, documents: HashMap::new(), shutdown_requested: false };
+
+    while let Some(body) = read_message(&mut input)? {
+        let message = match Json::parse(&body) {
+            Ok(message) => message,
+            Err(e) => {
+                server.send(Json::object([
+                    ("jsonrpc", "2.0".into()),
+                    ("id", Json::Null),
+                    ("error", Json::object([("code", Json::Number(-32700.0)), ("message", e.into())])),
+                ]))?;
+                continue;
+            }
+        };
+        if let Some(code) = server.handle(&message)? {
+            return Ok(code);
+        }
+    }
+    Ok(if server.shutdown_requested { 0 } else { 1 })
+}
+
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let mut body = vec![0; length.unwrap_or(0)];
+    input.read_exact(&mut body)?;
+    Ok(Some(String::from_utf8_lossy(&body).into_owned()))
+}
+
+impl<W: Write> Server<W> {
+    fn send(&mut self, message: Json) -> io::Result<()> {
+        let body = message.to_string();
+        write!(self.out, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        self.out.flush()
+    }
+
+    /// Handles one message; returns `Some(code)` once the client asks to exit.
+    fn handle(&mut self, message: &Json) -> io::Result<Option<i32>> {
+        let method = message.get("method").as_str().unwrap_or("");
+        let id = message.get("id").clone();
+        let params = message.get("params");
+
+        let result = match method {
+            "initialize" => Ok(capabilities()),
+            "shutdown" => {
+                self.shutdown_requested = true;
+                Ok(Json::Null)
+            }
+            "exit" => return Ok(Some(if self.shutdown_requested { 0 } else { 1 })),
+            "textDocument/didOpen" => {
+                let uri = params.at(&["textDocument", "uri"]).as_str().unwrap_or("");
+                let text = params.at(&["textDocument", "text"]).as_str().unwrap_or("");
+                self.update(uri, text.to_string())?;
+                return Ok(None);
+            }
+            "textDocument/didChange" => {
+                let uri = params.at(&["textDocument", "uri"]).as_str().unwrap_or("");
+                // Only full-document sync is advertised, so the last change
+                // holds the whole text.
+                if let Some(text) = params.get("contentChanges").as_array().last().and_then(|c| c.get("text").as_str()) {
+                    self.update(uri, text.to_string())?;
+                }
+                return Ok(None);
+            }
+            "textDocument/didClose" => {
+                let uri = params.at(&["textDocument", "uri"]).as_str().unwrap_or("").to_string();
+                self.documents.remove(&uri);
+                self.publish(&uri, Vec::new())?;
+                return Ok(None);
+            }
+            "textDocument/definition" => self.with_position(params, definition),
+            "textDocument/hover" => self.with_position(params, hover),
+            "textDocument/completion" => self.completion(params),
+            _ if id.is_null() => return Ok(None),
+            _ => Err((METHOD_NOT_FOUND, format!("method '{}' is not supported", method))),
+        };
+
+        if !id.is_null() {
+            let outcome = match result {
+                Ok(value) => ("result", value),
+                Err((code, message)) => (
+                    "error",
+                    Json::object([("code", Json::Number(code as f64)), ("message", message.into())]),
+                ),
+            };
+            self.send(Json::object([("jsonrpc", "2.0".into()), ("id", id), outcome]))?;
+        }
+        Ok(None)
+    }
+
+    fn update(&mut self, uri: &str, text: String) -> io::Result<()> {
+        let path = uri_to_path(uri);
+        let name = path.to_string_lossy().to_string();
+
+        let mut session = Compiler::new().with_resolver(compiler::directory_resolver(compiler::search_dirs(&path)));
+        session.add_source(&name, &text);
+        let analysis = session.analyze(&name);
+
+        let diagnostics = analysis.diagnostics.iter().map(|e| diagnostic(e, &name, &text)).collect();
+
+        let document = self.documents.entry(uri.to_string())
+            .or_insert(Document { name: name.clone(), text: String::new(), last_good: None });
+        document.text = text;
+        if analysis.program.is_some() {
+            let local_structs = session.parse(&name).map(|p| p.structs.len()).unwrap_or(0);
+            document.last_good = Some(Snapshot { session, analysis, local_structs });
+        }
+
+        self.publish(uri, diagnostics)
+    }
+
+    fn publish(&mut self, uri: &str, diagnostics: Vec<Json>) -> io::Result<()> {
+        self.send(Json::object([
+            ("jsonrpc", "2.0".into()),
+            ("method", "textDocument/publishDiagnostics".into()),
+            ("params", Json::object([("uri", uri.into()), ("diagnostics", Json::Array(diagnostics))])),
+        ]))
+    }
+
+    /// Runs `f` on the symbol at the request's position, answering `null`
+    /// when there is none.
+    fn with_position(
+        &self,
+        params: &Json,
+        f: impl Fn(&Document, &Symbol) -> Option<Json>,
+    ) -> Result<Json, (i64, String)> {
+        let (document, line, character) = self.locate(params)?;
+        Ok(symbol_at(document, line, character)
+            .and_then(|symbol| f(document, &symbol))
+            .unwrap_or(Json::Null))
+    }
+
+    fn locate(&self, params: &Json) -> Result<(&Document, usize, usize), (i64, String)> {
+        let uri = params.at(&["textDocument", "uri"]).as_str().unwrap_or("");
+        let document = self.documents.get(uri).ok_or((INVALID_PARAMS, format!("document '{}' is not open", uri)))?;
+        let line = params.at(&["position", "line"]).as_usize().unwrap_or(0);
+        let character = params.at(&["position", "character"]).as_usize().unwrap_or(0);
+        Ok((document, line, character))
+    }
+
+    fn completion(&self, params: &Json) -> Result<Json, (i64, String)> {
+        let (document, line, character) = self.locate(params)?;
+        let Some(snapshot) = &document.last_good else {
+            return Ok(Json::Array(Vec::new()));
+        };
+        let (analysis, program) = (&snapshot.analysis, snapshot.program());
+
+        let before: String = document.text.lines().nth(line).unwrap_or("").chars().take(character).collect();
+        let before = before.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_');
+
+        let mut items = Vec::new();
+        if let Some(alias) = before.strip_suffix('.') {
+            let alias: String = alias.chars().rev().take_while(|c| c.is_alphanumeric() || *c == '_').collect();
+            let alias: String = alias.chars().rev().collect();
+            if let Some(module) = module_path(program, &alias).and_then(|path| program.modules.get(&path)) {
+                for func in module.functions.iter().filter(|f| f.is_exported) {
+                    let key = format!("{}.{}", module.name, func.name);
+                    items.push(completion_item(&func.name, COMPLETION_FUNCTION, signature_text(analysis, &key, func)));
+                }
+            }
+        } else {
+            for func in &program.functions {
+                items.push(completion_item(&func.name, COMPLETION_FUNCTION, signature_text(analysis, &func.name, func)));
+            }
+            for import in &program.imports {
+                items.push(completion_item(&import_alias(import), COMPLETION_MODULE, format!("import \"{}\"", import.path)));
+            }
+            for decl in snapshot.local_structs() {
+                items.push(completion_item(&decl.name, COMPLETION_STRUCT, format!("struct {}", decl.name)));
+            }
+        }
+
+        Ok(Json::object([("isIncomplete", false.into()), ("items", Json::Array(items))]))
+    }
+}
+
+fn capabilities() -> Json {
+    Json::object([
+        (
+            "capabilities",
+            Json::object([
+                ("textDocumentSync", 1.into()),
+                ("hoverProvider", true.into()),
+                ("definitionProvider", true.into()),
+                ("completionProvider", Json::object([("triggerCharacters", Json::Array(vec![".".into()]))])),
+            ]),
+        ),
+        (
+            "serverInfo",
+            Json::object([("name", "perano-lang".into()), ("version", env!("CARGO_PKG_VERSION").into())]),
+        ),
+    ])
+}
+
+fn diagnostic(error: &CompileError, name: &str, text: &str) -> Json {
+    let (span, message) = if error.file == name {
+        (Span { line: error.line, column: error.column }, error.message.clone())
+    } else {
+        // Errors inside imported files are shown at the top of this one.
+        (Span { line: 1, column: 1 }, error.to_string())
+    };
+    Json::object([
+        ("range", range(span, word_length(text, span))),
+        ("severity", 1.into()),
+        ("source", "perano".into()),
+        ("message", message.into()),
+    ])
+}
+
+/// The number of characters of the word starting at `span`, at least 1.
+fn word_length(text: &str, span: Span) -> usize {
+    let line = text.lines().nth(span.line.saturating_sub(1)).unwrap_or("");
+    let word = line.chars().skip(span.column.saturating_sub(1)).take_while(|c| c.is_alphanumeric() || *c == '_').count();
+    word.max(1)
+}
+
+fn position(line: usize, character: usize) -> Json {
+    Json::object([("line", line.into()), ("character", character.into())])
+}
+
+/// Converts a 1-based span to an LSP range covering `length` characters.
+fn range(span: Span, length: usize) -> Json {
+    let line = span.line.saturating_sub(1);
+    let character = span.column.saturating_sub(1);
+    Json::object([("start", position(line, character)), ("end", position(line, character + length))])
+}
+
+fn symbol_at(document: &Document, line: usize, character: usize) -> Option<Symbol> {
+    let (tokens, spans) = Lexer::new(&document.text).tokenize_with_spans();
+    let (line, column) = (line + 1, character + 1);
+
+    let index = tokens.iter().zip(&spans).position(|(token, span)| match token {
+        Token::Identifier(name) => span.line == line && (span.column..=span.column + name.chars().count()).contains(&column),
+        _ => false,
+    })?;
+    let Token::Identifier(name) = &tokens[index] else {
+        return None;
+    };
+
+    let program = document.last_good.as_ref()?.program();
+
+    if index >= 2 && tokens[index - 1] == Token::Dot {
+        if let Token::Identifier(alias) = &tokens[index - 2] {
+            let module = module_path(program, alias)?;
+            return Some(Symbol::Member { module, name: name.clone() });
+        }
+    }
+    if tokens.get(index + 1) == Some(&Token::Dot) {
+        if let Some(module) = module_path(program, name) {
+            return Some(Symbol::Module(module));
+        }
+    }
+    Some(Symbol::Local(name.clone()))
+}
+
+fn import_alias(import: &crate::ast::Import) -> String {
+    import.alias.clone().unwrap_or_else(|| import.path.rsplit('/').next().unwrap_or(&import.path).to_string())
+}
+
+/// The module an alias in this file refers to.
+fn module_path(program: &Program, alias: &str) -> Option<String> {
+    program.imports.iter().find(|import| import_alias(import) == alias).map(|import| import.path.clone())
+}
+
+fn definition(document: &Document, symbol: &Symbol) -> Option<Json> {
+    let snapshot = document.last_good.as_ref()?;
+    let program = snapshot.program();
+
+    let (file, span) = match symbol {
+        Symbol::Member { module, name } => {
+            let module = program.modules.get(module)?;
+            let func = module.functions.iter().find(|f| &f.name == name)?;
+            (module.file.clone(), func.span)
+        }
+        Symbol::Module(module) => (program.modules.get(module)?.file.clone(), Span { line: 1, column: 1 }),
+        Symbol::Local(name) => {
+            let span = program.functions.iter().find(|f| &f.name == name).map(|f| f.span)
+                .or_else(|| snapshot.local_structs().iter().find(|s| &s.name == name).map(|s| s.span))?;
+            (document.name.clone(), span)
+        }
+    };
+
+    let length = snapshot.session.source(&file).map(|text| word_length(text, span)).unwrap_or(1);
+    Some(Json::object([("uri", path_to_uri(Path::new(&file)).into()), ("range", range(span, length))]))
+}
+
+fn hover(document: &Document, symbol: &Symbol) -> Option<Json> {
+    let snapshot = document.last_good.as_ref()?;
+    let program = snapshot.program();
+
+    let (key, func, file) = match symbol {
+        Symbol::Member { module, name } => {
+            let module = program.modules.get(module)?;
+            let func = module.functions.iter().find(|f| &f.name == name)?;
+            (format!("{}.{}", module.name, name), func, module.file.clone())
+        }
+        Symbol::Module(module) => {
+            let text = format!("```perano\nimport \"{}\"\n```", module);
+            return Some(Json::object([("contents", markdown(text))]));
+        }
+        Symbol::Local(name) => {
+            let func = program.functions.iter().find(|f| &f.name == name)?;
+            (name.clone(), func, document.name.clone())
+        }
+    };
+
+    let mut text = format!("```perano\n{}\n```", signature_text(&snapshot.analysis, &key, func));
+    let comment = snapshot.session.source(&file).map(|source| doc_comment(source, func.span.line)).unwrap_or_default();
+    if !comment.is_empty() {
+        text.push_str("\n\n");
+        text.push_str(&comment);
+    }
+    Some(Json::object([("contents", markdown(text))]))
+}
+
+fn markdown(text: String) -> Json {
+    Json::object([("kind", "markdown".into()), ("value", text.into())])
+}
+
+/// `fn Name(a: i64, b: i64) -> i64`, with types from the checker's
+/// `FunctionSignature` and the declared spelling where it could not resolve
+/// them.
+fn signature_text(analysis: &Analysis, key: &str, func: &Function) -> String {
+    let signature = analysis.checker.as_ref().and_then(|checker| checker.signature(key));
+
+    let params: Vec<String> = func.params.iter().enumerate().map(|(i, param)| {
+        let resolved = signature.and_then(|s| s.params.get(i)).map(|(_, ty)| ty.to_string());
+        match resolved {
+            Some(ty) if ty != "?" => format!("{}: {}", param.name, ty),
+            _ => format!("{}: {}", param.name, param.param_type),
+        }
+    }).collect();
+
+    let returns = match (signature.map(|s| s.return_type.to_string()), &func.return_type) {
+        (Some(ty), _) if ty != "?" && ty != "void" => format!(" -> {}", ty),
+        (_, Some(declared)) if declared != "void" => format!(" -> {}", declared),
+        _ => String::new(),
+    };
+
+    let visibility = if func.is_exported { "pub " } else { "" };
+    let abi = match func.external {
+        Some(External::C) => "extern \"C\" ",
+        Some(External::Syscall(_)) => "extern \"novaria\" ",
+        None => "",
+    };
+    format!("{}{}fn {}({}){}", visibility, abi, func.name, params.join(", "), returns)
+}
+
+/// The `//` comment lines directly above line `line` (1-based).
+fn doc_comment(source: &str, line: usize) -> String {
+    let lines: Vec<&str> = source.lines().take(line.saturating_sub(1)).collect();
+    let comment: Vec<&str> = lines.iter().rev()
+        .map(|l| l.trim())
+        .take_while(|l| l.starts_with("//"))
+        .map(|l| l.trim_start_matches('/').trim())
+        .collect();
+    comment.into_iter().rev().collect::<Vec<_>>().join("\n")
+}
+
+fn completion_item(label: &str, kind: usize, detail: String) -> Json {
+    Json::object([("label", label.into()), ("kind", kind.into()), ("detail", detail.into())])
+}
+
+fn uri_to_path(uri: &str) -> PathBuf {
+    let path = uri.strip_prefix("file://").unwrap_or(uri);
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok()).and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let absolute = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let mut uri = String::from("file://");
+    for byte in absolute.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
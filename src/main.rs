@@ -1,250 +1,353 @@
-mod lexer;
-mod parser;
-mod ast;
-mod elf;
-mod pe;
-mod nvm;
-mod error;
-mod typechecker;
+mod cli;
 
 use std::fs;
 use std::env;
 use std::process;
-use std::collections::HashSet;
+use std::thread;
 use std::path::Path;
+use cli::{Options, Stage, Subcommand, Target};
+use perano_lang::{ast, compiler, nvm, pe, CompileError, Compiler};
+
+/// The exit code of a failed step; its diagnostics have already been printed.
+type Step<T> = std::result::Result<T, i32>;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-
-    if args.len() < 2 {
-        eprintln!("Usage: {} <source.per> [--elf|--nvm-code|--novaria|--pe-c]", args[0]);
-        process::exit(1);
-    }
-
-    let source_file = &args[1];
-    let source = match fs::read_to_string(source_file) {
-        Ok(s) => s,
-        Err(e) => {
-            let err = error::CompileError::new(
-                error::ErrorKind::ModuleError,
-                format!("failed to read source file: {}", e),
-                source_file.to_string(),
-                1,
-                1,
-            );
-            err.display();
-            process::exit(1);
+    let program = args.first().map(String::as_str).unwrap_or("perano");
+
+    let options = match cli::parse_args(args.get(1..).unwrap_or_default()) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("\x1b[1;31merror\x1b[0m: {}", message);
+            eprintln!("Run '{} help' for usage.", program);
+            process::exit(cli::EXIT_USAGE);
         }
     };
 
-    let mut lexer = lexer::Lexer::new_with_file(&source, source_file);
-    let tokens = lexer.tokenize();
-
-    let mut parser = parser::Parser::new(tokens, source_file);
-    let mut ast = match parser.parse() {
-        Ok(ast) => ast,
-        Err(e) => {
-            e.display();
-            process::exit(1);
+    let status = match options.command {
+        Subcommand::Help => {
+            print!("{}", cli::usage(program));
+            Ok(())
+        }
+        Subcommand::Run => run(&options),
+        Subcommand::Repl => repl(),
+        Subcommand::Lsp => match perano_lang::lsp::serve(std::io::stdin().lock(), std::io::stdout().lock()) {
+            Ok(0) => Ok(()),
+            Ok(code) => Err(code),
+            Err(e) => {
+                eprintln!("Language server stopped: {}", e);
+                Err(cli::EXIT_IO_ERROR)
+            }
+        },
+        _ => {
+            // Every input is compiled on its own; keep going so all of them
+            // report their errors, and fail with the first failure's code.
+            let mut status = Ok(());
+            for input in &options.inputs {
+                let result = match options.command {
+                    Subcommand::Check => compile_frontend(input).map(|_| ()),
+                    Subcommand::Fmt => format(input, options.check),
+                    Subcommand::Emit => emit(input, &options),
+                    _ => build(input, &options),
+                };
+                if status.is_ok() {
+                    status = result;
+                }
+            }
+            status
         }
     };
 
-    let source_dir = std::path::Path::new(source_file).parent().unwrap_or(std::path::Path::new("."));
-    if let Err(e) = load_modules(&mut ast, source_dir, &mut std::collections::HashSet::new()) {
-        e.display();
-        process::exit(1);
+    if let Err(code) = status {
+        process::exit(code);
     }
+}
 
-    let mut type_checker = typechecker::TypeChecker::new();
-    if let Err(errors) = type_checker.check_program(&ast) {
-        eprintln!("Type checking failed with {} error(s):", errors.len());
-        type_checker.print_errors();
-        process::exit(1);
-    }
+fn build(input: &Path, options: &Options) -> Step<()> {
+    let output = match (&options.output, options.emit) {
+        (Some(output), _) => output.clone(),
+        (None, Some(stage)) => input.with_extension(stage.extension()),
+        (None, None) if is_nvm_asm(input) => input.with_extension("bin"),
+        (None, None) => options.target.default_output(input),
+    };
+    let output_file = output.to_string_lossy().to_string();
 
-    let target = if args.len() > 2 {
-        match args[2].as_str() {
-            "--elf" => "elf",
-            "--nvm-code" => "nvm-code",
-            "--novaria" => "novaria",
-            "--pe-asm" => "pe-asm",
-            _ => {
-                eprintln!("Unknown target: {}", args[2]);
-                eprintln!("Valid targets: --elf, --nvm-code, --novaria, --pe-asm");
-                process::exit(1);
+    if let Some(stage) = options.emit {
+        let artifact = emit_stage(input, stage, options.target)?;
+        write_output(&output_file, &artifact)?;
+    } else if is_nvm_asm(input) {
+        write_output(&output_file, &assemble(input)?)?;
+    } else {
+        let ast = compile_frontend(input)?;
+        match options.target {
+            Target::Novaria => {
+                let bytecode = compiler::nvm_bytecode(&ast).map_err(|e| report(vec![e]))?;
+                write_output(&output_file, &bytecode)?
             }
+            Target::Elf => compile_elf_direct(&ast, &output_file)?,
+            Target::ElfGcc => compile_elf_proper(&ast, &output_file)?,
+            Target::PeAsm => {
+                let mut codegen = pe::CodeGen::new("pe-asm");
+                let machine_code = codegen.generate(&ast).map_err(|e| report(vec![e]))?;
+                let mut pe_writer = pe::PEWriter::new();
+                if let Err(e) = pe_writer.write(&output_file, &machine_code) {
+                    eprintln!("Failed to write executable: {}", e);
+                    return Err(cli::EXIT_IO_ERROR);
+                }
+            }
+            Target::Pe => compile_pe_with_c(&ast, &output_file)?,
         }
-    } else if cfg!(target_os = "windows") {
-        "pe"
+    }
+
+    println!("Compilation successful: {}", output_file);
+    Ok(())
+}
+
+/// Rewrites `input` in the canonical style, or with `check` only reports
+/// that it would.
+fn format(input: &Path, check: bool) -> Step<()> {
+    let name = input.to_string_lossy().to_string();
+    let source = fs::read_to_string(input).map_err(|e| {
+        eprintln!("Failed to read {}: {}", name, e);
+        cli::EXIT_IO_ERROR
+    })?;
+    let formatted = perano_lang::formatter::format_source(&source, &name).map_err(report)?;
+
+    if formatted == source {
+        Ok(())
+    } else if check {
+        println!("{}", name);
+        Err(cli::EXIT_COMPILE_ERROR)
     } else {
-        "elf"
+        write_output(&name, formatted.as_bytes())
+    }
+}
+
+fn emit(input: &Path, options: &Options) -> Step<()> {
+    let Some(stage) = options.emit else {
+        return Ok(());
     };
+    let artifact = emit_stage(input, stage, options.target)?;
 
-    let output_file = match target {
-        "nvm-code" => {
-            if source_file.ends_with(".per") {
-                source_file.replace(".per", ".asm")
-            } else {
-                format!("{}.asm", source_file)
-            }
-        }
-        "novaria" => {
-            if source_file.ends_with(".per") {
-                source_file.replace(".per", ".bin")
-            } else {
-                format!("{}.bin", source_file)
-            }
-        }
-        "elf" => {
-            if source_file.ends_with(".per") {
-                source_file[..source_file.len()-4].to_string()
-            } else if source_file.ends_with(".nl") {
-                source_file[..source_file.len()-4].to_string()
-            } else {
-                source_file.to_string()
-            }
-        }
+    match &options.output {
+        Some(output) if output != Path::new("-") => write_output(&output.to_string_lossy(), &artifact),
         _ => {
-            if source_file.ends_with(".per") {
-                source_file.replace(".per", ".exe")
+            use std::io::Write;
+
+            // Bytecode goes to a terminal as a hex dump, 16 bytes per line.
+            let text = if stage == Stage::Bytecode {
+                artifact.chunks(16)
+                    .map(|line| line.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ") + "\n")
+                    .collect::<String>()
+                    .into_bytes()
             } else {
-                source_file.replace(".go", ".exe")
-            }
+                artifact
+            };
+            let _ = std::io::stdout().write_all(&text);
+            Ok(())
         }
-    };
+    }
+}
 
-    match target {
-        "novaria" => {
-            compile_nvm(&ast, &output_file);
-        }
-        "nvm-code" => {
-            compile_nvm_asm(&ast, &output_file);
+/// Runs the pipeline as far as `stage` needs and returns what it produced.
+fn emit_stage(input: &Path, stage: Stage, target: Target) -> Step<Vec<u8>> {
+    if is_nvm_asm(input) {
+        if stage != Stage::Bytecode {
+            eprintln!("\x1b[1;31merror\x1b[0m: {} is NVM assembly, which only assembles to bytecode", input.display());
+            return Err(cli::EXIT_USAGE);
         }
-        "elf" => {
-            compile_elf_proper(&ast, &output_file);
+        return assemble(input);
+    }
+    let (session, name) = open_session(input)?;
+
+    match stage {
+        Stage::Tokens => {
+            let tokens = session.tokens(&name).map_err(report)?;
+            let listing: String = tokens.iter()
+                .map(|(token, span)| format!("{}:{}\t{:?}\n", span.line, span.column, token))
+                .collect();
+            Ok(listing.into_bytes())
         }
-        "pe-asm" => {
-            let mut codegen = pe::CodeGen::new(target);
-            let machine_code = codegen.generate(&ast);
-            let mut pe_writer = pe::PEWriter::new();
-            pe_writer.write(&output_file, &machine_code)
-                .expect("Failed to write executable");
+        Stage::Ast => {
+            let ast = session.parse(&name).map_err(report)?;
+            Ok(format!("{:#?}\n", ast).into_bytes())
         }
         _ => {
-            compile_pe_with_c(&ast, &output_file);
+            let ast = check(session, &name)?;
+            match stage {
+                Stage::TypedAst => Ok(format!("{:#?}\n", ast).into_bytes()),
+                Stage::Asm => compiler::elf_assembly(&ast, target != Target::ElfGcc)
+                    .map(String::into_bytes)
+                    .map_err(|e| report(vec![e])),
+                Stage::NvmAsm => compiler::nvm_assembly(&ast).map(String::into_bytes).map_err(|e| report(vec![e])),
+                Stage::Bytecode => compiler::nvm_bytecode(&ast).map_err(|e| report(vec![e])),
+                _ => compiler::c_source(&ast).map(String::into_bytes).map_err(|e| report(vec![e])),
+            }
         }
     }
-
-    println!("Compilation successful: {}", output_file);
 }
 
-fn load_modules(ast: &mut ast::Program, base_dir: &Path, loaded: &mut HashSet<String>) -> error::Result<()> {
-    let imports = ast.imports.clone();
-
-    for import in &imports {
-        let module_name = import.path.clone();
+fn write_output(output_file: &str, contents: &[u8]) -> Step<()> {
+    fs::write(output_file, contents).map_err(|e| {
+        eprintln!("Failed to write {}: {}", output_file, e);
+        cli::EXIT_IO_ERROR
+    })
+}
 
-        if loaded.contains(&module_name) {
-            continue;
-        }
+fn report(errors: Vec<CompileError>) -> i32 {
+    for e in errors {
+        e.display();
+    }
+    cli::EXIT_COMPILE_ERROR
+}
 
-        loaded.insert(module_name.clone());
-
-        
-        let module_filename = format!("{}.per", module_name);
-        
-        
-        let mut module_file = base_dir.join(&module_filename);
-        
-        
-        if !module_file.exists() {
-            module_file = Path::new("stdlib").join(&module_filename);
-        }
-        
-        
-        if !module_file.exists() {
-            if let Ok(exe_path) = env::current_exe() {
-                if let Some(exe_dir) = exe_path.parent() {
-                    module_file = exe_dir.join("stdlib").join(&module_filename);
-                }
-            }
+/// Runs the REPL on a thread of its own, whose stack holds the deepest
+/// call chain the interpreter allows.
+fn repl() -> Step<()> {
+    let dirs = compiler::search_dirs(Path::new("repl.per"));
+    let session = thread::Builder::new()
+        .stack_size(perano_lang::repl::STACK_SIZE)
+        .spawn(move || perano_lang::repl::run(std::io::stdin().lock(), std::io::stdout().lock(), dirs))
+        .and_then(|session| session.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)));
+    match session {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            eprintln!("REPL stopped: {}", e);
+            Err(cli::EXIT_IO_ERROR)
         }
+    }
+}
 
-        let module_source = match fs::read_to_string(&module_file) {
-            Ok(s) => s,
-            Err(_) => {
-                return Err(error::CompileError::new(
-                    error::ErrorKind::ModuleError,
-                    format!("could not find module '{}'", module_name),
-                    module_file.to_string_lossy().to_string(),
-                    1,
-                    1,
-                ).with_source_line(format!("import \"{}\"", module_name)));
-            }
-        };
-
-        let mut module_lexer = lexer::Lexer::new_with_file(&module_source, &module_file.to_string_lossy());
-        let module_tokens = module_lexer.tokenize();
-        let mut module_parser = parser::Parser::new(module_tokens, &module_file.to_string_lossy());
-        let mut module_ast = module_parser.parse()?;
-
-        load_modules(&mut module_ast, base_dir, loaded)?;
+/// Inputs ending in `.asm` are NVM assembly, which is assembled rather
+/// than compiled.
+fn is_nvm_asm(input: &Path) -> bool {
+    input.extension().is_some_and(|ext| ext == "asm")
+}
 
-        for (mod_name, module) in module_ast.modules {
-            ast.modules.insert(mod_name, module);
-        }
+fn assemble(input: &Path) -> Step<Vec<u8>> {
+    let (source, name) = read_source(input)?;
+    nvm::assembler::assemble(&source, &name).map_err(|e| report(vec![e]))
+}
 
-        let module = ast::Module {
-            name: module_name.clone(),
-            functions: module_ast.functions,
-        };
+/// A session holding `input`, with imports searched for in `search_dirs`.
+fn open_session(input: &Path) -> Step<(Compiler, String)> {
+    let (source, name) = read_source(input)?;
+    let dirs = compiler::search_dirs(input);
+    let mut session = Compiler::new().with_resolver(compiler::directory_resolver(dirs));
+    session.add_source(&name, &source);
+    Ok((session, name))
+}
 
-        ast.modules.insert(module_name, module);
-    }
+fn read_source(input: &Path) -> Step<(String, String)> {
+    let name = input.to_string_lossy().to_string();
+    let source = fs::read_to_string(input).map_err(|e| {
+        CompileError::new(
+            perano_lang::ErrorKind::ModuleError,
+            format!("failed to read source file: {}", e),
+            name.clone(),
+            1,
+            1,
+        ).display();
+        cli::EXIT_IO_ERROR
+    })?;
+    Ok((source, name))
+}
 
-    Ok(())
+fn check(mut session: Compiler, name: &str) -> Step<ast::Program> {
+    session.check(name).map_err(report)
 }
 
-fn compile_nvm(ast: &ast::Program, output_file: &str) {
-    use std::io::Write;
+fn compile_frontend(input: &Path) -> Step<ast::Program> {
+    let (session, name) = open_session(input)?;
+    check(session, &name)
+}
 
-    let mut nvm_gen = nvm::NVMCodeGen::new();
-    let bytecode = nvm_gen.generate(ast);
+fn run(options: &Options) -> Step<()> {
+    let input = &options.inputs[0];
 
-    let mut file = fs::File::create(output_file).expect("Failed to create .nvm file");
-    file.write_all(&bytecode).expect("Failed to write NVM bytecode");
-}
+    if options.target == Target::Elf {
+        let dir = env::temp_dir().join(format!("perano-run-{}", process::id()));
+        if let Err(e) = fs::create_dir_all(&dir) {
+            eprintln!("Failed to create {}: {}", dir.display(), e);
+            return Err(cli::EXIT_IO_ERROR);
+        }
+        let executable = dir.join(input.file_stem().unwrap_or_default());
+        let ast = compile_frontend(input)?;
+        let built = compile_elf_direct(&ast, &executable.to_string_lossy());
+        let status = built.and_then(|_| {
+            process::Command::new(&executable).status().map_err(|e| {
+                eprintln!("Failed to run {}: {}", executable.display(), e);
+                cli::EXIT_IO_ERROR
+            })
+        });
+        let _ = fs::remove_dir_all(&dir);
+
+        let status = status?;
+        eprintln!("Program exited with code {}", status.code().unwrap_or(-1));
+        process::exit(status.code().unwrap_or(cli::EXIT_VM_ERROR));
+    }
 
-fn compile_nvm_asm(ast: &ast::Program, output_file: &str) {
-    use std::io::Write;
+    let bytecode = if input.extension().is_some_and(|ext| ext == "per") {
+        compiler::nvm_bytecode(&compile_frontend(input)?).map_err(|e| report(vec![e]))?
+    } else if is_nvm_asm(input) {
+        assemble(input)?
+    } else {
+        match fs::read(input) {
+            Ok(b) => b,
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", input.display(), e);
+                return Err(cli::EXIT_IO_ERROR);
+            }
+        }
+    };
 
-    let mut nvm_asm_gen = nvm::NVMAssemblyGenerator::new();
-    let asm_code = nvm_asm_gen.generate(ast);
+    let mut stdout = std::io::stdout();
+    let result = nvm::NVMInterpreter::new(&options.sandbox).run(&bytecode, &mut stdout);
+    let _ = std::io::Write::flush(&mut stdout);
 
-    let mut file = fs::File::create(output_file).expect("Failed to create .asm file");
-    file.write_all(asm_code.as_bytes()).expect("Failed to write NVM assembly");
+    match result {
+        Ok(code) => {
+            eprintln!("Program exited with code {}", code);
+            process::exit(code);
+        }
+        Err(e) => {
+            eprintln!("\x1b[1;31mnvm error\x1b[0m: {}", e);
+            Err(cli::EXIT_VM_ERROR)
+        }
+    }
 }
 
-fn compile_pe_with_c(ast: &ast::Program, output_file: &str) {
-    let mut c_gen = pe::c_codegen::CCodeGen::new();
-    let c_code = c_gen.generate(ast).expect("Failed to generate C code");
-    
-    if let Err(e) = c_gen.compile_c_code(&c_code, output_file) {
+fn compile_pe_with_c(ast: &ast::Program, output_file: &str) -> Step<()> {
+    let c_code = compiler::c_source(ast).map_err(|e| report(vec![e]))?;
+
+    if let Err(e) = pe::c_codegen::CCodeGen::new().compile_c_code(&c_code, output_file) {
         eprintln!("Failed to compile C code: {}", e);
         eprintln!("Make sure cl.exe is available (run from Developer Command Prompt)");
-        process::exit(1);
+        return Err(cli::EXIT_IO_ERROR);
     }
+    Ok(())
 }
 
-fn compile_elf_proper(ast: &ast::Program, output_file: &str) {
-    use std::io::Write;
+fn compile_elf_direct(ast: &ast::Program, output_file: &str) -> Step<()> {
+    let image = compiler::elf_executable(ast).map_err(|e| report(vec![e]))?;
+    write_output(output_file, &image)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Err(e) = fs::set_permissions(output_file, fs::Permissions::from_mode(0o755)) {
+            eprintln!("Failed to make {} executable: {}", output_file, e);
+            return Err(cli::EXIT_IO_ERROR);
+        }
+    }
+    Ok(())
+}
 
-    let mut asm_gen = elf::AsmGenerator::new();
-    let asm_code = asm_gen.generate(ast);
+fn compile_elf_proper(ast: &ast::Program, output_file: &str) -> Step<()> {
+    let asm_code = compiler::elf_assembly(ast, false).map_err(|e| report(vec![e]))?;
 
     let asm_file = format!("{}.s", output_file);
-    let mut file = fs::File::create(&asm_file).expect("Failed to create .s file");
-    file.write_all(asm_code.as_bytes()).expect("Failed to write assembly");
+    write_output(&asm_file, asm_code.as_bytes())?;
 
     let status = process::Command::new("gcc")
         .arg("-o")
@@ -256,18 +359,18 @@ fn compile_elf_proper(ast: &ast::Program, output_file: &str) {
     match status {
         Ok(s) if s.success() => {
             let _ = fs::remove_file(&asm_file);
-            println!("Compilation successful: {}", output_file);
+            Ok(())
         }
         Ok(s) => {
             eprintln!("GCC failed with exit code: {:?}", s.code());
             eprintln!("Assembly file kept at: {}", asm_file);
-            process::exit(1);
+            Err(cli::EXIT_IO_ERROR)
         }
         Err(e) => {
             eprintln!("Failed to run gcc: {}", e);
             eprintln!("Make sure gcc is installed");
             eprintln!("Assembly file kept at: {}", asm_file);
-            process::exit(1);
+            Err(cli::EXIT_IO_ERROR)
         }
     }
 }
@@ -0,0 +1,250 @@
+//! NVM assembly to bytecode, for `.asm` files and `asm { }` blocks.
+//!
+//! A line holds any number of `label:` definitions followed by an
+//! instruction or a directive; `;` starts a comment. Instructions are the
+//! mnemonics of `opcodes::OPCODES`. Operands are numbers (decimal or `0x`
+//! hex), labels wherever a 32-bit value or address goes, slots 0 to 255 and
+//! syscall names or numbers. The directives are:
+//!
+//! | directive          | emits                                    |
+//! |--------------------|------------------------------------------|
+//! | `.NVM0`            | the header every NVM image starts with   |
+//! | `.byte 1, 0x2a`    | bytes                                    |
+//! | `.word -1, 70000`  | 32-bit values, little-endian like memory |
+//! | `.string "hi\n"`   | the bytes of a string and a NUL          |
+//! | `.zero 16`         | that many zero bytes                     |
+//!
+//! Label addresses count from the start of the image, header included.
+
+use crate::error::{CompileError, ErrorKind};
+use super::opcodes::{self, Operand};
+use super::syscalls;
+use std::collections::HashMap;
+
+/// What a line of assembly turns into.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Piece {
+    Label(String),
+    Bytes(Vec<u8>),
+    /// The big-endian address of a label, as an instruction operand.
+    Ref(String),
+}
+
+/// A problem in a line, at a 1-based column.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineError {
+    pub column: usize,
+    pub message: String,
+}
+
+fn line_error(column: usize, message: String) -> LineError {
+    LineError { column, message }
+}
+
+/// Assembles a whole file, which has to start with the `.NVM0` header.
+pub fn assemble(source: &str, file: &str) -> Result<Vec<u8>, CompileError> {
+    let error = |kind, message, line: usize, column| {
+        CompileError::new(kind, message, file.to_string(), line, column).with_source(source)
+    };
+
+    let mut lines = Vec::new();
+    let mut labels = HashMap::new();
+    let mut size = 0;
+    for (index, line) in source.lines().enumerate() {
+        let pieces = parse_line(line).map_err(|e| error(ErrorKind::ParserError, e.message, index + 1, e.column))?;
+        for piece in &pieces {
+            match piece {
+                Piece::Label(name) => {
+                    if labels.insert(name.clone(), size as u32).is_some() {
+                        let column = line.find(name.as_str()).map_or(1, |i| i + 1);
+                        return Err(error(ErrorKind::CodeGenError, format!("label '{}' is defined twice", name), index + 1, column));
+                    }
+                }
+                Piece::Bytes(bytes) => size += bytes.len(),
+                Piece::Ref(_) => size += 4,
+            }
+        }
+        lines.push(pieces);
+    }
+
+    let mut bytecode = Vec::with_capacity(size);
+    for (index, pieces) in lines.into_iter().enumerate() {
+        for piece in pieces {
+            match piece {
+                Piece::Label(_) => {}
+                Piece::Bytes(bytes) => bytecode.extend_from_slice(&bytes),
+                Piece::Ref(name) => match labels.get(&name) {
+                    Some(address) => bytecode.extend_from_slice(&address.to_be_bytes()),
+                    None => {
+                        let line = source.lines().nth(index).unwrap_or("");
+                        let column = line.find(name.as_str()).map_or(1, |i| i + 1);
+                        return Err(error(ErrorKind::CodeGenError, format!("undefined label '{}'", name), index + 1, column));
+                    }
+                },
+            }
+        }
+    }
+
+    if !bytecode.starts_with(b"NVM0") {
+        return Err(error(ErrorKind::CodeGenError, "NVM images start with the .NVM0 header".to_string(), 1, 1));
+    }
+    Ok(bytecode)
+}
+
+/// Parses one line of assembly.
+pub fn parse_line(line: &str) -> Result<Vec<Piece>, LineError> {
+    let code = strip_comment(line);
+    let mut pieces = Vec::new();
+    let mut pos = 0;
+    loop {
+        pos += code[pos..].len() - code[pos..].trim_start().len();
+        let rest = &code[pos..];
+        let word_len = rest.find(|c: char| c.is_whitespace() || c == ':').unwrap_or(rest.len());
+        let (word, after) = rest.split_at(word_len);
+        if after.starts_with(':') {
+            if !is_label(word) {
+                return Err(line_error(pos + 1, format!("'{}' is not a valid label", word)));
+            }
+            pieces.push(Piece::Label(word.to_string()));
+            pos += word_len + 1;
+            continue;
+        }
+        if word.is_empty() {
+            return Ok(pieces);
+        }
+
+        let operand_pos = pos + word_len + (after.len() - after.trim_start().len());
+        let operand = after.trim();
+        if word.starts_with('.') {
+            pieces.push(Piece::Bytes(directive(word, pos, operand, operand_pos)?));
+            return Ok(pieces);
+        }
+
+        let Some(op) = opcodes::by_mnemonic(word) else {
+            return Err(line_error(pos + 1, format!("unknown instruction '{}'", word)));
+        };
+        pieces.push(Piece::Bytes(vec![op.byte]));
+        let at = |message: String| line_error(operand_pos + 1, message);
+        match (op.operand, operand) {
+            (Operand::None, "") => {}
+            (Operand::None, _) => return Err(at(format!("'{}' takes no operand", op.mnemonic))),
+            (_, "") => return Err(line_error(pos + 1, format!("'{}' needs an operand", op.mnemonic))),
+            (Operand::Imm32 | Operand::Address, _) if is_label(operand) => pieces.push(Piece::Ref(operand.to_string())),
+            (Operand::Imm32 | Operand::Address, _) => match number(operand) {
+                Some(n) if (i32::MIN as i64..=u32::MAX as i64).contains(&n) => {
+                    pieces.push(Piece::Bytes((n as i32).to_be_bytes().to_vec()));
+                }
+                _ => return Err(at(format!("'{}' is neither a 32-bit number nor a label", operand))),
+            },
+            (Operand::Slot, _) => match number(operand) {
+                Some(n) if (0..=255).contains(&n) => pieces.push(Piece::Bytes(vec![n as u8])),
+                _ => return Err(at(format!("'{}' is not a local slot (0 to 255)", operand))),
+            },
+            (Operand::Syscall, _) => {
+                let syscall = match number(operand) {
+                    Some(n) => u8::try_from(n).ok(),
+                    None => syscalls::by_name(operand).map(|s| s.number),
+                };
+                match syscall {
+                    Some(n) => pieces.push(Piece::Bytes(vec![n])),
+                    None => return Err(at(format!("unknown syscall '{}'", operand))),
+                }
+            }
+        }
+        return Ok(pieces);
+    }
+}
+
+/// The bytes of a directive; `name_pos` and `operand_pos` are where the
+/// name and its operands start in the line.
+fn directive(name: &str, name_pos: usize, operand: &str, operand_pos: usize) -> Result<Vec<u8>, LineError> {
+    let at = |message: String| line_error(operand_pos + 1, message);
+    let values = || operand.split(',').map(str::trim).map(|value| (value, number(value)));
+    match name.to_lowercase().as_str() {
+        ".nvm0" if operand.is_empty() => Ok(b"NVM0".to_vec()),
+        ".nvm0" => Err(at(".NVM0 takes no operand".to_string())),
+        ".byte" => values().map(|(value, n)| match n {
+            Some(n) if (-128..=255).contains(&n) => Ok(n as u8),
+            _ => Err(at(format!("'{}' is not a byte", value))),
+        }).collect(),
+        ".word" => values().try_fold(Vec::new(), |mut bytes, (value, n)| match n {
+            Some(n) if (i32::MIN as i64..=u32::MAX as i64).contains(&n) => {
+                bytes.extend_from_slice(&(n as i32).to_le_bytes());
+                Ok(bytes)
+            }
+            _ => Err(at(format!("'{}' is not a 32-bit number", value))),
+        }),
+        ".string" => {
+            let mut bytes = string(operand).ok_or_else(|| at(format!("{} is not a string literal", operand)))?;
+            bytes.push(0);
+            Ok(bytes)
+        }
+        ".zero" => match number(operand) {
+            Some(n) if n >= 0 => Ok(vec![0; n as usize]),
+            _ => Err(at(format!("'{}' is not a byte count", operand))),
+        },
+        _ => Err(line_error(name_pos + 1, format!("unknown directive '{}'", name))),
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+pub fn is_label(word: &str) -> bool {
+    let mut chars = word.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+        None if digits.starts_with(|c: char| c.is_ascii_digit()) => digits.parse().ok()?,
+        None => return None,
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// The bytes of a quoted string with `\n`, `\t`, `\r`, `\0`, `\\`, `\"` and
+/// `\xNN` escapes.
+fn string(text: &str) -> Option<Vec<u8>> {
+    let inner = text.strip_prefix('"')?.strip_suffix('"')?;
+    let mut bytes = Vec::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return None,
+            '\\' => match chars.next()? {
+                'n' => bytes.push(b'\n'),
+                't' => bytes.push(b'\t'),
+                'r' => bytes.push(b'\r'),
+                '0' => bytes.push(0),
+                '\\' => bytes.push(b'\\'),
+                '"' => bytes.push(b'"'),
+                'x' => {
+                    let hex: String = chars.by_ref().take(2).collect();
+                    bytes.push(u8::from_str_radix(&hex, 16).ok().filter(|_| hex.len() == 2)?);
+                }
+                _ => return None,
+            },
+            c => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    Some(bytes)
+}
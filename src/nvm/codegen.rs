@@ -1,73 +1,67 @@
 use crate::ast::*;
+use crate::error::{CompileError, ErrorKind};
+use super::assembler::{self, LineError, Piece};
+use super::frames::FrameLayout;
+use super::layout::LOCALS_BASE;
+use super::opcodes::*;
+use super::syscalls::{SYSCALL_EXIT, SYSCALL_GET_LOCAL_ADDR, SYSCALL_PRINT};
+use crate::typechecker::{compute_struct_layouts, expression_type, operand_type, FieldLayout, StructLayout, Type, EVAL_NOT_FOLDED};
 use std::collections::HashMap;
-const PUSH32: u8 = 0x02;
-const POP: u8 = 0x04;
-const SWAP: u8 = 0x06;
-
-const ADD: u8 = 0x10;
-const SUB: u8 = 0x11;
-const MUL: u8 = 0x12;
-const DIV: u8 = 0x13;
-const MOD: u8 = 0x14;
-
-const EQ: u8 = 0x21;
-const NEQ: u8 = 0x22;
-const GT: u8 = 0x23;
-const LT: u8 = 0x24;
-
-const JMP32: u8 = 0x30;
-const JZ32: u8 = 0x31;
-const JNZ32: u8 = 0x32;
-const CALL32: u8 = 0x33;
-const RET: u8 = 0x34;
-
-const LOAD: u8 = 0x40;
-const STORE: u8 = 0x41;
-const LOAD_ABS: u8 = 0x44;
-const STORE_ABS: u8 = 0x45;
-
-const SYSCALL: u8 = 0x50;
-
-const SYSCALL_EXIT: u8 = 0x00;
-const SYSCALL_PRINT: u8 = 0x0F;
-const SYSCALL_EXEC: u8 = 0x01;
-const SYSCALL_OPEN: u8 = 0x02;
-const SYSCALL_READ: u8 = 0x03;
-const SYSCALL_WRITE: u8 = 0x04;
-const SYSCALL_CREATE: u8 = 0x05;
-const SYSCALL_DELETE: u8 = 0x06;
-const SYSCALL_CAP_CHECK: u8 = 0x07;
-const SYSCALL_CAP_SPAWN: u8 = 0x08;
-const SYSCALL_MSG_SEND: u8 = 0x0A;
-const SYSCALL_MSG_RECEIVE: u8 = 0x0B;
-const SYSCALL_PORT_IN_BYTE: u8 = 0x0C;
-const SYSCALL_PORT_OUT_BYTE: u8 = 0x0D;
-const SYSCALL_GET_LOCAL_ADDR: u8 = 0x0E;
 
 pub struct NVMCodeGen {
     bytecode: Vec<u8>,
     labels: HashMap<String, u32>,
     label_patches: Vec<(u32, String)>,
+    /// The file and statement of the first asm block that refers to each
+    /// label, for reporting labels that are never defined.
+    asm_label_uses: HashMap<String, (String, Span)>,
     local_vars: HashMap<String, u8>,
+    var_types: HashMap<String, Type>,
+    structs: HashMap<String, StructLayout>,
+    frames: FrameLayout,
     next_local: u8,
     loop_stack: Vec<(String, String)>,
     current_function: String,
+    /// Source file of the function being generated, for errors.
+    file: String,
     string_literals: Vec<(String, String)>,
+    /// Where the string literals after the code start.
+    code_end: usize,
     compile_time_strings: HashMap<String, String>,
+    /// Whether the string runtime in `strings.asm` has to be appended.
+    uses_strings: bool,
+    /// Whether the integer runtime in `integers.asm` has to be appended; the
+    /// string runtime needs it too.
+    uses_integers: bool,
+    #[allow(dead_code)]
     vga_cursor: u32,
 }
 
+impl Default for NVMCodeGen {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl NVMCodeGen {
     pub fn new() -> Self {
         Self {
             bytecode: Vec::new(),
             labels: HashMap::new(),
             label_patches: Vec::new(),
+            asm_label_uses: HashMap::new(),
             local_vars: HashMap::new(),
+            var_types: HashMap::new(),
+            structs: HashMap::new(),
+            frames: FrameLayout::default(),
             next_local: 0,
             loop_stack: Vec::new(),
             current_function: String::new(),
+            file: String::new(),
             string_literals: Vec::new(),
+            uses_strings: false,
+            uses_integers: false,
+            code_end: 0,
             compile_time_strings: HashMap::new(),
             vga_cursor: 0xB8000 + (18 * 160),
         }
@@ -75,9 +69,9 @@ impl NVMCodeGen {
     
     fn has_return_or_exit(&self, stmts: &[Statement]) -> bool {
         for stmt in stmts {
-            match stmt {
-                Statement::Return(_) => return true,
-                Statement::InlineAsm { parts } => {
+            match &stmt.kind {
+                StatementKind::Return(_) => return true,
+                StatementKind::InlineAsm { parts, .. } => {
                     for part in parts {
                         if let crate::ast::AsmPart::Literal(s) = part {
                             if s.contains("syscall") && s.contains("exit") {
@@ -86,7 +80,7 @@ impl NVMCodeGen {
                         }
                     }
                 }
-                Statement::If { then_body, else_body, .. } => {
+                StatementKind::If { then_body, else_body, .. } => {
                     if self.has_return_or_exit(then_body) {
                         return true;
                     }
@@ -96,27 +90,26 @@ impl NVMCodeGen {
                         }
                     }
                 }
-                Statement::For { body, .. } => {
-                    if self.has_return_or_exit(body) {
-                        return true;
-                    }
-                }
+                StatementKind::For { body, .. } if self.has_return_or_exit(body) => return true,
                 _ => {}
             }
         }
         false
     }
 
-    pub fn generate(&mut self, program: &Program) -> Vec<u8> {
-        self.bytecode.extend_from_slice(&[b'N', b'V', b'M', b'0']);
+    pub fn generate(&mut self, program: &Program) -> Result<Vec<u8>, CompileError> {
+        self.bytecode.extend_from_slice(b"NVM0");
+        self.structs = compute_struct_layouts(&program.structs).unwrap_or_default();
+        self.frames = FrameLayout::new(program)?;
 
+        self.file = program.file.clone();
         if let Some(main_func) = program.functions.iter().find(|f| f.name == "main") {
-            self.generate_function(main_func, program);
+            self.generate_function(main_func, program)?;
         }
 
-        for func in &program.functions {
+        for func in program.functions.iter().filter(|f| f.external.is_none()) {
             if func.name != "main" {
-                self.generate_function(func, program);
+                self.generate_function(func, program)?;
             }
         }
 
@@ -124,79 +117,204 @@ impl NVMCodeGen {
             if module_name == "stdio" {
                 continue;
             }
-            for func in &module.functions {
-                if func.is_exported {
-                    let full_name = format!("{}_{}", module.name, func.name);
-                    self.generate_module_function(func, &full_name, program);
-                }
+            let compiled = module.functions.iter()
+                .filter(|f| f.external.is_none() && !is_string_builtin(module_name, &f.name));
+            for func in compiled {
+                let full_name = format!("{}_{}", module.name, func.name);
+                self.generate_module_function(func, &full_name, &module.file, program)?;
             }
         }
 
-        if program.modules.contains_key("stdio") {
+        // __print_int hands negative numbers to __print_uint.
+        if program.modules.contains_key("stdio") || self.uses_integers || self.uses_strings {
             self.generate_print_int_vga_helper();
+            self.uses_integers = true;
+        }
+        if self.uses_strings {
+            self.emit_runtime("strings.asm", include_str!("strings.asm"))?;
+        }
+        if self.uses_integers {
+            self.emit_runtime("integers.asm", include_str!("integers.asm"))?;
         }
 
+        if self.uses_strings {
+            self.string_literals.push(("__out_of_memory".to_string(), "out of memory\n".to_string()));
+        }
+        self.code_end = self.bytecode.len();
         self.emit_string_literals();
-        self.patch_labels();
+        if self.uses_strings {
+            self.add_label("__heap_limit");
+            self.bytecode.extend_from_slice(&(LOCALS_BASE as u32).to_le_bytes());
+            self.add_label("__heap");
+        }
+        self.patch_labels(&program.file)?;
 
-        self.bytecode.clone()
+        Ok(self.bytecode.clone())
     }
 
-    fn generate_function(&mut self, func: &Function, program: &Program) {
+    /// The address of every label in the generated bytecode.
+    pub fn labels(&self) -> &HashMap<String, u32> {
+        &self.labels
+    }
+
+    /// The operands that hold label addresses, by offset.
+    pub fn label_refs(&self) -> &[(u32, String)] {
+        &self.label_patches
+    }
+
+    /// Where the code ends and the string literals begin.
+    pub fn code_end(&self) -> usize {
+        self.code_end
+    }
+
+    fn generate_function(&mut self, func: &Function, program: &Program) -> Result<(), CompileError> {
         self.current_function = func.name.clone();
         self.local_vars.clear();
+        self.var_types.clear();
         self.compile_time_strings.clear();
-        self.next_local = 0;
+        self.next_local = self.frames.frame(&func.name).base;
 
         let func_label = format!("func_{}", func.name);
         self.add_label(&func_label);
 
-        for param in &func.params {
-            self.local_vars.insert(param.name.clone(), self.next_local);
-            self.next_local += 1;
-        }
+        self.store_params(func)?;
 
         for stmt in &func.body {
-            self.generate_statement(stmt, program);
+            self.generate_statement(stmt, program)?;
         }
 
-        if func.name == "main" && !self.has_return_or_exit(&func.body) {
-            self.emit_push32(0);
-            self.emit_byte(SYSCALL);
-            self.emit_byte(SYSCALL_EXIT);
+        if func.name == "main" {
+            if !self.has_return_or_exit(&func.body) {
+                self.emit_push32(0);
+                self.emit_byte(SYSCALL);
+                self.emit_byte(SYSCALL_EXIT);
+            }
+            self.emit_byte(RET);
+        } else {
+            self.emit_return(false);
         }
-        
-        self.emit_byte(RET);
+        Ok(())
     }
 
-    fn generate_module_function(&mut self, func: &Function, full_name: &str, program: &Program) {
+    fn generate_module_function(&mut self, func: &Function, full_name: &str, file: &str, program: &Program) -> Result<(), CompileError> {
         self.current_function = full_name.to_string();
+        self.file = file.to_string();
         self.local_vars.clear();
-        self.next_local = 0;
+        self.var_types.clear();
+        self.next_local = self.frames.frame(full_name).base;
 
         let func_label = format!("func_{}", full_name);
         self.add_label(&func_label);
 
+        self.store_params(func)?;
+
+        for stmt in &func.body {
+            self.generate_statement(stmt, program)?;
+        }
+
+        self.emit_return(false);
+        Ok(())
+    }
+
+    /// Moves the arguments from under the return address into the first
+    /// slots of the frame.
+    fn store_params(&mut self, func: &Function) -> Result<(), CompileError> {
+        if let Some(return_type) = &func.return_type {
+            self.check_cell_width(&Type::resolve(return_type, &self.structs), func.span)?;
+        }
         for param in &func.params {
+            let ty = Type::resolve(&param.param_type, &self.structs);
+            self.check_cell_width(&ty, param.span)?;
             self.local_vars.insert(param.name.clone(), self.next_local);
+            self.var_types.insert(param.name.clone(), ty);
+            self.emit_byte(SWAP);
+            self.emit_byte(STORE);
+            self.emit_byte(self.next_local);
             self.next_local += 1;
         }
+        Ok(())
+    }
 
-        for stmt in &func.body {
-            self.generate_statement(stmt, program);
+    /// Returns the value on top of the stack, or 0 without one, leaving it
+    /// where the return address was.
+    fn emit_return(&mut self, has_value: bool) {
+        if !has_value {
+            self.emit_push32(0);
         }
-
+        self.emit_byte(SWAP);
         self.emit_byte(RET);
     }
 
-    fn generate_statement(&mut self, stmt: &Statement, program: &Program) {
-        match stmt {
-            Statement::VarDecl { name, var_type: _, value } => {
+    /// Calls `func_<function>` with `args` pushed right to left. If the
+    /// callee can re-enter this function, the locals declared so far are
+    /// kept on the stack across the call.
+    fn emit_call(&mut self, function: &str, args: &[Expression], program: &Program) -> Result<(), CompileError> {
+        let saved = if self.frames.reenters(&self.current_function, function) {
+            self.frames.frame(&self.current_function).base..self.next_local
+        } else {
+            0..0
+        };
+        for slot in saved.clone() {
+            self.emit_byte(LOAD);
+            self.emit_byte(slot);
+        }
+
+        for arg in args.iter().rev() {
+            self.generate_expression(arg, program)?;
+        }
+        self.emit_byte(CALL32);
+        self.emit_label_ref(&format!("func_{}", function));
+
+        for slot in saved.rev() {
+            self.emit_byte(SWAP);
+            self.emit_byte(STORE);
+            self.emit_byte(slot);
+        }
+        Ok(())
+    }
+
+    /// Makes the syscall an `extern "novaria"` function is bound to. The
+    /// arguments are pushed right to left so the first is popped first; a
+    /// function without a return type yields 0 like any other call.
+    fn emit_syscall(&mut self, number: u8, returns: bool, args: &[Expression], program: &Program) -> Result<(), CompileError> {
+        for arg in args.iter().rev() {
+            self.generate_expression(arg, program)?;
+        }
+        self.emit_byte(SYSCALL);
+        self.emit_byte(number);
+        if !returns {
+            self.emit_push32(0);
+        }
+        Ok(())
+    }
+
+    fn generate_statement(&mut self, stmt: &Statement, program: &Program) -> Result<(), CompileError> {
+        match &stmt.kind {
+            StatementKind::VarDecl { name, var_type, value } => {
+                let ty = match var_type {
+                    Some(t) => Type::resolve(t, &self.structs),
+                    None => value.as_ref().and_then(|v| self.static_type(v)).unwrap_or(Type::Unknown),
+                };
+                self.check_cell_width(&ty, stmt.span)?;
+                self.var_types.insert(name.clone(), ty.clone());
+
+                if let (Type::Struct(struct_name), None) = (&ty, value) {
+                    let cells = self.structs.get(struct_name).map(|s| s.cells).unwrap_or(0);
+                    self.local_vars.insert(name.clone(), self.next_local);
+                    for _ in 0..cells {
+                        self.emit_push32(0);
+                        self.emit_byte(STORE);
+                        self.emit_byte(self.next_local);
+                        self.next_local += 1;
+                    }
+                    return Ok(());
+                }
+
                 if let Some(init_expr) = value {
-                    if let Expression::String(s) = init_expr {
+                    if let ExpressionKind::String(s) = &init_expr.kind {
                         self.compile_time_strings.insert(name.clone(), s.clone());
                     }
-                    self.generate_expression(init_expr, program);
+                    self.generate_expression(init_expr, program)?;
                 } else {
                     self.emit_push32(0);
                 }
@@ -209,20 +327,16 @@ impl NVMCodeGen {
                 self.emit_byte(local_index);
             }
 
-            Statement::Assignment { name, value } => {
-                self.generate_expression(value, program);
+            StatementKind::Assignment { name, value } => {
+                self.generate_expression(value, program)?;
                 
-                if let Some(&local_index) = self.local_vars.get(name) {
-                    self.emit_byte(STORE);
-                    self.emit_byte(local_index);
-                } else {
-                    
-                    panic!("Variable not found: {}", name);
-                }
+                let local_index = self.variable(name, stmt.span)?;
+                self.emit_byte(STORE);
+                self.emit_byte(local_index);
             }
 
-            Statement::If { condition, then_body, else_body } => {
-                self.generate_expression(condition, program);
+            StatementKind::If { condition, then_body, else_body } => {
+                self.generate_expression(condition, program)?;
                 
                 let else_label = self.generate_label("else");
                 let end_label = self.generate_label("endif");
@@ -231,7 +345,7 @@ impl NVMCodeGen {
                 self.emit_label_ref(&else_label);
                 
                 for stmt in then_body {
-                    self.generate_statement(stmt, program);
+                    self.generate_statement(stmt, program)?;
                 }
                 
                 self.emit_byte(JMP32);
@@ -241,17 +355,17 @@ impl NVMCodeGen {
                 
                 if let Some(else_stmts) = else_body {
                     for stmt in else_stmts {
-                        self.generate_statement(stmt, program);
+                        self.generate_statement(stmt, program)?;
                     }
                 }
                 
                 self.add_label(&end_label);
             }
 
-            Statement::For { init, condition, post, body } => {
+            StatementKind::For { init, condition, post, body } => {
                 
                 if let Some(init_stmt) = init {
-                    self.generate_statement(init_stmt, program);
+                    self.generate_statement(init_stmt, program)?;
                 }
                 
                 let loop_start = self.generate_label("for_start");
@@ -264,21 +378,21 @@ impl NVMCodeGen {
                 
                 
                 if let Some(cond) = condition {
-                    self.generate_expression(cond, program);
+                    self.generate_expression(cond, program)?;
                     self.emit_byte(JZ32);
                     self.emit_label_ref(&loop_end);
                 }
                 
                 
                 for stmt in body {
-                    self.generate_statement(stmt, program);
+                    self.generate_statement(stmt, program)?;
                 }
                 
                 self.add_label(&loop_continue);
                 
                 
                 if let Some(post_stmt) = post {
-                    self.generate_statement(post_stmt, program);
+                    self.generate_statement(post_stmt, program)?;
                 }
                 
                 self.emit_byte(JMP32);
@@ -288,19 +402,41 @@ impl NVMCodeGen {
                 self.loop_stack.pop();
             }
 
-            Statement::Return(value) => {
+            StatementKind::Return(value) => {
                 if let Some(expr) = value {
-                    self.generate_expression(expr, program);
+                    self.generate_expression(expr, program)?;
+                }
+                if self.current_function == "main" {
+                    if value.is_none() {
+                        self.emit_push32(0);
+                    }
+                    self.emit_byte(SYSCALL);
+                    self.emit_byte(SYSCALL_EXIT);
+                } else {
+                    self.emit_return(value.is_some());
+                }
+            }
+
+            StatementKind::Break => {
+                if let Some((loop_end, _)) = self.loop_stack.last().cloned() {
+                    self.emit_byte(JMP32);
+                    self.emit_label_ref(&loop_end);
                 }
-                self.emit_byte(RET);
             }
 
-            Statement::Expression(expr) => {
-                self.generate_expression(expr, program);
+            StatementKind::Continue => {
+                if let Some((_, loop_continue)) = self.loop_stack.last().cloned() {
+                    self.emit_byte(JMP32);
+                    self.emit_label_ref(&loop_continue);
+                }
+            }
+
+            StatementKind::Expression(expr) => {
+                self.generate_expression(expr, program)?;
                 self.emit_byte(POP);
             }
 
-            Statement::InlineAsm { parts } => {
+            StatementKind::InlineAsm { parts, .. } => {
                 use crate::ast::AsmPart;
                 
                 let mut asm_text = String::new();
@@ -316,45 +452,78 @@ impl NVMCodeGen {
                             } else if let Some(&local_index) = self.local_vars.get(var_name) {
                                 asm_text.push_str(&format!("load {}\n", local_index));
                             } else {
-                                eprintln!("Warning: Variable '{}' not found in asm block", var_name);
+                                return Err(self.error(stmt.span, format!("asm block uses '{}', which is not a local variable", var_name)));
                             }
                         }
                     }
                 }
                 
                 for line in asm_text.lines() {
-                    let line = line.trim();
-                    if line.is_empty() || line.starts_with(';') {
-                        continue;
-                    }
-                    let code = if let Some(comment_pos) = line.find(';') {
-                        line[..comment_pos].trim()
-                    } else {
-                        line
-                    };
-                    if !code.is_empty() {
-                        self.emit_asm_instruction(code);
+                    let first_ref = self.label_patches.len();
+                    self.emit_asm_line(line)
+                        .map_err(|e| self.error(stmt.span, format!("{} in asm block: {}", e.message, line.trim())))?;
+                    for (_, label) in &self.label_patches[first_ref..] {
+                        self.asm_label_uses.entry(label.clone()).or_insert_with(|| (self.file.clone(), stmt.span));
                     }
                 }
             }
 
-            Statement::PointerAssignment { target, value } => {
-                self.generate_expression(target, program);
-                self.generate_expression(value, program);
+            StatementKind::PointerAssignment { target, value } => {
+                self.generate_expression(target, program)?;
+                self.generate_expression(value, program)?;
                 self.emit_byte(STORE_ABS);
             }
 
-            _ => {}
+            StatementKind::ArrayDecl { name, element_type, size, .. } => {
+                let elem = Type::resolve(element_type, &self.structs);
+                self.check_cell_width(&elem, stmt.span)?;
+                let cells = (element_size(&elem) * *size).div_ceil(4);
+                self.local_vars.insert(name.clone(), self.next_local);
+                for _ in 0..cells {
+                    self.emit_push32(0);
+                    self.emit_byte(STORE);
+                    self.emit_byte(self.next_local);
+                    self.next_local += 1;
+                }
+                self.var_types.insert(name.clone(), Type::Array(Box::new(elem), *size));
+            }
+
+            StatementKind::ArrayAssignment { name, index, value } => {
+                let elem = self.generate_element_address(name, index, stmt.span, program)?;
+                self.generate_expression(value, program)?;
+                if element_size(&elem) == 1 {
+                    self.emit_integer_call("__store8");
+                } else {
+                    self.emit_byte(STORE_ABS);
+                }
+            }
+
+            StatementKind::FieldAssignment { object, field, value } => {
+                if let Some(slot) = self.field_slot(object, field) {
+                    self.generate_expression(value, program)?;
+                    self.emit_byte(STORE);
+                    self.emit_byte(slot);
+                } else {
+                    self.generate_field_address(object, field, program)?;
+                    self.generate_expression(value, program)?;
+                    self.emit_byte(STORE_ABS);
+                }
+            }
         }
+        Ok(())
     }
 
-    fn generate_expression(&mut self, expr: &Expression, program: &Program) {
-        match expr {
-            Expression::Number(n) => {
+    #[allow(clippy::only_used_in_recursion)]
+    fn generate_expression(&mut self, expr: &Expression, program: &Program) -> Result<(), CompileError> {
+        if let Some(ty) = self.static_type(expr) {
+            self.check_cell_width(&ty, expr.span)?;
+        }
+        match &expr.kind {
+            ExpressionKind::Number(n) => {
                 self.emit_push32(*n as i32);
             }
 
-            Expression::String(s) => {
+            ExpressionKind::String(s) => {
                 let string_label = self.generate_label("str");
                 self.string_literals.push((string_label.clone(), s.clone()));
                 self.emit_push32(0);
@@ -362,7 +531,7 @@ impl NVMCodeGen {
                 self.label_patches.push((patch_pos as u32, string_label));
             }
 
-            Expression::TemplateString { parts } => {
+            ExpressionKind::TemplateString { parts } => {
                 use crate::ast::TemplateStringPart;
                 
                 for part in parts {
@@ -374,33 +543,56 @@ impl NVMCodeGen {
                                 self.emit_byte(SYSCALL_PRINT);
                             }
                         }
-                        TemplateStringPart::Expression { expr, format: _ } => {
-                            self.generate_expression(expr, program);
-                            self.emit_byte(CALL32);
-                            self.emit_label_ref("__print_int");
+                        TemplateStringPart::Expression { expr, format } => {
+                            self.generate_expression(expr, program)?;
+                            match (self.static_type(expr), format) {
+                                (Some(Type::String), _) => self.emit_string_call("__print_str"),
+                                (ty, Some(spec)) => self.emit_print_formatted(ty, spec),
+                                (ty, None) => self.emit_print_int(ty),
+                            }
                         }
                     }
                 }
                 self.emit_push32(0);
             }
 
-            Expression::Identifier(name) => {
-                if let Some(&local_index) = self.local_vars.get(name) {
-                    self.emit_byte(LOAD);
-                    self.emit_byte(local_index);
-                } else {
-                    panic!("Variable not found: {}", name);
-                }
+            ExpressionKind::Identifier(name) => {
+                let local_index = self.variable(name, expr.span)?;
+                self.emit_byte(LOAD);
+                self.emit_byte(local_index);
             }
 
-            Expression::Binary { op, left, right } => {
-                self.generate_expression(left, program);
-                self.generate_expression(right, program);
-                
+            ExpressionKind::Binary { op, left, right } => {
+                self.generate_expression(left, program)?;
+                self.generate_expression(right, program)?;
+
+                let strings = [left, right].iter().any(|side| self.static_type(side) == Some(Type::String));
+                if strings && matches!(op, BinaryOp::Equal | BinaryOp::NotEqual) {
+                    self.emit_string_call("__str_compare");
+                    self.emit_push32(0);
+                    self.emit_byte(if matches!(op, BinaryOp::Equal) { EQ } else { NEQ });
+                    return Ok(());
+                }
+
+                let ty = operand_type(left, right, &self.var_types, &self.structs).unwrap_or(Type::I64);
+                let comparison = matches!(op, BinaryOp::Less | BinaryOp::Greater | BinaryOp::LessEqual | BinaryOp::GreaterEqual);
+                if ty.is_unsigned() && comparison {
+                    self.emit_sign_bias();
+                }
+
                 match op {
                     BinaryOp::Add => self.emit_byte(ADD),
                     BinaryOp::Sub => self.emit_byte(SUB),
                     BinaryOp::Mul => self.emit_byte(MUL),
+                    BinaryOp::Div if ty.is_unsigned() => {
+                        self.emit_integer_call("__divmodu");
+                        self.emit_byte(POP);
+                    }
+                    BinaryOp::Mod if ty.is_unsigned() => {
+                        self.emit_integer_call("__divmodu");
+                        self.emit_byte(SWAP);
+                        self.emit_byte(POP);
+                    }
                     BinaryOp::Div => self.emit_byte(DIV),
                     BinaryOp::Mod => self.emit_byte(MOD),
                     BinaryOp::Equal => self.emit_byte(EQ),
@@ -417,18 +609,26 @@ impl NVMCodeGen {
                         self.emit_push32(0);
                         self.emit_byte(EQ);
                     }
+                    BinaryOp::Concat => self.emit_string_call("__str_concat"),
                     _ => {}
                 }
+
+                if matches!(op, BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod) {
+                    self.emit_wrap(&ty);
+                }
             }
 
-            Expression::Unary { op, operand } => {
-                self.generate_expression(operand, program);
+            ExpressionKind::Unary { op, operand } => {
+                self.generate_expression(operand, program)?;
                 
                 match op {
                     UnaryOp::Neg => {
                         self.emit_push32(0);
                         self.emit_byte(SWAP);
                         self.emit_byte(SUB);
+                        if let Some(ty) = self.static_type(operand) {
+                            self.emit_wrap(&ty);
+                        }
                     }
                     UnaryOp::Not => {
                         self.emit_push32(0);
@@ -437,235 +637,351 @@ impl NVMCodeGen {
                 }
             }
 
-            Expression::Call { function, args } => {
-                for arg in args.iter().rev() {
-                    self.generate_expression(arg, program);
+            ExpressionKind::Call { function, args } => {
+                match program.callee(None, function) {
+                    Some(Function { external: Some(External::Syscall(number)), return_type, .. }) => {
+                        self.emit_syscall(*number, return_type.is_some(), args, program)?;
+                    }
+                    _ => self.emit_call(function, args, program)?,
                 }
-                
-                let func_label = format!("func_{}", function);
-                self.emit_byte(CALL32);
-                self.emit_label_ref(&func_label);
             }
 
-            Expression::ModuleCall { module, function, args } => {
+            ExpressionKind::ModuleCall { module, function, args } => {
+                if let Some(Function { external: Some(External::Syscall(number)), return_type, .. }) = program.callee(Some(module), function) {
+                    self.emit_syscall(*number, return_type.is_some(), args, program)?;
+                    return Ok(());
+                }
+
                 if module == "stdio" {
                     match function.as_str() {
-                        "Print" => {
-                            if !args.is_empty() {
-                                if let Expression::String(s) = &args[0] {
-                                    for ch in s.as_bytes() {
-                                        self.emit_push32(*ch as i32);
-                                        self.emit_byte(SYSCALL);
-                                        self.emit_byte(SYSCALL_PRINT);
-                                    }
-                                    self.emit_push32(0);
-                                    return;
-                                } else {
-                                    self.generate_expression(&args[0], program);
-                                    self.emit_byte(CALL32);
-                                    self.emit_label_ref("__print_int");
-                                    self.emit_push32(0);
-                                    return;
+                        "Print" if !args.is_empty() => {
+                            if let ExpressionKind::String(s) = &args[0].kind {
+                                for ch in s.as_bytes() {
+                                    self.emit_push32(*ch as i32);
+                                    self.emit_byte(SYSCALL);
+                                    self.emit_byte(SYSCALL_PRINT);
+                                }
+                                self.emit_push32(0);
+                                return Ok(());
+                            } else {
+                                self.generate_expression(&args[0], program)?;
+                                self.emit_print_int(self.static_type(&args[0]));
+                                self.emit_push32(0);
+                                return Ok(());
+                            }
+                        }
+                        "Println" if !args.is_empty() => {
+                            if let ExpressionKind::String(s) = &args[0].kind {
+                                for ch in s.as_bytes() {
+                                    self.emit_push32(*ch as i32);
+                                    self.emit_byte(SYSCALL);
+                                    self.emit_byte(SYSCALL_PRINT);
                                 }
+                                self.emit_push32('\n' as i32);
+                                self.emit_byte(SYSCALL);
+                                self.emit_byte(SYSCALL_PRINT);
+                                self.emit_push32(0);
+                                return Ok(());
+                            } else if let ExpressionKind::TemplateString { .. } = &args[0].kind {
+                                self.generate_expression(&args[0], program)?;
+                                self.emit_push32('\n' as i32);
+                                self.emit_byte(SYSCALL);
+                                self.emit_byte(SYSCALL_PRINT);
+                                return Ok(());
+                            } else {
+                                self.generate_expression(&args[0], program)?;
+                                self.emit_print_int(self.static_type(&args[0]));
+                                self.emit_push32('\n' as i32);
+                                self.emit_byte(SYSCALL);
+                                self.emit_byte(SYSCALL_PRINT);
+                                self.emit_push32(0);
+                                return Ok(());
                             }
                         }
-                        "Println" => {
-                            if !args.is_empty() {
-                                if let Expression::String(s) = &args[0] {
+                        "PrintStr" | "PrintlnStr" if !args.is_empty() => {
+                            match &args[0].kind {
+                                ExpressionKind::String(s) => {
                                     for ch in s.as_bytes() {
                                         self.emit_push32(*ch as i32);
                                         self.emit_byte(SYSCALL);
                                         self.emit_byte(SYSCALL_PRINT);
                                     }
-                                    self.emit_push32('\n' as i32);
-                                    self.emit_byte(SYSCALL);
-                                    self.emit_byte(SYSCALL_PRINT);
                                     self.emit_push32(0);
-                                    return;
-                                } else if let Expression::TemplateString { .. } = &args[0] {
-                                    self.generate_expression(&args[0], program);
-                                    self.emit_push32('\n' as i32);
-                                    self.emit_byte(SYSCALL);
-                                    self.emit_byte(SYSCALL_PRINT);
-                                    return;
-                                } else {
-                                    self.generate_expression(&args[0], program);
-                                    self.emit_byte(CALL32);
-                                    self.emit_label_ref("__print_int");
-                                    self.emit_push32('\n' as i32);
-                                    self.emit_byte(SYSCALL);
-                                    self.emit_byte(SYSCALL_PRINT);
+                                }
+                                ExpressionKind::TemplateString { .. } => {
+                                    self.generate_expression(&args[0], program)?;
+                                }
+                                _ => {
+                                    self.generate_expression(&args[0], program)?;
+                                    self.emit_string_call("__print_str");
                                     self.emit_push32(0);
-                                    return;
                                 }
                             }
+                            if function == "PrintlnStr" {
+                                self.emit_push32('\n' as i32);
+                                self.emit_byte(SYSCALL);
+                                self.emit_byte(SYSCALL_PRINT);
+                            }
+                            return Ok(());
                         }
                         _ => {}
                     }
                 }
 
-                if module == "novaria" {
-                    match function.as_str() {
-                        "FileCreateStr" => {
-                            if args.len() >= 2 {
-                                if let (Expression::String(filename), Expression::String(content)) = (&args[0], &args[1]) {
-                                    self.emit_push32(content.len() as i32);
-                                    let _content_label = self.generate_label("str_content");
-                                    self.emit_push32(0);
-                                    let _content_patch_pos = self.bytecode.len() - 4;
-                                    let _filename_label = self.generate_label("str_filename");
-                                    self.emit_push32(0);
-                                    let _filename_patch_pos = self.bytecode.len() - 4;
-                                    self.emit_byte(SYSCALL);
-                                    self.emit_byte(SYSCALL_CREATE);
-                                    let skip_label = self.generate_label("skip_strings");
-                                    self.emit_byte(JMP32);
-                                    self.emit_label_ref(&skip_label);
-                                    let filename_pos = self.bytecode.len();
-                                    for ch in filename.as_bytes() {
-                                        self.emit_byte(*ch);
-                                    }
-                                    self.emit_byte(0);
-                                    let content_pos = self.bytecode.len();
-                                    for ch in content.as_bytes() {
-                                        self.emit_byte(*ch);
-                                    }
-                                    self.emit_byte(0);
-                                    let _filename_addr = (filename_pos + 0x100000) as i32;
-                                    let _content_addr = (content_pos + 0x100000) as i32;
-                                    self.add_label(&skip_label);
-                                    self.emit_push32(0);
-                                    return;
-                                }
-                            }
-                        }
-                        _ => {}
-                    }
-                    
-                    for arg in args.iter().rev() {
-                        self.generate_expression(arg, program);
+                if is_string_builtin(module, function) {
+                    for arg in args {
+                        self.generate_expression(arg, program)?;
                     }
                     match function.as_str() {
-                        "Exit" => {
-                            self.emit_byte(SYSCALL);
-                            self.emit_byte(SYSCALL_EXIT);
-                        }
-                        "Exec" => {
-                            self.emit_byte(SYSCALL);
-                            self.emit_byte(SYSCALL_EXEC);
-                        }
-                        "FileRead" => {
-                            self.emit_byte(SYSCALL);
-                            self.emit_byte(SYSCALL_READ);
-                        }
-                        "FileWrite" => {
-                            self.emit_byte(SYSCALL);
-                            self.emit_byte(SYSCALL_WRITE);
-                        }
-                        "FileCreate" => {
-                            self.emit_byte(SYSCALL);
-                            self.emit_byte(SYSCALL_CREATE);
-                        }
-                        "FileDelete" => {
-                            self.emit_byte(SYSCALL);
-                            self.emit_byte(SYSCALL_DELETE);
-                        }
-                        "CapCheck" => {
-                            self.emit_byte(SYSCALL);
-                            self.emit_byte(SYSCALL_CAP_CHECK);
-                        }
-                        "CapSpawn" => {
-                            self.emit_byte(SYSCALL);
-                            self.emit_byte(SYSCALL_CAP_SPAWN);
-                        }
-                        "MsgSend" => {
-                            self.emit_byte(SYSCALL);
-                            self.emit_byte(SYSCALL_MSG_SEND);
-                        }
-                        "MsgReceive" => {
-                            self.emit_byte(SYSCALL);
-                            self.emit_byte(SYSCALL_MSG_RECEIVE);
-                        }
-                        "PortInByte" => {
-                            self.emit_byte(SYSCALL);
-                            self.emit_byte(SYSCALL_PORT_IN_BYTE);
-                        }
-                        "PortOutByte" => {
-                            self.emit_byte(SYSCALL);
-                            self.emit_byte(SYSCALL_PORT_OUT_BYTE);
-                        }
-                        "CAP_FS_READ" => {
-                            self.emit_push32(1);
-                        }
-                        "CAP_FS_WRITE" => {
-                            self.emit_push32(2);
-                        }
-                        "CAP_FS_CREATE" => {
+                        "len" => {
                             self.emit_push32(4);
+                            self.emit_byte(SUB);
+                            self.emit_byte(LOAD_ABS);
                         }
-                        "CAP_FS_DELETE" => {
-                            self.emit_push32(8);
-                        }
-                        "CAP_DRV_ACCESS" => {
-                            self.emit_push32(16);
-                        }
-                        "CAP_CAPS_MGMT" => {
-                            self.emit_push32(32);
-                        }
-                        "CAP_ALL" => {
-                            self.emit_push32(65535);
-                        }
-                        _ => {
-                            let func_label = format!("func_{}_{}", module, function);
-                            self.emit_byte(CALL32);
-                            self.emit_label_ref(&func_label);
-                        }
+                        "compare" => self.emit_string_call("__str_compare"),
+                        "concat" => self.emit_string_call("__str_concat"),
+                        _ => self.emit_string_call("__str_slice"),
                     }
-                    return;
+                    return Ok(());
                 }
 
-                for arg in args.iter().rev() {
-                    self.generate_expression(arg, program);
-                }
+                self.emit_call(&format!("{}_{}", module, function), args, program)?;
+            }
 
-                let func_label = format!("func_{}_{}", module, function);
-                self.emit_byte(CALL32);
-                self.emit_label_ref(&func_label);
+            ExpressionKind::FieldAccess { object, field } => {
+                if let Some(slot) = self.field_slot(object, field) {
+                    self.emit_byte(LOAD);
+                    self.emit_byte(slot);
+                } else {
+                    self.generate_field_address(object, field, program)?;
+                    self.emit_byte(LOAD_ABS);
+                }
             }
 
-            Expression::AddressOf { operand } => {
-                if let Expression::Identifier(name) = operand.as_ref() {
-                    if let Some(&local_index) = self.local_vars.get(name) {
-                        self.emit_push32(local_index as i32);
-                        self.emit_byte(SYSCALL);
-                        self.emit_byte(SYSCALL_GET_LOCAL_ADDR);
-                    } else {
-                        panic!("Variable not found: {}", name);
-                    }
+            ExpressionKind::AddressOf { operand } => {
+                if let ExpressionKind::FieldAccess { object, field } = &operand.kind {
+                    self.generate_field_address(object, field, program)?;
+                } else if let ExpressionKind::Identifier(name) = &operand.kind {
+                    let local_index = self.variable(name, operand.span)?;
+                    self.emit_push32(local_index as i32);
+                    self.emit_byte(SYSCALL);
+                    self.emit_byte(SYSCALL_GET_LOCAL_ADDR);
                 } else {
-                    panic!("AddressOf only supports identifiers");
+                    return Err(self.error(expr.span, "'&' takes a variable or a field".to_string()));
                 }
             }
 
-            Expression::Deref { operand } => {
-                self.generate_expression(operand, program);
+            ExpressionKind::Deref { operand } => {
+                self.generate_expression(operand, program)?;
                 self.emit_byte(LOAD_ABS);
             }
 
-            Expression::Eval { instruction } => {
-                self.generate_expression(instruction, program);
-                
-                if let Expression::String(instr_str) = instruction.as_ref() {
-                    self.emit_asm_instruction(instr_str.trim());
+            ExpressionKind::ArrayAccess { name, index } => {
+                let elem = self.generate_element_address(name, index, expr.span, program)?;
+                if element_size(&elem) == 1 {
+                    self.emit_integer_call("__load8");
+                    self.emit_wrap(&elem);
                 } else {
-                    eprintln!("Warning: eval() with non-literal string not fully supported yet");
+                    self.emit_byte(LOAD_ABS);
                 }
             }
 
+            ExpressionKind::Cast { operand, target_type } => {
+                self.generate_expression(operand, program)?;
+                match Type::resolve(target_type, &self.structs) {
+                    Type::Bool => {
+                        self.emit_push32(0);
+                        self.emit_byte(NEQ);
+                    }
+                    ty => self.emit_wrap(&ty),
+                }
+            }
+
+            ExpressionKind::Eval { .. } => return Err(self.error(expr.span, EVAL_NOT_FOLDED.to_string())),
+
+            ExpressionKind::StringIndex { string, index } => {
+                self.generate_expression(string, program)?;
+                self.generate_expression(index, program)?;
+                self.emit_byte(ADD);
+                self.emit_integer_call("__load8");
+            }
+        }
+        Ok(())
+    }
+
+    /// The local slot of `name`, which a program that passed the checker
+    /// always has.
+    fn variable(&self, name: &str, span: Span) -> Result<u8, CompileError> {
+        match self.local_vars.get(name) {
+            Some(&slot) => Ok(slot),
+            None => Err(self.error(span, format!("unknown variable '{}'", name))),
+        }
+    }
+
+    fn error(&self, span: Span, message: String) -> CompileError {
+        CompileError::new(ErrorKind::CodeGenError, message, self.file.clone(), span.line, span.column)
+    }
+
+    /// Calls a helper of the string runtime, which its arguments are already
+    /// pushed for.
+    fn emit_string_call(&mut self, helper: &str) {
+        self.uses_strings = true;
+        self.emit_byte(CALL32);
+        self.emit_label_ref(helper);
+    }
+
+    fn static_type(&self, expr: &Expression) -> Option<Type> {
+        expression_type(expr, &self.var_types, &self.structs)
+    }
+
+    /// Rejects `u64`, as a cell holds 32 bits. `i64`, the type of untyped
+    /// integers, is kept and behaves like `i32`.
+    fn check_cell_width(&self, ty: &Type, span: Span) -> Result<(), CompileError> {
+        match ty {
+            Type::U64 => Err(self.error(span, "u64 needs 64-bit cells, which NVM does not have; use u32".to_string())),
+            Type::Array(elem, _) => self.check_cell_width(elem, span),
+            _ => Ok(()),
+        }
+    }
+
+    /// Calls a helper of the integer runtime in `integers.asm`.
+    fn emit_integer_call(&mut self, helper: &str) {
+        self.uses_integers = true;
+        self.emit_byte(CALL32);
+        self.emit_label_ref(helper);
+    }
+
+    /// Prints the integer on top of the stack, as unsigned for `u32`.
+    fn emit_print_int(&mut self, ty: Option<Type>) {
+        if ty == Some(Type::U32) {
+            self.emit_integer_call("__print_uint");
+        } else {
+            self.emit_byte(CALL32);
+            self.emit_label_ref("__print_int");
+        }
+    }
+
+    /// Prints the integer on top of the stack as `spec` asks, the way
+    /// `printf` would: in decimal unless it is hex, padded to its width.
+    fn emit_print_formatted(&mut self, ty: Option<Type>, spec: &FormatSpec) {
+        let (base, letters) = match spec.format_type {
+            FormatType::Hex => (16, 'a' as i32 - 10),
+            FormatType::HexUpper => (16, 'A' as i32 - 10),
+            _ => (10, 0),
+        };
+        self.emit_push32(base);
+        self.emit_push32(letters);
+        self.emit_push32(spec.width.unwrap_or(0) as i32);
+        self.emit_push32(spec.padding as i32);
+        let signed = base == 10 && !ty.is_some_and(|t| t.is_unsigned());
+        self.emit_integer_call(if signed { "__print_fmt_signed" } else { "__print_fmt" });
+    }
+
+    /// Moves both operands on top of the stack by 2^31, so that signed
+    /// comparison orders them as unsigned values.
+    fn emit_sign_bias(&mut self) {
+        for _ in 0..2 {
+            self.emit_push32(i32::MIN);
+            self.emit_byte(ADD);
+            self.emit_byte(SWAP);
+        }
+    }
+
+    /// Wraps the value on top of the stack to `ty`. Cells are 32 bits wide,
+    /// so only the 8-bit types need it.
+    fn emit_wrap(&mut self, ty: &Type) {
+        match ty {
+            Type::U8 => self.emit_low_byte(),
+            Type::I8 => {
+                self.emit_push32(128);
+                self.emit_byte(ADD);
+                self.emit_low_byte();
+                self.emit_push32(128);
+                self.emit_byte(SUB);
+            }
+            _ => {}
+        }
+    }
+
+    /// Replaces the value on top of the stack by its low byte. `mod` keeps
+    /// the sign of the dividend, so negative values need a second round.
+    fn emit_low_byte(&mut self) {
+        self.emit_push32(256);
+        self.emit_byte(MOD);
+        self.emit_push32(256);
+        self.emit_byte(ADD);
+        self.emit_push32(256);
+        self.emit_byte(MOD);
+    }
+
+    /// Pushes the address of `name[index]` and returns the element type.
+    /// Arrays live in consecutive local slots with byte-sized elements packed.
+    fn generate_element_address(&mut self, name: &str, index: &Expression, span: Span, program: &Program) -> Result<Type, CompileError> {
+        let base = self.variable(name, span)?;
+        let elem = match self.var_types.get(name) {
+            Some(Type::Array(elem, _)) => (**elem).clone(),
+            _ => Type::Unknown,
+        };
+
+        self.emit_push32(base as i32);
+        self.emit_byte(SYSCALL);
+        self.emit_byte(SYSCALL_GET_LOCAL_ADDR);
+        self.generate_expression(index, program)?;
+        if element_size(&elem) != 1 {
+            self.emit_push32(element_size(&elem) as i32);
+            self.emit_byte(MUL);
+        }
+        self.emit_byte(ADD);
+        Ok(elem)
+    }
+
+    fn field_layout(&self, object: &Expression, field: &str) -> Option<(Type, FieldLayout)> {
+        let object_type = self.static_type(object)?;
+        let layout = self.structs.get(object_type.struct_name()?)?.field(field)?.clone();
+        Some((object_type, layout))
+    }
+
+    /// Local slot holding `object.field` when the struct lives directly in
+    /// this function's locals, so no address arithmetic is needed.
+    fn field_slot(&self, object: &Expression, field: &str) -> Option<u8> {
+        let (object_type, layout) = self.field_layout(object, field)?;
+        if !matches!(object_type, Type::Struct(_)) {
+            return None;
+        }
+        let base = match &object.kind {
+            ExpressionKind::Identifier(name) => *self.local_vars.get(name)?,
+            ExpressionKind::FieldAccess { object: inner, field: inner_field } => self.field_slot(inner, inner_field)?,
+            _ => return None,
+        };
+        Some(base + layout.cell as u8)
+    }
+
+    fn generate_field_address(&mut self, object: &Expression, field: &str, program: &Program) -> Result<(), CompileError> {
+        let Some((object_type, layout)) = self.field_layout(object, field) else {
+            return Err(self.error(object.span, format!("no field '{}' on this value", field)));
+        };
+
+        match (&object_type, &object.kind) {
+            (Type::Struct(_), ExpressionKind::FieldAccess { object: inner, field: inner_field }) => {
+                self.generate_field_address(inner, inner_field, program)?;
+            }
+            (Type::Struct(_), ExpressionKind::Deref { operand }) => {
+                self.generate_expression(operand, program)?;
+            }
+            (Type::Struct(_), _) => {
+                let address = Expression::new(ExpressionKind::AddressOf { operand: Box::new(object.clone()) }, object.span);
+                self.generate_expression(&address, program)?;
+            }
             _ => {
-                self.emit_push32(0);
+                self.generate_expression(object, program)?;
             }
         }
+
+        if layout.cell != 0 {
+            self.emit_push32(layout.cell as i32 * 4);
+            self.emit_byte(ADD);
+        }
+        Ok(())
     }
 
     fn emit_byte(&mut self, byte: u8) {
@@ -678,6 +994,7 @@ impl NVMCodeGen {
         self.bytecode.extend_from_slice(&bytes);
     }
     
+    #[allow(dead_code)]
     fn emit_vga_char(&mut self, ch: u8, attr: u8) {
         self.emit_push32(self.vga_cursor as i32);
         self.emit_push32(((attr as u32) << 8 | ch as u32) as i32);
@@ -685,6 +1002,7 @@ impl NVMCodeGen {
         self.vga_cursor += 2;
     }
     
+    #[allow(dead_code)]
     fn vga_newline(&mut self) {
         self.vga_cursor = ((self.vga_cursor - 0xB8000) / 160 + 1) * 160 + 0xB8000;
         self.vga_cursor += 160;
@@ -693,69 +1011,24 @@ impl NVMCodeGen {
         }
     }
 
-    fn emit_asm_instruction(&mut self, line: &str) {
-        let line = line.trim();
-        if line.is_empty() {
-            return;
-        }
-        
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.is_empty() {
-            return;
+    fn emit_asm_line(&mut self, line: &str) -> Result<(), LineError> {
+        for piece in assembler::parse_line(line)? {
+            match piece {
+                Piece::Label(label) => self.add_label(&label),
+                Piece::Bytes(bytes) => self.bytecode.extend_from_slice(&bytes),
+                Piece::Ref(label) => self.emit_label_ref(&label),
+            }
         }
+        Ok(())
+    }
 
-        let instr = parts[0].to_lowercase();
-        match instr.as_str() {
-            "push32" | "push" => {
-                if parts.len() > 1 {
-                    if let Ok(value) = parts[1].parse::<i32>() {
-                        self.emit_push32(value);
-                    }
-                }
-            }
-            "pop" => self.emit_byte(POP),
-            "add" => self.emit_byte(ADD),
-            "sub" => self.emit_byte(SUB),
-            "mul" => self.emit_byte(MUL),
-            "div" => self.emit_byte(DIV),
-            "mod" => self.emit_byte(MOD),
-            "syscall" => {
-                self.emit_byte(SYSCALL);
-                if parts.len() > 1 {
-                    let syscall_arg = parts[1];
-                    if let Ok(value) = syscall_arg.parse::<u8>() {
-                        self.emit_byte(value);
-                    } else {
-                        let syscall_num = match syscall_arg.to_lowercase().as_str() {
-                            "exit" => SYSCALL_EXIT,
-                            "exec" => SYSCALL_EXEC,
-                            "read" => SYSCALL_READ,
-                            "write" => SYSCALL_WRITE,
-                            "create" => SYSCALL_CREATE,
-                            "delete" => SYSCALL_DELETE,
-                            "cap_check" => SYSCALL_CAP_CHECK,
-                            "cap_spawn" => SYSCALL_CAP_SPAWN,
-                            "msg_send" => SYSCALL_MSG_SEND,
-                            "msg_receive" | "msg_recv" => SYSCALL_MSG_RECEIVE,
-                            "inb" | "port_in_byte" => SYSCALL_PORT_IN_BYTE,
-                            "outb" | "port_out_byte" => SYSCALL_PORT_OUT_BYTE,
-                            
-                            "get_local_addr" => SYSCALL_GET_LOCAL_ADDR,
-                            _ => {
-                                eprintln!("Warning: Unknown syscall name '{}', defaulting to 0", syscall_arg);
-                                0
-                            }
-                        };
-                        self.emit_byte(syscall_num);
-                    }
-                } else {
-                    eprintln!("Warning: syscall instruction without argument, defaulting to 0");
-                    self.emit_byte(0);
-                }
-            }
-            "ret" => self.emit_byte(RET),
-            _ => {}
+    /// Appends one of the runtime helper files, `name` being its file name.
+    fn emit_runtime(&mut self, name: &str, source: &str) -> Result<(), CompileError> {
+        for (index, line) in source.lines().enumerate() {
+            self.emit_asm_line(line)
+                .map_err(|e| CompileError::new(ErrorKind::CodeGenError, e.message, name.to_string(), index + 1, e.column))?;
         }
+        Ok(())
     }
 
     fn emit_label_ref(&mut self, label: &str) {
@@ -776,21 +1049,25 @@ impl NVMCodeGen {
         format!("{}_{}_{}", prefix, self.current_function, count)
     }
 
-    fn patch_labels(&mut self) {
+    /// Fills in every label operand. A label that is never defined is
+    /// reported at the asm block that used it, or in `file` otherwise.
+    fn patch_labels(&mut self, file: &str) -> Result<(), CompileError> {
         for (pos, label) in &self.label_patches {
-            if let Some(&target) = self.labels.get(label) {
-                let bytes = target.to_be_bytes();
-                let pos = *pos as usize;
-                self.bytecode[pos..pos + 4].copy_from_slice(&bytes);
-            } else {
-                eprintln!("Warning: Unresolved label: {}", label);
-            }
+            let Some(&target) = self.labels.get(label) else {
+                let (file, span) = self.asm_label_uses.get(label).cloned().unwrap_or((file.to_string(), Span::default()));
+                let message = format!("undefined label '{}'", label);
+                return Err(CompileError::new(ErrorKind::CodeGenError, message, file, span.line, span.column));
+            };
+            let pos = *pos as usize;
+            self.bytecode[pos..pos + 4].copy_from_slice(&target.to_be_bytes());
         }
+        Ok(())
     }
 
     fn emit_string_literals(&mut self) {
         let literals = self.string_literals.clone();
         for (label, content) in literals {
+            self.bytecode.extend_from_slice(&(content.len() as u32).to_le_bytes());
             self.add_label(&label);
             for ch in content.as_bytes() {
                 self.emit_byte(*ch);
@@ -821,13 +1098,16 @@ impl NVMCodeGen {
         self.emit_byte(SYSCALL);
         self.emit_byte(SYSCALL_PRINT);
         
+        // The magnitude, printed as unsigned so that -2147483648, whose
+        // negation wraps back to itself, comes out right.
+        self.emit_push32(0);
         self.emit_byte(LOAD);
         self.emit_byte(250);
-        self.emit_push32(0);
-        self.emit_byte(SWAP);
         self.emit_byte(SUB);
-        self.emit_byte(STORE);
-        self.emit_byte(250);
+        self.emit_byte(LOAD);
+        self.emit_byte(255);
+        self.emit_byte(JMP32);
+        self.emit_label_ref("__print_uint");
         
         self.add_label(&not_negative_label);
         
@@ -860,12 +1140,12 @@ impl NVMCodeGen {
         self.add_label(&find_power_loop);
         
         self.emit_byte(LOAD);
-        self.emit_byte(251);
+        self.emit_byte(250);
         self.emit_push32(10);
-        self.emit_byte(MUL);
+        self.emit_byte(DIV);
         self.emit_byte(LOAD);
-        self.emit_byte(250);
-        self.emit_byte(GT);
+        self.emit_byte(251);
+        self.emit_byte(LT);
         
         self.emit_byte(JNZ32);
         self.emit_label_ref(&find_power_done);
@@ -931,3 +1211,9 @@ impl NVMCodeGen {
         self.emit_byte(RET);
     }
 }
+
+/// Bytes per array element on NVM: 8-bit types are packed, everything else
+/// takes a 32-bit cell.
+pub fn element_size(ty: &Type) -> usize {
+    if ty.size() == 1 { 1 } else { 4 }
+}
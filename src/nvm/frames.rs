@@ -0,0 +1,133 @@
+//! Where the locals of each function live. NVM has a single file of 256
+//! local slots shared by every call, so each function gets a range of its
+//! own: locals survive calls and pointers to a caller's locals stay valid in
+//! the callee. A call that can re-enter the caller (recursion) saves the
+//! caller's range on the operand stack and restores it afterwards.
+//!
+//! Calls push their arguments right to left; the callee stores them into its
+//! first slots and returns its value on the operand stack.
+
+use crate::ast::*;
+use crate::error::{CompileError, ErrorKind};
+use crate::typechecker::{compute_struct_layouts, StructLayout, Type};
+use super::codegen::element_size;
+use std::collections::{HashMap, HashSet};
+
+/// Slots functions may use. The others are reserved for the runtime:
+///
+/// - 240 to 249: `__store8`, `__print_uint` and `__print_fmt` in
+///   `integers.asm`
+/// - 250, 251 and 253 to 255: scratch of `__print_int`, `__divmodu` and the
+///   string helpers in `strings.asm`
+/// - 252: the string heap pointer
+pub const USER_SLOTS: usize = 240;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Frame {
+    pub base: u8,
+    pub slots: u8,
+}
+
+#[derive(Default)]
+pub struct FrameLayout {
+    frames: HashMap<String, Frame>,
+    calls: HashMap<String, HashSet<String>>,
+}
+
+impl FrameLayout {
+    /// Lays out `main`, the program's functions and then module functions
+    /// (named `module_function`) one after another. `extern` functions have
+    /// no frame.
+    pub fn new(program: &Program) -> Result<Self, CompileError> {
+        let structs = compute_struct_layouts(&program.structs).unwrap_or_default();
+        let mut functions: Vec<(String, &str, &Function)> = program.functions.iter()
+            .filter(|f| f.external.is_none())
+            .map(|f| (f.name.clone(), program.file.as_str(), f))
+            .collect();
+        functions.sort_by_key(|(name, _, _)| name != "main");
+        for (module_name, module) in &program.modules {
+            if module_name != "stdio" {
+                let defined = module.functions.iter()
+                    .filter(|f| f.external.is_none() && !is_string_builtin(module_name, &f.name));
+                functions.extend(defined.map(|f| (format!("{}_{}", module.name, f.name), module.file.as_str(), f)));
+            }
+        }
+
+        let mut layout = FrameLayout::default();
+        let mut next = 0;
+        for (name, file, func) in functions {
+            let slots = func.params.len() + block_slots(&func.body, &structs);
+            if next + slots > USER_SLOTS {
+                return Err(CompileError::new(
+                    ErrorKind::CodeGenError,
+                    format!("locals of '{}' do not fit: NVM programs have {} local slots", func.name, USER_SLOTS),
+                    file.to_string(),
+                    func.span.line,
+                    func.span.column,
+                ));
+            }
+            layout.frames.insert(name.clone(), Frame { base: next as u8, slots: slots as u8 });
+            next += slots;
+
+            let mut calls = HashSet::new();
+            visit_calls(&func.body, &mut |call| match &call.kind {
+                ExpressionKind::Call { function, .. } => {
+                    calls.insert(function.clone());
+                }
+                ExpressionKind::ModuleCall { module, function, .. } => {
+                    calls.insert(format!("{}_{}", module, function));
+                }
+                _ => {}
+            });
+            layout.calls.insert(name, calls);
+        }
+        Ok(layout)
+    }
+
+    pub fn frame(&self, function: &str) -> Frame {
+        self.frames.get(function).copied().unwrap_or_default()
+    }
+
+    /// Whether calling `callee` from `caller` can lead back into `caller`.
+    pub fn reenters(&self, caller: &str, callee: &str) -> bool {
+        let mut seen = HashSet::new();
+        let mut pending = vec![callee];
+        while let Some(function) = pending.pop() {
+            if function == caller {
+                return true;
+            }
+            if seen.insert(function) {
+                pending.extend(self.calls.get(function).into_iter().flatten().map(String::as_str));
+            }
+        }
+        false
+    }
+}
+
+fn block_slots(body: &[Statement], structs: &HashMap<String, StructLayout>) -> usize {
+    body.iter().map(|stmt| statement_slots(stmt, structs)).sum()
+}
+
+/// Slots the declarations in `stmt` take, the way the generators allocate
+/// them.
+fn statement_slots(stmt: &Statement, structs: &HashMap<String, StructLayout>) -> usize {
+    match &stmt.kind {
+        StatementKind::VarDecl { var_type: Some(t), value: None, .. } => match Type::resolve(t, structs) {
+            Type::Struct(name) => structs.get(&name).map(|s| s.cells).unwrap_or(0),
+            _ => 1,
+        },
+        StatementKind::VarDecl { .. } => 1,
+        StatementKind::ArrayDecl { element_type, size, .. } => {
+            (element_size(&Type::resolve(element_type, structs)) * size).div_ceil(4)
+        }
+        StatementKind::If { then_body, else_body, .. } => {
+            block_slots(then_body, structs) + else_body.as_ref().map_or(0, |body| block_slots(body, structs))
+        }
+        StatementKind::For { init, post, body, .. } => {
+            init.as_ref().map_or(0, |s| statement_slots(s, structs))
+                + post.as_ref().map_or(0, |s| statement_slots(s, structs))
+                + block_slots(body, structs)
+        }
+        _ => 0,
+    }
+}
@@ -0,0 +1,281 @@
+; Integer runtime, appended to programs that use unsigned division, bytes,
+; format specs or strings.
+;
+; NVM cells are signed 32-bit values and the instruction set only has signed
+; division and comparison and word-sized memory access, so unsigned division
+; and byte access are built here from those. Slots 240 to 249 are this
+; runtime's; __divmodu also uses the scratch slots of strings.asm, as it
+; never runs inside a string helper.
+
+; [addr, ret] -> [the byte at addr]
+__load8:
+    swap
+    load_abs
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    swap
+    ret
+
+; [addr, v, ret] -> []: stores the low byte of v at addr, rewriting the three
+; bytes after it with their own values.
+__store8:
+    store 246
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    store 247
+    store 248
+    load 248
+    load 248
+    load_abs
+    store 249
+    load 249
+    load 249
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    sub
+    load 247
+    add
+    store_abs
+    load 246
+    ret
+
+; [a, b, ret] -> [a / b, a % b], both unsigned. Adding 0x80000000 to both
+; sides of a signed comparison makes it unsigned.
+__divmodu:
+    store 255
+    store 254
+    store 253
+    load 254
+    push 0
+    lt
+    jnz __divmodu_large_divisor
+    load 253
+    push 0
+    lt
+    jnz __divmodu_large_dividend
+    load 253
+    load 254
+    div
+    load 253
+    load 254
+    mod
+    load 255
+    ret
+; b >= 2^31: the quotient is 1 when a >= b and 0 otherwise.
+__divmodu_large_divisor:
+    load 253
+    push -2147483648
+    add
+    load 254
+    push -2147483648
+    add
+    lt
+    push 0
+    eq
+    store 251
+    jmp __divmodu_remainder
+; a >= 2^31 > b: divide a / 2 instead, double the quotient and add the one
+; that is missing when the remainder is still at least b.
+__divmodu_large_dividend:
+    load 253
+    push -2147483648
+    add
+    push 2
+    div
+    push 0x40000000
+    add
+    load 254
+    div
+    push 2
+    mul
+    store 251
+    load 253
+    load 251
+    load 254
+    mul
+    sub
+    push -2147483648
+    add
+    load 254
+    push -2147483648
+    add
+    lt
+    jnz __divmodu_remainder
+    load 251
+    push 1
+    add
+    store 251
+__divmodu_remainder:
+    load 251
+    load 253
+    load 251
+    load 254
+    mul
+    sub
+    load 255
+    ret
+
+; [n, ret] -> []: writes n as an unsigned number.
+__print_uint:
+    swap
+    store 249
+    load 249
+    push 0
+    lt
+    jnz __print_uint_large
+    load 249
+    call __print_int
+    ret
+__print_uint_large:
+    load 249
+    push 10
+    call __divmodu
+    swap
+    call __print_int
+    push 48
+    add
+    syscall print
+    ret
+
+; [value, base, letters, width, pad, ret] -> []: writes value as an unsigned
+; number in base 10 or 16, right-aligned in width characters filled with pad
+; (' ' or '0'). Hex digits above 9 are letters + 10, so letters is 'a' - 10
+; or 'A' - 10. __print_fmt_signed takes the same arguments and writes a
+; negative value with a '-', which comes before '0' padding and after ' '.
+__print_fmt_signed:
+    push 1
+    jmp __print_fmt_args
+__print_fmt:
+    push 0
+__print_fmt_args:
+    store 247
+    store 240
+    store 241
+    store 242
+    store 243
+    store 244
+    store 245
+    load 247
+    jz __print_fmt_count
+    load 245
+    push 0
+    lt
+    store 247
+    load 247
+    jz __print_fmt_count
+; The magnitude of -2147483648 wraps to itself, which the rest reads as
+; the unsigned 2147483648.
+    push 0
+    load 245
+    sub
+    store 245
+; 246 becomes the power of the base of the first digit and 248 the number
+; of digits.
+__print_fmt_count:
+    push 1
+    store 246
+    push 1
+    store 248
+    load 245
+    store 249
+__print_fmt_count_loop:
+    load 249
+    push -2147483648
+    add
+    load 244
+    push -2147483648
+    add
+    lt
+    jnz __print_fmt_pad
+    load 249
+    load 244
+    call __divmodu
+    pop
+    store 249
+    load 246
+    load 244
+    mul
+    store 246
+    load 248
+    push 1
+    add
+    store 248
+    jmp __print_fmt_count_loop
+__print_fmt_pad:
+    load 242
+    load 248
+    sub
+    load 247
+    sub
+    store 242
+    load 241
+    push 48
+    eq
+    jz __print_fmt_fill
+    load 247
+    jz __print_fmt_fill
+    push 45
+    syscall print
+    push 0
+    store 247
+__print_fmt_fill:
+    load 242
+    push 0
+    gt
+    jz __print_fmt_sign
+    load 241
+    syscall print
+    load 242
+    push 1
+    sub
+    store 242
+    jmp __print_fmt_fill
+__print_fmt_sign:
+    load 247
+    jz __print_fmt_digits
+    push 45
+    syscall print
+__print_fmt_digits:
+    load 246
+    jz __print_fmt_done
+    load 245
+    load 246
+    call __divmodu
+    store 245
+    store 249
+    load 249
+    push 10
+    lt
+    jz __print_fmt_letter
+    load 249
+    push 48
+    add
+    syscall print
+    jmp __print_fmt_next
+__print_fmt_letter:
+    load 249
+    load 243
+    add
+    syscall print
+__print_fmt_next:
+    load 246
+    load 244
+    call __divmodu
+    pop
+    store 246
+    jmp __print_fmt_digits
+__print_fmt_done:
+    load 240
+    ret
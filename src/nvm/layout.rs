@@ -0,0 +1,10 @@
+//! The memory of an NVM process. The image is mapped at address 0 and the
+//! local slots sit at the top; the string runtime's heap grows from the end
+//! of the image up to them.
+
+/// Bytes of memory each process gets.
+pub const MEMORY_SIZE: usize = 0x100000;
+/// Address of local slot 0, and the end of the heap.
+pub const LOCALS_BASE: usize = 0xF0000;
+/// Local slots, one 32-bit cell each.
+pub const LOCAL_SLOTS: usize = 256;
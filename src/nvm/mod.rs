@@ -1,5 +1,12 @@
 pub mod codegen;
 pub mod asm_generator;
+pub mod assembler;
+pub mod frames;
+pub mod layout;
+pub mod opcodes;
+pub mod syscalls;
+pub mod vm;
 
 pub use codegen::NVMCodeGen;
 pub use asm_generator::NVMAssemblyGenerator;
+pub use vm::NVMInterpreter;
@@ -0,0 +1,114 @@
+//! The NVM instruction set: opcode bytes, their assembly mnemonics and the
+//! operand each one takes. `NVMCodeGen`, the interpreter, the assembler and
+//! the `--emit=nvm-asm` listing all encode and decode through this table.
+//!
+//! Instruction operands are big-endian; values in memory are little-endian.
+
+pub const PUSH32: u8 = 0x02;
+pub const POP: u8 = 0x04;
+pub const SWAP: u8 = 0x06;
+
+pub const ADD: u8 = 0x10;
+pub const SUB: u8 = 0x11;
+pub const MUL: u8 = 0x12;
+pub const DIV: u8 = 0x13;
+pub const MOD: u8 = 0x14;
+
+pub const EQ: u8 = 0x21;
+pub const NEQ: u8 = 0x22;
+pub const GT: u8 = 0x23;
+pub const LT: u8 = 0x24;
+
+pub const JMP32: u8 = 0x30;
+pub const JZ32: u8 = 0x31;
+pub const JNZ32: u8 = 0x32;
+pub const CALL32: u8 = 0x33;
+pub const RET: u8 = 0x34;
+
+pub const LOAD: u8 = 0x40;
+pub const STORE: u8 = 0x41;
+pub const LOAD_ABS: u8 = 0x44;
+pub const STORE_ABS: u8 = 0x45;
+
+pub const SYSCALL: u8 = 0x50;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand {
+    None,
+    /// A 32-bit value: a number or the address of a label.
+    Imm32,
+    /// A local slot, 0 to 255.
+    Slot,
+    /// A 32-bit code address: a label or a number.
+    Address,
+    /// A syscall number or name from `syscalls::SYSCALLS`.
+    Syscall,
+}
+
+impl Operand {
+    /// Bytes the operand takes after the opcode.
+    pub fn size(self) -> usize {
+        match self {
+            Operand::None => 0,
+            Operand::Slot | Operand::Syscall => 1,
+            Operand::Imm32 | Operand::Address => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Opcode {
+    pub byte: u8,
+    /// Spelling in NVM assembly.
+    pub mnemonic: &'static str,
+    pub operand: Operand,
+}
+
+const fn opcode(byte: u8, mnemonic: &'static str, operand: Operand) -> Opcode {
+    Opcode { byte, mnemonic, operand }
+}
+
+pub const OPCODES: &[Opcode] = &[
+    opcode(PUSH32, "push", Operand::Imm32),
+    opcode(POP, "pop", Operand::None),
+    opcode(SWAP, "swap", Operand::None),
+    opcode(ADD, "add", Operand::None),
+    opcode(SUB, "sub", Operand::None),
+    opcode(MUL, "mul", Operand::None),
+    opcode(DIV, "div", Operand::None),
+    opcode(MOD, "mod", Operand::None),
+    opcode(EQ, "eq", Operand::None),
+    opcode(NEQ, "neq", Operand::None),
+    opcode(GT, "gt", Operand::None),
+    opcode(LT, "lt", Operand::None),
+    opcode(JMP32, "jmp", Operand::Address),
+    opcode(JZ32, "jz", Operand::Address),
+    opcode(JNZ32, "jnz", Operand::Address),
+    opcode(CALL32, "call", Operand::Address),
+    opcode(RET, "ret", Operand::None),
+    opcode(LOAD, "load", Operand::Slot),
+    opcode(STORE, "store", Operand::Slot),
+    opcode(LOAD_ABS, "load_abs", Operand::None),
+    opcode(STORE_ABS, "store_abs", Operand::None),
+    opcode(SYSCALL, "syscall", Operand::Syscall),
+];
+
+pub fn by_byte(byte: u8) -> Option<&'static Opcode> {
+    OPCODES.iter().find(|op| op.byte == byte)
+}
+
+/// Looks up a mnemonic case-insensitively. The 32-bit forms `push32`,
+/// `jmp32`, `jz32`, `jnz32` and `call32` and the older `load_ptr` are
+/// accepted too.
+pub fn by_mnemonic(name: &str) -> Option<&'static Opcode> {
+    let name = match name.to_lowercase().as_str() {
+        "push32" => "push",
+        "jmp32" => "jmp",
+        "jz32" => "jz",
+        "jnz32" => "jnz",
+        "call32" => "call",
+        "load_ptr" => "load_abs",
+        _ => return OPCODES.iter().find(|op| op.mnemonic.eq_ignore_ascii_case(name)),
+    };
+    OPCODES.iter().find(|op| op.mnemonic == name)
+}
@@ -0,0 +1,269 @@
+; String runtime, appended to programs that use strings.
+;
+; A string is the address of its bytes, which end in a NUL and follow their
+; length as a little-endian word at -4. Strings are allocated from a heap
+; between the image and the locals that is never freed; the generator puts
+; the address of the locals in the word at __heap_limit and the message
+; __out_of_memory among the literals. Slot 252 holds the heap pointer; 250,
+; 251 and 253 to 255 are scratch, like the slots __print_int uses.
+
+; [n, ret] -> [block]: n bytes, 4-byte aligned. Prints "out of memory" and
+; exits with 12 when the heap runs into the locals.
+__alloc:
+    swap
+    load 252
+    jnz __alloc_ready
+    push __heap
+    store 252
+__alloc_ready:
+    load 252
+    swap
+    load 252
+    add
+    push 3
+    add
+    push 4
+    div
+    push 4
+    mul
+    store 252
+    load 252
+    push __heap_limit
+    load_abs
+    gt
+    jnz __alloc_full
+    swap
+    ret
+__alloc_full:
+    push __out_of_memory
+    call __print_str
+    push 12
+    syscall exit
+
+; [n, ret] -> [s]: a string of n bytes with its length and NUL set. Leaves
+; n in slot 250 and s in slot 251.
+__str_alloc:
+    swap
+    store 250
+    load 250
+    push 5
+    add
+    call __alloc
+    store 251
+    load 251
+    load 250
+    store_abs
+    load 251
+    push 4
+    add
+    store 251
+    load 251
+    load 250
+    add
+    push 0
+    call __store8
+    load 251
+    swap
+    ret
+
+; Copies slot 250 bytes from the address in slot 253 to the address in slot
+; 251, leaving both past the bytes copied.
+__str_copy:
+    load 250
+    jz __str_copy_done
+    load 251
+    load 253
+    call __load8
+    call __store8
+    load 251
+    push 1
+    add
+    store 251
+    load 253
+    push 1
+    add
+    store 253
+    load 250
+    push 1
+    sub
+    store 250
+    jmp __str_copy
+__str_copy_done:
+    ret
+
+; [a, b, ret] -> [a ++ b]
+__str_concat:
+    store 254
+    store 253
+    store 255
+    load 255
+    push 4
+    sub
+    load_abs
+    load 253
+    push 4
+    sub
+    load_abs
+    add
+    call __str_alloc
+    load 253
+    load 255
+    store 253
+    load 253
+    push 4
+    sub
+    load_abs
+    store 250
+    call __str_copy
+    store 253
+    load 253
+    push 4
+    sub
+    load_abs
+    store 250
+    call __str_copy
+    load 254
+    ret
+
+; [a, b, ret] -> [-1, 0 or 1], comparing unsigned bytes
+__str_compare:
+    store 254
+    store 253
+    store 251
+    load 251
+    push 4
+    sub
+    load_abs
+    store 250
+    load 253
+    push 4
+    sub
+    load_abs
+    store 255
+__str_compare_loop:
+    load 250
+    jz __str_compare_end
+    load 255
+    jz __str_compare_greater
+    load 251
+    call __load8
+    load 253
+    call __load8
+    lt
+    jnz __str_compare_less
+    load 251
+    call __load8
+    load 253
+    call __load8
+    gt
+    jnz __str_compare_greater
+    load 251
+    push 1
+    add
+    store 251
+    load 253
+    push 1
+    add
+    store 253
+    load 250
+    push 1
+    sub
+    store 250
+    load 255
+    push 1
+    sub
+    store 255
+    jmp __str_compare_loop
+__str_compare_end:
+    load 255
+    jnz __str_compare_less
+    push 0
+    jmp __str_compare_done
+__str_compare_less:
+    push -1
+    jmp __str_compare_done
+__str_compare_greater:
+    push 1
+__str_compare_done:
+    load 254
+    ret
+
+; [s, start, end, ret] -> [the bytes of s from start to end], both clamped
+; to 0..len(s)
+__str_slice:
+    store 254
+    store 255
+    store 250
+    store 253
+    load 255
+    load 253
+    push 4
+    sub
+    load_abs
+    gt
+    jz __str_slice_end_low
+    load 253
+    push 4
+    sub
+    load_abs
+    store 255
+__str_slice_end_low:
+    load 255
+    push 0
+    lt
+    jz __str_slice_start
+    push 0
+    store 255
+__str_slice_start:
+    load 250
+    push 0
+    lt
+    jz __str_slice_start_high
+    push 0
+    store 250
+__str_slice_start_high:
+    load 250
+    load 255
+    gt
+    jz __str_slice_copy
+    load 255
+    store 250
+__str_slice_copy:
+    load 253
+    load 250
+    add
+    store 253
+    load 255
+    load 250
+    sub
+    call __str_alloc
+    call __str_copy
+    load 254
+    ret
+
+; [s, ret] -> []: writes the bytes of s
+__print_str:
+    swap
+    store 253
+    load 253
+    push 4
+    sub
+    load_abs
+    store 250
+__print_str_loop:
+    load 250
+    jz __print_str_done
+    load 253
+    call __load8
+    syscall print
+    load 253
+    push 1
+    add
+    store 253
+    load 250
+    push 1
+    sub
+    store 250
+    jmp __print_str_loop
+__print_str_done:
+    ret
@@ -0,0 +1,90 @@
+//! The NovariaOS syscalls: their numbers, the names NVM assembly uses for
+//! them and how they use the operand stack. Arguments are popped first
+//! argument first, so callers push them right to left.
+
+pub const SYSCALL_EXIT: u8 = 0x00;
+pub const SYSCALL_EXEC: u8 = 0x01;
+pub const SYSCALL_OPEN: u8 = 0x02;
+pub const SYSCALL_READ: u8 = 0x03;
+pub const SYSCALL_WRITE: u8 = 0x04;
+pub const SYSCALL_CREATE: u8 = 0x05;
+pub const SYSCALL_DELETE: u8 = 0x06;
+pub const SYSCALL_CAP_CHECK: u8 = 0x07;
+pub const SYSCALL_CAP_SPAWN: u8 = 0x08;
+pub const SYSCALL_MSG_SEND: u8 = 0x0A;
+pub const SYSCALL_MSG_RECEIVE: u8 = 0x0B;
+pub const SYSCALL_PORT_IN_BYTE: u8 = 0x0C;
+pub const SYSCALL_PORT_OUT_BYTE: u8 = 0x0D;
+pub const SYSCALL_GET_LOCAL_ADDR: u8 = 0x0E;
+pub const SYSCALL_PRINT: u8 = 0x0F;
+
+/// Capability flags, as `CapCheck` and `CapSpawn` take them and the
+/// `CAP_*` functions of the `novaria` module return them.
+pub const CAP_FS_READ: i32 = 1;
+pub const CAP_FS_WRITE: i32 = 2;
+pub const CAP_FS_CREATE: i32 = 4;
+pub const CAP_FS_DELETE: i32 = 8;
+pub const CAP_DRV_ACCESS: i32 = 16;
+pub const CAP_CAPS_MGMT: i32 = 32;
+pub const CAP_ALL: i32 = 65535;
+
+/// Every capability flag under the name of the `novaria` function that
+/// returns it.
+pub const CAPABILITIES: &[(&str, i32)] = &[
+    ("CAP_FS_READ", CAP_FS_READ),
+    ("CAP_FS_WRITE", CAP_FS_WRITE),
+    ("CAP_FS_CREATE", CAP_FS_CREATE),
+    ("CAP_FS_DELETE", CAP_FS_DELETE),
+    ("CAP_DRV_ACCESS", CAP_DRV_ACCESS),
+    ("CAP_CAPS_MGMT", CAP_CAPS_MGMT),
+    ("CAP_ALL", CAP_ALL),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Syscall {
+    pub number: u8,
+    /// Spelling after `syscall` in NVM assembly.
+    pub name: &'static str,
+    /// Values popped from the operand stack.
+    pub args: usize,
+    /// Whether a result is pushed; `exit` and `print` push nothing.
+    pub returns: bool,
+}
+
+const fn syscall(number: u8, name: &'static str, args: usize, returns: bool) -> Syscall {
+    Syscall { number, name, args, returns }
+}
+
+pub const SYSCALLS: &[Syscall] = &[
+    syscall(SYSCALL_EXIT, "exit", 1, false),
+    syscall(SYSCALL_EXEC, "exec", 1, true),
+    syscall(SYSCALL_OPEN, "open", 1, true),
+    syscall(SYSCALL_READ, "read", 3, true),
+    syscall(SYSCALL_WRITE, "write", 3, true),
+    syscall(SYSCALL_CREATE, "create", 3, true),
+    syscall(SYSCALL_DELETE, "delete", 1, true),
+    syscall(SYSCALL_CAP_CHECK, "cap_check", 2, true),
+    syscall(SYSCALL_CAP_SPAWN, "cap_spawn", 3, true),
+    syscall(SYSCALL_MSG_SEND, "msg_send", 3, true),
+    syscall(SYSCALL_MSG_RECEIVE, "msg_receive", 2, true),
+    syscall(SYSCALL_PORT_IN_BYTE, "port_in_byte", 1, true),
+    syscall(SYSCALL_PORT_OUT_BYTE, "port_out_byte", 2, true),
+    syscall(SYSCALL_GET_LOCAL_ADDR, "get_local_addr", 1, true),
+    syscall(SYSCALL_PRINT, "print", 1, false),
+];
+
+pub fn by_number(number: u8) -> Option<&'static Syscall> {
+    SYSCALLS.iter().find(|s| s.number == number)
+}
+
+/// Looks up a name as written in inline assembly, case-insensitively and
+/// with the short spellings `msg_recv`, `inb` and `outb`.
+pub fn by_name(name: &str) -> Option<&'static Syscall> {
+    let name = match name.to_lowercase().as_str() {
+        "msg_recv" => "msg_receive",
+        "inb" => "port_in_byte",
+        "outb" => "port_out_byte",
+        _ => return SYSCALLS.iter().find(|s| s.name.eq_ignore_ascii_case(name)),
+    };
+    SYSCALLS.iter().find(|s| s.name == name)
+}
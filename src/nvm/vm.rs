@@ -0,0 +1,459 @@
+use super::opcodes::{
+    PUSH32, POP, SWAP, ADD, SUB, MUL, DIV, MOD, EQ, NEQ, GT, LT, JMP32, JZ32,
+    JNZ32, CALL32, RET, LOAD, STORE, LOAD_ABS, STORE_ABS, SYSCALL,
+};
+use super::syscalls::{
+    SYSCALL_EXIT, SYSCALL_PRINT, SYSCALL_EXEC, SYSCALL_OPEN, SYSCALL_READ, SYSCALL_WRITE, SYSCALL_CREATE,
+    SYSCALL_DELETE, SYSCALL_CAP_CHECK, SYSCALL_CAP_SPAWN, SYSCALL_MSG_SEND, SYSCALL_MSG_RECEIVE,
+    SYSCALL_PORT_IN_BYTE, SYSCALL_PORT_OUT_BYTE, SYSCALL_GET_LOCAL_ADDR, CAP_FS_READ, CAP_FS_WRITE,
+    CAP_FS_CREATE, CAP_FS_DELETE, CAP_DRV_ACCESS, CAP_CAPS_MGMT, CAP_ALL,
+};
+use super::layout::{LOCALS_BASE, LOCAL_SLOTS, MEMORY_SIZE};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+const STACK_LIMIT: usize = 0x10000;
+const MAX_PROCESS_DEPTH: usize = 16;
+
+const ROOT_PID: i32 = 1;
+
+#[derive(Debug, Clone)]
+pub struct VmError {
+    pub message: String,
+    pub pid: i32,
+    pub ip: usize,
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "pid {} at 0x{:04X}: {}", self.pid, self.ip, self.message)
+    }
+}
+
+impl std::error::Error for VmError {}
+
+struct Process {
+    pid: i32,
+    memory: Vec<u8>,
+    image_len: usize,
+    stack: Vec<i32>,
+    ip: usize,
+}
+
+/// Host-side emulator for NVM bytecode as produced by `NVMCodeGen`.
+///
+/// The image is mapped at address 0, so label addresses (which include the
+/// `NVM0` header) are plain memory addresses. The 256 local slots live in
+/// process memory at `LOCALS_BASE`, which keeps `SYSCALL_GET_LOCAL_ADDR`
+/// pointers usable with `LOAD_ABS`/`STORE_ABS`. File syscalls are confined to
+/// the sandbox directory and message queues are kept in-process.
+pub struct NVMInterpreter {
+    sandbox: PathBuf,
+    caps: HashMap<i32, i32>,
+    exit_codes: HashMap<i32, i32>,
+    queues: HashMap<i32, VecDeque<Vec<u8>>>,
+    ports: HashMap<i32, u8>,
+    next_pid: i32,
+    depth: usize,
+}
+
+impl NVMInterpreter {
+    pub fn new(sandbox: &Path) -> Self {
+        NVMInterpreter {
+            sandbox: sandbox.to_path_buf(),
+            caps: HashMap::new(),
+            exit_codes: HashMap::new(),
+            queues: HashMap::new(),
+            ports: HashMap::new(),
+            next_pid: ROOT_PID,
+            depth: 0,
+        }
+    }
+
+    /// Runs `bytecode` as the root process with every capability and returns
+    /// its exit code.
+    pub fn run(&mut self, bytecode: &[u8], out: &mut dyn Write) -> Result<i32, VmError> {
+        let pid = self.spawn(bytecode, CAP_ALL, out)?;
+        Ok(self.exit_codes.get(&pid).copied().unwrap_or(0))
+    }
+
+    fn spawn(&mut self, bytecode: &[u8], caps: i32, out: &mut dyn Write) -> Result<i32, VmError> {
+        let pid = self.next_pid;
+        self.next_pid += 1;
+
+        if bytecode.len() < 4 || &bytecode[..4] != b"NVM0" {
+            return Err(VmError { message: "missing NVM0 header".to_string(), pid, ip: 0 });
+        }
+        if bytecode.len() > LOCALS_BASE {
+            return Err(VmError {
+                message: format!("image of {} bytes does not fit below locals at 0x{:X}", bytecode.len(), LOCALS_BASE),
+                pid,
+                ip: 0,
+            });
+        }
+        if self.depth >= MAX_PROCESS_DEPTH {
+            return Err(VmError { message: "process nesting limit reached".to_string(), pid, ip: 0 });
+        }
+
+        let mut memory = vec![0u8; MEMORY_SIZE];
+        memory[..bytecode.len()].copy_from_slice(bytecode);
+
+        let mut process = Process {
+            pid,
+            memory,
+            image_len: bytecode.len(),
+            stack: Vec::new(),
+            ip: 4,
+        };
+
+        self.caps.insert(pid, caps);
+        self.queues.entry(pid).or_default();
+
+        self.depth += 1;
+        let result = self.execute(&mut process, out);
+        self.depth -= 1;
+
+        let code = result?;
+        self.exit_codes.insert(pid, code);
+        Ok(pid)
+    }
+
+    fn execute(&mut self, p: &mut Process, out: &mut dyn Write) -> Result<i32, VmError> {
+        loop {
+            let at = p.ip;
+            let opcode = self.fetch_byte(p)?;
+
+            match opcode {
+                PUSH32 => {
+                    let value = self.fetch_i32(p)?;
+                    self.push(p, value, at)?;
+                }
+                POP => {
+                    self.pop(p, at)?;
+                }
+                SWAP => {
+                    let b = self.pop(p, at)?;
+                    let a = self.pop(p, at)?;
+                    self.push(p, b, at)?;
+                    self.push(p, a, at)?;
+                }
+                ADD | SUB | MUL | DIV | MOD | EQ | NEQ | GT | LT => {
+                    let b = self.pop(p, at)?;
+                    let a = self.pop(p, at)?;
+                    let value = match opcode {
+                        ADD => a.wrapping_add(b),
+                        SUB => a.wrapping_sub(b),
+                        MUL => a.wrapping_mul(b),
+                        DIV | MOD if b == 0 => {
+                            return Err(Self::error(p, at, "division by zero".to_string()));
+                        }
+                        DIV => a.wrapping_div(b),
+                        MOD => a.wrapping_rem(b),
+                        EQ => (a == b) as i32,
+                        NEQ => (a != b) as i32,
+                        GT => (a > b) as i32,
+                        _ => (a < b) as i32,
+                    };
+                    self.push(p, value, at)?;
+                }
+                JMP32 => {
+                    p.ip = self.fetch_i32(p)? as u32 as usize;
+                }
+                JZ32 | JNZ32 => {
+                    let target = self.fetch_i32(p)? as u32 as usize;
+                    let cond = self.pop(p, at)?;
+                    if (cond == 0) == (opcode == JZ32) {
+                        p.ip = target;
+                    }
+                }
+                CALL32 => {
+                    let target = self.fetch_i32(p)? as u32 as usize;
+                    let return_addr = p.ip as i32;
+                    self.push(p, return_addr, at)?;
+                    p.ip = target;
+                }
+                RET => {
+                    if p.stack.is_empty() {
+                        return Ok(0);
+                    }
+                    p.ip = self.pop(p, at)? as u32 as usize;
+                }
+                LOAD => {
+                    let slot = self.fetch_byte(p)? as usize;
+                    let value = self.read_i32(p, LOCALS_BASE + slot * 4, at)?;
+                    self.push(p, value, at)?;
+                }
+                STORE => {
+                    let slot = self.fetch_byte(p)? as usize;
+                    let value = self.pop(p, at)?;
+                    self.write_i32(p, LOCALS_BASE + slot * 4, value, at)?;
+                }
+                LOAD_ABS => {
+                    let addr = self.pop(p, at)? as u32 as usize;
+                    let value = self.read_i32(p, addr, at)?;
+                    self.push(p, value, at)?;
+                }
+                STORE_ABS => {
+                    let value = self.pop(p, at)?;
+                    let addr = self.pop(p, at)? as u32 as usize;
+                    self.write_i32(p, addr, value, at)?;
+                }
+                SYSCALL => {
+                    let number = self.fetch_byte(p)?;
+                    if let Some(code) = self.syscall(p, number, at, out)? {
+                        return Ok(code);
+                    }
+                }
+                _ => {
+                    return Err(Self::error(p, at, format!("unknown opcode 0x{:02X}", opcode)));
+                }
+            }
+        }
+    }
+
+    fn syscall(&mut self, p: &mut Process, number: u8, at: usize, out: &mut dyn Write) -> Result<Option<i32>, VmError> {
+        let result = match number {
+            SYSCALL_EXIT => {
+                let code = self.pop(p, at)?;
+                return Ok(Some(code));
+            }
+            SYSCALL_PRINT => {
+                let ch = self.pop(p, at)?;
+                out.write_all(&[ch as u8])
+                    .map_err(|e| Self::error(p, at, format!("failed to write output: {}", e)))?;
+                return Ok(None);
+            }
+            SYSCALL_EXEC => {
+                let filename = self.pop_string(p, at)?;
+                let caps = self.caps_of(p.pid);
+                self.spawn_file(p, &filename, caps, at, out)?
+            }
+            SYSCALL_OPEN => {
+                let filename = self.pop_string(p, at)?;
+                match self.resolve(&filename) {
+                    Some(path) if path.is_file() => 0,
+                    _ => -1,
+                }
+            }
+            SYSCALL_READ => {
+                let filename = self.pop_string(p, at)?;
+                let buffer = self.pop(p, at)? as u32 as usize;
+                let max_size = self.pop(p, at)?.max(0) as usize;
+                match self.fs_path(p.pid, &filename, CAP_FS_READ).and_then(|path| fs::read(path).ok()) {
+                    Some(data) => {
+                        let count = data.len().min(max_size);
+                        self.write_bytes(p, buffer, &data[..count], at)?;
+                        count as i32
+                    }
+                    None => -1,
+                }
+            }
+            SYSCALL_WRITE | SYSCALL_CREATE => {
+                let filename = self.pop_string(p, at)?;
+                let data_ptr = self.pop(p, at)? as u32 as usize;
+                let size = self.pop(p, at)?.max(0) as usize;
+                let data = self.read_bytes(p, data_ptr, size, at)?.to_vec();
+                let cap = if number == SYSCALL_CREATE { CAP_FS_CREATE } else { CAP_FS_WRITE };
+                match self.fs_path(p.pid, &filename, cap) {
+                    Some(path) if (number == SYSCALL_CREATE) != path.exists() && fs::write(&path, &data).is_ok() => 0,
+                    _ => -1,
+                }
+            }
+            SYSCALL_DELETE => {
+                let filename = self.pop_string(p, at)?;
+                match self.fs_path(p.pid, &filename, CAP_FS_DELETE) {
+                    Some(path) if fs::remove_file(&path).is_ok() => 0,
+                    _ => -1,
+                }
+            }
+            SYSCALL_CAP_CHECK => {
+                let pid = self.pop(p, at)?;
+                let cap = self.pop(p, at)?;
+                match self.caps.get(&pid) {
+                    Some(&caps) if caps & cap == cap => 1,
+                    _ => 0,
+                }
+            }
+            SYSCALL_CAP_SPAWN => {
+                let filename = self.pop_string(p, at)?;
+                let caps_ptr = self.pop(p, at)? as u32 as usize;
+                let caps_count = self.pop(p, at)?.max(0) as usize;
+                let mut requested = 0;
+                for i in 0..caps_count {
+                    requested |= self.read_i32(p, caps_ptr + i * 4, at)?;
+                }
+                let parent_caps = self.caps_of(p.pid);
+                if parent_caps & CAP_CAPS_MGMT == 0 || requested & !parent_caps != 0 {
+                    -1
+                } else {
+                    self.spawn_file(p, &filename, requested, at, out)?
+                }
+            }
+            SYSCALL_MSG_SEND => {
+                let target = self.pop(p, at)?;
+                let data_ptr = self.pop(p, at)? as u32 as usize;
+                let size = self.pop(p, at)?.max(0) as usize;
+                let data = self.read_bytes(p, data_ptr, size, at)?.to_vec();
+                match self.queues.get_mut(&target) {
+                    Some(queue) => {
+                        queue.push_back(data);
+                        0
+                    }
+                    None => -1,
+                }
+            }
+            SYSCALL_MSG_RECEIVE => {
+                let buffer = self.pop(p, at)? as u32 as usize;
+                let max_size = self.pop(p, at)?.max(0) as usize;
+                // There is no scheduler to block on, so an empty queue is an error.
+                match self.queues.get_mut(&p.pid).and_then(|q| q.pop_front()) {
+                    Some(data) => {
+                        let count = data.len().min(max_size);
+                        self.write_bytes(p, buffer, &data[..count], at)?;
+                        count as i32
+                    }
+                    None => -1,
+                }
+            }
+            SYSCALL_PORT_IN_BYTE => {
+                let port = self.pop(p, at)?;
+                if self.caps_of(p.pid) & CAP_DRV_ACCESS == 0 {
+                    -1
+                } else {
+                    self.ports.get(&port).copied().unwrap_or(0) as i32
+                }
+            }
+            SYSCALL_PORT_OUT_BYTE => {
+                let port = self.pop(p, at)?;
+                let value = self.pop(p, at)?;
+                if self.caps_of(p.pid) & CAP_DRV_ACCESS == 0 {
+                    -1
+                } else {
+                    self.ports.insert(port, value as u8);
+                    0
+                }
+            }
+            SYSCALL_GET_LOCAL_ADDR => {
+                let slot = self.pop(p, at)?;
+                if slot < 0 || slot as usize >= LOCAL_SLOTS {
+                    return Err(Self::error(p, at, format!("local slot {} out of range", slot)));
+                }
+                (LOCALS_BASE + slot as usize * 4) as i32
+            }
+            _ => {
+                return Err(Self::error(p, at, format!("unknown syscall 0x{:02X}", number)));
+            }
+        };
+
+        self.push(p, result, at)?;
+        Ok(None)
+    }
+
+    fn spawn_file(&mut self, p: &Process, filename: &str, caps: i32, at: usize, out: &mut dyn Write) -> Result<i32, VmError> {
+        let image = match self.resolve(filename).and_then(|path| fs::read(path).ok()) {
+            Some(image) => image,
+            None => return Ok(-1),
+        };
+        self.spawn(&image, caps, out).map_err(|e| {
+            Self::error(p, at, format!("child process failed: {}", e))
+        })
+    }
+
+    fn caps_of(&self, pid: i32) -> i32 {
+        self.caps.get(&pid).copied().unwrap_or(0)
+    }
+
+    fn fs_path(&self, pid: i32, filename: &str, cap: i32) -> Option<PathBuf> {
+        if self.caps_of(pid) & cap == 0 {
+            return None;
+        }
+        self.resolve(filename)
+    }
+
+    /// Maps a guest path into the sandbox, rejecting anything that could
+    /// escape it.
+    fn resolve(&self, filename: &str) -> Option<PathBuf> {
+        let relative = Path::new(filename.trim_start_matches('/'));
+        if filename.is_empty() || relative.components().any(|c| !matches!(c, Component::Normal(_))) {
+            return None;
+        }
+        Some(self.sandbox.join(relative))
+    }
+
+    fn error(p: &Process, ip: usize, message: String) -> VmError {
+        VmError { message, pid: p.pid, ip }
+    }
+
+    fn fetch_byte(&self, p: &mut Process) -> Result<u8, VmError> {
+        if p.ip >= p.image_len {
+            return Err(Self::error(p, p.ip, "execution ran past the end of the image".to_string()));
+        }
+        let byte = p.memory[p.ip];
+        p.ip += 1;
+        Ok(byte)
+    }
+
+    fn fetch_i32(&self, p: &mut Process) -> Result<i32, VmError> {
+        let mut bytes = [0u8; 4];
+        for byte in &mut bytes {
+            *byte = self.fetch_byte(p)?;
+        }
+        Ok(i32::from_be_bytes(bytes))
+    }
+
+    fn push(&self, p: &mut Process, value: i32, at: usize) -> Result<(), VmError> {
+        if p.stack.len() >= STACK_LIMIT {
+            return Err(Self::error(p, at, "stack overflow".to_string()));
+        }
+        p.stack.push(value);
+        Ok(())
+    }
+
+    fn pop(&self, p: &mut Process, at: usize) -> Result<i32, VmError> {
+        match p.stack.pop() {
+            Some(value) => Ok(value),
+            None => Err(Self::error(p, at, "stack underflow".to_string())),
+        }
+    }
+
+    fn pop_string(&self, p: &mut Process, at: usize) -> Result<String, VmError> {
+        let addr = self.pop(p, at)? as u32 as usize;
+        let mut end = addr;
+        while end < p.memory.len() && p.memory[end] != 0 {
+            end += 1;
+        }
+        if end >= p.memory.len() {
+            return Err(Self::error(p, at, format!("unterminated string at 0x{:X}", addr)));
+        }
+        Ok(String::from_utf8_lossy(&p.memory[addr..end]).to_string())
+    }
+
+    fn read_bytes<'p>(&self, p: &'p Process, addr: usize, len: usize, at: usize) -> Result<&'p [u8], VmError> {
+        match addr.checked_add(len) {
+            Some(end) if end <= p.memory.len() => Ok(&p.memory[addr..end]),
+            _ => Err(Self::error(p, at, format!("memory access out of bounds at 0x{:X}", addr))),
+        }
+    }
+
+    fn write_bytes(&self, p: &mut Process, addr: usize, data: &[u8], at: usize) -> Result<(), VmError> {
+        match addr.checked_add(data.len()) {
+            Some(end) if end <= p.memory.len() => {
+                p.memory[addr..end].copy_from_slice(data);
+                Ok(())
+            }
+            _ => Err(Self::error(p, at, format!("memory access out of bounds at 0x{:X}", addr))),
+        }
+    }
+
+    fn read_i32(&self, p: &Process, addr: usize, at: usize) -> Result<i32, VmError> {
+        let bytes = self.read_bytes(p, addr, 4, at)?;
+        Ok(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn write_i32(&self, p: &mut Process, addr: usize, value: i32, at: usize) -> Result<(), VmError> {
+        self.write_bytes(p, addr, &value.to_le_bytes(), at)
+    }
+}
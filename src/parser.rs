@@ -1,18 +1,22 @@
-use crate::lexer::Token;
+use crate::lexer::{Span, Token};
 use crate::ast::*;
 
 pub struct Parser {
     tokens: Vec<Token>,
+    spans: Vec<Span>,
     position: usize,
     file: String,
+    errors: Vec<crate::error::CompileError>,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>, file: &str) -> Self {
+    pub fn new_with_spans(tokens: Vec<Token>, spans: Vec<Span>, file: &str) -> Self {
         Parser {
             tokens,
+            spans,
             position: 0,
             file: file.to_string(),
+            errors: Vec::new(),
         }
     }
 
@@ -36,29 +40,96 @@ impl Parser {
 
     fn expect(&mut self, expected: Token) -> crate::error::Result<()> {
         if self.current_token() != &expected {
-            return Err(crate::error::CompileError::new(
-                crate::error::ErrorKind::ParserError,
-                format!("expected {:?}, found {:?}", expected, self.current_token()),
-                self.file.clone(),
-                1,
-                1,
-            ));
+            return Err(self.error(format!("expected {:?}, found {:?}", expected, self.current_token())));
         }
         self.advance();
         Ok(())
     }
 
+    fn current_span(&self) -> Span {
+        self.spans.get(self.position)
+            .or(self.spans.last())
+            .copied()
+            .unwrap_or(Span { line: 1, column: 1 })
+    }
+
     fn error(&self, message: String) -> crate::error::CompileError {
+        let span = self.current_span();
         crate::error::CompileError::new(
             crate::error::ErrorKind::ParserError,
             message,
             self.file.clone(),
-            1,
-            1,
+            span.line,
+            span.column,
         )
     }
 
-    pub fn parse(&mut self) -> crate::error::Result<Program> {
+    /// Skips the rest of a broken statement: up to the next newline or the
+    /// closing brace of the enclosing block.
+    fn synchronize_statement(&mut self) {
+        let mut depth = 0;
+        loop {
+            match self.current_token() {
+                Token::Eof => return,
+                Token::Newline if depth == 0 => return,
+                Token::RightBrace if depth == 0 => return,
+                Token::LeftBrace => depth += 1,
+                Token::RightBrace => depth -= 1,
+                _ => {}
+            }
+            self.advance();
+        }
+    }
+
+    /// Skips to the next top-level declaration at the start of a line.
+    fn synchronize_declaration(&mut self) {
+        loop {
+            self.advance();
+            let at_line_start = matches!(self.tokens.get(self.position.wrapping_sub(1)), Some(Token::Newline));
+            match self.current_token() {
+                Token::Eof => return,
+                Token::Func | Token::Struct | Token::Extern if at_line_start => return,
+                Token::Identifier(id) if at_line_start && id == "pub" => return,
+                _ => {}
+            }
+        }
+    }
+
+    /// Parses `{ statements }`, recording statement errors and resuming at
+    /// the next statement so one run reports all of them.
+    fn parse_block(&mut self) -> crate::error::Result<Vec<Statement>> {
+        self.expect(Token::LeftBrace)?;
+        self.skip_newlines();
+
+        let mut body = Vec::new();
+        while !matches!(self.current_token(), Token::RightBrace | Token::Eof) {
+            match self.parse_statement() {
+                Ok(stmt) => body.push(stmt),
+                Err(e) => {
+                    self.errors.push(e);
+                    self.synchronize_statement();
+                }
+            }
+            self.skip_newlines();
+        }
+
+        self.expect(Token::RightBrace)?;
+        Ok(body)
+    }
+
+    /// Parses a whole file, returning every syntax error found.
+    pub fn parse(&mut self) -> Result<Program, Vec<crate::error::CompileError>> {
+        match self.parse_program() {
+            Ok(program) if self.errors.is_empty() => Ok(program),
+            Ok(_) => Err(std::mem::take(&mut self.errors)),
+            Err(e) => {
+                self.errors.push(e);
+                Err(std::mem::take(&mut self.errors))
+            }
+        }
+    }
+
+    fn parse_program(&mut self) -> crate::error::Result<Program> {
         self.skip_newlines();
 
         self.expect(Token::Package)?;
@@ -74,37 +145,145 @@ impl Parser {
 
         let mut imports = Vec::new();
         while matches!(self.current_token(), Token::Import) {
+            let span = self.current_span();
             self.advance();
             if let Token::String(path) = self.current_token() {
-                let import = Import {
-                    path: path.clone(),
-                    alias: None,
-                };
-                imports.push(import);
+                let path = path.clone();
                 self.advance();
+
+                let alias = if matches!(self.current_token(), Token::Identifier(kw) if kw == "as") {
+                    self.advance();
+                    if let Token::Identifier(name) = self.current_token() {
+                        let name = name.clone();
+                        self.advance();
+                        Some(name)
+                    } else {
+                        return Err(self.error("expected alias name after 'as'".to_string()));
+                    }
+                } else {
+                    None
+                };
+
+                imports.push(Import { path, alias, span });
             } else {
                 return Err(self.error("expected import path string".to_string()));
             }
             self.skip_newlines();
         }
 
+        let mut structs = Vec::new();
         let mut functions = Vec::new();
         while !matches!(self.current_token(), Token::Eof) {
             self.skip_newlines();
             if matches!(self.current_token(), Token::Eof) {
                 break;
             }
-            functions.push(self.parse_function()?);
+            let result = if self.is_struct_decl() {
+                self.parse_struct().map(|s| structs.push(s))
+            } else {
+                self.parse_function().map(|f| functions.push(f))
+            };
+            if let Err(e) = result {
+                self.errors.push(e);
+                self.synchronize_declaration();
+            }
         }
 
         Ok(Program {
             package,
+            file: self.file.clone(),
             imports,
+            structs,
             functions,
-            modules: std::collections::HashMap::new(),
+            modules: std::collections::BTreeMap::new(),
         })
     }
 
+    fn is_struct_decl(&self) -> bool {
+        match self.current_token() {
+            Token::Struct => true,
+            Token::Identifier(id) if id == "pub" => {
+                matches!(self.tokens.get(self.position + 1), Some(Token::Struct))
+            }
+            _ => false,
+        }
+    }
+
+    fn parse_struct(&mut self) -> crate::error::Result<StructDecl> {
+        let is_pub = matches!(self.current_token(), Token::Identifier(_));
+        if is_pub {
+            self.advance();
+        }
+        self.expect(Token::Struct)?;
+
+        let span = self.current_span();
+        let name = if let Token::Identifier(n) = self.current_token() {
+            let name = n.clone();
+            self.advance();
+            name
+        } else {
+            return Err(self.error("expected struct name".to_string()));
+        };
+
+        self.skip_newlines();
+        self.expect(Token::LeftBrace)?;
+        self.skip_newlines();
+
+        let mut fields = Vec::new();
+        while !matches!(self.current_token(), Token::RightBrace | Token::Eof) {
+            let field_span = self.current_span();
+            let field_name = if let Token::Identifier(n) = self.current_token() {
+                let name = n.clone();
+                self.advance();
+                name
+            } else {
+                return Err(self.error("expected field name".to_string()));
+            };
+
+            if matches!(self.current_token(), Token::Colon) {
+                self.advance();
+            }
+
+            let field_type = match self.parse_type() {
+                Some(ty) => ty,
+                None => return Err(self.error(format!("expected type for field '{}'", field_name))),
+            };
+
+            fields.push(StructField {
+                name: field_name,
+                field_type,
+                span: field_span,
+            });
+
+            if matches!(self.current_token(), Token::Comma) {
+                self.advance();
+            }
+            self.skip_newlines();
+        }
+
+        self.expect(Token::RightBrace)?;
+        self.skip_newlines();
+
+        let is_exported = is_pub || name.chars().next().is_some_and(|c| c.is_uppercase());
+        Ok(StructDecl { name, fields, is_exported, span })
+    }
+
+    fn parse_type(&mut self) -> Option<String> {
+        let mut prefix = String::new();
+        while matches!(self.current_token(), Token::Star) {
+            prefix.push('*');
+            self.advance();
+        }
+
+        if let Token::Identifier(t) = self.current_token() {
+            let ty = format!("{}{}", prefix, t);
+            self.advance();
+            Some(ty)
+        } else {
+            None
+        }
+    }
+
     fn parse_function(&mut self) -> crate::error::Result<Function> {
         let is_pub = if let Token::Identifier(id) = self.current_token() {
             if id == "pub" {
@@ -117,8 +296,24 @@ impl Parser {
             false
         };
 
+        let abi = if matches!(self.current_token(), Token::Extern) {
+            self.advance();
+            match self.current_token() {
+                Token::String(abi) if abi == "C" || abi == "novaria" => {
+                    let abi = abi.clone();
+                    self.advance();
+                    Some(abi)
+                }
+                Token::String(abi) => return Err(self.error(format!("unknown ABI \"{}\"; expected \"C\" or \"novaria\"", abi))),
+                _ => return Err(self.error("expected ABI string after 'extern'".to_string())),
+            }
+        } else {
+            None
+        };
+
         self.expect(Token::Func)?;
 
+        let span = self.current_span();
         let name = if let Token::Identifier(n) = self.current_token() {
             let name = n.clone();
             self.advance();
@@ -131,6 +326,7 @@ impl Parser {
         let mut params = Vec::new();
 
         while !matches!(self.current_token(), Token::RightParen) {
+            let param_span = self.current_span();
             let param_name = if let Token::Identifier(n) = self.current_token() {
                 let name = n.clone();
                 self.advance();
@@ -143,17 +339,15 @@ impl Parser {
                 self.advance();
             }
 
-            let param_type = if let Token::Identifier(t) = self.current_token() {
-                let ty = t.clone();
-                self.advance();
-                ty
-            } else {
-                return Err(self.error("expected parameter type".to_string()));
+            let param_type = match self.parse_type() {
+                Some(ty) => ty,
+                None => return Err(self.error("expected parameter type".to_string())),
             };
 
             params.push(Parameter {
                 name: param_name,
                 param_type,
+                span: param_span,
             });
 
             if matches!(self.current_token(), Token::Comma) {
@@ -165,13 +359,7 @@ impl Parser {
 
         let return_type = if matches!(self.current_token(), Token::Arrow) {
             self.advance();
-            if let Token::Identifier(t) = self.current_token() {
-                let ty = t.clone();
-                self.advance();
-                Some(ty)
-            } else {
-                None
-            }
+            self.parse_type()
         } else if let Token::Identifier(t) = self.current_token() {
             let ty = t.clone();
             self.advance();
@@ -180,17 +368,28 @@ impl Parser {
             None
         };
 
-        self.skip_newlines();
-        self.expect(Token::LeftBrace)?;
-        self.skip_newlines();
+        let external = match abi.as_deref() {
+            Some("novaria") => {
+                self.expect(Token::Assign)?;
+                match self.current_token() {
+                    Token::Number(n) if (0..=255).contains(n) => {
+                        let number = *n as u8;
+                        self.advance();
+                        Some(External::Syscall(number))
+                    }
+                    _ => return Err(self.error("expected a syscall number from 0 to 255".to_string())),
+                }
+            }
+            Some(_) => Some(External::C),
+            None => None,
+        };
 
-        let mut body = Vec::new();
-        while !matches!(self.current_token(), Token::RightBrace) {
-            body.push(self.parse_statement()?);
+        let body = if external.is_some() {
+            Vec::new()
+        } else {
             self.skip_newlines();
-        }
-
-        self.expect(Token::RightBrace)?;
+            self.parse_block()?
+        };
         self.skip_newlines();
 
         let is_exported = is_pub || name.chars().next().map(|c| c.is_uppercase()).unwrap_or(false);
@@ -201,15 +400,31 @@ impl Parser {
             return_type,
             body,
             is_exported,
+            external,
+            span,
         })
     }
 
     fn parse_statement(&mut self) -> crate::error::Result<Statement> {
+        let span = self.current_span();
+        let kind = self.parse_statement_kind()?;
+        Ok(Statement::new(kind, span))
+    }
+
+    fn parse_statement_kind(&mut self) -> crate::error::Result<StatementKind> {
         match self.current_token() {
             Token::Var => self.parse_var_decl(),
             Token::If => self.parse_if(),
             Token::For => self.parse_for(),
             Token::Return => self.parse_return(),
+            Token::Break => {
+                self.advance();
+                Ok(StatementKind::Break)
+            }
+            Token::Continue => {
+                self.advance();
+                Ok(StatementKind::Continue)
+            }
             Token::Asm => self.parse_asm(),
             Token::Star => {
                 let next_pos = self.position + 1;
@@ -225,21 +440,60 @@ impl Parser {
                         _ => break,
                     }
                 }
-                Ok(Statement::Expression(self.parse_expression()))
+                Ok(StatementKind::Expression(self.parse_expression()?))
             }
             Token::Identifier(_) => {
                 let next_pos = self.position + 1;
-                if next_pos < self.tokens.len() && (matches!(self.tokens[next_pos], Token::Assign) || matches!(self.tokens[next_pos], Token::LBracket)) {
+                if matches!(self.tokens.get(next_pos), Some(Token::Assign)) || self.is_element_assignment(next_pos) {
                     self.parse_assignment()
+                } else if next_pos < self.tokens.len() && matches!(self.tokens[next_pos], Token::Dot) {
+                    let target = self.parse_expression()?;
+                    if !matches!(self.current_token(), Token::Assign) {
+                        return Ok(StatementKind::Expression(target));
+                    }
+                    self.advance();
+                    let value = self.parse_expression()?;
+
+                    match target.kind {
+                        ExpressionKind::FieldAccess { object, field } => Ok(StatementKind::FieldAssignment {
+                            object: *object,
+                            field,
+                            value,
+                        }),
+                        _ => Err(self.error("invalid assignment target".to_string())),
+                    }
                 } else {
-                    Ok(Statement::Expression(self.parse_expression()))
+                    Ok(StatementKind::Expression(self.parse_expression()?))
+                }
+            }
+            _ => Ok(StatementKind::Expression(self.parse_expression()?)),
+        }
+    }
+
+    /// Whether the `[` at `open` starts `name[index] = value` rather than an
+    /// element read.
+    fn is_element_assignment(&self, open: usize) -> bool {
+        if !matches!(self.tokens.get(open), Some(Token::LBracket)) {
+            return false;
+        }
+        let mut depth = 0;
+        for (i, token) in self.tokens.iter().enumerate().skip(open) {
+            match token {
+                Token::LBracket => depth += 1,
+                Token::RBracket => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return matches!(self.tokens.get(i + 1), Some(Token::Assign));
+                    }
                 }
+                Token::Newline | Token::Eof => return false,
+                _ => {}
             }
-            _ => Ok(Statement::Expression(self.parse_expression())),
         }
+        false
     }
 
-    fn parse_var_decl(&mut self) -> crate::error::Result<Statement> {
+    fn parse_var_decl(&mut self) -> crate::error::Result<StatementKind> {
         self.expect(Token::Var)?;
 
         let name = if let Token::Identifier(n) = self.current_token() {
@@ -257,13 +511,18 @@ impl Parser {
         if matches!(self.current_token(), Token::LBracket) {
             self.advance();
 
-            let size = if let Token::Number(n) = self.current_token() {
-                let s = *n as usize;
-                self.advance();
-                s
-            } else {
-                return Err(self.error("expected array size".to_string()));
-            };
+            // Both `[N]T` and `[T; N]` are accepted.
+            if let Token::Identifier(t) = self.current_token().clone() {
+                if t != "eval" {
+                    self.advance();
+                    self.expect(Token::Semicolon)?;
+                    let (size, size_eval) = self.parse_array_size()?;
+                    self.expect(Token::RBracket)?;
+                    return Ok(StatementKind::ArrayDecl { name, element_type: t, size, size_eval });
+                }
+            }
+
+            let (size, size_eval) = self.parse_array_size()?;
 
             self.expect(Token::RBracket)?;
 
@@ -275,28 +534,41 @@ impl Parser {
                 return Err(self.error("expected array element type".to_string()));
             };
 
-            return Ok(Statement::ArrayDecl { name, element_type, size });
+            return Ok(StatementKind::ArrayDecl { name, element_type, size, size_eval });
         }
 
-        let var_type = if let Token::Identifier(t) = self.current_token() {
-            let ty = t.clone();
-            self.advance();
-            Some(ty)
-        } else {
-            None
-        };
+        let var_type = self.parse_type();
 
         let value = if matches!(self.current_token(), Token::Assign) {
             self.advance();
-            Some(self.parse_expression())
+            Some(self.parse_expression()?)
         } else {
             None
         };
 
-        Ok(Statement::VarDecl { name, var_type, value })
+        Ok(StatementKind::VarDecl { name, var_type, value })
     }
 
-    fn parse_assignment(&mut self) -> crate::error::Result<Statement> {
+    /// A number, or `eval(...)` for a size `fold_constants` fills in later.
+    fn parse_array_size(&mut self) -> crate::error::Result<(usize, Option<Expression>)> {
+        match self.current_token() {
+            Token::Number(n) => {
+                let size = *n as usize;
+                self.advance();
+                Ok((size, None))
+            }
+            Token::Identifier(name) if name == "eval" => {
+                let expr = self.parse_primary()?;
+                match expr.kind {
+                    ExpressionKind::Eval { instruction } => Ok((0, Some(*instruction))),
+                    _ => Err(self.error("expected array size".to_string())),
+                }
+            }
+            _ => Err(self.error("expected array size".to_string())),
+        }
+    }
+
+    fn parse_assignment(&mut self) -> crate::error::Result<StatementKind> {
         let name = if let Token::Identifier(n) = self.current_token() {
             let name = n.clone();
             self.advance();
@@ -307,114 +579,121 @@ impl Parser {
 
         if matches!(self.current_token(), Token::LBracket) {
             self.advance();
-            let index = self.parse_expression();
+            let index = self.parse_expression()?;
             self.expect(Token::RBracket)?;
             self.expect(Token::Assign)?;
-            let value = self.parse_expression();
+            let value = self.parse_expression()?;
 
-            return Ok(Statement::ArrayAssignment { name, index, value });
+            return Ok(StatementKind::ArrayAssignment { name, index, value });
         }
 
         self.expect(Token::Assign)?;
-        let value = self.parse_expression();
+        let value = self.parse_expression()?;
 
-        Ok(Statement::Assignment { name, value })
+        Ok(StatementKind::Assignment { name, value })
     }
 
-    fn parse_pointer_assignment(&mut self) -> crate::error::Result<Statement> {
+    fn parse_pointer_assignment(&mut self) -> crate::error::Result<StatementKind> {
         self.expect(Token::Star)?;
-        let target = self.parse_primary();
+        let target = self.parse_primary()?;
         self.expect(Token::Assign)?;
-        let value = self.parse_expression();
+        let value = self.parse_expression()?;
 
-        Ok(Statement::PointerAssignment { target, value })
+        Ok(StatementKind::PointerAssignment { target, value })
     }
 
-    fn parse_if(&mut self) -> crate::error::Result<Statement> {
+    fn parse_if(&mut self) -> crate::error::Result<StatementKind> {
         self.expect(Token::If)?;
 
-        let condition = self.parse_expression();
+        let condition = self.parse_expression()?;
 
         self.skip_newlines();
-        self.expect(Token::LeftBrace)?;
-        self.skip_newlines();
-
-        let mut then_body = Vec::new();
-        while !matches!(self.current_token(), Token::RightBrace) {
-            then_body.push(self.parse_statement()?);
-            self.skip_newlines();
-        }
-
-        self.expect(Token::RightBrace)?;
+        let then_body = self.parse_block()?;
         self.skip_newlines();
 
         let else_body = if matches!(self.current_token(), Token::Else) {
             self.advance();
             self.skip_newlines();
-            self.expect(Token::LeftBrace)?;
-            self.skip_newlines();
-
-            let mut body = Vec::new();
-            while !matches!(self.current_token(), Token::RightBrace) {
-                body.push(self.parse_statement()?);
-                self.skip_newlines();
-            }
-
-            self.expect(Token::RightBrace)?;
+            let body = self.parse_block()?;
             Some(body)
         } else {
             None
         };
 
-        Ok(Statement::If {
+        Ok(StatementKind::If {
             condition,
             then_body,
             else_body,
         })
     }
 
-    fn parse_for(&mut self) -> crate::error::Result<Statement> {
+    fn parse_for(&mut self) -> crate::error::Result<StatementKind> {
         self.expect(Token::For)?;
 
-        let condition = if matches!(self.current_token(), Token::LeftBrace) {
+        let mut init = None;
+        let mut post = None;
+
+        let condition = if self.has_for_clauses() {
+            if !matches!(self.current_token(), Token::Semicolon) {
+                init = Some(Box::new(self.parse_statement()?));
+            }
+            self.expect(Token::Semicolon)?;
+
+            let condition = if matches!(self.current_token(), Token::Semicolon) {
+                None
+            } else {
+                Some(self.parse_expression()?)
+            };
+            self.expect(Token::Semicolon)?;
+
+            if !matches!(self.current_token(), Token::LeftBrace) {
+                post = Some(Box::new(self.parse_statement()?));
+            }
+            condition
+        } else if matches!(self.current_token(), Token::LeftBrace) {
             None
         } else {
-            Some(self.parse_expression())
+            Some(self.parse_expression()?)
         };
 
         self.skip_newlines();
-        self.expect(Token::LeftBrace)?;
-        self.skip_newlines();
+        let body = self.parse_block()?;
 
-        let mut body = Vec::new();
-        while !matches!(self.current_token(), Token::RightBrace) {
-            body.push(self.parse_statement()?);
-            self.skip_newlines();
-        }
-
-        self.expect(Token::RightBrace)?;
-
-        Ok(Statement::For {
-            init: None,
+        Ok(StatementKind::For {
+            init,
             condition,
-            post: None,
+            post,
             body,
         })
     }
 
-    fn parse_return(&mut self) -> crate::error::Result<Statement> {
+    fn has_for_clauses(&self) -> bool {
+        let mut depth = 0;
+        for token in &self.tokens[self.position..] {
+            match token {
+                Token::LeftParen | Token::LBracket => depth += 1,
+                Token::RightParen | Token::RBracket => depth -= 1,
+                Token::Semicolon if depth == 0 => return true,
+                Token::LeftBrace | Token::Newline | Token::Eof => return false,
+                _ => {}
+            }
+        }
+        false
+    }
+
+    fn parse_return(&mut self) -> crate::error::Result<StatementKind> {
         self.expect(Token::Return)?;
 
         let value = if matches!(self.current_token(), Token::Newline | Token::RightBrace) {
             None
         } else {
-            Some(self.parse_expression())
+            Some(self.parse_expression()?)
         };
 
-        Ok(Statement::Return(value))
+        Ok(StatementKind::Return(value))
     }
 
-    fn parse_asm(&mut self) -> crate::error::Result<Statement> {
+    fn parse_asm(&mut self) -> crate::error::Result<StatementKind> {
         use crate::ast::AsmPart;
         
         self.expect(Token::Asm)?;
@@ -423,16 +702,16 @@ impl Parser {
             let asm_code = code.clone();
             self.advance();
             let parts = self.parse_asm_interpolation(&asm_code);
-            Ok(Statement::InlineAsm { parts })
+            let (outputs, inputs, clobbers) = self.parse_asm_operands()?;
+            Ok(StatementKind::InlineAsm { parts, outputs, inputs, clobbers })
         } else if matches!(self.current_token(), Token::LeftBrace) {
             self.advance();
             self.skip_newlines();
             
-            use crate::ast::AsmPart;
             let mut parts = Vec::new();
             let mut current_line = String::new();
             
-            while !matches!(self.current_token(), Token::RightBrace) {
+            while !matches!(self.current_token(), Token::RightBrace | Token::Eof) {
                 match self.current_token() {
                     Token::Dollar => {
                         let line_before_var = current_line.trim().to_string();
@@ -519,12 +798,57 @@ impl Parser {
             }
             
             self.expect(Token::RightBrace)?;
-            Ok(Statement::InlineAsm { parts })
+            let (outputs, inputs, clobbers) = self.parse_asm_operands()?;
+            Ok(StatementKind::InlineAsm { parts, outputs, inputs, clobbers })
         } else {
             Err(self.error("expected assembly code string or block after 'asm'".to_string()))
         }
     }
     
+    /// The `: outputs : inputs : clobbers` after `asm` code, each list
+    /// optional and allowed to start on a new line.
+    fn parse_asm_operands(&mut self) -> crate::error::Result<(Vec<AsmOperand>, Vec<AsmOperand>, Vec<String>)> {
+        let mut outputs = Vec::new();
+        let mut inputs = Vec::new();
+        let mut clobbers = Vec::new();
+        for section in 0..3 {
+            let next = self.tokens[self.position..].iter().position(|t| !matches!(t, Token::Newline));
+            if next.map(|i| &self.tokens[self.position + i]) != Some(&Token::Colon) {
+                break;
+            }
+            self.skip_newlines();
+            self.advance();
+            while let Token::String(text) = self.current_token() {
+                let text = text.clone();
+                let span = self.current_span();
+                self.advance();
+                if section == 2 {
+                    clobbers.push(text);
+                } else {
+                    self.expect_closing(Token::LeftParen, "'(' and the variable the operand binds")?;
+                    let Token::Identifier(variable) = self.current_token() else {
+                        return Err(self.error(format!("expected a variable name, found {:?}", self.current_token())));
+                    };
+                    let variable = variable.clone();
+                    self.advance();
+                    self.expect_closing(Token::RightParen, "')' after the operand's variable")?;
+                    let operand = AsmOperand { constraint: text, variable, span };
+                    if section == 0 {
+                        outputs.push(operand);
+                    } else {
+                        inputs.push(operand);
+                    }
+                }
+                if !matches!(self.current_token(), Token::Comma) {
+                    break;
+                }
+                self.advance();
+                self.skip_newlines();
+            }
+        }
+        Ok((outputs, inputs, clobbers))
+    }
+
     fn parse_asm_interpolation(&self, code: &str) -> Vec<crate::ast::AsmPart> {
         use crate::ast::AsmPart;
         
@@ -542,7 +866,7 @@ impl Parser {
                 }
                 
                 let mut var_name = String::new();
-                while let Some(ch) = chars.next() {
+                for ch in chars.by_ref() {
                     if ch == ')' {
                         break;
                     }
@@ -562,44 +886,46 @@ impl Parser {
         parts
     }
 
-    fn parse_expression(&mut self) -> Expression {
+    fn parse_expression(&mut self) -> crate::error::Result<Expression> {
         self.parse_or()
     }
 
-    fn parse_or(&mut self) -> Expression {
-        let mut left = self.parse_and();
+    fn parse_or(&mut self) -> crate::error::Result<Expression> {
+        let mut left = self.parse_and()?;
 
         while matches!(self.current_token(), Token::Or) {
+            let op_span = self.current_span();
             self.advance();
-            let right = self.parse_and();
-            left = Expression::Binary {
+            let right = self.parse_and()?;
+            left = Expression::new(ExpressionKind::Binary {
                 op: BinaryOp::Or,
                 left: Box::new(left),
                 right: Box::new(right),
-            };
+            }, op_span);
         }
 
-        left
+        Ok(left)
     }
 
-    fn parse_and(&mut self) -> Expression {
-        let mut left = self.parse_equality();
+    fn parse_and(&mut self) -> crate::error::Result<Expression> {
+        let mut left = self.parse_equality()?;
 
         while matches!(self.current_token(), Token::And) {
+            let op_span = self.current_span();
             self.advance();
-            let right = self.parse_equality();
-            left = Expression::Binary {
+            let right = self.parse_equality()?;
+            left = Expression::new(ExpressionKind::Binary {
                 op: BinaryOp::And,
                 left: Box::new(left),
                 right: Box::new(right),
-            };
+            }, op_span);
         }
 
-        left
+        Ok(left)
     }
 
-    fn parse_equality(&mut self) -> Expression {
-        let mut left = self.parse_comparison();
+    fn parse_equality(&mut self) -> crate::error::Result<Expression> {
+        let mut left = self.parse_comparison()?;
 
         loop {
             let op = match self.current_token() {
@@ -608,20 +934,21 @@ impl Parser {
                 _ => break,
             };
 
+            let op_span = self.current_span();
             self.advance();
-            let right = self.parse_comparison();
-            left = Expression::Binary {
+            let right = self.parse_comparison()?;
+            left = Expression::new(ExpressionKind::Binary {
                 op,
                 left: Box::new(left),
                 right: Box::new(right),
-            };
+            }, op_span);
         }
 
-        left
+        Ok(left)
     }
 
-    fn parse_comparison(&mut self) -> Expression {
-        let mut left = self.parse_additive();
+    fn parse_comparison(&mut self) -> crate::error::Result<Expression> {
+        let mut left = self.parse_additive()?;
 
         loop {
             let op = match self.current_token() {
@@ -632,20 +959,21 @@ impl Parser {
                 _ => break,
             };
 
+            let op_span = self.current_span();
             self.advance();
-            let right = self.parse_additive();
-            left = Expression::Binary {
+            let right = self.parse_additive()?;
+            left = Expression::new(ExpressionKind::Binary {
                 op,
                 left: Box::new(left),
                 right: Box::new(right),
-            };
+            }, op_span);
         }
 
-        left
+        Ok(left)
     }
 
-    fn parse_additive(&mut self) -> Expression {
-        let mut left = self.parse_multiplicative();
+    fn parse_additive(&mut self) -> crate::error::Result<Expression> {
+        let mut left = self.parse_multiplicative()?;
 
         loop {
             let op = match self.current_token() {
@@ -655,20 +983,21 @@ impl Parser {
                 _ => break,
             };
 
+            let op_span = self.current_span();
             self.advance();
-            let right = self.parse_multiplicative();
-            left = Expression::Binary {
+            let right = self.parse_multiplicative()?;
+            left = Expression::new(ExpressionKind::Binary {
                 op,
                 left: Box::new(left),
                 right: Box::new(right),
-            };
+            }, op_span);
         }
 
-        left
+        Ok(left)
     }
 
-    fn parse_multiplicative(&mut self) -> Expression {
-        let mut left = self.parse_unary();
+    fn parse_multiplicative(&mut self) -> crate::error::Result<Expression> {
+        let mut left = self.parse_cast()?;
 
         loop {
             let op = match self.current_token() {
@@ -678,56 +1007,77 @@ impl Parser {
                 _ => break,
             };
 
+            let op_span = self.current_span();
             self.advance();
-            let right = self.parse_unary();
-            left = Expression::Binary {
+            let right = self.parse_cast()?;
+            left = Expression::new(ExpressionKind::Binary {
                 op,
                 left: Box::new(left),
                 right: Box::new(right),
+            }, op_span);
+        }
+
+        Ok(left)
+    }
+
+    fn parse_cast(&mut self) -> crate::error::Result<Expression> {
+        let mut operand = self.parse_unary()?;
+
+        while matches!(self.current_token(), Token::Identifier(kw) if kw == "as") {
+            let span = self.current_span();
+            self.advance();
+            let Some(target_type) = self.parse_type() else {
+                return Err(self.error(format!("expected type after 'as', found {:?}", self.current_token())));
             };
+            operand = Expression::new(ExpressionKind::Cast {
+                operand: Box::new(operand),
+                target_type,
+            }, span);
         }
 
-        left
+        Ok(operand)
     }
 
-    fn parse_unary(&mut self) -> Expression {
-        match self.current_token() {
+    fn parse_unary(&mut self) -> crate::error::Result<Expression> {
+        let span = self.current_span();
+        let kind = match self.current_token() {
             Token::Minus => {
                 self.advance();
-                let operand = self.parse_unary();
-                Expression::Unary {
+                let operand = self.parse_unary()?;
+                ExpressionKind::Unary {
                     op: UnaryOp::Neg,
                     operand: Box::new(operand),
                 }
             }
             Token::Not => {
                 self.advance();
-                let operand = self.parse_unary();
-                Expression::Unary {
+                let operand = self.parse_unary()?;
+                ExpressionKind::Unary {
                     op: UnaryOp::Not,
                     operand: Box::new(operand),
                 }
             }
             Token::Ampersand => {
                 self.advance();
-                let operand = self.parse_unary();
-                Expression::AddressOf {
+                let operand = self.parse_unary()?;
+                ExpressionKind::AddressOf {
                     operand: Box::new(operand),
                 }
             }
             Token::Star => {
                 self.advance();
-                let operand = self.parse_unary();
-                Expression::Deref {
+                let operand = self.parse_unary()?;
+                ExpressionKind::Deref {
                     operand: Box::new(operand),
                 }
             }
-            _ => self.parse_primary(),
-        }
+            _ => return self.parse_primary(),
+        };
+        Ok(Expression::new(kind, span))
     }
 
-    fn parse_template_string(&mut self, s: String) -> Expression {
-        use crate::ast::{TemplateStringPart, FormatSpec, FormatType};
+    fn parse_template_string(&mut self, s: String, span: Span) -> crate::error::Result<ExpressionKind> {
+        use crate::ast::TemplateStringPart;
         
         let mut parts = Vec::new();
         let mut current_literal = String::new();
@@ -745,7 +1095,7 @@ impl Parser {
                 let mut expr_str = String::new();
                 let mut paren_depth = 1;
                 
-                while let Some(ch) = chars.next() {
+                for ch in chars.by_ref() {
                     if ch == '(' {
                         paren_depth += 1;
                         expr_str.push(ch);
@@ -759,13 +1109,29 @@ impl Parser {
                         expr_str.push(ch);
                     }
                 }
+
+                if paren_depth != 0 {
+                    return Err(self.error("unterminated '$(' in template string".to_string()));
+                }
                 
                 let (expr_str, format_spec) = self.parse_format_spec(&expr_str);
                 
                 let mut lexer = crate::lexer::Lexer::new(&expr_str);
                 let tokens = lexer.tokenize();
-                let mut parser = Parser::new(tokens, &self.file);
-                let expr = parser.parse_expression();
+                let spans = vec![span; tokens.len()];
+                let mut parser = Parser::new_with_spans(tokens, spans, &self.file);
+                let expr = match parser.parse_expression() {
+                    Ok(expr) => expr,
+                    Err(e) => {
+                        return Err(crate::error::CompileError::new(
+                            crate::error::ErrorKind::ParserError,
+                            format!("invalid expression '{}' in template string: {}", expr_str, e.message),
+                            self.file.clone(),
+                            span.line,
+                            span.column,
+                        ));
+                    }
+                };
                 
                 parts.push(TemplateStringPart::Expression {
                     expr: Box::new(expr),
@@ -780,7 +1146,7 @@ impl Parser {
             parts.push(TemplateStringPart::Literal(current_literal));
         }
         
-        Expression::TemplateString { parts }
+        Ok(ExpressionKind::TemplateString { parts })
     }
     
     fn parse_format_spec(&self, expr_str: &str) -> (String, Option<crate::ast::FormatSpec>) {
@@ -838,32 +1204,63 @@ impl Parser {
         (expr_str.to_string(), None)
     }
 
-    fn parse_primary(&mut self) -> Expression {
-        match self.current_token().clone() {
+    fn parse_call_args(&mut self, context: &str) -> crate::error::Result<Vec<Expression>> {
+        self.expect(Token::LeftParen)?;
+        let mut args = Vec::new();
+
+        while !matches!(self.current_token(), Token::RightParen) {
+            args.push(self.parse_expression()?);
+
+            if matches!(self.current_token(), Token::Comma) {
+                self.advance();
+            } else {
+                break;
+            }
+        }
+
+        if !matches!(self.current_token(), Token::RightParen) {
+            return Err(self.error(format!(
+                "expected ')' to close {}, found {:?}",
+                context,
+                self.current_token()
+            )));
+        }
+        self.advance();
+
+        Ok(args)
+    }
+
+    fn expect_closing(&mut self, expected: Token, what: &str) -> crate::error::Result<()> {
+        if self.current_token() != &expected {
+            return Err(self.error(format!("expected {}, found {:?}", what, self.current_token())));
+        }
+        self.advance();
+        Ok(())
+    }
+
+    fn parse_primary(&mut self) -> crate::error::Result<Expression> {
+        let span = self.current_span();
+        let kind = match self.current_token().clone() {
             Token::Number(n) => {
                 self.advance();
-                Expression::Number(n)
+                ExpressionKind::Number(n)
             }
             Token::String(s) => {
                 self.advance();
 
                 if matches!(self.current_token(), Token::LBracket) {
                     self.advance();
-                    let index = self.parse_expression();
-                    if let Err(_) = self.expect(Token::RBracket) {
-                        panic!("Expected closing bracket in string index");
-                    }
+                    let index = self.parse_expression()?;
+                    self.expect_closing(Token::RBracket, "']' after string index")?;
 
-                    return Expression::StringIndex {
-                        string: Box::new(Expression::String(s)),
+                    ExpressionKind::StringIndex {
+                        string: Box::new(Expression::new(ExpressionKind::String(s), span)),
                         index: Box::new(index),
-                    };
-                }
-
-                if s.contains("$(") {
-                    self.parse_template_string(s)
+                    }
+                } else if s.contains("$(") {
+                    self.parse_template_string(s, span)?
                 } else {
-                    Expression::String(s)
+                    ExpressionKind::String(s)
                 }
             }
             Token::Identifier(name) => {
@@ -871,86 +1268,88 @@ impl Parser {
 
                 if matches!(self.current_token(), Token::Dot) {
                     self.advance();
-                    if let Token::Identifier(func_name) = self.current_token() {
-                        let func_name = func_name.clone();
+                    let member_span = self.current_span();
+                    let member = if let Token::Identifier(member) = self.current_token() {
+                        let member = member.clone();
                         self.advance();
+                        member
+                    } else {
+                        return Err(self.error(format!(
+                            "expected function or field name after '.', found {:?}",
+                            self.current_token()
+                        )));
+                    };
 
-                        if matches!(self.current_token(), Token::LeftParen) {
-                            self.advance();
-                            let mut args = Vec::new();
-
-                            while !matches!(self.current_token(), Token::RightParen) {
-                                args.push(self.parse_expression());
-
-                                if matches!(self.current_token(), Token::Comma) {
-                                    self.advance();
-                                }
-                            }
-
-                            if let Err(_) = self.expect(Token::RightParen) {
-                                panic!("Expected closing parenthesis in module call");
-                            }
-
-                            return Expression::ModuleCall {
-                                module: name,
-                                function: func_name,
-                                args,
-                            };
-                        }
+                    if matches!(self.current_token(), Token::LeftParen) {
+                        let context = format!("call to {}.{}", name, member);
+                        let args = self.parse_call_args(&context)?;
+                        return Ok(Expression::new(ExpressionKind::ModuleCall {
+                            module: name,
+                            function: member,
+                            args,
+                        }, span));
                     }
-                    panic!("Expected function name after module.");
-                }
-
-                if matches!(self.current_token(), Token::LeftParen) {
-                    self.advance();
-                    let mut args = Vec::new();
 
-                    while !matches!(self.current_token(), Token::RightParen) {
-                        args.push(self.parse_expression());
+                    let mut expr = Expression::new(ExpressionKind::FieldAccess {
+                        object: Box::new(Expression::new(ExpressionKind::Identifier(name), span)),
+                        field: member,
+                    }, member_span);
 
-                        if matches!(self.current_token(), Token::Comma) {
+                    while matches!(self.current_token(), Token::Dot) {
+                        self.advance();
+                        let field_span = self.current_span();
+                        if let Token::Identifier(field) = self.current_token() {
+                            expr = Expression::new(ExpressionKind::FieldAccess {
+                                object: Box::new(expr),
+                                field: field.clone(),
+                            }, field_span);
                             self.advance();
+                        } else {
+                            return Err(self.error(format!(
+                                "expected field name after '.', found {:?}",
+                                self.current_token()
+                            )));
                         }
                     }
 
-                    if let Err(_) = self.expect(Token::RightParen) {
-                        panic!("Expected closing parenthesis in function call");
-                    }
+                    return Ok(expr);
+                }
+
+                if matches!(self.current_token(), Token::LeftParen) {
+                    let context = format!("call to {}", name);
+                    let args = self.parse_call_args(&context)?;
 
                     if name == "eval" && args.len() == 1 {
-                        return Expression::Eval {
+                        ExpressionKind::Eval {
                             instruction: Box::new(args[0].clone()),
-                        };
-                    }
-
-                    Expression::Call {
-                        function: name,
-                        args,
+                        }
+                    } else {
+                        ExpressionKind::Call {
+                            function: name,
+                            args,
+                        }
                     }
                 } else if matches!(self.current_token(), Token::LBracket) {
                     self.advance();
-                    let index = self.parse_expression();
-                    if let Err(_) = self.expect(Token::RBracket) {
-                        panic!("Expected closing bracket in array access");
-                    }
+                    let index = self.parse_expression()?;
+                    self.expect_closing(Token::RBracket, "']' after array index")?;
 
-                    Expression::ArrayAccess {
+                    ExpressionKind::ArrayAccess {
                         name,
                         index: Box::new(index),
                     }
                 } else {
-                    Expression::Identifier(name)
+                    ExpressionKind::Identifier(name)
                 }
             }
             Token::LeftParen => {
                 self.advance();
-                let expr = self.parse_expression();
-                if let Err(_) = self.expect(Token::RightParen) {
-                    panic!("Expected closing parenthesis");
-                }
-                expr
+                let expr = self.parse_expression()?;
+                self.expect_closing(Token::RightParen, "')'")?;
+                return Ok(expr);
             }
-            _ => panic!("Unexpected token: {:?}", self.current_token()),
-        }
+            token => return Err(self.error(format!("expected expression, found {:?}", token))),
+        };
+        Ok(Expression::new(kind, span))
     }
 }
@@ -1,50 +1,151 @@
 use crate::ast::*;
+use crate::error::{CompileError, ErrorKind};
+use crate::literal;
+use crate::typechecker::EVAL_NOT_FOLDED;
 use std::process::Command;
 use std::fs;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+/// Strings are `char*` to NUL-terminated bytes with their length in the
+/// `long long` before them, the layout the ELF backend uses.
+const STRING_RUNTIME: &str = r#"static char* per_str_new(long long len) {
+    long long* block = malloc(sizeof(long long) + len + 1);
+    if (!block) exit(1);
+    block[0] = len;
+    ((char*)(block + 1))[len] = 0;
+    return (char*)(block + 1);
+}
+
+#define per_str_len(s) (((const long long*)(s))[-1])
+
+static char* per_str_format(const char* format, ...) {
+    va_list args;
+    va_start(args, format);
+    char* s = per_str_new(vsnprintf(NULL, 0, format, args));
+    va_end(args);
+    va_start(args, format);
+    vsprintf(s, format, args);
+    va_end(args);
+    return s;
+}
+
+static char* per_str_concat(const char* a, const char* b) {
+    char* s = per_str_new(per_str_len(a) + per_str_len(b));
+    memcpy(s, a, per_str_len(a));
+    memcpy(s + per_str_len(a), b, per_str_len(b));
+    return s;
+}
+
+static long long per_str_compare(const char* a, const char* b) {
+    long long la = per_str_len(a), lb = per_str_len(b);
+    int c = memcmp(a, b, la < lb ? la : lb);
+    if (c == 0) c = (la > lb) - (la < lb);
+    return (c > 0) - (c < 0);
+}
+
+static char* per_str_slice(const char* s, long long start, long long end) {
+    if (end > per_str_len(s)) end = per_str_len(s);
+    if (end < 0) end = 0;
+    if (start < 0) start = 0;
+    if (start > end) start = end;
+    char* slice = per_str_new(end - start);
+    memcpy(slice, s + start, end - start);
+    return slice;
+}
+
+"#;
 
 pub struct CCodeGen {
     output: String,
+    /// Definitions of the string literals, which carry their length.
+    literals: String,
+    literal_count: usize,
     var_types: HashMap<String, bool>,
+    /// Functions of the program that return a string.
+    string_functions: HashSet<String>,
     temp_counter: usize,
+    loop_stack: Vec<String>,
+    /// Source file of the program being generated, for errors.
+    file: String,
+}
+
+impl Default for CCodeGen {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CCodeGen {
     pub fn new() -> Self {
         CCodeGen {
             output: String::new(),
+            literals: String::new(),
+            literal_count: 0,
             var_types: HashMap::new(),
+            string_functions: HashSet::new(),
             temp_counter: 0,
+            loop_stack: Vec::new(),
+            file: String::new(),
         }
     }
 
-    pub fn generate(&mut self, program: &Program) -> Result<String, String> {
-        self.output.push_str("#include <stdio.h>\n");
-        self.output.push_str("#include <stdlib.h>\n");
-        self.output.push_str("#include <string.h>\n\n");
+    pub fn generate(&mut self, program: &Program) -> Result<String, CompileError> {
+        self.file = program.file.clone();
+        let functions: Vec<&Function> = program.functions.iter().filter(|f| f.external.is_none()).collect();
+        self.string_functions =
+            functions.iter().filter(|f| f.return_type.as_deref() == Some("string")).map(|f| f.name.clone()).collect();
 
-        for func in &program.functions {
+        let mut prototypes = String::new();
+        for func in functions.iter().filter(|f| f.name != "main") {
+            prototypes.push_str(&self.signature(func));
+            prototypes.push_str(";\n");
+        }
+        for func in functions {
             self.generate_function(func)?;
         }
 
-        Ok(self.output.clone())
+        let mut source = String::new();
+        source.push_str("#include <stdarg.h>\n");
+        source.push_str("#include <stdio.h>\n");
+        source.push_str("#include <stdlib.h>\n");
+        source.push_str("#include <string.h>\n\n");
+        source.push_str(STRING_RUNTIME);
+        if !self.literals.is_empty() {
+            source.push_str(&self.literals);
+            source.push('\n');
+        }
+        if !prototypes.is_empty() {
+            source.push_str(&prototypes);
+            source.push('\n');
+        }
+        source.push_str(&self.output);
+        Ok(source)
     }
 
-    fn generate_function(&mut self, func: &Function) -> Result<(), String> {
-        self.output.push_str("void ");
-        self.output.push_str(&func.name);
-        self.output.push_str("(");
-        
-        for (i, param) in func.params.iter().enumerate() {
-            if i > 0 {
-                self.output.push_str(", ");
-            }
-            self.output.push_str("long long ");
-            self.output.push_str(&param.name);
+    /// The C declarator of `func`. `main` returns `int`, as C requires.
+    fn signature(&self, func: &Function) -> String {
+        let return_type = match func.return_type.as_deref() {
+            _ if func.name == "main" => "int",
+            None => "void",
+            Some("string") => "char*",
+            Some(_) => "long long",
+        };
+        let params: Vec<String> = func
+            .params
+            .iter()
+            .map(|param| format!("{}{}", if param.param_type == "string" { "char* " } else { "long long " }, param.name))
+            .collect();
+        let params = if params.is_empty() { "void".to_string() } else { params.join(", ") };
+        format!("{} {}({})", return_type, func.name, params)
+    }
+
+    fn generate_function(&mut self, func: &Function) -> Result<(), CompileError> {
+        for param in &func.params {
+            self.var_types.insert(param.name.clone(), param.param_type == "string");
         }
-        
-        self.output.push_str(") {\n");
+        self.output.push_str(&self.signature(func));
+        self.output.push_str(" {\n");
         
         for stmt in &func.body {
             self.generate_statement(stmt)?;
@@ -54,137 +155,178 @@ impl CCodeGen {
         Ok(())
     }
 
-    fn generate_statement(&mut self, stmt: &Statement) -> Result<(), String> {
-        match stmt {
-            Statement::VarDecl { name, value, .. } => {
-                let is_string = if let Some(val) = value {
-                    matches!(val, Expression::String(_) | Expression::TemplateString {..})
-                } else {
-                    false
-                };
+    fn generate_statement(&mut self, stmt: &Statement) -> Result<(), CompileError> {
+        match &stmt.kind {
+            StatementKind::VarDecl { name, value, .. } => {
+                let is_string = value.as_ref().is_some_and(|val| self.is_string(val));
                 
                 self.var_types.insert(name.clone(), is_string);
-                
-                if let Some(Expression::TemplateString { parts }) = value {
-                    use crate::ast::TemplateStringPart;
-                    
-                    self.output.push_str("    char* ");
-                    self.output.push_str(name);
-                    self.output.push_str(" = malloc(2048);\n    sprintf(");
-                    self.output.push_str(name);
-                    self.output.push_str(", \"");
-                    
-                    let mut args = Vec::new();
-                    for part in parts {
-                        match part {
-                            TemplateStringPart::Literal(lit) => {
-                                self.output.push_str(&lit.replace("%", "%%"));
-                            }
-                            TemplateStringPart::Expression { expr, .. } => {
-                                let is_str = match **expr {
-                                    Expression::Identifier(ref n) => {
-                                        self.var_types.get(n).copied().unwrap_or(false)
-                                    }
-                                    Expression::String(_) => true,
-                                    _ => false,
-                                };
-                                self.output.push_str(if is_str { "%s" } else { "%lld" });
-                                args.push((expr.clone(), is_str));
-                            }
-                        }
-                    }
-                    
-                    self.output.push_str("\"");
-                    for (arg, is_str) in &args {
-                        self.output.push_str(", ");
-                        if *is_str {
-                            self.generate_expression(arg)?;
-                        } else {
-                            self.output.push_str("(long long)(");
-                            self.generate_expression(arg)?;
-                            self.output.push_str(")");
-                        }
-                    }
-                    self.output.push_str(");\n");
+
+                self.output.push_str("    ");
+                if is_string {
+                    self.output.push_str("char* ");
                 } else {
-                    self.output.push_str("    ");
-                    if is_string {
-                        self.output.push_str("char* ");
-                    } else {
-                        self.output.push_str("long long ");
-                    }
-                    self.output.push_str(name);
-                    
-                    if let Some(val) = value {
-                        self.output.push_str(" = ");
-                        self.generate_expression(val)?;
-                    }
-                    self.output.push_str(";\n");
+                    self.output.push_str("long long ");
                 }
+                self.output.push_str(name);
+                
+                if let Some(val) = value {
+                    self.output.push_str(" = ");
+                    self.generate_expression(val)?;
+                }
+                self.output.push_str(";\n");
             }
-            Statement::Expression(expr) => {
+            StatementKind::Expression(expr) => {
                 self.output.push_str("    ");
                 self.generate_expression(expr)?;
                 self.output.push_str(";\n");
             }
-            Statement::Return(expr) => {
+            StatementKind::Return(expr) => {
                 self.output.push_str("    return ");
                 if let Some(e) = expr {
                     self.generate_expression(e)?;
                 }
                 self.output.push_str(";\n");
             }
-            _ => {}
+            StatementKind::Assignment { name, value } => {
+                self.output.push_str(&format!("    {} = ", name));
+                self.generate_expression(value)?;
+                self.output.push_str(";\n");
+            }
+            StatementKind::If { condition, then_body, else_body } => {
+                self.output.push_str("    if (");
+                self.generate_expression(condition)?;
+                self.output.push_str(") {\n");
+                for stmt in then_body {
+                    self.generate_statement(stmt)?;
+                }
+                if let Some(body) = else_body {
+                    self.output.push_str("    } else {\n");
+                    for stmt in body {
+                        self.generate_statement(stmt)?;
+                    }
+                }
+                self.output.push_str("    }\n");
+            }
+            StatementKind::For { init, condition, post, body } => {
+                let continue_label = format!("_continue_{}", self.temp_counter);
+                self.temp_counter += 1;
+
+                self.output.push_str("    {\n");
+                if let Some(init_stmt) = init {
+                    self.generate_statement(init_stmt)?;
+                }
+                self.output.push_str("    while (");
+                match condition {
+                    Some(cond) => self.generate_expression(cond)?,
+                    None => self.output.push('1'),
+                }
+                self.output.push_str(") {\n");
+
+                self.loop_stack.push(continue_label.clone());
+                for stmt in body {
+                    self.generate_statement(stmt)?;
+                }
+                self.loop_stack.pop();
+
+                self.output.push_str(&format!("    {}: ;\n", continue_label));
+                if let Some(post_stmt) = post {
+                    self.generate_statement(post_stmt)?;
+                }
+                self.output.push_str("    }\n");
+                self.output.push_str("    }\n");
+            }
+            StatementKind::Break => {
+                self.output.push_str("    break;\n");
+            }
+            StatementKind::Continue => {
+                if let Some(label) = self.loop_stack.last() {
+                    self.output.push_str(&format!("    goto {};\n", label));
+                }
+            }
+            StatementKind::ArrayDecl { .. } | StatementKind::ArrayAssignment { .. } => {
+                return Err(self.unsupported(stmt.span, "arrays"));
+            }
+            StatementKind::PointerAssignment { .. } => return Err(self.unsupported(stmt.span, "pointers")),
+            StatementKind::FieldAssignment { .. } => return Err(self.unsupported(stmt.span, "struct fields")),
+            StatementKind::InlineAsm { .. } => return Err(self.unsupported(stmt.span, "asm blocks")),
         }
         Ok(())
     }
 
-    fn generate_expression(&mut self, expr: &Expression) -> Result<(), String> {
-        match expr {
-            Expression::Number(n) => {
+    fn generate_expression(&mut self, expr: &Expression) -> Result<(), CompileError> {
+        match &expr.kind {
+            ExpressionKind::Number(n) => {
                 self.output.push_str(&n.to_string());
             }
-            Expression::String(s) => {
-                if s.contains("$(") {
-                    self.generate_string_interpolation(s)?;
-                } else {
-                    self.output.push('"');
-                    self.output.push_str(&s.replace("\\", "\\\\").replace("\"", "\\\""));
-                    self.output.push('"');
-                }
+            ExpressionKind::String(s) => {
+                let name = format!("per_lit_{}", self.literal_count);
+                self.literal_count += 1;
+                self.literals.push_str(&format!(
+                    "static struct {{ long long len; char text[{}]; }} {} = {{ {}, {} }};\n",
+                    s.len() + 1,
+                    name,
+                    s.len(),
+                    literal::c(s.as_bytes()),
+                ));
+                self.output.push_str(&format!("{}.text", name));
             }
-            Expression::Identifier(name) => {
+            ExpressionKind::Identifier(name) => {
                 self.output.push_str(name);
             }
-            Expression::ModuleCall { module, function, args } => {
-                if module == "stdio" {
-                    match function.as_str() {
-                        "PrintlnStr" => {
-                            self.output.push_str("printf(\"%s\\n\", ");
-                            if !args.is_empty() {
-                                self.generate_expression(&args[0])?;
-                            }
-                            self.output.push_str(")");
+            ExpressionKind::ModuleCall { module, function, args } if is_string_builtin(module, function) => {
+                self.output.push_str(&format!("per_str_{}(", function));
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        self.output.push_str(", ");
+                    }
+                    self.generate_expression(arg)?;
+                }
+                self.output.push(')');
+            }
+            ExpressionKind::ModuleCall { module, function, args } if module == "stdio" => {
+                match function.as_str() {
+                    "PrintlnStr" => {
+                        self.output.push_str("printf(\"%s\\n\", ");
+                        if !args.is_empty() {
+                            self.generate_expression(&args[0])?;
                         }
-                        "PrintStr" => {
-                            self.output.push_str("printf(\"%s\", ");
-                            if !args.is_empty() {
-                                self.generate_expression(&args[0])?;
-                            }
-                            self.output.push_str(")");
+                        self.output.push(')');
+                    }
+                    "PrintStr" => {
+                        self.output.push_str("printf(\"%s\", ");
+                        if !args.is_empty() {
+                            self.generate_expression(&args[0])?;
                         }
-                        "Println" => {
-                            self.output.push_str("printf(\"%lld\\n\", (long long)");
-                            if !args.is_empty() {
-                                self.generate_expression(&args[0])?;
-                            }
-                            self.output.push_str(")");
+                        self.output.push(')');
+                    }
+                    "Println" => {
+                        self.output.push_str("printf(\"%lld\\n\", (long long)");
+                        if !args.is_empty() {
+                            self.generate_expression(&args[0])?;
                         }
-                        _ => return Err(format!("Unknown stdio function: {}", function)),
+                        self.output.push(')');
                     }
+                    _ => return Err(self.error(expr.span, format!("Unknown stdio function: {}", function))),
                 }
             }
-            Expression::Binary { op, left, right } => {
+            ExpressionKind::Binary { op: op @ (BinaryOp::Equal | BinaryOp::NotEqual), left, right }
+                if self.is_string(left) || self.is_string(right) =>
+            {
+                self.output.push_str("(per_str_compare(");
+                self.generate_expression(left)?;
+                self.output.push_str(", ");
+                self.generate_expression(right)?;
+                self.output.push_str(if matches!(op, BinaryOp::Equal) { ") == 0)" } else { ") != 0)" });
+            }
+            ExpressionKind::Binary { op: BinaryOp::Concat, left, right } => {
+                self.output.push_str("per_str_concat(");
+                self.generate_expression(left)?;
+                self.output.push_str(", ");
+                self.generate_expression(right)?;
+                self.output.push(')');
+            }
+            ExpressionKind::Binary { op, left, right } => {
                 use crate::ast::BinaryOp;
                 let op_str = match op {
                     BinaryOp::Add => "+",
@@ -202,28 +344,109 @@ impl CCodeGen {
                     BinaryOp::Or => "||",
                     _ => "+",
                 };
-                self.output.push_str("(");
+                self.output.push('(');
                 self.generate_expression(left)?;
                 self.output.push_str(&format!(" {} ", op_str));
                 self.generate_expression(right)?;
-                self.output.push_str(")");
+                self.output.push(')');
             }
-            Expression::TemplateString { parts } => {
-                let temp_name = format!("_temp_str_{}", self.temp_counter);
-                self.temp_counter += 1;
-                
-                self.output.push_str(&temp_name);
+            ExpressionKind::TemplateString { parts } => {
+                let mut format = String::new();
+                let mut args = Vec::new();
+                for part in parts {
+                    match part {
+                        TemplateStringPart::Literal(lit) => format.push_str(&lit.replace('%', "%%")),
+                        TemplateStringPart::Expression { expr, format: spec } => {
+                            let is_str = self.is_string(expr);
+                            format.push('%');
+                            if let Some(spec) = spec {
+                                if spec.padding == '0' && !is_str {
+                                    format.push('0');
+                                }
+                                if let Some(width) = spec.width {
+                                    format.push_str(&width.to_string());
+                                }
+                            }
+                            format.push_str(match spec.as_ref().map(|spec| &spec.format_type) {
+                                _ if is_str => "s",
+                                Some(FormatType::Hex) => "llx",
+                                Some(FormatType::HexUpper) => "llX",
+                                _ => "lld",
+                            });
+                            args.push((expr, is_str));
+                        }
+                    }
+                }
+
+                self.output.push_str("per_str_format(");
+                self.output.push_str(&literal::c(format.as_bytes()));
+                for (arg, is_str) in args {
+                    self.output.push_str(if is_str { ", " } else { ", (long long)(" });
+                    self.generate_expression(arg)?;
+                    if !is_str {
+                        self.output.push(')');
+                    }
+                }
+                self.output.push(')');
+            }
+            ExpressionKind::Unary { op, operand } => {
+                self.output.push_str(match op {
+                    UnaryOp::Neg => "(-",
+                    UnaryOp::Not => "(!",
+                });
+                self.generate_expression(operand)?;
+                self.output.push(')');
+            }
+            ExpressionKind::Call { function, args } => {
+                self.output.push_str(function);
+                self.output.push('(');
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        self.output.push_str(", ");
+                    }
+                    self.generate_expression(arg)?;
+                }
+                self.output.push(')');
+            }
+            ExpressionKind::Cast { operand, target_type } => {
+                let c_type = match target_type.as_str() {
+                    "i32" => "int",
+                    "i8" => "signed char",
+                    "u64" => "unsigned long long",
+                    "u32" => "unsigned int",
+                    "u8" => "unsigned char",
+                    "bool" => "_Bool",
+                    _ => "long long",
+                };
+                self.output.push_str(&format!("(({})", c_type));
+                self.generate_expression(operand)?;
+                self.output.push(')');
+            }
+            ExpressionKind::Eval { .. } => return Err(self.error(expr.span, EVAL_NOT_FOLDED.to_string())),
+            ExpressionKind::ModuleCall { module, .. } => {
+                return Err(self.unsupported(expr.span, &format!("calls into module '{}'", module)));
+            }
+            ExpressionKind::FieldAccess { .. } => return Err(self.unsupported(expr.span, "struct fields")),
+            ExpressionKind::ArrayAccess { .. } => return Err(self.unsupported(expr.span, "arrays")),
+            ExpressionKind::StringIndex { .. } => return Err(self.unsupported(expr.span, "string indexing")),
+            ExpressionKind::AddressOf { .. } | ExpressionKind::Deref { .. } => {
+                return Err(self.unsupported(expr.span, "pointers"));
             }
-            _ => {}
         }
         Ok(())
     }
 
-    fn generate_string_interpolation(&mut self, s: &str) -> Result<(), String> {
-        self.output.push('"');
-        self.output.push_str(&s.replace("\\", "\\\\").replace("\"", "\\\""));
-        self.output.push('"');
-        Ok(())
+    fn is_string(&self, expr: &Expression) -> bool {
+        match &expr.kind {
+            ExpressionKind::String(_) | ExpressionKind::TemplateString { .. } => true,
+            ExpressionKind::Identifier(name) => self.var_types.get(name).copied().unwrap_or(false),
+            ExpressionKind::Binary { op: BinaryOp::Concat, .. } => true,
+            ExpressionKind::Call { function, .. } => self.string_functions.contains(function),
+            ExpressionKind::ModuleCall { module, function, .. } => {
+                is_string_builtin(module, function) && matches!(function.as_str(), "concat" | "slice")
+            }
+            _ => false,
+        }
     }
 
 
@@ -234,7 +457,7 @@ impl CCodeGen {
         fs::write(temp_c, c_code).map_err(|e| e.to_string())?;
 
         let result = if let Ok(output) = Command::new("cl.exe")
-            .args(&["/nologo", "/O2", temp_c, &format!("/Fe:{}", output_path)])
+            .args(["/nologo", "/O2", temp_c, &format!("/Fe:{}", output_path)])
             .current_dir(".")
             .output()
         {
@@ -254,11 +477,11 @@ impl CCodeGen {
             None
         };
         
-        let result = if let Some(r) = result {
+        if let Some(r) = result {
             r
         } else if Command::new("gcc").arg("--version").output().is_ok() {
             let output = Command::new("gcc")
-                .args(&["-O2", temp_c, "-o", output_path])
+                .args(["-O2", temp_c, "-o", output_path])
                 .output()
                 .map_err(|e| format!("Failed to run gcc: {}", e))?;
             
@@ -272,7 +495,7 @@ impl CCodeGen {
             let wsl_output = output_path.replace("\\", "/").replace("E:", "/mnt/e");
             
             let output = Command::new("wsl")
-                .args(&["gcc", "-O2", &wsl_temp_c, "-o", &wsl_output])
+                .args(["gcc", "-O2", &wsl_temp_c, "-o", &wsl_output])
                 .output()
                 .map_err(|e| format!("Failed to run WSL gcc: {}", e))?;
             
@@ -283,11 +506,16 @@ impl CCodeGen {
             }
         } else {
             Err("No compiler found. Install Visual Studio (cl.exe), MinGW (gcc), or WSL with gcc".to_string())
-        };
-
-        if result.is_ok() {
-            println!("Compilation successful: {}", output_path);
         }
-        result
+    }
+
+    fn error(&self, span: Span, message: String) -> CompileError {
+        CompileError::new(ErrorKind::CodeGenError, message, self.file.clone(), span.line, span.column)
+    }
+
+    /// For what the C backend does not lower, instead of emitting C that
+    /// does not compile.
+    fn unsupported(&self, span: Span, what: &str) -> CompileError {
+        self.error(span, format!("{} are not supported by the C backend", what))
     }
 }
@@ -1,4 +1,6 @@
 use crate::ast::*;
+use crate::error::{CompileError, ErrorKind};
+use crate::typechecker::EVAL_NOT_FOLDED;
 use std::collections::HashMap;
 
 pub struct CodeGen<'a> {
@@ -11,6 +13,7 @@ pub struct CodeGen<'a> {
     target: String,
     program: Option<&'a Program>,
     in_main: bool,
+    loop_stack: Vec<(Vec<usize>, Vec<usize>)>,
 }
 
 impl<'a> CodeGen<'a> {
@@ -24,23 +27,24 @@ impl<'a> CodeGen<'a> {
             target: target.to_string(),
             program: None,
             in_main: false,
+            loop_stack: Vec::new(),
         }
     }
 
-    pub fn generate(&mut self, program: &'a Program) -> MachineCode {
+    pub fn generate(&mut self, program: &'a Program) -> Result<MachineCode, CompileError> {
         self.program = Some(program);
         self.in_main = true;
 
-        let main_func = program.functions.iter()
-            .find(|f| f.name == "main")
-            .expect("No main function found");
+        let Some(main_func) = program.functions.iter().find(|f| f.name == "main") else {
+            return Err(CompileError::new(ErrorKind::CodeGenError, "no main function".to_string(), program.file.clone(), 1, 1));
+        };
 
         if self.target == "elf" {
             self.emit(&[0x55]);
             self.emit(&[0x48, 0x89, 0xE5]);
 
             for stmt in &main_func.body {
-                self.generate_statement(stmt);
+                self.generate_statement(stmt)?;
             }
             self.emit_exit_with_rax();
         } else {
@@ -49,47 +53,48 @@ impl<'a> CodeGen<'a> {
             self.emit(&[0x48, 0x83, 0xEC, 0x40]);
 
             for stmt in &main_func.body {
-                self.generate_statement(stmt);
+                self.generate_statement(stmt)?;
             }
 
             self.emit_exit(0);
         }
 
-        MachineCode {
+        Ok(MachineCode {
             code: self.code.clone(),
             data: self.data.clone(),
             entry_point: 0,
-        }
+        })
     }
 
     #[allow(dead_code)]
-    fn generate_function(&mut self, func: &Function) {
+    fn generate_function(&mut self, func: &Function) -> Result<(), CompileError> {
         self.emit(&[0x55]);
         self.emit(&[0x48, 0x89, 0xE5]);
 
         self.emit(&[0x48, 0x83, 0xEC, 0x40]);
 
         for stmt in &func.body {
-            self.generate_statement(stmt);
+            self.generate_statement(stmt)?;
         }
 
         self.emit(&[0x48, 0x89, 0xEC]);
         self.emit(&[0x5D]);
         self.emit(&[0xC3]);
+        Ok(())
     }
 
-    fn generate_statement(&mut self, stmt: &Statement) {
-        match stmt {
-            Statement::VarDecl { name, var_type: _, value } => {
+    fn generate_statement(&mut self, stmt: &Statement) -> Result<(), CompileError> {
+        match &stmt.kind {
+            StatementKind::VarDecl { name, var_type: _, value } => {
                 if let Some(expr) = value {
-                    self.generate_expression(expr);
+                    self.generate_expression(expr)?;
                     self.stack_offset -= 8;
                     self.variables.insert(name.clone(), self.stack_offset);
                     self.emit(&[0x48, 0x89, 0x85]);
                     self.emit_i32(self.stack_offset);
                 }
             }
-            Statement::ArrayDecl { name, element_type: _, size } => {
+            StatementKind::ArrayDecl { name, element_type: _, size, .. } => {
                 let array_size = (*size as i32) * 8;
                 self.stack_offset -= array_size;
                 self.variables.insert(name.clone(), self.stack_offset);
@@ -100,15 +105,15 @@ impl<'a> CodeGen<'a> {
                     self.emit_i32(0);
                 }
             }
-            Statement::ArrayAssignment { name, index, value } => {
-                self.generate_expression(value);
+            StatementKind::ArrayAssignment { name, index, value } => {
+                self.generate_expression(value)?;
                 self.emit(&[0x50]);
 
-                self.generate_expression(index);
+                self.generate_expression(index)?;
 
                 if let Some(&base_offset) = self.variables.get(name) {
                     self.emit(&[0x48, 0x6B, 0xC0, 0x08]);
-                    if base_offset >= -128 && base_offset < 128 {
+                    if (-128..128).contains(&base_offset) {
                         self.emit(&[0x48, 0x83, 0xC0, (base_offset as u8)]);
                     } else {
                         self.emit(&[0x48, 0x05]);
@@ -120,25 +125,25 @@ impl<'a> CodeGen<'a> {
                     self.emit(&[0x48, 0x89, 0x08]);
                 }
             }
-            Statement::Assignment { name, value } => {
-                self.generate_expression(value);
+            StatementKind::Assignment { name, value } => {
+                self.generate_expression(value)?;
                 if let Some(&offset) = self.variables.get(name) {
                     self.emit(&[0x48, 0x89, 0x85]);
                     self.emit_i32(offset);
                 }
             }
-            Statement::PointerAssignment { target, value } => {
-                self.generate_expression(value);
+            StatementKind::PointerAssignment { target, value } => {
+                self.generate_expression(value)?;
                 self.emit(&[0x50]);
                 
-                self.generate_expression(target);
+                self.generate_expression(target)?;
                 
                 self.emit(&[0x59]);
                 self.emit(&[0x48, 0x89, 0x08]);
             }
-            Statement::Return(expr) => {
+            StatementKind::Return(expr) => {
                 if let Some(e) = expr {
-                    self.generate_expression(e);
+                    self.generate_expression(e)?;
                 } else {
                     self.emit(&[0x48, 0x31, 0xC0]);
                 }
@@ -154,13 +159,13 @@ impl<'a> CodeGen<'a> {
                     }
                 }
             }
-            Statement::Expression(expr) => {
-                self.generate_expression(expr);
+            StatementKind::Expression(expr) => {
+                self.generate_expression(expr)?;
             }
-            Statement::InlineAsm { .. } => {
+            StatementKind::InlineAsm { .. } | StatementKind::FieldAssignment { .. } => {
             }
-            Statement::If { condition, then_body, else_body } => {
-                self.generate_expression(condition);
+            StatementKind::If { condition, then_body, else_body } => {
+                self.generate_expression(condition)?;
 
                 self.emit(&[0x48, 0x85, 0xC0]);
 
@@ -169,7 +174,7 @@ impl<'a> CodeGen<'a> {
                 self.emit_i32(0);
 
                 for stmt in then_body {
-                    self.generate_statement(stmt);
+                    self.generate_statement(stmt)?;
                 }
 
                 self.emit(&[0xE9]);
@@ -182,7 +187,7 @@ impl<'a> CodeGen<'a> {
 
                 if let Some(body) = else_body {
                     for stmt in body {
-                        self.generate_statement(stmt);
+                        self.generate_statement(stmt)?;
                     }
                 }
 
@@ -190,57 +195,81 @@ impl<'a> CodeGen<'a> {
                 let end_offset = (end_label as i32) - (end_jump_pos as i32) - 4;
                 self.patch_i32(end_jump_pos, end_offset);
             }
-            Statement::For { init: _, condition, post: _, body } => {
+            StatementKind::For { init, condition, post, body } => {
+                if let Some(init_stmt) = init {
+                    self.generate_statement(init_stmt)?;
+                }
+
                 let loop_start = self.code.len();
 
+                let mut end_jump_pos = None;
                 if let Some(cond) = condition {
-                    self.generate_expression(cond);
+                    self.generate_expression(cond)?;
                     self.emit(&[0x48, 0x85, 0xC0]);
                     self.emit(&[0x0F, 0x84]);
-                    let end_jump_pos = self.code.len();
+                    end_jump_pos = Some(self.code.len());
                     self.emit_i32(0);
+                }
 
-                    for stmt in body {
-                        self.generate_statement(stmt);
-                    }
+                self.loop_stack.push((Vec::new(), Vec::new()));
+                for stmt in body {
+                    self.generate_statement(stmt)?;
+                }
+                let (break_jumps, continue_jumps) = self.loop_stack.pop().unwrap_or_default();
 
-                    self.emit(&[0xE9]);
-                    let back_offset = (loop_start as i32) - (self.code.len() as i32) - 4;
-                    self.emit_i32(back_offset);
+                let continue_label = self.code.len();
+                for pos in continue_jumps {
+                    self.patch_i32(pos, (continue_label as i32) - (pos as i32) - 4);
+                }
 
-                    let end_label = self.code.len();
-                    let end_offset = (end_label as i32) - (end_jump_pos as i32) - 4;
-                    self.patch_i32(end_jump_pos, end_offset);
-                } else {
-                    for stmt in body {
-                        self.generate_statement(stmt);
-                    }
+                if let Some(post_stmt) = post {
+                    self.generate_statement(post_stmt)?;
+                }
+
+                self.emit(&[0xE9]);
+                let back_offset = (loop_start as i32) - (self.code.len() as i32) - 4;
+                self.emit_i32(back_offset);
 
+                let end_label = self.code.len();
+                for pos in end_jump_pos.into_iter().chain(break_jumps) {
+                    let end_offset = (end_label as i32) - (pos as i32) - 4;
+                    self.patch_i32(pos, end_offset);
+                }
+            }
+            StatementKind::Break | StatementKind::Continue => {
+                if !self.loop_stack.is_empty() {
                     self.emit(&[0xE9]);
-                    let back_offset = (loop_start as i32) - (self.code.len() as i32) - 4;
-                    self.emit_i32(back_offset);
+                    let pos = self.code.len();
+                    self.emit_i32(0);
+                    let (break_jumps, continue_jumps) = self.loop_stack.last_mut().unwrap();
+                    if matches!(stmt.kind, StatementKind::Break) {
+                        break_jumps.push(pos);
+                    } else {
+                        continue_jumps.push(pos);
+                    }
                 }
             }
         }
+        Ok(())
     }
 
-    fn generate_expression(&mut self, expr: &Expression) {
-        match expr {
-            Expression::Number(n) => {
+    fn generate_expression(&mut self, expr: &Expression) -> Result<(), CompileError> {
+        match &expr.kind {
+            ExpressionKind::Number(n) => {
                 self.emit(&[0x48, 0xB8]);
                 self.emit_i64(*n);
             }
-            Expression::Identifier(name) => {
+            ExpressionKind::Identifier(name) => {
                 if let Some(&offset) = self.variables.get(name) {
                     self.emit(&[0x48, 0x8B, 0x85]);
                     self.emit_i32(offset);
                 }
             }
-            Expression::Binary { op, left, right } => {
-                self.generate_expression(right);
+            ExpressionKind::Binary { op, left, right } => {
+                self.generate_expression(right)?;
                 self.emit(&[0x50]);
 
-                self.generate_expression(left);
+                self.generate_expression(left)?;
                 self.emit(&[0x59]);
 
                 match op {
@@ -297,8 +326,8 @@ impl<'a> CodeGen<'a> {
                     _ => {}
                 }
             }
-            Expression::Unary { op, operand } => {
-                self.generate_expression(operand);
+            ExpressionKind::Unary { op, operand } => {
+                self.generate_expression(operand)?;
                 match op {
                     UnaryOp::Neg => {
                         self.emit(&[0x48, 0xF7, 0xD8]);
@@ -310,12 +339,12 @@ impl<'a> CodeGen<'a> {
                     }
                 }
             }
-            Expression::ArrayAccess { name, index } => {
-                self.generate_expression(index);
+            ExpressionKind::ArrayAccess { name, index } => {
+                self.generate_expression(index)?;
 
                 if let Some(&base_offset) = self.variables.get(name) {
                     self.emit(&[0x48, 0x6B, 0xC0, 0x08]);
-                    if base_offset >= -128 && base_offset < 128 {
+                    if (-128..128).contains(&base_offset) {
                         self.emit(&[0x48, 0x83, 0xC0, (base_offset as u8)]);
                     } else {
                         self.emit(&[0x48, 0x05]);
@@ -326,23 +355,23 @@ impl<'a> CodeGen<'a> {
                     self.emit(&[0x48, 0x8B, 0x00]);
                 }
             }
-            Expression::Call { function, args } => {
+            ExpressionKind::Call { function, args } => {
                 if function == "exit" {
                     self.emit_exit(0);
                 } else if function == "println" {
                     if !args.is_empty() {
-                        match &args[0] {
-                            Expression::String(s) => {
+                        match &args[0].kind {
+                            ExpressionKind::String(s) => {
                                 self.emit_println(s);
                             }
                             _ => {
-                                self.generate_expression(&args[0]);
+                                self.generate_expression(&args[0])?;
                                 self.emit_println_int();
                             }
                         }
                     }
                 } else if function == "len" && args.len() == 1 {
-                    if let Expression::String(s) = &args[0] {
+                    if let ExpressionKind::String(s) = &args[0].kind {
                         self.emit(&[0x48, 0xB8]);
                         self.emit_i64(s.len() as i64);
                     } else {
@@ -351,8 +380,8 @@ impl<'a> CodeGen<'a> {
                 } else if function == "concat" && args.len() == 2 {
                     self.emit(&[0x48, 0x31, 0xC0]);
                 } else if function == "compare" && args.len() == 2 {
-                    if let Expression::String(s1) = &args[0] {
-                        if let Expression::String(s2) = &args[1] {
+                    if let ExpressionKind::String(s1) = &args[0].kind {
+                        if let ExpressionKind::String(s2) = &args[1].kind {
                             let result = if s1 == s2 { 0 } else if s1 < s2 { -1 } else { 1 };
                             self.emit(&[0x48, 0xB8]);
                             self.emit_i64(result);
@@ -363,33 +392,54 @@ impl<'a> CodeGen<'a> {
                         self.emit(&[0x48, 0x31, 0xC0]);
                     }
                 } else {
-                    self.generate_iperine_call(function, args);
+                    self.generate_iperine_call(function, args)?;
                 }
             }
-            Expression::ModuleCall { module, function, args } => {
-                self.generate_module_call(module, function, args);
+            ExpressionKind::ModuleCall { module, function, args } => {
+                self.generate_module_call(module, function, args, expr.span)?;
             }
-            Expression::StringIndex { string, index } => {
-                if let Expression::String(_s) = string.as_ref() {
-                    self.generate_expression(index);
+            ExpressionKind::StringIndex { string, index } => {
+                if let ExpressionKind::String(_s) = &string.kind {
+                    self.generate_expression(index)?;
                 }
             }
-            Expression::AddressOf { operand } => {
-                if let Expression::Identifier(name) = operand.as_ref() {
+            ExpressionKind::AddressOf { operand } => {
+                if let ExpressionKind::Identifier(name) = &operand.kind {
                     if let Some(&offset) = self.variables.get(name) {
                         self.emit(&[0x48, 0x8D, 0x85]);
                         self.emit_i32(offset);
                     }
                 }
             }
-            Expression::Deref { operand } => {
-                self.generate_expression(operand);
+            ExpressionKind::Deref { operand } => {
+                self.generate_expression(operand)?;
                 self.emit(&[0x48, 0x8B, 0x00]);
             }
-            Expression::Eval { instruction: _ } => {
-                self.emit(&[0x48, 0xC7, 0xC0, 0x00, 0x00, 0x00, 0x00]);
+            ExpressionKind::Cast { operand, target_type } => {
+                self.generate_expression(operand)?;
+                match target_type.as_str() {
+                    "i32" => self.emit(&[0x48, 0x63, 0xC0]),
+                    "u32" => self.emit(&[0x89, 0xC0]),
+                    "i8" => self.emit(&[0x48, 0x0F, 0xBE, 0xC0]),
+                    "u8" => self.emit(&[0x48, 0x0F, 0xB6, 0xC0]),
+                    "bool" => {
+                        self.emit(&[0x48, 0x85, 0xC0]);
+                        self.emit(&[0x0F, 0x95, 0xC0]);
+                        self.emit(&[0x48, 0x0F, 0xB6, 0xC0]);
+                    }
+                    _ => {}
+                }
+            }
+            ExpressionKind::Eval { .. } => {
+                return Err(CompileError::new(
+                    ErrorKind::CodeGenError,
+                    EVAL_NOT_FOLDED.to_string(),
+                    self.program.map_or_else(String::new, |program| program.file.clone()),
+                    expr.span.line,
+                    expr.span.column,
+                ));
             }
-            Expression::String(s) => {
+            ExpressionKind::String(s) => {
                 if s.contains("$(") {
                     self.generate_string_interpolation(s);
                 } else {
@@ -406,6 +456,7 @@ impl<'a> CodeGen<'a> {
             }
             _ => {}
         }
+        Ok(())
     }
     
     fn generate_string_interpolation(&mut self, s: &str) {
@@ -533,7 +584,7 @@ impl<'a> CodeGen<'a> {
             let str_len = text.len() + 1;
 
             self.emit(&[0xEB]);
-            self.emit(&[(str_len as u8) & 0xFF]);
+            self.emit(&[str_len as u8]);
 
             let string_addr = self.code.len();
             self.code.extend_from_slice(text.as_bytes());
@@ -563,7 +614,7 @@ impl<'a> CodeGen<'a> {
             self.emit(&[0x48, 0x89, 0xC3]);
 
             self.emit(&[0xEB]);
-            self.emit(&[(str_len as u8) & 0xFF]);
+            self.emit(&[str_len as u8]);
 
             let string_addr = self.code.len();
             self.code.extend_from_slice(text.as_bytes());
@@ -736,7 +787,7 @@ impl<'a> CodeGen<'a> {
         }
     }
 
-    fn generate_iperine_call(&mut self, function: &str, args: &[Expression]) {
+    fn generate_iperine_call(&mut self, function: &str, args: &[Expression]) -> Result<(), CompileError> {
         let saved_vars = self.variables.clone();
         let saved_offset = self.stack_offset;
         let saved_in_main = self.in_main;
@@ -746,7 +797,7 @@ impl<'a> CodeGen<'a> {
             if let Some(func) = prog.functions.iter().find(|f| f.name == function) {
                 for (i, arg) in args.iter().enumerate() {
                     if i < func.params.len() {
-                        self.generate_expression(arg);
+                        self.generate_expression(arg)?;
                         self.stack_offset -= 8;
                         self.variables.insert(func.params[i].name.clone(), self.stack_offset);
                         self.emit(&[0x48, 0x89, 0x85]);
@@ -755,7 +806,7 @@ impl<'a> CodeGen<'a> {
                 }
 
                 for stmt in &func.body {
-                    self.generate_statement(stmt);
+                    self.generate_statement(stmt)?;
                 }
             }
         }
@@ -763,11 +814,13 @@ impl<'a> CodeGen<'a> {
         self.variables = saved_vars;
         self.stack_offset = saved_offset;
         self.in_main = saved_in_main;
+        Ok(())
     }
 
-    fn generate_stdio_println(&mut self, value: &Expression) {
-        self.generate_expression(value);
+    fn generate_stdio_println(&mut self, value: &Expression) -> Result<(), CompileError> {
+        self.generate_expression(value)?;
         self.emit_println_int();
+        Ok(())
     }
 
     fn emit_print_int(&mut self) {
@@ -897,7 +950,7 @@ impl<'a> CodeGen<'a> {
             let str_len = text.len();
 
             self.emit(&[0xEB]);
-            self.emit(&[(str_len as u8) & 0xFF]);
+            self.emit(&[str_len as u8]);
 
             let string_addr = self.code.len();
             self.code.extend_from_slice(text.as_bytes());
@@ -926,7 +979,7 @@ impl<'a> CodeGen<'a> {
             self.emit(&[0x48, 0x89, 0xC3]);
 
             self.emit(&[0xEB]);
-            self.emit(&[(str_len as u8) & 0xFF]);
+            self.emit(&[str_len as u8]);
 
             let string_addr = self.code.len();
             self.code.extend_from_slice(text.as_bytes());
@@ -1123,38 +1176,38 @@ impl<'a> CodeGen<'a> {
         }
     }
 
-    fn generate_module_call(&mut self, module: &str, function: &str, args: &[Expression]) {
+    fn generate_module_call(&mut self, module: &str, function: &str, args: &[Expression], span: Span) -> Result<(), CompileError> {
         if module == "stdio" {
             if function == "Println" && args.len() == 1 {
-                self.generate_stdio_println(&args[0]);
-                return;
+                self.generate_stdio_println(&args[0])?;
+                return Ok(());
             } else if function == "Print" && args.len() == 1 {
-                self.generate_expression(&args[0]);
+                self.generate_expression(&args[0])?;
                 self.emit_print_int();
-                return;
+                return Ok(());
             } else if function == "PrintlnStr" && args.len() == 1 {
-                if let Expression::String(s) = &args[0] {
+                if let ExpressionKind::String(s) = &args[0].kind {
                     self.emit_println(s);
                 }
-                return;
+                return Ok(());
             } else if function == "PrintStr" && args.len() == 1 {
-                if let Expression::String(s) = &args[0] {
+                if let ExpressionKind::String(s) = &args[0].kind {
                     self.emit_print_str(s);
                 }
-                return;
+                return Ok(());
             } else if function == "PrintChar" && args.len() == 1 {
-                self.generate_expression(&args[0]);
+                self.generate_expression(&args[0])?;
                 self.emit_print_char();
-                return;
+                return Ok(());
             } else if function == "ReadInt" && args.is_empty() {
                 self.emit_read_int();
-                return;
+                return Ok(());
             } else if function == "ReadChar" && args.is_empty() {
                 self.emit_read_char();
-                return;
+                return Ok(());
             } else if function == "Flush" && args.is_empty() {
                 self.emit_flush();
-                return;
+                return Ok(());
             }
         }
         let saved_vars = self.variables.clone();
@@ -1165,13 +1218,9 @@ impl<'a> CodeGen<'a> {
         if let Some(prog) = self.program {
             if let Some(module_def) = prog.modules.get(module) {
                 if let Some(func) = module_def.functions.iter().find(|f| f.name == function) {
-                    if !func.is_exported {
-                        panic!("Function '{}' is not exported from module '{}'", function, module);
-                    }
-
                     for (i, arg) in args.iter().enumerate() {
                         if i < func.params.len() {
-                            self.generate_expression(arg);
+                            self.generate_expression(arg)?;
                             self.stack_offset -= 8;
                             self.variables.insert(func.params[i].name.clone(), self.stack_offset);
                             self.emit(&[0x48, 0x89, 0x85]);
@@ -1180,26 +1229,37 @@ impl<'a> CodeGen<'a> {
                     }
 
                     for stmt in &func.body {
-                        self.generate_statement(stmt);
+                        self.generate_statement(stmt)?;
                     }
                 } else {
-                    panic!("Function '{}' not found in module '{}'", function, module);
+                    return Err(CompileError::new(
+                        ErrorKind::CodeGenError,
+                        format!("module '{}' has no function '{}'", module, function),
+                        prog.file.clone(),
+                        span.line,
+                        span.column,
+                    ));
                 }
             } else {
-                panic!("Module '{}' not found", module);
+                return Err(CompileError::new(
+                    ErrorKind::CodeGenError,
+                    format!("unknown module '{}'", module),
+                    prog.file.clone(),
+                    span.line,
+                    span.column,
+                ));
             }
         }
 
         self.variables = saved_vars;
         self.stack_offset = saved_offset;
         self.in_main = saved_in_main;
+        Ok(())
     }
 }
 
 pub struct MachineCode {
     pub code: Vec<u8>,
-    #[allow(dead_code)]
     pub data: Vec<u8>,
-    #[allow(dead_code)]
     pub entry_point: usize,
 }
@@ -0,0 +1,502 @@
+use crate::ast::*;
+use crate::typechecker::{Type, EVAL_NOT_FOLDED};
+use std::collections::HashMap;
+use std::io::Write;
+
+const MAX_CALL_DEPTH: usize = 1000;
+
+/// A value held in a memory slot. Struct fields and array elements live in
+/// slots of their own so pointers can refer to them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Str(String),
+    Ptr(usize),
+    Array(Vec<usize>),
+    Struct(Vec<(String, usize)>),
+    Void,
+}
+
+enum Flow {
+    Next,
+    Break,
+    Continue,
+    Return(Value),
+}
+
+type Frame = HashMap<String, usize>;
+type Eval<T> = Result<T, String>;
+
+/// Walks the AST of a checked program. Variables declared at the top level
+/// persist across calls to `execute`.
+pub struct Interpreter {
+    memory: Vec<Value>,
+    /// The declared type of each slot; integers stored into a sized slot
+    /// wrap to its width.
+    types: Vec<Type>,
+    globals: Frame,
+    depth: usize,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Interpreter { memory: Vec::new(), types: Vec::new(), globals: Frame::new(), depth: 0 }
+    }
+
+    /// Runs top-level statements of `program`. Returns the value of the last
+    /// one if it is an expression.
+    pub fn execute(&mut self, program: &Program, statements: &[Statement], out: &mut dyn Write) -> Eval<Value> {
+        let mut frame = std::mem::take(&mut self.globals);
+        let mut context = Context { program, out };
+        let mut last = Value::Void;
+        let mut result = Ok(());
+        for statement in statements {
+            if let StatementKind::Expression(expr) = &statement.kind {
+                match self.eval(expr, &mut frame, &mut context) {
+                    Ok(value) => last = value,
+                    Err(e) => {
+                        result = Err(e);
+                        break;
+                    }
+                }
+                continue;
+            }
+            last = Value::Void;
+            match self.statement(statement, &mut frame, &mut context) {
+                Ok(Flow::Next) => {}
+                Ok(_) => {
+                    result = Err("'return', 'break' and 'continue' need an enclosing function or loop".to_string());
+                    break;
+                }
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        self.globals = frame;
+        let _ = context.out.flush();
+        result.map(|_| last)
+    }
+
+    /// Renders a value the way `stdio.Print` would.
+    pub fn display(&self, value: &Value) -> String {
+        match value {
+            Value::Int(n) => n.to_string(),
+            Value::Str(s) => s.clone(),
+            Value::Ptr(slot) => format!("0x{:x}", slot),
+            Value::Array(items) => {
+                let items: Vec<String> = items.iter().map(|slot| self.display(&self.memory[*slot])).collect();
+                format!("[{}]", items.join(", "))
+            }
+            Value::Struct(fields) => {
+                let fields: Vec<String> = fields.iter()
+                    .map(|(name, slot)| format!("{}: {}", name, self.display(&self.memory[*slot])))
+                    .collect();
+                format!("{{ {} }}", fields.join(", "))
+            }
+            Value::Void => String::new(),
+        }
+    }
+
+    fn alloc(&mut self, ty: &str, structs: &[StructDecl]) -> usize {
+        let value = match structs.iter().find(|s| s.name == ty) {
+            Some(decl) => Value::Struct(decl.fields.iter().map(|f| (f.name.clone(), self.alloc(&f.field_type, structs))).collect()),
+            None if ty == "string" => Value::Str(String::new()),
+            None => Value::Int(0),
+        };
+        self.memory.push(value);
+        self.types.push(Type::from_string(ty));
+        self.memory.len() - 1
+    }
+
+    fn store(&mut self, slot: usize, value: Value) {
+        self.memory[slot] = match value {
+            Value::Int(n) => Value::Int(wrap(n, &self.types[slot])),
+            value => value,
+        };
+    }
+
+    fn statement(&mut self, statement: &Statement, frame: &mut Frame, context: &mut Context) -> Eval<Flow> {
+        match &statement.kind {
+            StatementKind::VarDecl { name, var_type, value } => {
+                let structs = &context.program.structs;
+                let slot = self.alloc(var_type.as_deref().unwrap_or(""), structs);
+                if let Some(value) = value {
+                    let value = self.eval(value, frame, context)?;
+                    self.store(slot, value);
+                }
+                frame.insert(name.clone(), slot);
+            }
+            StatementKind::ArrayDecl { name, element_type, size, .. } => {
+                let elements = (0..*size).map(|_| self.alloc(element_type, &context.program.structs)).collect();
+                self.memory.push(Value::Array(elements));
+                self.types.push(Type::Unknown);
+                frame.insert(name.clone(), self.memory.len() - 1);
+            }
+            StatementKind::Assignment { name, value } => {
+                let slot = self.variable(name, frame)?;
+                let value = self.eval(value, frame, context)?;
+                self.store(slot, value);
+            }
+            StatementKind::ArrayAssignment { name, index, value } => {
+                let slot = self.element(name, index, frame, context)?;
+                let value = self.eval(value, frame, context)?;
+                self.store(slot, value);
+            }
+            StatementKind::PointerAssignment { target, value } => {
+                let Value::Ptr(slot) = self.eval(target, frame, context)? else {
+                    return Err("assignment through a value that is not a pointer".to_string());
+                };
+                let value = self.eval(value, frame, context)?;
+                self.store(slot, value);
+            }
+            StatementKind::FieldAssignment { object, field, value } => {
+                let slot = self.field(object, field, frame, context)?;
+                let value = self.eval(value, frame, context)?;
+                self.store(slot, value);
+            }
+            StatementKind::If { condition, then_body, else_body } => {
+                if self.truthy(condition, frame, context)? {
+                    return self.block(then_body, frame, context);
+                } else if let Some(else_body) = else_body {
+                    return self.block(else_body, frame, context);
+                }
+            }
+            StatementKind::For { init, condition, post, body } => {
+                if let Some(init) = init {
+                    self.statement(init, frame, context)?;
+                }
+                loop {
+                    if let Some(condition) = condition {
+                        if !self.truthy(condition, frame, context)? {
+                            break;
+                        }
+                    }
+                    match self.block(body, frame, context)? {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        Flow::Next | Flow::Continue => {}
+                    }
+                    if let Some(post) = post {
+                        self.statement(post, frame, context)?;
+                    }
+                }
+            }
+            StatementKind::Return(value) => {
+                let value = match value {
+                    Some(value) => self.eval(value, frame, context)?,
+                    None => Value::Void,
+                };
+                return Ok(Flow::Return(value));
+            }
+            StatementKind::Break => return Ok(Flow::Break),
+            StatementKind::Continue => return Ok(Flow::Continue),
+            StatementKind::Expression(expr) => {
+                self.eval(expr, frame, context)?;
+            }
+            StatementKind::InlineAsm { .. } => return Err("inline assembly cannot be interpreted".to_string()),
+        }
+        Ok(Flow::Next)
+    }
+
+    fn block(&mut self, body: &[Statement], frame: &mut Frame, context: &mut Context) -> Eval<Flow> {
+        for statement in body {
+            match self.statement(statement, frame, context)? {
+                Flow::Next => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn truthy(&mut self, expr: &Expression, frame: &mut Frame, context: &mut Context) -> Eval<bool> {
+        Ok(self.int(expr, frame, context)? != 0)
+    }
+
+    fn int(&mut self, expr: &Expression, frame: &mut Frame, context: &mut Context) -> Eval<i64> {
+        match self.eval(expr, frame, context)? {
+            Value::Int(n) => Ok(n),
+            Value::Ptr(slot) => Ok(slot as i64),
+            other => Err(format!("expected a number, found {}", self.describe(&other))),
+        }
+    }
+
+    fn describe(&self, value: &Value) -> &'static str {
+        match value {
+            Value::Int(_) => "a number",
+            Value::Str(_) => "a string",
+            Value::Ptr(_) => "a pointer",
+            Value::Array(_) => "an array",
+            Value::Struct(_) => "a struct",
+            Value::Void => "no value",
+        }
+    }
+
+    fn variable(&self, name: &str, frame: &Frame) -> Eval<usize> {
+        frame.get(name).copied().ok_or_else(|| format!("variable '{}' is not defined", name))
+    }
+
+    fn element(&mut self, name: &str, index: &Expression, frame: &mut Frame, context: &mut Context) -> Eval<usize> {
+        let slot = self.variable(name, frame)?;
+        let index = self.int(index, frame, context)?;
+        match &self.memory[slot] {
+            Value::Array(items) => usize::try_from(index).ok()
+                .and_then(|i| items.get(i).copied())
+                .ok_or_else(|| format!("index {} is out of bounds for '{}' of length {}", index, name, items.len())),
+            _ => Err(format!("'{}' is not an array", name)),
+        }
+    }
+
+    /// The slot of `object.field`, following pointers to structs.
+    fn field(&mut self, object: &Expression, field: &str, frame: &mut Frame, context: &mut Context) -> Eval<usize> {
+        let mut slot = match &object.kind {
+            ExpressionKind::Identifier(name) => self.variable(name, frame)?,
+            ExpressionKind::FieldAccess { object, field } => self.field(object, field, frame, context)?,
+            _ => match self.eval(object, frame, context)? {
+                Value::Ptr(slot) => slot,
+                _ => return Err(format!("cannot access field '{}' here", field)),
+            },
+        };
+        while let Value::Ptr(target) = self.memory[slot] {
+            slot = target;
+        }
+        match &self.memory[slot] {
+            Value::Struct(fields) => fields.iter().find(|(name, _)| name == field).map(|(_, slot)| *slot)
+                .ok_or_else(|| format!("no field '{}'", field)),
+            other => Err(format!("cannot access field '{}' on {}", field, self.describe(other))),
+        }
+    }
+
+    fn eval(&mut self, expr: &Expression, frame: &mut Frame, context: &mut Context) -> Eval<Value> {
+        Ok(match &expr.kind {
+            ExpressionKind::Number(n) => Value::Int(*n),
+            ExpressionKind::String(s) => Value::Str(s.clone()),
+            ExpressionKind::TemplateString { parts } => {
+                let mut text = String::new();
+                for part in parts {
+                    match part {
+                        TemplateStringPart::Literal(literal) => text.push_str(literal),
+                        TemplateStringPart::Expression { expr, format } => {
+                            let value = self.eval(expr, frame, context)?;
+                            text.push_str(&self.format(&value, format.as_ref()));
+                        }
+                    }
+                }
+                Value::Str(text)
+            }
+            ExpressionKind::Identifier(name) => self.memory[self.variable(name, frame)?].clone(),
+            ExpressionKind::Binary { op: BinaryOp::And, left, right } => {
+                Value::Int((self.truthy(left, frame, context)? && self.truthy(right, frame, context)?) as i64)
+            }
+            ExpressionKind::Binary { op: BinaryOp::Or, left, right } => {
+                Value::Int((self.truthy(left, frame, context)? || self.truthy(right, frame, context)?) as i64)
+            }
+            ExpressionKind::Binary { op, left, right } => {
+                let left = self.eval(left, frame, context)?;
+                let right = self.eval(right, frame, context)?;
+                self.binary(op, left, right)?
+            }
+            ExpressionKind::Unary { op, operand } => {
+                let n = self.int(operand, frame, context)?;
+                match op {
+                    UnaryOp::Neg => Value::Int(n.wrapping_neg()),
+                    UnaryOp::Not => Value::Int((n == 0) as i64),
+                }
+            }
+            ExpressionKind::Call { function, args } => {
+                let program = context.program;
+                let Some(func) = program.functions.iter().find(|f| &f.name == function) else {
+                    return Err(format!("function '{}' is not defined", function));
+                };
+                self.call(func, args, frame, context)?
+            }
+            ExpressionKind::ModuleCall { module, function, args } if module == "stdio" => {
+                self.stdio(function, args, frame, context)?
+            }
+            ExpressionKind::ModuleCall { module, function, args } if is_string_builtin(module, function) => {
+                self.string(function, args, frame, context)?
+            }
+            ExpressionKind::ModuleCall { module, function, args } => {
+                let program = context.program;
+                let func = program.modules.get(module)
+                    .and_then(|m| m.functions.iter().find(|f| &f.name == function))
+                    .ok_or_else(|| format!("function '{}.{}' is not defined", module, function))?;
+                self.call(func, args, frame, context)?
+            }
+            ExpressionKind::FieldAccess { object, field } => {
+                let slot = self.field(object, field, frame, context)?;
+                self.memory[slot].clone()
+            }
+            ExpressionKind::ArrayAccess { name, index } => {
+                let slot = self.element(name, index, frame, context)?;
+                self.memory[slot].clone()
+            }
+            ExpressionKind::StringIndex { string, index } => {
+                let Value::Str(s) = self.eval(string, frame, context)? else {
+                    return Err("only strings can be indexed this way".to_string());
+                };
+                let i = self.int(index, frame, context)?;
+                let byte = usize::try_from(i).ok().and_then(|i| s.as_bytes().get(i).copied());
+                Value::Int(byte.ok_or_else(|| format!("index {} is out of bounds for a string of length {}", i, s.len()))? as i64)
+            }
+            ExpressionKind::AddressOf { operand } => Value::Ptr(self.place(operand, frame, context)?),
+            ExpressionKind::Deref { operand } => match self.eval(operand, frame, context)? {
+                Value::Ptr(slot) => self.memory[slot].clone(),
+                other => return Err(format!("cannot dereference {}", self.describe(&other))),
+            },
+            ExpressionKind::Cast { operand, target_type } => {
+                Value::Int(wrap(self.int(operand, frame, context)?, &Type::from_string(target_type)))
+            }
+            ExpressionKind::Eval { .. } => return Err(EVAL_NOT_FOLDED.to_string()),
+        })
+    }
+
+    /// The slot `expr` refers to, for `&expr`.
+    fn place(&mut self, expr: &Expression, frame: &mut Frame, context: &mut Context) -> Eval<usize> {
+        match &expr.kind {
+            ExpressionKind::Identifier(name) => self.variable(name, frame),
+            ExpressionKind::FieldAccess { object, field } => self.field(object, field, frame, context),
+            ExpressionKind::ArrayAccess { name, index } => self.element(name, index, frame, context),
+            ExpressionKind::Deref { operand } => match self.eval(operand, frame, context)? {
+                Value::Ptr(slot) => Ok(slot),
+                other => Err(format!("cannot dereference {}", self.describe(&other))),
+            },
+            _ => Err("cannot take the address of a temporary value".to_string()),
+        }
+    }
+
+    fn binary(&self, op: &BinaryOp, left: Value, right: Value) -> Eval<Value> {
+        if let BinaryOp::Concat = op {
+            return Ok(Value::Str(self.display(&left) + &self.display(&right)));
+        }
+        if let (Value::Str(l), Value::Str(r)) = (&left, &right) {
+            return match op {
+                BinaryOp::Equal => Ok(Value::Int((l == r) as i64)),
+                BinaryOp::NotEqual => Ok(Value::Int((l != r) as i64)),
+                _ => Err(format!("{:?} is not defined on strings", op)),
+            };
+        }
+        let number = |value: &Value| match value {
+            Value::Int(n) => Ok(*n),
+            Value::Ptr(slot) => Ok(*slot as i64),
+            other => Err(format!("expected a number, found {}", self.describe(other))),
+        };
+        let (l, r) = (number(&left)?, number(&right)?);
+        Ok(Value::Int(match op {
+            BinaryOp::Add => l.wrapping_add(r),
+            BinaryOp::Sub => l.wrapping_sub(r),
+            BinaryOp::Mul => l.wrapping_mul(r),
+            BinaryOp::Div | BinaryOp::Mod if r == 0 => return Err("division by zero".to_string()),
+            BinaryOp::Div => l.wrapping_div(r),
+            BinaryOp::Mod => l.wrapping_rem(r),
+            BinaryOp::Equal => (l == r) as i64,
+            BinaryOp::NotEqual => (l != r) as i64,
+            BinaryOp::Less => (l < r) as i64,
+            BinaryOp::LessEqual => (l <= r) as i64,
+            BinaryOp::Greater => (l > r) as i64,
+            BinaryOp::GreaterEqual => (l >= r) as i64,
+            BinaryOp::And | BinaryOp::Or | BinaryOp::Concat => unreachable!("handled above"),
+        }))
+    }
+
+    fn call(&mut self, func: &Function, args: &[Expression], frame: &mut Frame, context: &mut Context) -> Eval<Value> {
+        if args.len() != func.params.len() {
+            return Err(format!("'{}' expects {} arguments, got {}", func.name, func.params.len(), args.len()));
+        }
+        if func.external.is_some() {
+            return Err(format!("extern function '{}' cannot be interpreted", func.name));
+        }
+        if self.depth == MAX_CALL_DEPTH {
+            return Err(format!("call depth exceeded {} in '{}'", MAX_CALL_DEPTH, func.name));
+        }
+
+        let mut callee = Frame::new();
+        for (param, arg) in func.params.iter().zip(args) {
+            let value = self.eval(arg, frame, context)?;
+            let slot = self.alloc(&param.param_type, &[]);
+            self.store(slot, value);
+            callee.insert(param.name.clone(), slot);
+        }
+
+        self.depth += 1;
+        let flow = self.block(&func.body, &mut callee, context);
+        self.depth -= 1;
+        match flow? {
+            Flow::Return(value) => Ok(value),
+            _ => Ok(Value::Void),
+        }
+    }
+
+    fn stdio(&mut self, function: &str, args: &[Expression], frame: &mut Frame, context: &mut Context) -> Eval<Value> {
+        let mut values = Vec::new();
+        for arg in args {
+            values.push(self.eval(arg, frame, context)?);
+        }
+        let text = match (function, values.as_slice()) {
+            ("Print" | "PrintStr", [value]) => self.display(value),
+            ("Println" | "PrintlnStr", [value]) => self.display(value) + "\n",
+            ("PrintChar", [Value::Int(c)]) => char::from(*c as u8).to_string(),
+            ("Flush", []) => String::new(),
+            _ => return Err(format!("stdio.{} is not available here", function)),
+        };
+        context.out.write_all(text.as_bytes()).map_err(|e| e.to_string())?;
+        Ok(Value::Void)
+    }
+
+    /// The `string` functions the backends implement in their runtimes.
+    fn string(&mut self, function: &str, args: &[Expression], frame: &mut Frame, context: &mut Context) -> Eval<Value> {
+        let mut values = Vec::new();
+        for arg in args {
+            values.push(self.eval(arg, frame, context)?);
+        }
+        Ok(match (function, values.as_slice()) {
+            ("len", [Value::Str(s)]) => Value::Int(s.len() as i64),
+            ("compare", [Value::Str(a), Value::Str(b)]) => Value::Int(a.as_bytes().cmp(b.as_bytes()) as i64),
+            ("concat", [Value::Str(a), Value::Str(b)]) => Value::Str(format!("{}{}", a, b)),
+            ("slice", [Value::Str(s), Value::Int(start), Value::Int(end)]) => {
+                let end = (*end).clamp(0, s.len() as i64) as usize;
+                let start = (*start).clamp(0, end as i64) as usize;
+                Value::Str(String::from_utf8_lossy(&s.as_bytes()[start..end]).into_owned())
+            }
+            _ => return Err(format!("string.{} expects strings", function)),
+        })
+    }
+
+    fn format(&self, value: &Value, spec: Option<&FormatSpec>) -> String {
+        let Some(spec) = spec else {
+            return self.display(value);
+        };
+        let text = match (value, &spec.format_type) {
+            (Value::Int(n), FormatType::Hex) => format!("{:x}", n),
+            (Value::Int(n), FormatType::HexUpper) => format!("{:X}", n),
+            _ => self.display(value),
+        };
+        let width = spec.width.unwrap_or(0);
+        let padding: String = std::iter::repeat_n(spec.padding, width.saturating_sub(text.chars().count())).collect();
+        padding + &text
+    }
+}
+
+struct Context<'a> {
+    program: &'a Program,
+    out: &'a mut dyn Write,
+}
+
+/// `n` truncated to the width of `ty` and sign- or zero-extended back.
+fn wrap(n: i64, ty: &Type) -> i64 {
+    match ty {
+        Type::I32 => n as i32 as i64,
+        Type::U32 => n as u32 as i64,
+        Type::I8 => n as i8 as i64,
+        Type::U8 => n as u8 as i64,
+        Type::Bool => (n != 0) as i64,
+        _ => n,
+    }
+}
@@ -0,0 +1,306 @@
+//! `perano-lang repl`: declarations and statements entered line by line.
+//!
+//! Everything entered so far is kept as source and checked as one program:
+//! imports and declarations at the top, statements in the body of `main`.
+//! Only statements that were not run before are interpreted, so variables
+//! persist between inputs. `stdio` is always imported.
+
+mod interpreter;
+
+pub use interpreter::{Interpreter, Value};
+
+use crate::compiler::{self, Compiler, Diagnostics};
+use crate::error::CompileError;
+use crate::lexer::{Lexer, Token};
+use crate::typechecker::Type;
+use crate::Analysis;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+/// The stack `run` needs: the interpreter recurses for every call it
+/// evaluates, down to its limit of nested calls.
+pub const STACK_SIZE: usize = 256 << 20;
+
+const FILE: &str = "<repl>";
+const STDIO: &str = include_str!("../../stdlib/stdio.per");
+
+const HELP: &str = "Enter declarations (fn, struct, import) or statements; expressions print their value.
+  :type <expr>    show the type of an expression
+  :asm [snippet]  show the x86-64 assembly of what was entered, plus an optional snippet
+  :help           show this text
+  :quit           leave the REPL
+";
+
+/// Reads inputs from `input` until it ends or `:quit`, writing prompts,
+/// values and program output to `output`. Diagnostics go to stderr.
+pub fn run(input: impl BufRead, mut output: impl Write, dirs: Vec<PathBuf>) -> io::Result<i32> {
+    let mut session = Session::new(dirs);
+    let mut pending = String::new();
+    let mut lines = input.lines();
+
+    loop {
+        write!(output, "{}", if pending.is_empty() { "> " } else { "... " })?;
+        output.flush()?;
+        let Some(line) = lines.next() else {
+            writeln!(output)?;
+            return Ok(0);
+        };
+        let line = line?;
+
+        if pending.is_empty() {
+            match line.trim() {
+                "" => continue,
+                ":quit" | ":q" => return Ok(0),
+                command if command.starts_with(':') => {
+                    session.command(command, &mut output)?;
+                    continue;
+                }
+                _ => {}
+            }
+        }
+
+        pending.push_str(&line);
+        pending.push('\n');
+        if open_braces(&pending) <= 0 {
+            session.submit(&std::mem::take(&mut pending), &mut output)?;
+        }
+    }
+}
+
+/// How many more `{` than `}` the input has, so multi-line input can be
+/// continued.
+fn open_braces(text: &str) -> i64 {
+    Lexer::new(text).tokenize().iter().map(|token| match token {
+        Token::LeftBrace => 1,
+        Token::RightBrace => -1,
+        _ => 0,
+    }).sum()
+}
+
+enum Input {
+    Import,
+    Declaration(String),
+    Statements,
+}
+
+fn classify(text: &str) -> Input {
+    let tokens = Lexer::new(text).tokenize();
+    let mut tokens = tokens.iter().filter(|t| **t != Token::Newline).peekable();
+    if matches!(tokens.peek(), Some(Token::Identifier(id)) if id == "pub") {
+        tokens.next();
+    }
+    if matches!(tokens.peek(), Some(Token::Extern)) {
+        tokens.next();
+        tokens.next();
+    }
+    match (tokens.next(), tokens.next()) {
+        (Some(Token::Import), _) => Input::Import,
+        (Some(Token::Func | Token::Struct), Some(Token::Identifier(name))) => Input::Declaration(name.clone()),
+        _ => Input::Statements,
+    }
+}
+
+/// The program built from a session's inputs, and where each input starts.
+struct Source {
+    text: String,
+    chunks: Vec<(usize, String)>,
+}
+
+impl Source {
+    fn push(&mut self, chunk: &str, is_input: bool) {
+        if is_input {
+            self.chunks.push((self.text.lines().count() + 1, chunk.to_string()));
+        }
+        self.text.push_str(chunk);
+        if !chunk.ends_with('\n') {
+            self.text.push('\n');
+        }
+    }
+
+    /// Points `e` at the input it came from rather than the whole program.
+    fn locate(&self, mut e: CompileError) -> CompileError {
+        if e.file != FILE {
+            return e;
+        }
+        let chunk = self.chunks.iter().rev().find(|(first, _)| *first <= e.line);
+        if let Some((first, text)) = chunk {
+            if let Some(line) = text.lines().nth(e.line - first) {
+                e.line = e.line - first + 1;
+                e.source_line = Some(line.to_string());
+            }
+        }
+        e
+    }
+}
+
+pub struct Session {
+    dirs: Vec<PathBuf>,
+    imports: Vec<String>,
+    declarations: Vec<(String, String)>,
+    statements: Vec<String>,
+    /// How many statements of `main` have been run.
+    executed: usize,
+    interpreter: Interpreter,
+}
+
+impl Session {
+    pub fn new(dirs: Vec<PathBuf>) -> Self {
+        Session {
+            dirs,
+            imports: Vec::new(),
+            declarations: Vec::new(),
+            statements: Vec::new(),
+            executed: 0,
+            interpreter: Interpreter::new(),
+        }
+    }
+
+    fn source(&self, imports: &[String], declarations: &[(String, String)], statements: &[String]) -> Source {
+        let mut source = Source { text: String::new(), chunks: Vec::new() };
+        source.push("package main\nimport \"stdio\"", false);
+        for import in imports {
+            source.push(import, true);
+        }
+        for (_, declaration) in declarations {
+            source.push(declaration, true);
+        }
+        source.push("fn main() {", false);
+        for statement in statements {
+            source.push(statement, true);
+        }
+        source.push("}", false);
+        source
+    }
+
+    fn analyze(&self, source: &Source) -> Result<Analysis, ()> {
+        let mut session = Compiler::new().with_resolver(compiler::directory_resolver(self.dirs.clone()));
+        session.add_source("stdio.per", STDIO);
+        session.add_source(FILE, &source.text);
+        let analysis = session.analyze(FILE);
+        if analysis.diagnostics.is_empty() && analysis.program.is_some() {
+            Ok(analysis)
+        } else {
+            report(source, analysis.diagnostics);
+            Err(())
+        }
+    }
+
+    /// Checks `input` together with everything before it and, if it is a
+    /// statement, runs it.
+    pub fn submit(&mut self, input: &str, output: &mut impl Write) -> io::Result<()> {
+        let mut imports = self.imports.clone();
+        let mut declarations = self.declarations.clone();
+        let mut statements = self.statements.clone();
+        match classify(input) {
+            Input::Import if input.trim() == "import \"stdio\"" => return Ok(()),
+            Input::Import => imports.push(input.to_string()),
+            Input::Declaration(name) => match declarations.iter_mut().find(|(n, _)| *n == name) {
+                Some(existing) => existing.1 = input.to_string(),
+                None => declarations.push((name, input.to_string())),
+            },
+            Input::Statements => statements.push(input.to_string()),
+        }
+
+        let source = self.source(&imports, &declarations, &statements);
+        let Ok(mut analysis) = self.analyze(&source) else {
+            return Ok(());
+        };
+        self.imports = imports;
+        self.declarations = declarations;
+        self.statements = statements;
+
+        let Some(program) = analysis.program.take() else {
+            return Ok(());
+        };
+        let Some(main) = program.functions.iter().find(|f| f.name == "main") else {
+            return Ok(());
+        };
+        let new = &main.body[self.executed..];
+        self.executed = main.body.len();
+
+        match self.interpreter.execute(&program, new, output) {
+            Ok(Value::Void) => {}
+            Ok(value) => {
+                let ty = match new.last().map(|s| &s.kind) {
+                    Some(crate::ast::StatementKind::Expression(expr)) => analysis.checker.as_mut()
+                        .map(|checker| checker.infer_expression(expr))
+                        .unwrap_or(Type::Unknown),
+                    _ => Type::Unknown,
+                };
+                writeln!(output, "{}", self.render(&value, &ty))?;
+            }
+            Err(message) => eprintln!("\x1b[1;31mruntime error\x1b[0m: {}", message),
+        }
+        Ok(())
+    }
+
+    fn render(&self, value: &Value, ty: &Type) -> String {
+        match (value, ty) {
+            (Value::Int(n), Type::Bool) => (*n != 0).to_string(),
+            (Value::Str(s), _) => format!("{:?}", s),
+            _ => self.interpreter.display(value),
+        }
+    }
+
+    /// Runs a `:command`.
+    pub fn command(&mut self, command: &str, output: &mut impl Write) -> io::Result<()> {
+        let (name, argument) = command.split_once(' ').unwrap_or((command, ""));
+        let argument = argument.trim();
+        match name {
+            ":help" | ":h" => write!(output, "{}", HELP),
+            ":type" | ":t" if !argument.is_empty() => {
+                let mut statements = self.statements.clone();
+                statements.push(argument.to_string());
+                let source = self.source(&self.imports, &self.declarations, &statements);
+                let Ok(mut analysis) = self.analyze(&source) else {
+                    return Ok(());
+                };
+                let main = analysis.program.as_ref().and_then(|p| p.functions.iter().find(|f| f.name == "main"));
+                let expr = match main.and_then(|m| m.body.last()).map(|s| &s.kind) {
+                    Some(crate::ast::StatementKind::Expression(expr)) => expr.clone(),
+                    _ => return writeln!(output, "'{}' is not an expression", argument),
+                };
+                match analysis.checker.as_mut().map(|checker| checker.infer_expression(&expr)) {
+                    Some(ty) => writeln!(output, "{}", ty),
+                    None => Ok(()),
+                }
+            }
+            ":asm" => {
+                let mut statements = self.statements.clone();
+                if !argument.is_empty() {
+                    statements.push(argument.to_string());
+                }
+                let source = self.source(&self.imports, &self.declarations, &statements);
+                let Some(program) = self.analyze(&source).ok().and_then(|analysis| analysis.program) else {
+                    return Ok(());
+                };
+                // Only the functions entered here and `main`, not the runtime
+                // or imported modules.
+                let asm = match compiler::elf_assembly(&program, true) {
+                    Ok(asm) => asm,
+                    Err(e) => {
+                        report(&source, vec![e]);
+                        return Ok(());
+                    }
+                };
+                let names: Vec<&str> = self.declarations.iter().map(|(n, _)| n.as_str()).chain(["main"]).collect();
+                for block in asm.split("\n\n") {
+                    let defines = block.lines().any(|line| {
+                        matches!(line.split_whitespace().collect::<Vec<_>>()[..], [".globl", name] if names.contains(&name))
+                    });
+                    if defines || block.trim_start().starts_with(".section") {
+                        writeln!(output, "{}\n", block.trim_end())?;
+                    }
+                }
+                Ok(())
+            }
+            _ => writeln!(output, "unknown command '{}'; try :help", command),
+        }
+    }
+}
+
+fn report(source: &Source, diagnostics: Diagnostics) {
+    for e in diagnostics {
+        source.locate(e).display();
+    }
+}
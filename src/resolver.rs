@@ -0,0 +1,222 @@
+use crate::ast::*;
+use crate::error::{CompileError, ErrorKind, Result};
+use std::collections::{HashMap, HashSet};
+
+/// Functions of every loaded module and whether each is exported.
+struct ModuleTable {
+    functions: HashMap<String, HashMap<String, bool>>,
+}
+
+struct Scope<'a> {
+    file: String,
+    aliases: HashMap<String, String>,
+    module: Option<&'a str>,
+    locals: HashSet<String>,
+}
+
+/// Rewrites every call to use canonical module names: import aliases are
+/// replaced by the module they refer to and unqualified calls inside a module
+/// become calls into that module. Calls to another module's private
+/// functions are rejected.
+pub fn resolve_program(program: &mut Program, source_file: &str) -> Result<()> {
+    let table = ModuleTable {
+        functions: program.modules.iter()
+            .map(|(name, module)| {
+                let funcs = module.functions.iter()
+                    .map(|f| (f.name.clone(), f.is_exported))
+                    .collect();
+                (name.clone(), funcs)
+            })
+            .collect(),
+    };
+
+    let scope = Scope {
+        file: source_file.to_string(),
+        aliases: build_aliases(&program.imports, source_file)?,
+        module: None,
+        locals: program.functions.iter().map(|f| f.name.clone()).collect(),
+    };
+    for func in &mut program.functions {
+        resolve_body(&mut func.body, &scope, &table)?;
+    }
+
+    for (name, module) in program.modules.iter_mut() {
+        let scope = Scope {
+            aliases: build_aliases(&module.imports, &module.file)?,
+            file: module.file.clone(),
+            module: Some(name),
+            locals: module.functions.iter().map(|f| f.name.clone()).collect(),
+        };
+        for func in &mut module.functions {
+            resolve_body(&mut func.body, &scope, &table)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn build_aliases(imports: &[Import], file: &str) -> Result<HashMap<String, String>> {
+    let mut aliases = HashMap::new();
+    for import in imports {
+        let alias = import.alias.clone().unwrap_or_else(|| {
+            import.path.rsplit('/').next().unwrap_or(&import.path).to_string()
+        });
+        if let Some(previous) = aliases.insert(alias.clone(), import.path.clone()) {
+            return Err(module_error(
+                format!("'{}' refers to both \"{}\" and \"{}\"", alias, previous, import.path),
+                file,
+                import.span,
+            ));
+        }
+    }
+    Ok(aliases)
+}
+
+fn module_error(message: String, file: &str, span: Span) -> CompileError {
+    CompileError::new(ErrorKind::ModuleError, message, file.to_string(), span.line, span.column)
+}
+
+fn resolve_body(body: &mut [Statement], scope: &Scope, table: &ModuleTable) -> Result<()> {
+    for stmt in body {
+        resolve_statement(stmt, scope, table)?;
+    }
+    Ok(())
+}
+
+fn resolve_statement(stmt: &mut Statement, scope: &Scope, table: &ModuleTable) -> Result<()> {
+    match &mut stmt.kind {
+        StatementKind::VarDecl { value, .. } => {
+            if let Some(expr) = value {
+                resolve_expression(expr, scope, table)?;
+            }
+        }
+        StatementKind::Assignment { value, .. } => resolve_expression(value, scope, table)?,
+        StatementKind::ArrayAssignment { index, value, .. } => {
+            resolve_expression(index, scope, table)?;
+            resolve_expression(value, scope, table)?;
+        }
+        StatementKind::PointerAssignment { target, value } => {
+            resolve_expression(target, scope, table)?;
+            resolve_expression(value, scope, table)?;
+        }
+        StatementKind::FieldAssignment { object, value, .. } => {
+            resolve_expression(object, scope, table)?;
+            resolve_expression(value, scope, table)?;
+        }
+        StatementKind::If { condition, then_body, else_body } => {
+            resolve_expression(condition, scope, table)?;
+            resolve_body(then_body, scope, table)?;
+            if let Some(else_stmts) = else_body {
+                resolve_body(else_stmts, scope, table)?;
+            }
+        }
+        StatementKind::For { init, condition, post, body } => {
+            if let Some(init_stmt) = init {
+                resolve_statement(init_stmt, scope, table)?;
+            }
+            if let Some(cond) = condition {
+                resolve_expression(cond, scope, table)?;
+            }
+            if let Some(post_stmt) = post {
+                resolve_statement(post_stmt, scope, table)?;
+            }
+            resolve_body(body, scope, table)?;
+        }
+        StatementKind::Return(value) => {
+            if let Some(expr) = value {
+                resolve_expression(expr, scope, table)?;
+            }
+        }
+        StatementKind::Expression(expr) => resolve_expression(expr, scope, table)?,
+        StatementKind::ArrayDecl { size_eval, .. } => {
+            if let Some(expr) = size_eval {
+                resolve_expression(expr, scope, table)?;
+            }
+        }
+        StatementKind::Break
+        | StatementKind::Continue
+        | StatementKind::InlineAsm { .. } => {}
+    }
+    Ok(())
+}
+
+fn resolve_expression(expr: &mut Expression, scope: &Scope, table: &ModuleTable) -> Result<()> {
+    match &mut expr.kind {
+        ExpressionKind::Call { function, args } => {
+            for arg in args.iter_mut() {
+                resolve_expression(arg, scope, table)?;
+            }
+            if let Some(module) = scope.module {
+                if scope.locals.contains(function.as_str()) {
+                    expr.kind = ExpressionKind::ModuleCall {
+                        module: module.to_string(),
+                        function: std::mem::take(function),
+                        args: std::mem::take(args),
+                    };
+                }
+            }
+        }
+        ExpressionKind::ModuleCall { module, function, args } => {
+            for arg in args.iter_mut() {
+                resolve_expression(arg, scope, table)?;
+            }
+
+            let target = match scope.aliases.get(module.as_str()) {
+                Some(target) => target.clone(),
+                None if scope.module == Some(module.as_str()) => module.clone(),
+                None => {
+                    return Err(module_error(
+                        format!("module '{}' is not imported (calling {}.{})", module, module, function),
+                        &scope.file,
+                        expr.span,
+                    ));
+                }
+            };
+
+            match table.functions.get(&target).and_then(|funcs| funcs.get(function.as_str())) {
+                Some(true) => {}
+                Some(false) if scope.module == Some(target.as_str()) => {}
+                Some(false) => {
+                    return Err(module_error(
+                        format!("function '{}' is private to module '{}'", function, target),
+                        &scope.file,
+                        expr.span,
+                    ));
+                }
+                None => {
+                    return Err(module_error(
+                        format!("module '{}' has no function '{}'", target, function),
+                        &scope.file,
+                        expr.span,
+                    ));
+                }
+            }
+
+            *module = target;
+        }
+        ExpressionKind::TemplateString { parts } => {
+            for part in parts {
+                if let TemplateStringPart::Expression { expr, .. } = part {
+                    resolve_expression(expr, scope, table)?;
+                }
+            }
+        }
+        ExpressionKind::Binary { left, right, .. } => {
+            resolve_expression(left, scope, table)?;
+            resolve_expression(right, scope, table)?;
+        }
+        ExpressionKind::Unary { operand, .. }
+        | ExpressionKind::AddressOf { operand }
+        | ExpressionKind::Deref { operand }
+        | ExpressionKind::Cast { operand, .. } => resolve_expression(operand, scope, table)?,
+        ExpressionKind::FieldAccess { object, .. } => resolve_expression(object, scope, table)?,
+        ExpressionKind::ArrayAccess { index, .. } => resolve_expression(index, scope, table)?,
+        ExpressionKind::StringIndex { string, index } => {
+            resolve_expression(string, scope, table)?;
+            resolve_expression(index, scope, table)?;
+        }
+        ExpressionKind::Eval { instruction } => resolve_expression(instruction, scope, table)?,
+        ExpressionKind::Number(_) | ExpressionKind::String(_) | ExpressionKind::Identifier(_) => {}
+    }
+    Ok(())
+}
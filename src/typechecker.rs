@@ -1,4 +1,5 @@
 use crate::ast::*;
+use crate::error::{CompileError, ErrorKind};
 use std::collections::HashMap;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -13,6 +14,7 @@ pub enum Type {
     String,
     Ptr(Box<Type>),
     Array(Box<Type>, usize),
+    Struct(String),
     Void,
     Unknown,
 }
@@ -30,8 +32,8 @@ impl Type {
             "string" => Type::String,
             "void" => Type::Void,
             _ => {
-                if s.starts_with('*') {
-                    let inner = Type::from_string(&s[1..]);
+                if let Some(pointee) = s.strip_prefix('*') {
+                    let inner = Type::from_string(pointee);
                     return Type::Ptr(Box::new(inner));
                 }
                 if s.starts_with('[') && s.ends_with(']') {
@@ -50,6 +52,29 @@ impl Type {
         }
     }
 
+    /// The type named `s`, where `structs` holds the struct names in scope,
+    /// as declarations while laying them out and as layouts after.
+    pub fn resolve<T>(s: &str, structs: &HashMap<String, T>) -> Self {
+        if let Some(pointee) = s.strip_prefix('*') {
+            return Type::Ptr(Box::new(Type::resolve(pointee, structs)));
+        }
+        if structs.contains_key(s) {
+            return Type::Struct(s.to_string());
+        }
+        Type::from_string(s)
+    }
+
+    pub fn struct_name(&self) -> Option<&str> {
+        match self {
+            Type::Struct(name) => Some(name),
+            Type::Ptr(inner) => match inner.as_ref() {
+                Type::Struct(name) => Some(name),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
     pub fn is_numeric(&self) -> bool {
         matches!(self, Type::I64 | Type::I32 | Type::I8 | Type::U64 | Type::U32 | Type::U8)
     }
@@ -58,28 +83,310 @@ impl Type {
         self.is_numeric()
     }
 
+    pub fn is_unsigned(&self) -> bool {
+        matches!(self, Type::U64 | Type::U32 | Type::U8)
+    }
+
+    /// Size in bytes of a scalar of this type on native targets.
+    pub fn size(&self) -> usize {
+        match self {
+            Type::I32 | Type::U32 => 4,
+            Type::I8 | Type::U8 | Type::Bool => 1,
+            _ => 8,
+        }
+    }
+
+    /// Whether the integer constant `n` is representable in this type.
+    pub fn fits(&self, n: i64) -> bool {
+        match self {
+            Type::I32 => i32::try_from(n).is_ok(),
+            Type::I8 => i8::try_from(n).is_ok(),
+            Type::U64 => n >= 0,
+            Type::U32 => u32::try_from(n).is_ok(),
+            Type::U8 => u8::try_from(n).is_ok(),
+            Type::Bool => n == 0 || n == 1,
+            _ => true,
+        }
+    }
+
+    /// Implicit conversions only ever widen: every value of `self` must be
+    /// representable in `other`. Narrowing needs an explicit `as` cast.
     pub fn can_assign_to(&self, other: &Type) -> bool {
         if self == other {
             return true;
         }
         
-        if self.is_numeric() && other.is_numeric() {
+        if matches!(self, Type::Unknown) || matches!(other, Type::Unknown) {
             return true;
         }
+
+        if matches!(self, Type::Bool) {
+            return other.is_numeric();
+        }
         
-        if matches!(self, Type::Unknown) || matches!(other, Type::Unknown) {
-            return true;
+        if self.is_numeric() && other.is_numeric() {
+            return if self.is_unsigned() == other.is_unsigned() {
+                self.size() <= other.size()
+            } else {
+                self.is_unsigned() && self.size() < other.size()
+            };
         }
         
         false
     }
+
+    pub fn can_cast_to(&self, other: &Type) -> bool {
+        let scalar = |t: &Type| t.is_numeric() || matches!(t, Type::Bool | Type::Ptr(_) | Type::Unknown);
+        (scalar(self) && scalar(other)) || (*self == Type::String && *other == Type::Ptr(Box::new(Type::U8)))
+    }
+}
+
+/// Types as they are spelled in source.
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Type::I64 => write!(f, "i64"),
+            Type::I32 => write!(f, "i32"),
+            Type::I8 => write!(f, "i8"),
+            Type::U64 => write!(f, "u64"),
+            Type::U32 => write!(f, "u32"),
+            Type::U8 => write!(f, "u8"),
+            Type::Bool => write!(f, "bool"),
+            Type::String => write!(f, "string"),
+            Type::Ptr(inner) => write!(f, "*{}", inner),
+            Type::Array(elem, size) => write!(f, "[{}; {}]", elem, size),
+            Type::Struct(name) => write!(f, "{}", name),
+            Type::Void => write!(f, "void"),
+            Type::Unknown => write!(f, "?"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FieldLayout {
+    pub name: String,
+    pub ty: Type,
+    pub offset: usize,
+    pub cell: usize,
+}
+
+/// Memory layout of a struct. `size`/`offset` are bytes for native targets,
+/// `cells`/`cell` count 4-byte NVM locals (one per scalar field).
+#[derive(Debug, Clone)]
+pub struct StructLayout {
+    pub fields: Vec<FieldLayout>,
+    pub size: usize,
+    pub align: usize,
+    pub cells: usize,
+}
+
+impl StructLayout {
+    pub fn field(&self, name: &str) -> Option<&FieldLayout> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+}
+
+pub fn compute_struct_layouts(decls: &[StructDecl]) -> Result<HashMap<String, StructLayout>, (Span, String)> {
+    let mut by_name: HashMap<String, &StructDecl> = HashMap::new();
+    for decl in decls {
+        if by_name.insert(decl.name.clone(), decl).is_some() {
+            return Err((decl.span, format!("Struct '{}' declared more than once", decl.name)));
+        }
+    }
+
+    let mut layouts = HashMap::new();
+    let mut visiting = Vec::new();
+    for decl in decls {
+        layout_struct(&decl.name, &by_name, &mut layouts, &mut visiting)?;
+    }
+    Ok(layouts)
+}
+
+/// Value of an integer literal, including a negated one.
+pub fn literal_value(expr: &Expression) -> Option<i64> {
+    match &expr.kind {
+        ExpressionKind::Number(n) => Some(*n),
+        ExpressionKind::Unary { op: UnaryOp::Neg, operand } => literal_value(operand).map(i64::wrapping_neg),
+        _ => None,
+    }
+}
+
+/// Type a numeric binary operation is carried out in: a literal operand
+/// adopts the other operand's type, otherwise the narrower operand is
+/// widened. `None` when neither operand converts to the other.
+pub fn common_type(left: &Expression, left_type: &Type, right: &Expression, right_type: &Type) -> Option<Type> {
+    if matches!(left_type, Type::Unknown) {
+        return Some(right_type.clone());
+    }
+    if matches!(right_type, Type::Unknown) {
+        return Some(left_type.clone());
+    }
+    if literal_value(right).is_some_and(|n| left_type.fits(n)) {
+        return Some(left_type.clone());
+    }
+    if literal_value(left).is_some_and(|n| right_type.fits(n)) {
+        return Some(right_type.clone());
+    }
+    if left_type.can_assign_to(right_type) {
+        Some(right_type.clone())
+    } else if right_type.can_assign_to(left_type) {
+        Some(left_type.clone())
+    } else {
+        None
+    }
+}
+
+/// Best-effort static type of an expression, used by the backends to find
+/// struct layouts and operand widths without re-running the checker.
+/// Literals and calls have no static type here.
+pub fn expression_type(
+    expr: &Expression,
+    vars: &HashMap<String, Type>,
+    structs: &HashMap<String, StructLayout>,
+) -> Option<Type> {
+    match &expr.kind {
+        ExpressionKind::Identifier(name) => vars.get(name).cloned(),
+        ExpressionKind::Cast { target_type, .. } => Some(Type::resolve(target_type, structs)),
+        ExpressionKind::ArrayAccess { name, .. } => match vars.get(name)? {
+            Type::Array(elem, _) => Some((**elem).clone()),
+            _ => None,
+        },
+        ExpressionKind::String(_) | ExpressionKind::TemplateString { .. } => Some(Type::String),
+        ExpressionKind::StringIndex { .. } => Some(Type::U8),
+        ExpressionKind::ModuleCall { module, function, .. } if is_string_builtin(module, function) => {
+            match function.as_str() {
+                "concat" | "slice" => Some(Type::String),
+                _ => Some(Type::I64),
+            }
+        }
+        ExpressionKind::Unary { op: UnaryOp::Neg, operand } => expression_type(operand, vars, structs),
+        ExpressionKind::Unary { op: UnaryOp::Not, .. } => Some(Type::Bool),
+        ExpressionKind::Binary { op, left, right } => match op {
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => {
+                operand_type(left, right, vars, structs)
+            }
+            BinaryOp::Concat => Some(Type::String),
+            _ => Some(Type::Bool),
+        },
+        ExpressionKind::AddressOf { operand } => {
+            expression_type(operand, vars, structs).map(|t| Type::Ptr(Box::new(t)))
+        }
+        ExpressionKind::Deref { operand } => match expression_type(operand, vars, structs)? {
+            Type::Ptr(inner) => Some(*inner),
+            _ => None,
+        },
+        ExpressionKind::FieldAccess { object, field } => {
+            let object_type = expression_type(object, vars, structs)?;
+            let layout = structs.get(object_type.struct_name()?)?;
+            layout.field(field).map(|f| f.ty.clone())
+        }
+        _ => None,
+    }
+}
+
+/// Static type both operands of `left op right` are converted to, which
+/// decides signed vs unsigned division and comparison in the backends.
+pub fn operand_type(
+    left: &Expression,
+    right: &Expression,
+    vars: &HashMap<String, Type>,
+    structs: &HashMap<String, StructLayout>,
+) -> Option<Type> {
+    let left_type = expression_type(left, vars, structs).unwrap_or(Type::Unknown);
+    let right_type = expression_type(right, vars, structs).unwrap_or(Type::Unknown);
+    common_type(left, &left_type, right, &right_type).filter(|t| !matches!(t, Type::Unknown))
+}
+
+fn layout_struct(
+    name: &str,
+    decls: &HashMap<String, &StructDecl>,
+    layouts: &mut HashMap<String, StructLayout>,
+    visiting: &mut Vec<String>,
+) -> Result<(), (Span, String)> {
+    if layouts.contains_key(name) {
+        return Ok(());
+    }
+    let decl = decls[name];
+    if visiting.iter().any(|v| v == name) {
+        return Err((decl.span, format!("Struct '{}' contains itself; use a pointer field instead", name)));
+    }
+    visiting.push(name.to_string());
+
+    let mut fields: Vec<FieldLayout> = Vec::new();
+    let mut offset: usize = 0;
+    let mut align = 1;
+    let mut cells = 0;
+
+    for field in &decl.fields {
+        if fields.iter().any(|f| f.name == field.name) {
+            return Err((field.span, format!("Duplicate field '{}' in struct '{}'", field.name, name)));
+        }
+
+        let ty = Type::resolve(&field.field_type, decls);
+
+        let (size, field_align, field_cells) = match &ty {
+            Type::I64 | Type::U64 | Type::String | Type::Ptr(_) => (8, 8, 1),
+            Type::I32 | Type::U32 => (4, 4, 1),
+            Type::I8 | Type::U8 | Type::Bool => (1, 1, 1),
+            Type::Struct(inner) => {
+                layout_struct(inner, decls, layouts, visiting)?;
+                let inner = &layouts[inner];
+                (inner.size, inner.align, inner.cells)
+            }
+            _ => {
+                return Err((field.span, format!(
+                    "Unknown type '{}' for field '{}' in struct '{}'",
+                    field.field_type, field.name, name
+                )))
+            }
+        };
+
+        offset = offset.next_multiple_of(field_align);
+        fields.push(FieldLayout {
+            name: field.name.clone(),
+            ty,
+            offset,
+            cell: cells,
+        });
+        offset += size;
+        cells += field_cells;
+        align = align.max(field_align);
+    }
+
+    let size = offset.next_multiple_of(align);
+    visiting.pop();
+    layouts.insert(name.to_string(), StructLayout { fields, size, align, cells });
+    Ok(())
+}
+
+/// Whether `expr` of type `from` may be stored into `to` without a cast.
+/// Integer literals are accepted by any integer type they fit in.
+fn assignable(expr: &Expression, from: &Type, to: &Type) -> bool {
+    from.can_assign_to(to) || (to.is_integer() && literal_value(expr).is_some_and(|n| to.fits(n)))
+}
+
+fn cast_hint(from: &Type, to: &Type) -> &'static str {
+    if from.is_numeric() && to.is_numeric() {
+        "; narrowing or sign changes need an explicit 'as' cast"
+    } else {
+        ""
+    }
+}
+
+/// Whether a value of type `ty` fits in one register, as extern functions
+/// take and return their values.
+fn passable(ty: &Type) -> bool {
+    ty.is_integer() || matches!(ty, Type::Bool | Type::String | Type::Ptr(_))
 }
 
 pub struct TypeChecker {
     variables: HashMap<String, Type>,
+    structs: HashMap<String, StructLayout>,
     functions: HashMap<String, FunctionSignature>,
-    errors: Vec<TypeError>,
+    file: String,
+    errors: Vec<CompileError>,
     current_function: Option<String>,
+    loop_depth: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -88,19 +395,16 @@ pub struct FunctionSignature {
     pub return_type: Type,
 }
 
-#[derive(Debug, Clone)]
-pub struct TypeError {
-    pub message: String,
-    pub location: String,
-}
-
 impl TypeChecker {
-    pub fn new() -> Self {
+    pub fn new(file: &str) -> Self {
         let mut checker = Self {
             variables: HashMap::new(),
+            structs: HashMap::new(),
             functions: HashMap::new(),
+            file: file.to_string(),
             errors: Vec::new(),
             current_function: None,
+            loop_depth: 0,
         };
         
         checker.functions.insert("stdio.Print".to_string(), FunctionSignature {
@@ -123,16 +427,22 @@ impl TypeChecker {
         checker
     }
 
-    pub fn check_program(&mut self, program: &Program) -> Result<(), Vec<TypeError>> {
+    pub fn check_program(&mut self, program: &Program) -> Result<(), Vec<CompileError>> {
+        match compute_struct_layouts(&program.structs) {
+            Ok(layouts) => self.structs = layouts,
+            Err((span, message)) => self.add_error(span, message),
+        }
+
         for func in &program.functions {
-            self.collect_function_signature(func);
+            if self.functions.contains_key(&func.name) {
+                self.add_error(func.span, format!("Function '{}' declared more than once", func.name));
+            }
+            self.collect_function_signature(func.name.clone(), func);
         }
         
-        for (_module_name, module) in &program.modules {
+        for module in program.modules.values() {
             for func in &module.functions {
-                if func.is_exported {
-                    self.collect_function_signature(func);
-                }
+                self.collect_function_signature(format!("{}.{}", module.name, func.name), func);
             }
         }
         
@@ -143,20 +453,30 @@ impl TypeChecker {
         if self.errors.is_empty() {
             Ok(())
         } else {
-            Err(self.errors.clone())
+            Err(std::mem::take(&mut self.errors))
         }
     }
 
-    fn collect_function_signature(&mut self, func: &Function) {
+    /// Signature of a function seen by `check_program`; module functions are
+    /// named `module.Func`.
+    pub fn signature(&self, name: &str) -> Option<&FunctionSignature> {
+        self.functions.get(name)
+    }
+
+    pub fn take_errors(&mut self) -> Vec<CompileError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn collect_function_signature(&mut self, name: String, func: &Function) {
         let params: Vec<(String, Type)> = func.params.iter()
-            .map(|p| (p.name.clone(), Type::from_string(&p.param_type)))
+            .map(|p| (p.name.clone(), self.resolve_type(&p.param_type)))
             .collect();
         
         let return_type = func.return_type.as_ref()
-            .map(|t| Type::from_string(t))
+            .map(|t| self.resolve_type(t))
             .unwrap_or(Type::Void);
         
-        self.functions.insert(func.name.clone(), FunctionSignature {
+        self.functions.insert(name, FunctionSignature {
             params,
             return_type,
         });
@@ -165,11 +485,32 @@ impl TypeChecker {
     fn check_function(&mut self, func: &Function) {
         self.current_function = Some(func.name.clone());
         self.variables.clear();
+        self.loop_depth = 0;
         
         for param in &func.params {
-            let param_type = Type::from_string(&param.param_type);
+            let param_type = self.resolve_type(&param.param_type);
+            if let Type::Struct(name) = &param_type {
+                self.add_error(param.span, format!(
+                    "Parameter '{}' passes struct '{}' by value; use *{} instead",
+                    param.name, name, name
+                ));
+            } else if func.external.is_some() && !passable(&param_type) {
+                self.add_error(param.span, format!(
+                    "Parameter '{}' of extern function '{}' has type '{}'; extern functions take integers, bool, string and pointers",
+                    param.name, func.name, param.param_type
+                ));
+            }
             self.variables.insert(param.name.clone(), param_type);
         }
+
+        if let (Some(_), Some(return_type)) = (func.external, &func.return_type) {
+            if !passable(&self.resolve_type(return_type)) {
+                self.add_error(func.span, format!(
+                    "Extern function '{}' returns '{}'; extern functions return integers, bool, string or pointers",
+                    func.name, return_type
+                ));
+            }
+        }
         
         for stmt in &func.body {
             self.check_statement(stmt);
@@ -179,19 +520,26 @@ impl TypeChecker {
     }
 
     fn check_statement(&mut self, stmt: &Statement) {
-        match stmt {
-            Statement::VarDecl { name, var_type, value } => {
+        match &stmt.kind {
+            StatementKind::VarDecl { name, var_type, value } => {
                 let declared_type = var_type.as_ref()
-                    .map(|t| Type::from_string(t))
+                    .map(|t| self.resolve_type(t))
                     .unwrap_or(Type::Unknown);
                 
                 if let Some(expr) = value {
                     let expr_type = self.infer_expression(expr);
+
+                    if matches!(declared_type, Type::Struct(_)) || matches!(expr_type, Type::Struct(_)) {
+                        self.add_error(stmt.span, format!(
+                            "Struct variable '{}' cannot be initialized by value; assign its fields instead",
+                            name
+                        ));
+                    }
                     
-                    if !declared_type.can_assign_to(&expr_type) && !expr_type.can_assign_to(&declared_type) {
-                        self.add_error(format!(
-                            "Type mismatch in variable '{}': declared as {:?}, but initialized with {:?}",
-                            name, declared_type, expr_type
+                    if !assignable(expr, &expr_type, &declared_type) {
+                        self.add_error(stmt.span, format!(
+                            "Type mismatch in variable '{}': declared as {:?}, but initialized with {:?}{}",
+                            name, declared_type, expr_type, cast_hint(&expr_type, &declared_type)
                         ));
                     }
                     
@@ -207,55 +555,61 @@ impl TypeChecker {
                 }
             }
             
-            Statement::Assignment { name, value } => {
+            StatementKind::Assignment { name, value } => {
                 let expr_type = self.infer_expression(value);
                 
                 if let Some(var_type) = self.variables.get(name) {
-                    if !expr_type.can_assign_to(var_type) {
-                        self.add_error(format!(
-                            "Type mismatch in assignment to '{}': expected {:?}, got {:?}",
-                            name, var_type, expr_type
+                    if let Type::Struct(struct_name) = var_type {
+                        let message = format!(
+                            "Cannot assign struct '{}' to '{}' by value; assign its fields instead",
+                            struct_name, name
+                        );
+                        self.add_error(stmt.span, message);
+                    } else if !assignable(value, &expr_type, var_type) {
+                        self.add_error(stmt.span, format!(
+                            "Type mismatch in assignment to '{}': expected {:?}, got {:?}{}",
+                            name, var_type, expr_type, cast_hint(&expr_type, var_type)
                         ));
                     }
                 } else {
-                    self.add_error(format!("Variable '{}' not declared", name));
+                    self.add_error(stmt.span, format!("Variable '{}' not declared", name));
                 }
             }
             
-            Statement::ArrayAssignment { name, index, value } => {
+            StatementKind::ArrayAssignment { name, index, value } => {
                 let var_type_opt = self.variables.get(name).cloned();
                 if let Some(var_type) = var_type_opt {
                     if let Type::Array(elem_type, _) = var_type {
                         let index_type = self.infer_expression(index);
                         if !index_type.is_integer() {
-                            self.add_error(format!(
+                            self.add_error(index.span, format!(
                                 "Array index must be an integer, got {:?}",
                                 index_type
                             ));
                         }
                         
                         let value_type = self.infer_expression(value);
-                        if !value_type.can_assign_to(&elem_type) {
-                            self.add_error(format!(
-                                "Type mismatch in array assignment: expected {:?}, got {:?}",
-                                elem_type, value_type
+                        if !assignable(value, &value_type, &elem_type) {
+                            self.add_error(value.span, format!(
+                                "Type mismatch in array assignment: expected {:?}, got {:?}{}",
+                                elem_type, value_type, cast_hint(&value_type, &elem_type)
                             ));
                         }
                     } else {
-                        self.add_error(format!(
+                        self.add_error(stmt.span, format!(
                             "Cannot index into non-array type {:?}",
                             var_type
                         ));
                     }
                 } else {
-                    self.add_error(format!("Variable '{}' not declared", name));
+                    self.add_error(stmt.span, format!("Variable '{}' not declared", name));
                 }
             }
             
-            Statement::If { condition, then_body, else_body } => {
+            StatementKind::If { condition, then_body, else_body } => {
                 let cond_type = self.infer_expression(condition);
-                if !matches!(cond_type, Type::Bool | Type::I64 | Type::Unknown) {
-                    self.add_error(format!(
+                if !cond_type.is_integer() && !matches!(cond_type, Type::Bool | Type::Unknown) {
+                    self.add_error(condition.span, format!(
                         "If condition must be boolean or numeric, got {:?}",
                         cond_type
                     ));
@@ -272,15 +626,15 @@ impl TypeChecker {
                 }
             }
             
-            Statement::For { init, condition, post, body } => {
+            StatementKind::For { init, condition, post, body } => {
                 if let Some(init_stmt) = init {
                     self.check_statement(init_stmt);
                 }
                 
                 if let Some(cond) = condition {
                     let cond_type = self.infer_expression(cond);
-                    if !matches!(cond_type, Type::Bool | Type::I64 | Type::Unknown) {
-                        self.add_error(format!(
+                    if !cond_type.is_integer() && !matches!(cond_type, Type::Bool | Type::Unknown) {
+                        self.add_error(cond.span, format!(
                             "Loop condition must be boolean or numeric, got {:?}",
                             cond_type
                         ));
@@ -291,25 +645,34 @@ impl TypeChecker {
                     self.check_statement(post_stmt);
                 }
                 
+                self.loop_depth += 1;
                 for stmt in body {
                     self.check_statement(stmt);
                 }
+                self.loop_depth -= 1;
+            }
+
+            StatementKind::Break | StatementKind::Continue => {
+                if self.loop_depth == 0 {
+                    let keyword = if matches!(stmt.kind, StatementKind::Break) { "break" } else { "continue" };
+                    self.add_error(stmt.span, format!("'{}' used outside of a loop", keyword));
+                }
             }
             
-            Statement::Return(value) => {
+            StatementKind::Return(value) => {
                 if let Some(func_name) = &self.current_function {
                     let sig_opt = self.functions.get(func_name).cloned();
                     if let Some(sig) = sig_opt {
                         if let Some(expr) = value {
                             let expr_type = self.infer_expression(expr);
-                            if !expr_type.can_assign_to(&sig.return_type) {
-                                self.add_error(format!(
-                                    "Return type mismatch: expected {:?}, got {:?}",
-                                    sig.return_type, expr_type
+                            if !assignable(expr, &expr_type, &sig.return_type) {
+                                self.add_error(expr.span, format!(
+                                    "Return type mismatch: expected {:?}, got {:?}{}",
+                                    sig.return_type, expr_type, cast_hint(&expr_type, &sig.return_type)
                                 ));
                             }
                         } else if !matches!(sig.return_type, Type::Void) {
-                            self.add_error(format!(
+                            self.add_error(stmt.span, format!(
                                 "Function '{}' must return a value of type {:?}",
                                 func_name, sig.return_type
                             ));
@@ -318,14 +681,14 @@ impl TypeChecker {
                 }
             }
             
-            Statement::Expression(expr) => {
+            StatementKind::Expression(expr) => {
                 self.infer_expression(expr);
             }
             
-            Statement::PointerAssignment { target, value } => {
+            StatementKind::PointerAssignment { target, value } => {
                 let target_type = self.infer_expression(target);
                 if !matches!(target_type, Type::Ptr(_) | Type::Unknown) {
-                    self.add_error(format!(
+                    self.add_error(target.span, format!(
                         "Pointer dereference assignment requires a pointer type, got {:?}",
                         target_type
                     ));
@@ -334,56 +697,88 @@ impl TypeChecker {
                 self.infer_expression(value);
             }
             
-            Statement::InlineAsm { .. } => {
+            StatementKind::FieldAssignment { object, field, value } => {
+                let object_type = self.infer_expression(object);
+                let field_type = self.field_type(&object_type, field, stmt.span);
+                let value_type = self.infer_expression(value);
+
+                if matches!(field_type, Type::Struct(_)) {
+                    self.add_error(stmt.span, format!(
+                        "Cannot assign struct field '{}' by value; assign its fields instead",
+                        field
+                    ));
+                } else if !assignable(value, &value_type, &field_type) {
+                    self.add_error(stmt.span, format!(
+                        "Type mismatch in assignment to field '{}': expected {:?}, got {:?}{}",
+                        field, field_type, value_type, cast_hint(&value_type, &field_type)
+                    ));
+                }
+            }
+            
+            StatementKind::InlineAsm { outputs, inputs, .. } => {
+                for operand in outputs.iter().chain(inputs) {
+                    match self.variables.get(&operand.variable) {
+                        None => self.add_error(operand.span, format!("Variable '{}' not declared", operand.variable)),
+                        Some(Type::Array(..) | Type::Struct(_)) => {
+                            self.add_error(operand.span, format!("asm operand '{}' must be a scalar variable", operand.variable));
+                        }
+                        Some(_) => {}
+                    }
+                }
             }
             
-            Statement::ArrayDecl { name, element_type, size } => {
-                let elem_type = Type::from_string(element_type);
+            StatementKind::ArrayDecl { name, element_type, size, .. } => {
+                let elem_type = self.resolve_type(element_type);
                 let array_type = Type::Array(Box::new(elem_type), *size);
                 self.variables.insert(name.clone(), array_type);
             }
         }
     }
 
-    fn infer_expression(&mut self, expr: &Expression) -> Type {
-        match expr {
-            Expression::Number(_) => Type::I64,
+    /// Type of `expr` in the scope of the function checked last. Errors it
+    /// finds are collected for `take_errors`.
+    pub fn infer_expression(&mut self, expr: &Expression) -> Type {
+        match &expr.kind {
+            ExpressionKind::Number(_) => Type::I64,
             
-            Expression::String(_) => Type::String,
+            ExpressionKind::String(_) => Type::String,
             
-            Expression::TemplateString { .. } => Type::String,
+            ExpressionKind::TemplateString { .. } => Type::String,
             
-            Expression::Identifier(name) => {
+            ExpressionKind::Identifier(name) => {
                 self.variables.get(name).cloned().unwrap_or_else(|| {
-                    self.add_error(format!("Variable '{}' not declared", name));
+                    self.add_error(expr.span, format!("Variable '{}' not declared", name));
                     Type::Unknown
                 })
             }
             
-            Expression::Binary { op, left, right } => {
+            ExpressionKind::Binary { op, left, right } => {
                 let left_type = self.infer_expression(left);
                 let right_type = self.infer_expression(right);
                 
                 match op {
                     BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => {
                         if !left_type.is_numeric() {
-                            self.add_error(format!(
+                            self.add_error(left.span, format!(
                                 "Left operand of {:?} must be numeric, got {:?}",
                                 op, left_type
                             ));
                         }
                         if !right_type.is_numeric() {
-                            self.add_error(format!(
+                            self.add_error(right.span, format!(
                                 "Right operand of {:?} must be numeric, got {:?}",
                                 op, right_type
                             ));
                         }
-                        left_type
+                        self.operand_type(op, left, &left_type, right, &right_type, expr.span)
                     }
                     
                     BinaryOp::Equal | BinaryOp::NotEqual | 
                     BinaryOp::Less | BinaryOp::LessEqual | 
                     BinaryOp::Greater | BinaryOp::GreaterEqual => {
+                        if left_type.is_numeric() && right_type.is_numeric() {
+                            self.operand_type(op, left, &left_type, right, &right_type, expr.span);
+                        }
                         Type::Bool
                     }
                     
@@ -392,18 +787,23 @@ impl TypeChecker {
                     }
                     
                     BinaryOp::Concat => {
+                        for (operand, ty) in [(left, &left_type), (right, &right_type)] {
+                            if !matches!(ty, Type::String | Type::Unknown) {
+                                self.add_error(operand.span, format!("'++' joins strings, got {}", ty));
+                            }
+                        }
                         Type::String
                     }
                 }
             }
             
-            Expression::Unary { op, operand } => {
+            ExpressionKind::Unary { op, operand } => {
                 let operand_type = self.infer_expression(operand);
                 
                 match op {
                     UnaryOp::Neg => {
                         if !operand_type.is_numeric() {
-                            self.add_error(format!(
+                            self.add_error(expr.span, format!(
                                 "Negation operand must be numeric, got {:?}",
                                 operand_type
                             ));
@@ -417,52 +817,37 @@ impl TypeChecker {
                 }
             }
             
-            Expression::Call { function, args } => {
+            ExpressionKind::Call { function, args } => {
                 let sig_opt = self.functions.get(function).cloned();
                 if let Some(sig) = sig_opt {
-                    if args.len() != sig.params.len() {
-                        self.add_error(format!(
-                            "Function '{}' expects {} arguments, got {}",
-                            function, sig.params.len(), args.len()
-                        ));
-                    } else {
-                        for (i, (arg, (_, param_type))) in args.iter().zip(sig.params.iter()).enumerate() {
-                            let arg_type = self.infer_expression(arg);
-                            if !arg_type.can_assign_to(param_type) {
-                                self.add_error(format!(
-                                    "Argument {} of function '{}': expected {:?}, got {:?}",
-                                    i, function, param_type, arg_type
-                                ));
-                            }
-                        }
-                    }
+                    self.check_arguments(function, &sig, args, expr.span);
                     sig.return_type.clone()
                 } else {
-                    self.add_error(format!("Function '{}' not declared", function));
+                    self.add_error(expr.span, format!("Function '{}' not declared", function));
                     Type::Unknown
                 }
             }
             
-            Expression::ModuleCall { module, function, args } => {
+            ExpressionKind::ModuleCall { module, function, args } => {
                 let full_name = format!("{}.{}", module, function);
                 let sig_opt = self.functions.get(&full_name).cloned();
                 if let Some(sig) = sig_opt {
-                    if args.len() != sig.params.len() {
-                        self.add_error(format!(
-                            "Function '{}' expects {} arguments, got {}",
-                            full_name, sig.params.len(), args.len()
-                        ));
-                    }
+                    self.check_arguments(&full_name, &sig, args, expr.span);
                     sig.return_type.clone()
                 } else {
                     Type::Unknown
                 }
             }
             
-            Expression::ArrayAccess { name, index } => {
+            ExpressionKind::FieldAccess { object, field } => {
+                let object_type = self.infer_expression(object);
+                self.field_type(&object_type, field, expr.span)
+            }
+            
+            ExpressionKind::ArrayAccess { name, index } => {
                 let index_type = self.infer_expression(index);
                 if !index_type.is_integer() {
-                    self.add_error(format!(
+                    self.add_error(index.span, format!(
                         "Array index must be an integer, got {:?}",
                         index_type
                     ));
@@ -473,24 +858,24 @@ impl TypeChecker {
                     if let Type::Array(elem_type, _) = var_type {
                         (*elem_type).clone()
                     } else {
-                        self.add_error(format!(
+                        self.add_error(expr.span, format!(
                             "Cannot index into non-array type {:?}",
                             var_type
                         ));
                         Type::Unknown
                     }
                 } else {
-                    self.add_error(format!("Variable '{}' not declared", name));
+                    self.add_error(expr.span, format!("Variable '{}' not declared", name));
                     Type::Unknown
                 }
             }
             
-            Expression::StringIndex { string, index } => {
+            ExpressionKind::StringIndex { string, index } => {
                 let _string_type = self.infer_expression(string);
                 let index_type = self.infer_expression(index);
                 
                 if !index_type.is_integer() {
-                    self.add_error(format!(
+                    self.add_error(index.span, format!(
                         "String index must be an integer, got {:?}",
                         index_type
                     ));
@@ -499,17 +884,17 @@ impl TypeChecker {
                 Type::U8
             }
             
-            Expression::AddressOf { operand } => {
+            ExpressionKind::AddressOf { operand } => {
                 let inner_type = self.infer_expression(operand);
                 Type::Ptr(Box::new(inner_type))
             }
             
-            Expression::Deref { operand } => {
+            ExpressionKind::Deref { operand } => {
                 let operand_type = self.infer_expression(operand);
                 if let Type::Ptr(inner) = operand_type {
                     (*inner).clone()
                 } else {
-                    self.add_error(format!(
+                    self.add_error(expr.span, format!(
                         "Cannot dereference non-pointer type {:?}",
                         operand_type
                     ));
@@ -517,24 +902,511 @@ impl TypeChecker {
                 }
             }
             
-            Expression::Eval { instruction } => {
-                self.infer_expression(instruction);
+            ExpressionKind::Cast { operand, target_type } => {
+                let operand_type = self.infer_expression(operand);
+                let target = self.resolve_type(target_type);
+                if !operand_type.can_cast_to(&target) {
+                    self.add_error(expr.span, format!(
+                        "Cannot cast {:?} to {:?}",
+                        operand_type, target
+                    ));
+                }
+                target
+            }
+            
+            // Folded to a number by `fold_constants` before checking.
+            ExpressionKind::Eval { .. } => Type::I64,
+        }
+    }
+
+    /// Type a numeric binary operation is carried out in; mixing types that
+    /// do not widen into one another (e.g. `i32` and `u32`) needs a cast.
+    fn operand_type(&mut self, op: &BinaryOp, left: &Expression, left_type: &Type, right: &Expression, right_type: &Type, span: Span) -> Type {
+        match common_type(left, left_type, right, right_type) {
+            Some(ty) => ty,
+            None => {
+                self.add_error(span, format!(
+                    "Operands of {:?} have incompatible types {:?} and {:?}; use an explicit 'as' cast",
+                    op, left_type, right_type
+                ));
+                left_type.clone()
+            }
+        }
+    }
+
+    fn check_arguments(&mut self, function: &str, sig: &FunctionSignature, args: &[Expression], span: Span) {
+        if args.len() != sig.params.len() {
+            self.add_error(span, format!(
+                "Function '{}' expects {} arguments, got {}",
+                function, sig.params.len(), args.len()
+            ));
+            return;
+        }
+        for (i, (arg, (_, param_type))) in args.iter().zip(sig.params.iter()).enumerate() {
+            let arg_type = self.infer_expression(arg);
+            if !assignable(arg, &arg_type, param_type) {
+                self.add_error(arg.span, format!(
+                    "Argument {} of function '{}': expected {:?}, got {:?}{}",
+                    i, function, param_type, arg_type, cast_hint(&arg_type, param_type)
+                ));
+            }
+        }
+    }
+
+    fn resolve_type(&self, s: &str) -> Type {
+        Type::resolve(s, &self.structs)
+    }
+
+    fn field_type(&mut self, object_type: &Type, field: &str, span: Span) -> Type {
+        if matches!(object_type, Type::Unknown) {
+            return Type::Unknown;
+        }
+
+        let Some(struct_name) = object_type.struct_name() else {
+            self.add_error(span, format!(
+                "Cannot access field '{}' on non-struct type {:?}",
+                field, object_type
+            ));
+            return Type::Unknown;
+        };
+
+        let field_layout = self.structs.get(struct_name).and_then(|layout| layout.field(field)).cloned();
+        match field_layout {
+            Some(f) => f.ty,
+            None => {
+                self.add_error(span, format!("Struct '{}' has no field '{}'", struct_name, field));
                 Type::Unknown
             }
         }
     }
 
-    fn add_error(&mut self, message: String) {
-        let location = self.current_function.clone().unwrap_or_else(|| "global".to_string());
-        self.errors.push(TypeError {
+    fn add_error(&mut self, span: Span, message: String) {
+        self.errors.push(CompileError::new(
+            ErrorKind::TypeError,
             message,
-            location,
-        });
+            self.file.clone(),
+            span.line,
+            span.column,
+        ));
+    }
+}
+
+/// Statements and expressions one `eval(...)` may work through, so a loop
+/// that never ends is an error rather than a hang.
+const EVAL_STEPS: usize = 1_000_000;
+/// Calls one `eval(...)` may nest.
+const EVAL_DEPTH: usize = 256;
+
+/// What backends report for an `eval(...)` left in a program that did not go
+/// through `fold_constants`.
+pub const EVAL_NOT_FOLDED: &str = "eval(...) not folded: run typechecker::fold_constants before generating code";
+
+/// Replaces every `eval(expr)` in `program`, and every array size written as
+/// one, with the value of `expr` worked out at compile time. The argument
+/// may use literals, operators, casts and calls to functions whose bodies
+/// only do the same with their parameters and locals; anything else is a
+/// `TypeError`.
+pub fn fold_constants(program: &mut Program) -> Result<(), Vec<CompileError>> {
+    let source = program.clone();
+    let mut errors = Vec::new();
+    let mut fold = |file: &str, body: &mut [Statement]| {
+        let mut folder = Folder { evaluator: Evaluator::new(&source), file, errors: &mut errors };
+        folder.body(body);
+    };
+    for func in &mut program.functions {
+        fold(&program.file, &mut func.body);
+    }
+    for module in program.modules.values_mut() {
+        for func in &mut module.functions {
+            fold(&module.file, &mut func.body);
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+struct Folder<'a, 'e> {
+    evaluator: Evaluator<'a>,
+    file: &'e str,
+    errors: &'e mut Vec<CompileError>,
+}
+
+impl Folder<'_, '_> {
+    fn body(&mut self, body: &mut [Statement]) {
+        for stmt in body {
+            self.statement(stmt);
+        }
+    }
+
+    fn statement(&mut self, stmt: &mut Statement) {
+        match &mut stmt.kind {
+            StatementKind::VarDecl { value, .. } | StatementKind::Return(value) => {
+                if let Some(expr) = value {
+                    self.expression(expr);
+                }
+            }
+            StatementKind::ArrayDecl { size, size_eval, .. } => {
+                if let Some(expr) = size_eval {
+                    if let Some(value) = self.evaluate(expr) {
+                        match usize::try_from(value) {
+                            Ok(n) => *size = n,
+                            Err(_) => self.error(expr.span, format!("array size must not be negative, got {}", value)),
+                        }
+                    }
+                }
+            }
+            StatementKind::Assignment { value, .. } | StatementKind::Expression(value) => self.expression(value),
+            StatementKind::ArrayAssignment { index: first, value, .. }
+            | StatementKind::PointerAssignment { target: first, value }
+            | StatementKind::FieldAssignment { object: first, value, .. } => {
+                self.expression(first);
+                self.expression(value);
+            }
+            StatementKind::If { condition, then_body, else_body } => {
+                self.expression(condition);
+                self.body(then_body);
+                if let Some(body) = else_body {
+                    self.body(body);
+                }
+            }
+            StatementKind::For { init, condition, post, body } => {
+                if let Some(s) = init {
+                    self.statement(s);
+                }
+                if let Some(expr) = condition {
+                    self.expression(expr);
+                }
+                if let Some(s) = post {
+                    self.statement(s);
+                }
+                self.body(body);
+            }
+            StatementKind::Break | StatementKind::Continue | StatementKind::InlineAsm { .. } => {}
+        }
+    }
+
+    fn expression(&mut self, expr: &mut Expression) {
+        match &mut expr.kind {
+            ExpressionKind::Eval { instruction } => {
+                if let Some(value) = self.evaluate(instruction) {
+                    expr.kind = ExpressionKind::Number(value);
+                }
+            }
+            ExpressionKind::Call { args, .. } | ExpressionKind::ModuleCall { args, .. } => {
+                args.iter_mut().for_each(|arg| self.expression(arg));
+            }
+            ExpressionKind::TemplateString { parts } => {
+                for part in parts {
+                    if let TemplateStringPart::Expression { expr, .. } = part {
+                        self.expression(expr);
+                    }
+                }
+            }
+            ExpressionKind::Binary { left, right, .. } | ExpressionKind::StringIndex { string: left, index: right } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            ExpressionKind::Unary { operand, .. }
+            | ExpressionKind::AddressOf { operand }
+            | ExpressionKind::Deref { operand }
+            | ExpressionKind::Cast { operand, .. }
+            | ExpressionKind::FieldAccess { object: operand, .. }
+            | ExpressionKind::ArrayAccess { index: operand, .. } => self.expression(operand),
+            ExpressionKind::Number(_) | ExpressionKind::String(_) | ExpressionKind::Identifier(_) => {}
+        }
+    }
+
+    fn evaluate(&mut self, expr: &Expression) -> Option<i64> {
+        match self.evaluator.evaluate(expr) {
+            Ok(value) => Some(value),
+            Err((span, message)) => {
+                self.error(span, message);
+                None
+            }
+        }
+    }
+
+    fn error(&mut self, span: Span, message: String) {
+        self.errors.push(CompileError::new(ErrorKind::TypeError, message, self.file.to_string(), span.line, span.column));
+    }
+}
+
+/// An integer worked out by `eval`, wrapped to its type.
+#[derive(Debug, Clone)]
+struct Constant {
+    value: i64,
+    ty: Type,
+}
+
+impl Constant {
+    fn new(value: i64, ty: Type) -> Self {
+        let value = match ty {
+            Type::I32 => value as i32 as i64,
+            Type::U32 => value as u32 as i64,
+            Type::I8 => value as i8 as i64,
+            Type::U8 => value as u8 as i64,
+            Type::Bool => (value != 0) as i64,
+            _ => value,
+        };
+        Constant { value, ty }
+    }
+}
+
+enum Flow {
+    Next,
+    Break,
+    Continue,
+    Return(Constant),
+}
+
+type Evaluated<T> = Result<T, (Span, String)>;
+
+/// The `const fn`-style interpreter behind `eval`.
+struct Evaluator<'a> {
+    program: &'a Program,
+    structs: HashMap<String, StructLayout>,
+    steps: usize,
+    depth: usize,
+}
+
+impl<'a> Evaluator<'a> {
+    fn new(program: &'a Program) -> Self {
+        let structs = compute_struct_layouts(&program.structs).unwrap_or_default();
+        Evaluator { program, structs, steps: 0, depth: 0 }
+    }
+
+    fn evaluate(&mut self, expr: &Expression) -> Evaluated<i64> {
+        self.steps = 0;
+        self.depth = 0;
+        let mut locals = HashMap::new();
+        self.expression(expr, &mut locals).map(|c| c.value)
+    }
+
+    fn step(&mut self, span: Span) -> Evaluated<()> {
+        self.steps += 1;
+        if self.steps > EVAL_STEPS {
+            return Err((span, format!("eval(...) did not finish within {} steps", EVAL_STEPS)));
+        }
+        Ok(())
+    }
+
+    fn resolve(&self, ty: &str, span: Span) -> Evaluated<Type> {
+        match Type::resolve(ty, &self.structs) {
+            ty if ty.is_integer() || ty == Type::Bool => Ok(ty),
+            _ => Err((span, format!("eval(...) works on integers, not {}", ty))),
+        }
     }
 
-    pub fn print_errors(&self) {
-        for error in &self.errors {
-            eprintln!("Type error in {}: {}", error.location, error.message);
+    fn expression(&mut self, expr: &Expression, locals: &mut HashMap<String, Constant>) -> Evaluated<Constant> {
+        self.step(expr.span)?;
+        let not_constant = |what: String| Err((expr.span, format!("eval(...) needs a constant expression; {} is not constant", what)));
+        match &expr.kind {
+            ExpressionKind::Number(n) => Ok(Constant::new(*n, Type::I64)),
+            ExpressionKind::Identifier(name) => match locals.get(name) {
+                Some(constant) => Ok(constant.clone()),
+                None => not_constant(format!("variable '{}'", name)),
+            },
+            ExpressionKind::Binary { op: op @ (BinaryOp::And | BinaryOp::Or), left, right } => {
+                let left = self.expression(left, locals)?.value != 0;
+                let value = if left == (*op == BinaryOp::Or) {
+                    left
+                } else {
+                    self.expression(right, locals)?.value != 0
+                };
+                Ok(Constant::new(value as i64, Type::Bool))
+            }
+            ExpressionKind::Binary { op, left, right } => {
+                let left = self.expression(left, locals)?;
+                let right = self.expression(right, locals)?;
+                self.binary(op, left, right, expr.span)
+            }
+            ExpressionKind::Unary { op, operand } => {
+                let operand = self.expression(operand, locals)?;
+                Ok(match op {
+                    UnaryOp::Neg => Constant::new(operand.value.wrapping_neg(), operand.ty),
+                    UnaryOp::Not => Constant::new((operand.value == 0) as i64, Type::Bool),
+                })
+            }
+            ExpressionKind::Cast { operand, target_type } => {
+                let operand = self.expression(operand, locals)?;
+                Ok(Constant::new(operand.value, self.resolve(target_type, expr.span)?))
+            }
+            ExpressionKind::Eval { instruction } => self.expression(instruction, locals),
+            ExpressionKind::Call { function, args } => self.call(None, function, args, locals, expr.span),
+            ExpressionKind::ModuleCall { module, function, args } => {
+                self.call(Some(module), function, args, locals, expr.span)
+            }
+            ExpressionKind::String(_) | ExpressionKind::TemplateString { .. } | ExpressionKind::StringIndex { .. } => {
+                not_constant("a string".to_string())
+            }
+            ExpressionKind::FieldAccess { field, .. } => not_constant(format!("field '{}'", field)),
+            ExpressionKind::ArrayAccess { name, .. } => not_constant(format!("an element of '{}'", name)),
+            ExpressionKind::AddressOf { .. } | ExpressionKind::Deref { .. } => not_constant("a pointer".to_string()),
+        }
+    }
+
+    fn binary(&self, op: &BinaryOp, left: Constant, right: Constant, span: Span) -> Evaluated<Constant> {
+        // A plain literal takes the type of the other operand, as it does
+        // in the checker.
+        let ty = if left.ty == Type::I64 { right.ty.clone() } else { left.ty.clone() };
+        let unsigned = left.ty.is_unsigned() || right.ty.is_unsigned();
+        let (a, b) = (left.value, right.value);
+        let compare = |ordering: std::cmp::Ordering| {
+            let actual = if unsigned { (a as u64).cmp(&(b as u64)) } else { a.cmp(&b) };
+            Ok(Constant::new((actual == ordering) as i64, Type::Bool))
+        };
+        let value = match op {
+            BinaryOp::Add => a.wrapping_add(b),
+            BinaryOp::Sub => a.wrapping_sub(b),
+            BinaryOp::Mul => a.wrapping_mul(b),
+            BinaryOp::Div | BinaryOp::Mod if b == 0 => return Err((span, "division by zero in eval(...)".to_string())),
+            BinaryOp::Div if unsigned => ((a as u64) / (b as u64)) as i64,
+            BinaryOp::Mod if unsigned => ((a as u64) % (b as u64)) as i64,
+            BinaryOp::Div => a.wrapping_div(b),
+            BinaryOp::Mod => a.wrapping_rem(b),
+            BinaryOp::Equal => return Ok(Constant::new((a == b) as i64, Type::Bool)),
+            BinaryOp::NotEqual => return Ok(Constant::new((a != b) as i64, Type::Bool)),
+            BinaryOp::Less => return compare(std::cmp::Ordering::Less),
+            BinaryOp::Greater => return compare(std::cmp::Ordering::Greater),
+            BinaryOp::LessEqual => return compare(std::cmp::Ordering::Greater).map(|c| Constant::new(1 - c.value, Type::Bool)),
+            BinaryOp::GreaterEqual => return compare(std::cmp::Ordering::Less).map(|c| Constant::new(1 - c.value, Type::Bool)),
+            BinaryOp::And | BinaryOp::Or => unreachable!("short-circuited in expression"),
+            BinaryOp::Concat => {
+                return Err((span, "eval(...) needs a constant expression; a string is not constant".to_string()));
+            }
+        };
+        Ok(Constant::new(value, ty))
+    }
+
+    fn call(
+        &mut self,
+        module: Option<&str>,
+        function: &str,
+        args: &[Expression],
+        locals: &mut HashMap<String, Constant>,
+        span: Span,
+    ) -> Evaluated<Constant> {
+        let name = match module {
+            Some(module) => format!("{}.{}", module, function),
+            None => function.to_string(),
+        };
+        let callee = self.program.callee(module, function)
+            .filter(|f| f.external.is_none() && !module.is_some_and(|m| m == "stdio" || is_string_builtin(m, function)));
+        let Some(callee) = callee else {
+            return Err((span, format!("eval(...) needs a constant expression; a call to '{}' is not constant", name)));
+        };
+        if args.len() != callee.params.len() {
+            return Err((span, format!("'{}' takes {} arguments, got {}", name, callee.params.len(), args.len())));
+        }
+        if self.depth == EVAL_DEPTH {
+            return Err((span, format!("eval(...) nests calls deeper than {}", EVAL_DEPTH)));
+        }
+
+        let mut frame = HashMap::new();
+        for (param, arg) in callee.params.iter().zip(args) {
+            let value = self.expression(arg, locals)?;
+            let ty = self.resolve(&param.param_type, span)?;
+            frame.insert(param.name.clone(), Constant::new(value.value, ty));
+        }
+        let return_type = match &callee.return_type {
+            Some(ty) => self.resolve(ty, span)?,
+            None => return Err((span, format!("'{}' returns no value for eval(...)", name))),
+        };
+
+        // Errors inside the callee point at the call, since its body may be
+        // in another file.
+        self.depth += 1;
+        let flow = self.body(&callee.body, &mut frame);
+        self.depth -= 1;
+        let flow = flow.map_err(|(inner, message)| match self.depth {
+            0 => (span, format!("{}, in the call to '{}'", message, name)),
+            _ => (inner, message),
+        })?;
+        match flow {
+            Flow::Return(value) => Ok(Constant::new(value.value, return_type)),
+            _ => Err((span, format!("'{}' ended without returning a value", name))),
+        }
+    }
+
+    fn body(&mut self, body: &[Statement], locals: &mut HashMap<String, Constant>) -> Evaluated<Flow> {
+        for stmt in body {
+            match self.statement(stmt, locals)? {
+                Flow::Next => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn statement(&mut self, stmt: &Statement, locals: &mut HashMap<String, Constant>) -> Evaluated<Flow> {
+        self.step(stmt.span)?;
+        match &stmt.kind {
+            StatementKind::VarDecl { name, var_type, value } => {
+                let value = match value {
+                    Some(expr) => self.expression(expr, locals)?,
+                    None => Constant::new(0, Type::I64),
+                };
+                let ty = match var_type {
+                    Some(ty) => self.resolve(ty, stmt.span)?,
+                    None => value.ty,
+                };
+                locals.insert(name.clone(), Constant::new(value.value, ty));
+            }
+            StatementKind::Assignment { name, value } => {
+                let value = self.expression(value, locals)?;
+                let Some(ty) = locals.get(name).map(|c| c.ty.clone()) else {
+                    return Err((stmt.span, format!("eval(...) needs a constant expression; variable '{}' is not constant", name)));
+                };
+                locals.insert(name.clone(), Constant::new(value.value, ty));
+            }
+            StatementKind::If { condition, then_body, else_body } => {
+                if self.expression(condition, locals)?.value != 0 {
+                    return self.body(then_body, locals);
+                } else if let Some(body) = else_body {
+                    return self.body(body, locals);
+                }
+            }
+            StatementKind::For { init, condition, post, body } => {
+                if let Some(s) = init {
+                    self.statement(s, locals)?;
+                }
+                loop {
+                    self.step(stmt.span)?;
+                    if let Some(expr) = condition {
+                        if self.expression(expr, locals)?.value == 0 {
+                            break;
+                        }
+                    }
+                    match self.body(body, locals)? {
+                        Flow::Break => break,
+                        flow @ Flow::Return(_) => return Ok(flow),
+                        Flow::Next | Flow::Continue => {}
+                    }
+                    if let Some(s) = post {
+                        self.statement(s, locals)?;
+                    }
+                }
+            }
+            StatementKind::Return(Some(expr)) => return Ok(Flow::Return(self.expression(expr, locals)?)),
+            StatementKind::Return(None) => return Err((stmt.span, "eval(...) needs a value to be returned".to_string())),
+            StatementKind::Break => return Ok(Flow::Break),
+            StatementKind::Continue => return Ok(Flow::Continue),
+            StatementKind::Expression(expr) => {
+                self.expression(expr, locals)?;
+            }
+            StatementKind::ArrayDecl { .. }
+            | StatementKind::ArrayAssignment { .. }
+            | StatementKind::PointerAssignment { .. }
+            | StatementKind::FieldAssignment { .. } => {
+                return Err((stmt.span, "eval(...) cannot run code that uses arrays, pointers or structs".to_string()));
+            }
+            StatementKind::InlineAsm { .. } => {
+                return Err((stmt.span, "eval(...) cannot run inline assembly".to_string()));
+            }
         }
+        Ok(Flow::Next)
     }
 }
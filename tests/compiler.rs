@@ -0,0 +1,353 @@
+//! The library API: in-memory sources, the resolver callback and diagnostics.
+
+use perano_lang::{ast, compiler, nvm, Compiler, ErrorKind, SourceFile};
+
+const MAIN: &str = "package main
+
+import \"util\"
+
+func main() i64 {
+    return util.Twice(21)
+}
+";
+
+const UTIL: &str = "package util
+
+pub func Twice(x i64) i64 {
+    return x * 2
+}
+";
+
+#[test]
+fn imports_come_from_added_sources() {
+    let mut session = Compiler::new();
+    session.add_source("main.per", MAIN);
+    session.add_source("util.per", UTIL);
+
+    let program = session.check("main.per").expect("program should check");
+    assert!(program.modules.contains_key("util"));
+    assert!(compiler::nvm_bytecode(&program).unwrap().starts_with(b"NVM0"));
+    assert!(compiler::elf_assembly(&program, true).unwrap().contains("util"));
+}
+
+#[test]
+fn imports_fall_back_to_the_resolver() {
+    let mut session = Compiler::new().with_resolver(|module| {
+        (module == "util").then(|| SourceFile { name: "lib/util.per".to_string(), text: UTIL.to_string() })
+    });
+    session.add_source("main.per", MAIN);
+
+    let program = session.check("main.per").expect("program should check");
+    assert_eq!(program.modules["util"].file, "lib/util.per");
+}
+
+#[test]
+fn errors_carry_kind_position_and_source_line() {
+    let mut session = Compiler::new();
+    session.add_source("main.per", "package main\n\nfunc main() i64 {\n    var s u8 = 1\n    return s + \"x\"\n}\n");
+
+    let errors = session.check("main.per").unwrap_err();
+    let error = &errors[0];
+    assert_eq!(error.kind, ErrorKind::TypeError);
+    assert_eq!((error.file.as_str(), error.line), ("main.per", 5));
+    assert_eq!(error.source_line.as_deref(), Some("    return s + \"x\""));
+
+    let errors = session.check("missing.per").unwrap_err();
+    assert_eq!(errors[0].kind, ErrorKind::ModuleError);
+}
+
+#[test]
+fn unresolved_imports_point_at_the_import() {
+    let mut session = Compiler::new();
+    session.add_source("main.per", MAIN);
+
+    let errors = session.check("main.per").unwrap_err();
+    assert_eq!(errors[0].message, "could not find module 'util'");
+    assert_eq!((errors[0].line, errors[0].column), (3, 1));
+}
+
+#[test]
+fn oversized_frames_are_codegen_errors() {
+    let mut session = Compiler::new();
+    session.add_source("main.per", "package main\n\nfunc main() i64 {\n    var big [i64; 200000]\n    big[0] = 1\n    return big[0]\n}\n");
+
+    let program = session.check("main.per").expect("program should check");
+    let error = compiler::elf_assembly(&program, true).unwrap_err();
+    assert_eq!(error.kind, ErrorKind::CodeGenError);
+    assert_eq!((error.file.as_str(), error.line), ("main.per", 3));
+}
+
+#[test]
+fn extern_c_functions_are_called_through_the_plt() {
+    let mut session = Compiler::new();
+    session.add_source("main.per", "package main\n\nextern \"C\" fn abs(n i32) -> i32\n\nfunc main() i64 {\n    return abs(-3)\n}\n");
+
+    let program = session.check("main.per").expect("program should check");
+    let asm = compiler::elf_assembly(&program, false).unwrap();
+    assert!(asm.contains("call    abs@PLT\n    movslq  %eax, %rax"));
+    assert!(!asm.contains("abs:"));
+
+    let error = compiler::elf_assembly(&program, true).unwrap_err();
+    assert_eq!((error.kind, error.line), (ErrorKind::CodeGenError, 6));
+
+    session.add_source("main.per", "package main\n\nstruct P {\n    x i64\n}\n\nextern \"C\" fn f(p P)\n\nfunc main() i64 {\n    return abs(1, 2)\n}\n\nextern \"C\" fn abs(n i32) -> i32\n");
+    let errors = session.check("main.per").unwrap_err();
+    let lines: Vec<usize> = errors.iter().map(|e| e.line).collect();
+    assert_eq!(lines, [7, 10]);
+}
+
+#[test]
+fn syscall_bindings_must_match_the_syscall_table() {
+    let mut session = Compiler::new();
+    session.add_source("main.per", "package main\n\nextern \"novaria\" fn read(path string) -> i64 = 3\n\nfunc main() i64 {\n    return read(\"x\")\n}\n");
+
+    let program = session.check("main.per").expect("program should check");
+    let error = compiler::nvm_bytecode(&program).unwrap_err();
+    assert_eq!(error.message, "'read' takes 1 arguments, but syscall read pops 3");
+    assert_eq!(error.line, 3);
+}
+
+#[test]
+fn concatenation_joins_strings_only() {
+    let mut session = Compiler::new();
+    session.add_source("main.per", "package main\n\nfunc main() {\n    var s = \"a\" ++ \"b\"\n    var t = s ++ 1\n}\n");
+
+    let errors = session.check("main.per").unwrap_err();
+    assert_eq!(errors[0].message, "'++' joins strings, got i64");
+    assert_eq!(errors[0].kind, ErrorKind::TypeError);
+    assert_eq!((errors[0].line, errors[0].column), (5, 18));
+
+    session.add_source("main.per", "package main\n\nfunc main() {\n    var s = \"a\" ++ \"b\"\n}\n");
+    let program = session.check("main.per").expect("program should check");
+    let c = compiler::c_source(&program).unwrap();
+    assert!(c.contains("= { 1, \"a\" };"));
+    assert!(c.contains("char* s = per_str_concat(per_lit_0.text, per_lit_1.text);"));
+}
+
+#[test]
+fn nvm_assembly_resolves_labels_and_data() {
+    let source = ".NVM0\nstart: push text ; address of the string\n    jmp start\ntext: .string \"a;\\n\"\n    .word 258\n";
+    let bytecode = nvm::assembler::assemble(source, "main.asm").unwrap();
+    assert_eq!(bytecode, b"NVM0\x02\x00\x00\x00\x0e\x30\x00\x00\x00\x04a;\n\x00\x02\x01\x00\x00");
+
+    let error = nvm::assembler::assemble(".NVM0\n    store 256\n", "main.asm").unwrap_err();
+    assert_eq!(error.message, "'256' is not a local slot (0 to 255)");
+    assert_eq!((error.kind, error.line, error.column), (ErrorKind::ParserError, 2, 11));
+}
+
+#[test]
+fn string_escapes_are_encoded_for_each_backend() {
+    let mut session = Compiler::new();
+    session.add_source("main.per", "package main\n\nfunc main() {\n    var s = \"a\\\"b\\\\c\\td\\0\\x7f\\u{e9}??\"\n}\n");
+
+    let program = session.check("main.per").expect("program should check");
+    let elf = compiler::elf_assembly(&program, true).unwrap();
+    assert!(elf.contains(".string \"a\\\"b\\\\c\\td\\000\\177é??\""));
+    let c = compiler::c_source(&program).unwrap();
+    assert!(c.contains("= { 13, \"a\\\"b\\\\c\\td\\000\\177é?\\?\" };"));
+    let nvm = compiler::nvm_assembly(&program).unwrap();
+    assert!(nvm.contains(".string \"a\\\"b\\\\c\\td\\x00\\x7fé??\""));
+    assert_eq!(nvm::assembler::assemble(&nvm, "main.asm").unwrap(), compiler::nvm_bytecode(&program).unwrap());
+
+    session.add_source("main.per", "package main\n\nfunc main() {\n    var s = \"\\x41\\q\"\n}\n");
+    let errors = session.check("main.per").unwrap_err();
+    assert_eq!(errors[0].message, "unknown escape '\\q' in string");
+    assert_eq!((errors[0].kind.clone(), errors[0].line, errors[0].column), (ErrorKind::LexerError, 4, 18));
+}
+
+#[test]
+fn asm_operands_are_bound_to_registers_and_frame_slots() {
+    let mut session = Compiler::new();
+    session.add_source("main.per", "package main\n\nfunc main() i64 {\n    var a i32 = 1\n    var b = 2\n    asm \"addl $(b), $(a)\" : \"+r\"(a) : \"m\"(b) : \"rbx\"\n    return a\n}\n");
+
+    let program = session.check("main.per").expect("program should check");
+    let asm = compiler::elf_assembly(&program, true).unwrap();
+    assert!(asm.contains("    pushq   %rbx\n    movslq  -8(%rbp), %rax\n    addl -16(%rbp), %eax\n    movl    %eax, -8(%rbp)\n    popq    %rbx\n"));
+
+    session.add_source("main.per", "package main\n\nfunc main() {\n    var a = 1\n    asm \"nop\" : \"=c\"(a) : : \"rcx\"\n}\n");
+    let program = session.check("main.per").expect("program should check");
+    let error = compiler::elf_assembly(&program, true).unwrap_err();
+    assert_eq!(error.message, "no register left for 'a' with constraint \"=c\"");
+    assert_eq!((error.kind, error.line, error.column), (ErrorKind::CodeGenError, 5, 17));
+}
+
+#[test]
+fn eval_folds_constants_and_rejects_the_rest() {
+    let mut session = Compiler::new();
+    session.add_source("main.per", "package main\n\nfunc sq(n i64) i64 {\n    return n * n\n}\n\nfunc main() i64 {\n    var n = 3\n    var a = eval(sq(4) + 1)\n    var b = eval(n + 1)\n    var c = eval(sq(n))\n    return a\n}\n");
+
+    let errors = session.check("main.per").unwrap_err();
+    let messages: Vec<_> = errors.iter().map(|e| (e.kind.clone(), e.line, e.column, e.message.as_str())).collect();
+    assert_eq!(messages, [
+        (ErrorKind::TypeError, 10, 18, "eval(...) needs a constant expression; variable 'n' is not constant"),
+        (ErrorKind::TypeError, 11, 21, "eval(...) needs a constant expression; variable 'n' is not constant"),
+    ]);
+
+    session.add_source("main.per", "package main\n\nfunc main() i64 {\n    var a [i64; eval(2 * 3)]\n    return eval(7 / (3 - 3))\n}\n");
+    let errors = session.check("main.per").unwrap_err();
+    assert_eq!(errors[0].message, "division by zero in eval(...)");
+    assert_eq!(errors.len(), 1);
+}
+
+#[test]
+fn returning_from_main_exits_the_nvm_process() {
+    let stdlib = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("stdlib");
+    let mut session = Compiler::new().with_resolver(compiler::directory_resolver(vec![stdlib]));
+    session.add_source("main.per", "package main\n\nimport \"stdio\"\n\nfunc main() i64 {\n    for var i = 0; i < 10; i = i + 1 {\n        if i == 3 {\n            return i + 4\n        }\n        stdio.Println(i)\n    }\n    return 1\n}\n");
+
+    let program = session.check("main.per").expect("program should check");
+    let bytecode = compiler::nvm_bytecode(&program).unwrap();
+    let mut out = Vec::new();
+    let code = nvm::vm::NVMInterpreter::new(&std::env::temp_dir()).run(&bytecode, &mut out).unwrap();
+    assert_eq!((code, String::from_utf8(out).unwrap().as_str()), (7, "0\n1\n2\n"));
+}
+
+#[test]
+fn unchecked_programs_get_codegen_errors_instead_of_panics() {
+    let mut session = Compiler::new();
+    session.add_source("main.per", "package main\n\nfunc main() i64 {\n    x = 1\n    return x\n}\n");
+
+    let program = session.parse("main.per").expect("program should parse");
+    let error = compiler::nvm_bytecode(&program).unwrap_err();
+    assert_eq!(error.kind, ErrorKind::CodeGenError);
+    assert_eq!((error.file.as_str(), error.line, error.column, error.message.as_str()), ("main.per", 4, 5, "unknown variable 'x'"));
+}
+
+#[test]
+fn u64_is_rejected_on_nvm() {
+    let mut session = Compiler::new();
+    session.add_source("main.per", "package main\n\nfunc main() i64 {\n    var n i64 = 5\n    return (n as u64 + 1) as i64\n}\n");
+
+    let program = session.check("main.per").expect("program should check");
+    assert!(compiler::elf_assembly(&program, true).is_ok());
+    let error = compiler::nvm_bytecode(&program).unwrap_err();
+    assert_eq!(error.kind, ErrorKind::CodeGenError);
+    assert_eq!((error.file.as_str(), error.line), ("main.per", 5));
+    assert!(error.message.starts_with("u64"), "{}", error.message);
+}
+
+#[test]
+fn nvm_programs_report_running_out_of_heap() {
+    let mut session = Compiler::new();
+    session.add_source(
+        "main.per",
+        "package main\n\nfunc main() i64 {\n    var s = \"0123456789abcdef\"\n    for var i = 0; i < 20; i = i + 1 {\n        s = s ++ s\n    }\n    return 0\n}\n",
+    );
+
+    let program = session.check("main.per").expect("program should check");
+    let bytecode = compiler::nvm_bytecode(&program).unwrap();
+    let mut out = Vec::new();
+    let code = nvm::vm::NVMInterpreter::new(&std::env::temp_dir()).run(&bytecode, &mut out).unwrap();
+    assert_eq!((code, String::from_utf8(out).unwrap().as_str()), (12, "out of memory\n"));
+}
+
+#[test]
+fn backends_reject_eval_that_was_not_folded() {
+    let mut session = Compiler::new();
+    session.add_source("main.per", "package main\n\nfunc main() i64 {\n    return eval(2 + 3)\n}\n");
+
+    let program = session.parse("main.per").expect("program should parse");
+    let errors = [
+        compiler::elf_assembly(&program, true).unwrap_err(),
+        compiler::nvm_bytecode(&program).unwrap_err(),
+        compiler::c_source(&program).unwrap_err(),
+    ];
+    for error in errors {
+        assert_eq!(error.kind, ErrorKind::CodeGenError);
+        assert_eq!((error.file.as_str(), error.line, error.column), ("main.per", 4, 12));
+        assert!(error.message.starts_with("eval(...) not folded"), "{}", error.message);
+    }
+}
+
+#[test]
+fn nvm_asm_block_errors_point_at_the_block() {
+    let cases = [
+        ("asm \"jmp nowhere\"", "undefined label 'nowhere'"),
+        ("asm \"push $(missing)\"", "asm block uses 'missing', which is not a local variable"),
+        ("asm \"bogus\"", "unknown instruction 'bogus' in asm block: bogus"),
+    ];
+    for (statement, message) in cases {
+        let mut session = Compiler::new();
+        session.add_source("main.per", &format!("package main\n\nfunc main() i64 {{\n    {}\n    return 0\n}}\n", statement));
+        let program = session.check("main.per").expect("program should check");
+        let error = compiler::nvm_bytecode(&program).unwrap_err();
+        assert_eq!(error.message, message);
+        assert_eq!((error.file.as_str(), error.line, error.column), ("main.per", 4, 5));
+    }
+}
+
+#[test]
+fn oversized_number_literals_are_lexer_errors() {
+    let mut session = Compiler::new();
+    session.add_source("main.per", "package main\n\nfunc main() i64 {\n    return 99999999999999999999\n}\n");
+
+    let errors = session.check("main.per").unwrap_err();
+    assert_eq!(errors[0].kind, ErrorKind::LexerError);
+    assert_eq!((errors[0].line, errors[0].column), (4, 12));
+    assert_eq!(errors[0].message, "number '99999999999999999999' is too large; the largest is 9223372036854775807");
+}
+
+#[test]
+fn c_source_rejects_what_the_c_backend_does_not_lower() {
+    let cases = [
+        ("var p *i64 = &x", "pointers are not supported by the C backend", 18),
+        ("var a [4]i64", "arrays are not supported by the C backend", 5),
+    ];
+    for (statement, message, column) in cases {
+        let mut session = Compiler::new();
+        session.add_source("main.per", &format!("package main\n\nfunc main() i64 {{\n    var x = 1\n    {}\n    return x\n}}\n", statement));
+        let program = session.check("main.per").expect("program should check");
+        let error = compiler::c_source(&program).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::CodeGenError);
+        assert_eq!(error.message, message);
+        assert_eq!((error.file.as_str(), error.line, error.column), ("main.per", 5, column));
+    }
+}
+
+#[test]
+fn c_source_lowers_calls_and_templates() {
+    let mut session = Compiler::new();
+    session.add_source("main.per", "package main\n\nfunc Name(n i64) string {\n    return \"n$(-n:04d)\"\n}\n\nfunc main() i64 {\n    var s = Name(7)\n    return !(s == \"n\")\n}\n");
+
+    let program = session.check("main.per").expect("program should check");
+    let c = compiler::c_source(&program).unwrap();
+    assert!(c.contains("char* Name(long long n);\n"));
+    assert!(c.contains("return per_str_format(\"n%04lld\", (long long)((-n)));"), "{}", c);
+    assert!(c.contains("int main(void) {\n    char* s = Name(7);\n    return (!(per_str_compare(s, per_lit_0.text) == 0));"), "{}", c);
+}
+
+#[test]
+fn stdlib_signatures_carry_integer_widths() {
+    let stdlib = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("stdlib");
+    let mut session = Compiler::new().with_resolver(compiler::directory_resolver(vec![stdlib]));
+    session.add_source("main.per", "package main\n\nimport \"math\"\nimport \"string\"\n\nfunc main() i64 {\n    var q i32 = math.Max(1, 2)\n    var e string = string.is_empty(\"\")\n    return 0\n}\n");
+
+    let errors = session.check("main.per").unwrap_err();
+    let lines: Vec<usize> = errors.iter().map(|e| e.line).collect();
+    assert_eq!(lines, [7, 8]);
+    assert!(errors[0].message.contains("declared as I32, but initialized with I64"), "{}", errors[0].message);
+    assert!(errors[1].message.contains("declared as String, but initialized with Bool"), "{}", errors[1].message);
+}
+
+#[test]
+fn novaria_module_matches_the_syscall_table() {
+    let stdlib = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("stdlib");
+    let mut session = Compiler::new().with_resolver(compiler::directory_resolver(vec![stdlib]));
+    session.add_source("main.per", "package main\n\nimport \"novaria\"\n\nfunc main() i64 {\n    return novaria.CAP_ALL()\n}\n");
+
+    let program = session.check("main.per").expect("program should check");
+    compiler::nvm_bytecode(&program).expect("novaria bindings should match the syscall table");
+
+    let mut capabilities: Vec<(&str, i64)> = Vec::new();
+    for function in program.modules["novaria"].functions.iter().filter(|f| f.name.starts_with("CAP_")) {
+        match function.body.as_slice() {
+            [ast::Statement { kind: ast::StatementKind::Return(Some(value)), .. }] => match value.kind {
+                ast::ExpressionKind::Number(flag) => capabilities.push((&function.name, flag)),
+                ref other => panic!("{} returns {:?}, not a number", function.name, other),
+            },
+            body => panic!("{} should only return its flag, but is {:?}", function.name, body),
+        }
+    }
+    let expected: Vec<(&str, i64)> = nvm::syscalls::CAPABILITIES.iter().map(|&(name, flag)| (name, flag as i64)).collect();
+    assert_eq!(capabilities, expected);
+}
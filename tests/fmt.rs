@@ -0,0 +1,87 @@
+//! `perano-lang fmt`: canonical spellings, comments and `--check`.
+
+use perano_lang::formatter::format_source;
+use std::path::Path;
+use std::process::Command;
+
+const MESSY: &str = "# build with --target elf
+package main
+
+use \"stdio\"
+
+// Doubles x.
+func twice(x i64) i64 {   // header
+\tlet y = (x+x)*1   // body
+\twhile y > 100 { y = y-1 }
+
+
+\tif y == 0 { return 0 } else {
+\t\t// unreachable for now
+\t}
+\tasm {
+\t\tpush $(y)   ; keep
+\t}
+\treturn (-y) as i64
+}
+
+struct pair { a i64, b u8 }
+";
+
+const CANONICAL: &str = "# build with --target elf
+package main
+
+import \"stdio\"
+
+// Doubles x.
+fn twice(x: i64) -> i64 { // header
+    var y = (x + x) * 1 // body
+    for y > 100 {
+        y = y - 1
+    }
+
+    if y == 0 {
+        return 0
+    } else {
+        // unreachable for now
+    }
+    asm {
+        push $(y)   ; keep
+    }
+    return -y as i64
+}
+
+struct pair {
+    a: i64
+    b: u8
+}
+";
+
+#[test]
+fn canonical_style_keeps_comments() {
+    let formatted = format_source(MESSY, "messy.per").unwrap();
+    assert_eq!(formatted, CANONICAL);
+    assert_eq!(format_source(&formatted, "messy.per").unwrap(), formatted);
+}
+
+#[test]
+fn check_reports_and_fmt_rewrites() {
+    let stdlib = Path::new(env!("CARGO_MANIFEST_DIR")).join("stdlib");
+    let status = Command::new(env!("CARGO_BIN_EXE_perano-lang"))
+        .args(["fmt", "--check", "math.per", "novaria.per", "stdio.per", "string.per"])
+        .current_dir(&stdlib)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("fmt");
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("messy.per");
+    std::fs::write(&file, MESSY).unwrap();
+    let check = Command::new(env!("CARGO_BIN_EXE_perano-lang")).arg("fmt").arg("--check").arg(&file).output().unwrap();
+    assert_eq!(check.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&check.stdout).trim(), file.to_string_lossy());
+
+    let status = Command::new(env!("CARGO_BIN_EXE_perano-lang")).arg("fmt").arg(&file).status().unwrap();
+    assert!(status.success());
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), CANONICAL);
+}
@@ -0,0 +1,289 @@
+//! Golden tests: every program in `tests/programs/` is compiled for each
+//! target and the result is compared with the expectation file next to it.
+//!
+//! | build with              | expectation           | contents                           |
+//! |-------------------------|-----------------------|------------------------------------|
+//! | `--target elf`          | `<name>.elf.out`      | stdout of the program, exit status |
+//! | `--target novaria`      | `<name>.nvm.out`      | the same, run in the host-side NVM |
+//! | `--emit=nvm-asm`        | `<name>.nvm.asm`      | generated NVM assembly             |
+//! | `--target novaria`      | `<name>.novaria.hex`  | bytecode as hex, 16 bytes per line |
+//!
+//! The NVM assembly must also assemble (`build <name>.asm`) to exactly the
+//! bytecode `--target novaria` produces.
+//!
+//! A program that does not compile expects the compiler's diagnostics instead,
+//! so error messages are covered too. Run
+//! `PERANO_BLESS=1 cargo test --test golden` to write the current results as
+//! the new expectations.
+
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const COMPILER: &str = env!("CARGO_BIN_EXE_perano-lang");
+const RUN_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Target {
+    name: &'static str,
+    args: &'static [&'static str],
+    expectation: &'static str,
+    /// Turns a successful compilation of `<name>.per` in the work directory
+    /// into the text that is compared with the expectation.
+    result: fn(&Path, &str) -> String,
+}
+
+const ELF: Target = Target {
+    name: "elf",
+    args: &["--target", "elf"],
+    expectation: "elf.out",
+    result: run_elf,
+};
+const NVM: Target = Target {
+    name: "nvm",
+    args: &["--target", "novaria"],
+    expectation: "nvm.out",
+    result: run_nvm,
+};
+const NVM_CODE: Target = Target {
+    name: "nvm-code",
+    args: &["--emit=nvm-asm"],
+    expectation: "nvm.asm",
+    result: read_nvm_asm,
+};
+const NOVARIA: Target = Target {
+    name: "novaria",
+    args: &["--target", "novaria"],
+    expectation: "novaria.hex",
+    result: read_novaria_hex,
+};
+
+#[test]
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn elf() {
+    check(&ELF);
+}
+
+#[test]
+fn nvm() {
+    check(&NVM);
+}
+
+#[test]
+fn nvm_code() {
+    check(&NVM_CODE);
+}
+
+#[test]
+fn novaria() {
+    check(&NOVARIA);
+}
+
+fn check(target: &Target) {
+    let work = work_dir(target);
+    let bless = env::var_os("PERANO_BLESS").is_some();
+    let mut failures = Vec::new();
+
+    for program in programs() {
+        let name = program.file_stem().unwrap().to_string_lossy().to_string();
+        fs::copy(&program, work.join(format!("{}.per", name))).expect("failed to copy program");
+
+        let actual = compile(&work, &name, target);
+        let expected_file = program.with_extension(target.expectation);
+
+        if bless {
+            fs::write(&expected_file, &actual).expect("failed to write expectation");
+            continue;
+        }
+
+        match fs::read_to_string(&expected_file) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => failures.push(format!(
+                "{}: {}\n{}",
+                name,
+                expected_file.display(),
+                first_difference(&expected, &actual)
+            )),
+            Err(_) => failures.push(format!("{}: missing {}", name, expected_file.display())),
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} program(s) differ for {}; rerun with PERANO_BLESS=1 to accept the new output\n\n{}",
+        failures.len(),
+        target.name,
+        failures.join("\n\n")
+    );
+}
+
+fn programs() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("programs");
+    let mut programs: Vec<PathBuf> = fs::read_dir(&dir)
+        .expect("tests/programs is missing")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "per"))
+        .collect();
+    programs.sort();
+    programs
+}
+
+/// A scratch directory per target with its own copy of the standard library,
+/// so outputs never land in the source tree and diagnostics show bare names.
+fn work_dir(target: &Target) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join("golden")
+        .join(target.name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("stdlib")).expect("failed to create work directory");
+
+    let stdlib = Path::new(env!("CARGO_MANIFEST_DIR")).join("stdlib");
+    for entry in fs::read_dir(stdlib).expect("stdlib is missing") {
+        let path = entry.unwrap().path();
+        fs::copy(&path, dir.join("stdlib").join(path.file_name().unwrap())).expect("failed to copy stdlib");
+    }
+    dir
+}
+
+fn compile(work: &Path, name: &str, target: &Target) -> String {
+    let output = Command::new(COMPILER)
+        .arg("build")
+        .arg(format!("{}.per", name))
+        .args(target.args)
+        .current_dir(work)
+        .output()
+        .expect("failed to run the compiler");
+
+    if output.status.success() {
+        (target.result)(work, name)
+    } else {
+        format!("--- compile error\n{}", strip_ansi(&String::from_utf8_lossy(&output.stderr)))
+    }
+}
+
+fn run_elf(work: &Path, name: &str) -> String {
+    run(Command::new(work.join(name)).current_dir(work))
+}
+
+fn run_nvm(work: &Path, name: &str) -> String {
+    run(Command::new(COMPILER).arg("run").arg(format!("{}.bin", name)).current_dir(work).stderr(Stdio::null()))
+}
+
+/// Stdout of `command` followed by how it ended.
+fn run(command: &mut Command) -> String {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to start the compiled program");
+
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = stdout.read_to_end(&mut buf);
+        buf
+    });
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break Some(status);
+        }
+        if started.elapsed() > RUN_TIMEOUT {
+            let _ = child.kill();
+            let _ = child.wait();
+            break None;
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    let mut result = String::from_utf8_lossy(&reader.join().unwrap()).to_string();
+    if !result.is_empty() && !result.ends_with('\n') {
+        result.push('\n');
+    }
+    match status.map(|s| (s.code(), signal(&s))) {
+        Some((Some(code), _)) => result.push_str(&format!("--- exit {}\n", code)),
+        Some((None, signal)) => result.push_str(&format!("--- signal {}\n", signal.unwrap_or(0))),
+        None => result.push_str("--- timed out\n"),
+    }
+    result
+}
+
+/// The signal that ended the program; only unix hosts have them.
+#[cfg(unix)]
+fn signal(status: &ExitStatus) -> Option<i32> {
+    std::os::unix::process::ExitStatusExt::signal(status)
+}
+
+#[cfg(not(unix))]
+fn signal(_: &ExitStatus) -> Option<i32> {
+    None
+}
+
+fn read_nvm_asm(work: &Path, name: &str) -> String {
+    let asm = fs::read_to_string(work.join(format!("{}.asm", name))).expect("compiler wrote no .asm file");
+
+    let build = |args: &[&str]| {
+        let output = Command::new(COMPILER).arg("build").args(args).current_dir(work).output().expect("failed to run the compiler");
+        assert!(output.status.success(), "build {:?} failed:\n{}", args, String::from_utf8_lossy(&output.stderr));
+    };
+    build(&[&format!("{}.per", name), "--target", "novaria", "-o", "compiled.bin"]);
+    build(&[&format!("{}.asm", name), "-o", "assembled.bin"]);
+    assert!(
+        fs::read(work.join("compiled.bin")).unwrap() == fs::read(work.join("assembled.bin")).unwrap(),
+        "{}.asm does not assemble to the bytecode of {}.per",
+        name,
+        name
+    );
+    asm
+}
+
+fn read_novaria_hex(work: &Path, name: &str) -> String {
+    let bytecode = fs::read(work.join(format!("{}.bin", name))).expect("compiler wrote no .bin file");
+    bytecode
+        .chunks(16)
+        .map(|line| {
+            let bytes: Vec<String> = line.iter().map(|b| format!("{:02x}", b)).collect();
+            bytes.join(" ") + "\n"
+        })
+        .collect()
+}
+
+fn strip_ansi(s: &str) -> String {
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn first_difference(expected: &str, actual: &str) -> String {
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    let mut line = 1;
+    loop {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(e), Some(a)) if e == a => line += 1,
+            (None, None) => return "(output differs only in trailing newlines)".to_string(),
+            (e, a) => {
+                return format!(
+                    "  first difference at line {}\n  - expected: {}\n  + actual:   {}",
+                    line,
+                    e.unwrap_or("<end of output>"),
+                    a.unwrap_or("<end of output>")
+                );
+            }
+        }
+    }
+}
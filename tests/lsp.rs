@@ -0,0 +1,206 @@
+//! Drives `perano-lang lsp` over stdio like an editor would.
+
+use perano_lang::lsp::Json;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+const MAIN: &str = "package main
+
+import \"stdio\"
+import \"math\" as m
+
+// Adds one.
+func inc(x i64) i64 {
+    return x + 1
+}
+
+func main() i64 {
+    stdio.Println(m.Max(1, 2))
+    return inc(41)
+}
+";
+
+struct Client {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    notifications: Vec<Json>,
+    next_id: usize,
+}
+
+impl Client {
+    fn start() -> Client {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_perano-lang"))
+            .arg("lsp")
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("failed to start the language server");
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Client { child, stdin, stdout, notifications: Vec::new(), next_id: 1 }
+    }
+
+    fn send(&mut self, message: &str) {
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{}", message.len(), message).unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    fn receive(&mut self) -> Json {
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            assert!(self.stdout.read_line(&mut line).unwrap() > 0, "server closed its output");
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(value) = line.strip_prefix("Content-Length:") {
+                length = value.trim().parse().unwrap();
+            }
+        }
+        let mut body = vec![0; length];
+        self.stdout.read_exact(&mut body).unwrap();
+        Json::parse(std::str::from_utf8(&body).unwrap()).unwrap()
+    }
+
+    fn notify(&mut self, method: &str, params: &str) {
+        self.send(&format!(r#"{{"jsonrpc":"2.0","method":"{}","params":{}}}"#, method, params));
+    }
+
+    fn request(&mut self, method: &str, params: &str) -> Json {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(&format!(r#"{{"jsonrpc":"2.0","id":{},"method":"{}","params":{}}}"#, id, method, params));
+        loop {
+            let message = self.receive();
+            if message.get("id").as_usize() == Some(id) {
+                return message;
+            }
+            self.notifications.push(message);
+        }
+    }
+
+    fn diagnostics(&mut self) -> Json {
+        let message = match self.notifications.pop() {
+            Some(message) => message,
+            None => self.receive(),
+        };
+        assert_eq!(message.get("method").as_str(), Some("textDocument/publishDiagnostics"));
+        message.at(&["params", "diagnostics"]).clone()
+    }
+
+    fn open(&mut self, uri: &str, text: &str) {
+        let params = format!(
+            r#"{{"textDocument":{{"uri":"{}","languageId":"perano","version":1,"text":{}}}}}"#,
+            uri,
+            Json::from(text)
+        );
+        self.notify("textDocument/didOpen", &params);
+    }
+
+    fn change(&mut self, uri: &str, text: &str) {
+        let params = format!(
+            r#"{{"textDocument":{{"uri":"{}","version":2}},"contentChanges":[{{"text":{}}}]}}"#,
+            uri,
+            Json::from(text)
+        );
+        self.notify("textDocument/didChange", &params);
+    }
+
+    fn at(&mut self, method: &str, uri: &str, line: usize, character: usize) -> Json {
+        let params = format!(
+            r#"{{"textDocument":{{"uri":"{}"}},"position":{{"line":{},"character":{}}}}}"#,
+            uri, line, character
+        );
+        self.request(method, &params).get("result").clone()
+    }
+}
+
+fn document_uri() -> String {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("lsp");
+    std::fs::create_dir_all(&dir).unwrap();
+    format!("file://{}/main.per", dir.canonicalize().unwrap().display())
+}
+
+fn start_session(uri: &str) -> Client {
+    let mut client = Client::start();
+    let init = client.request("initialize", r#"{"processId":null,"rootUri":null,"capabilities":{}}"#);
+    assert_eq!(init.at(&["result", "capabilities", "definitionProvider"]), &Json::Bool(true));
+    client.notify("initialized", "{}");
+    client.open(uri, MAIN);
+    client
+}
+
+fn finish(mut client: Client) {
+    let response = client.request("shutdown", "null");
+    assert!(response.get("result").is_null());
+    client.notify("exit", "null");
+    assert_eq!(client.child.wait().unwrap().code(), Some(0));
+}
+
+#[test]
+fn navigation_and_hover() {
+    let uri = document_uri();
+    let mut client = start_session(&uri);
+    assert_eq!(client.diagnostics(), Json::Array(Vec::new()));
+
+    let definition = client.at("textDocument/definition", &uri, 11, 20);
+    let target = definition.get("uri").as_str().unwrap();
+    assert!(target.ends_with("/stdlib/math.per"), "{}", target);
+    assert_eq!(definition.at(&["range", "start", "line"]).as_usize(), Some(5));
+    assert_eq!(definition.at(&["range", "start", "character"]).as_usize(), Some(7));
+
+    let definition = client.at("textDocument/definition", &uri, 12, 12);
+    assert_eq!(definition.get("uri").as_str(), Some(uri.as_str()));
+    assert_eq!(definition.at(&["range", "start", "line"]).as_usize(), Some(6));
+
+    let hover = client.at("textDocument/hover", &uri, 11, 20);
+    let text = hover.at(&["contents", "value"]).as_str().unwrap();
+    assert!(text.contains("pub fn Max(a: i64, b: i64) -> i64"), "{}", text);
+    assert!(text.contains("Maximum of two numbers"), "{}", text);
+
+    let hover = client.at("textDocument/hover", &uri, 12, 12);
+    let text = hover.at(&["contents", "value"]).as_str().unwrap();
+    assert!(text.contains("fn inc(x: i64) -> i64"), "{}", text);
+    assert!(text.contains("Adds one."), "{}", text);
+
+    assert!(client.at("textDocument/hover", &uri, 0, 0).is_null());
+    finish(client);
+}
+
+#[test]
+fn diagnostics_and_completion_while_editing() {
+    let uri = document_uri();
+    let mut client = start_session(&uri);
+    client.diagnostics();
+
+    client.change(&uri, &MAIN.replace("return inc(41)", "var b bool = 1 == 1\n    return b + \"x\""));
+    let diagnostics = client.diagnostics();
+    let first = &diagnostics.as_array()[0];
+    assert_eq!(first.at(&["range", "start", "line"]).as_usize(), Some(13));
+    assert!(first.get("message").as_str().unwrap().contains("must be numeric"));
+
+    // Mid-edit the file does not parse; completion uses the last version
+    // that did.
+    client.change(&uri, &MAIN.replace("return inc(41)", "m.\n    return 0"));
+    assert!(!client.diagnostics().as_array().is_empty());
+    let completion = client.at("textDocument/completion", &uri, 12, 6);
+    let labels: Vec<&str> = completion.get("items").as_array().iter()
+        .filter_map(|item| item.get("label").as_str())
+        .collect();
+    assert!(labels.contains(&"Max") && labels.contains(&"IsPrime"), "{:?}", labels);
+    assert!(!labels.contains(&"inc"), "{:?}", labels);
+
+    let completion = client.at("textDocument/completion", &uri, 12, 4);
+    let labels: Vec<&str> = completion.get("items").as_array().iter()
+        .filter_map(|item| item.get("label").as_str())
+        .collect();
+    assert!(labels.contains(&"inc") && labels.contains(&"m") && labels.contains(&"stdio"), "{:?}", labels);
+
+    let unknown = client.request("textDocument/rename", "{}");
+    assert_eq!(unknown.at(&["error", "code"]), &Json::Number(-32601.0));
+    finish(client);
+}
@@ -0,0 +1,4 @@
+--- compile error
+error: asm operands and clobbers need an x86-64 target, 'elf' or 'elf-gcc'
+  --> inline_asm.per:7:5
+
@@ -0,0 +1,556 @@
+.NVM0
+; Generated by Perano Language Compiler
+
+func_main:
+    push 1
+    push 1
+    push 1
+    push 1
+    push 1
+    push 1
+    push 1
+    push 1
+    call func_Weigh
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push 1
+    push 2
+    push 3
+    push 4
+    push 5
+    push 6
+    push 7
+    push 8
+    call func_Weigh
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push 1
+    push 0
+    push 1
+    push 0
+    push 0
+    push 0
+    push 0
+    push 0
+    push 0
+    call func_Weigh
+    push 0
+    push 0
+    push 0
+    push 0
+    push 0
+    push 0
+    push 0
+    call func_Weigh
+    add
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push 200
+    push 0
+    push 0
+    push 0
+    push 0
+    push 0
+    push 0
+    push 0
+    call func_Last
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push 6
+    push 5
+    push 4
+    push 3
+    push 2
+    push 1
+    push 5
+    call func_Sum
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push 1
+    push 0
+    push 0
+    push 0
+    push 0
+    push 0
+    push 0
+    push 1
+    call func_Weigh
+    syscall exit
+    ret
+
+func_Weigh:
+    swap
+    store 0
+    swap
+    store 1
+    swap
+    store 2
+    swap
+    store 3
+    swap
+    store 4
+    swap
+    store 5
+    swap
+    store 6
+    swap
+    store 7
+    load 0
+    push 2
+    load 1
+    mul
+    add
+    push 3
+    load 2
+    mul
+    add
+    push 4
+    load 3
+    mul
+    add
+    push 5
+    load 4
+    mul
+    add
+    push 6
+    load 5
+    mul
+    add
+    push 7
+    load 6
+    mul
+    add
+    push 8
+    load 7
+    mul
+    add
+    swap
+    ret
+    push 0
+    swap
+    ret
+
+func_Last:
+    swap
+    store 8
+    swap
+    store 9
+    swap
+    store 10
+    swap
+    store 11
+    swap
+    store 12
+    swap
+    store 13
+    swap
+    store 14
+    swap
+    store 15
+    load 15
+    swap
+    ret
+    push 0
+    swap
+    ret
+
+func_Sum:
+    swap
+    store 16
+    swap
+    store 17
+    swap
+    store 18
+    swap
+    store 19
+    swap
+    store 20
+    swap
+    store 21
+    swap
+    store 22
+    load 16
+    push 0
+    eq
+    jz else_Sum_0
+    load 17
+    load 18
+    add
+    load 19
+    add
+    load 20
+    add
+    load 21
+    add
+    load 22
+    add
+    swap
+    ret
+    jmp endif_Sum_1
+else_Sum_0:
+endif_Sum_1:
+    load 16
+    load 17
+    load 18
+    load 19
+    load 20
+    load 21
+    load 22
+    load 21
+    push 1
+    add
+    load 20
+    load 19
+    load 18
+    load 17
+    load 22
+    load 16
+    push 1
+    sub
+    call func_Sum
+    swap
+    store 22
+    swap
+    store 21
+    swap
+    store 20
+    swap
+    store 19
+    swap
+    store 18
+    swap
+    store 17
+    swap
+    store 16
+    swap
+    ret
+    push 0
+    swap
+    ret
+__print_int:
+    store 255
+    store 250
+    load 250
+    push 0
+    lt
+    jz not_negative_Sum_2
+    push 45
+    syscall print
+    push 0
+    load 250
+    sub
+    load 255
+    jmp __print_uint
+not_negative_Sum_2:
+    load 250
+    push 0
+    eq
+    jz not_zero_Sum_3
+    push 48
+    syscall print
+    load 255
+    ret
+not_zero_Sum_3:
+    push 1
+    store 251
+find_power_Sum_4:
+    load 250
+    push 10
+    div
+    load 251
+    lt
+    jnz find_power_done_Sum_5
+    load 251
+    push 10
+    mul
+    store 251
+    jmp find_power_Sum_4
+find_power_done_Sum_5:
+print_digit_loop_Sum_6:
+    load 251
+    push 0
+    gt
+    jz print_done_Sum_7
+    load 250
+    load 251
+    div
+    push 48
+    add
+    syscall print
+    load 250
+    load 251
+    mod
+    store 250
+    load 251
+    push 10
+    div
+    store 251
+    jmp print_digit_loop_Sum_6
+print_done_Sum_7:
+    load 255
+    ret
+__load8:
+    swap
+    load_abs
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    swap
+    ret
+__store8:
+    store 246
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    store 247
+    store 248
+    load 248
+    load 248
+    load_abs
+    store 249
+    load 249
+    load 249
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    sub
+    load 247
+    add
+    store_abs
+    load 246
+    ret
+__divmodu:
+    store 255
+    store 254
+    store 253
+    load 254
+    push 0
+    lt
+    jnz __divmodu_large_divisor
+    load 253
+    push 0
+    lt
+    jnz __divmodu_large_dividend
+    load 253
+    load 254
+    div
+    load 253
+    load 254
+    mod
+    load 255
+    ret
+__divmodu_large_divisor:
+    load 253
+    push -2147483648
+    add
+    load 254
+    push -2147483648
+    add
+    lt
+    push 0
+    eq
+    store 251
+    jmp __divmodu_remainder
+__divmodu_large_dividend:
+    load 253
+    push -2147483648
+    add
+    push 2
+    div
+    push 1073741824
+    add
+    load 254
+    div
+    push 2
+    mul
+    store 251
+    load 253
+    load 251
+    load 254
+    mul
+    sub
+    push -2147483648
+    add
+    load 254
+    push -2147483648
+    add
+    lt
+    jnz __divmodu_remainder
+    load 251
+    push 1
+    add
+    store 251
+__divmodu_remainder:
+    load 251
+    load 253
+    load 251
+    load 254
+    mul
+    sub
+    load 255
+    ret
+__print_uint:
+    swap
+    store 249
+    load 249
+    push 0
+    lt
+    jnz __print_uint_large
+    load 249
+    call __print_int
+    ret
+__print_uint_large:
+    load 249
+    push 10
+    call __divmodu
+    swap
+    call __print_int
+    push 48
+    add
+    syscall print
+    ret
+__print_fmt_signed:
+    push 1
+    jmp __print_fmt_args
+__print_fmt:
+    push 0
+__print_fmt_args:
+    store 247
+    store 240
+    store 241
+    store 242
+    store 243
+    store 244
+    store 245
+    load 247
+    jz __print_fmt_count
+    load 245
+    push 0
+    lt
+    store 247
+    load 247
+    jz __print_fmt_count
+    push 0
+    load 245
+    sub
+    store 245
+__print_fmt_count:
+    push 1
+    store 246
+    push 1
+    store 248
+    load 245
+    store 249
+__print_fmt_count_loop:
+    load 249
+    push -2147483648
+    add
+    load 244
+    push -2147483648
+    add
+    lt
+    jnz __print_fmt_pad
+    load 249
+    load 244
+    call __divmodu
+    pop
+    store 249
+    load 246
+    load 244
+    mul
+    store 246
+    load 248
+    push 1
+    add
+    store 248
+    jmp __print_fmt_count_loop
+__print_fmt_pad:
+    load 242
+    load 248
+    sub
+    load 247
+    sub
+    store 242
+    load 241
+    push 48
+    eq
+    jz __print_fmt_fill
+    load 247
+    jz __print_fmt_fill
+    push 45
+    syscall print
+    push 0
+    store 247
+__print_fmt_fill:
+    load 242
+    push 0
+    gt
+    jz __print_fmt_sign
+    load 241
+    syscall print
+    load 242
+    push 1
+    sub
+    store 242
+    jmp __print_fmt_fill
+__print_fmt_sign:
+    load 247
+    jz __print_fmt_digits
+    push 45
+    syscall print
+__print_fmt_digits:
+    load 246
+    jz __print_fmt_done
+    load 245
+    load 246
+    call __divmodu
+    store 245
+    store 249
+    load 249
+    push 10
+    lt
+    jz __print_fmt_letter
+    load 249
+    push 48
+    add
+    syscall print
+    jmp __print_fmt_next
+__print_fmt_letter:
+    load 249
+    load 243
+    add
+    syscall print
+__print_fmt_next:
+    load 246
+    load 244
+    call __divmodu
+    pop
+    store 246
+    jmp __print_fmt_digits
+__print_fmt_done:
+    load 240
+    ret
@@ -0,0 +1,901 @@
+.NVM0
+; Generated by Perano Language Compiler
+
+func_main:
+    push 9
+    push 3
+    call func_math_Max
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push 9
+    push 3
+    call func_math_Min
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push 10
+    push 2
+    call func_math_Pow
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push 144
+    call func_math_Sqrt
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push 2
+    push 1
+    call func_math_Max
+    syscall exit
+    ret
+
+func_math_Max:
+    swap
+    store 0
+    swap
+    store 1
+    load 0
+    load 1
+    gt
+    jz else_math_Max_0
+    load 0
+    swap
+    ret
+    jmp endif_math_Max_1
+else_math_Max_0:
+endif_math_Max_1:
+    load 1
+    swap
+    ret
+    push 0
+    swap
+    ret
+
+func_math_Min:
+    swap
+    store 2
+    swap
+    store 3
+    load 2
+    load 3
+    lt
+    jz else_math_Min_2
+    load 2
+    swap
+    ret
+    jmp endif_math_Min_3
+else_math_Min_2:
+endif_math_Min_3:
+    load 3
+    swap
+    ret
+    push 0
+    swap
+    ret
+
+func_math_Pow:
+    swap
+    store 4
+    swap
+    store 5
+    load 5
+    push 0
+    eq
+    jz else_math_Pow_4
+    push 1
+    swap
+    ret
+    jmp endif_math_Pow_5
+else_math_Pow_4:
+endif_math_Pow_5:
+    push 1
+    store 6
+    push 0
+    store 7
+for_start_math_Pow_6:
+    load 7
+    load 5
+    lt
+    jz for_end_math_Pow_7
+    load 6
+    load 4
+    mul
+    store 6
+    load 7
+    push 1
+    add
+    store 7
+for_continue_math_Pow_8:
+    jmp for_start_math_Pow_6
+for_end_math_Pow_7:
+    load 6
+    swap
+    ret
+    push 0
+    swap
+    ret
+
+func_math_Sqrt:
+    swap
+    store 8
+    load 8
+    push 0
+    eq
+    jz else_math_Sqrt_9
+    push 0
+    swap
+    ret
+    jmp endif_math_Sqrt_10
+else_math_Sqrt_9:
+endif_math_Sqrt_10:
+    load 8
+    push 1
+    eq
+    jz else_math_Sqrt_11
+    push 1
+    swap
+    ret
+    jmp endif_math_Sqrt_12
+else_math_Sqrt_11:
+endif_math_Sqrt_12:
+    load 8
+    push 2
+    div
+    store 9
+    push 0
+    store 10
+    push 0
+    store 11
+for_start_math_Sqrt_13:
+    load 9
+    load 10
+    neq
+    jz for_end_math_Sqrt_14
+    load 11
+    push 20
+    gt
+    jz else_math_Sqrt_16
+    load 9
+    swap
+    ret
+    jmp endif_math_Sqrt_17
+else_math_Sqrt_16:
+endif_math_Sqrt_17:
+    load 9
+    store 10
+    load 9
+    load 8
+    load 9
+    div
+    add
+    push 2
+    div
+    store 9
+    load 11
+    push 1
+    add
+    store 11
+for_continue_math_Sqrt_15:
+    jmp for_start_math_Sqrt_13
+for_end_math_Sqrt_14:
+    load 9
+    swap
+    ret
+    push 0
+    swap
+    ret
+
+func_math_GCD:
+    swap
+    store 12
+    swap
+    store 13
+    load 12
+    store 14
+    load 13
+    store 15
+for_start_math_GCD_18:
+    load 15
+    push 0
+    neq
+    jz for_end_math_GCD_19
+    load 15
+    store 16
+    load 14
+    load 15
+    mod
+    store 15
+    load 16
+    store 14
+for_continue_math_GCD_20:
+    jmp for_start_math_GCD_18
+for_end_math_GCD_19:
+    load 14
+    swap
+    ret
+    push 0
+    swap
+    ret
+
+func_math_LCM:
+    swap
+    store 17
+    swap
+    store 18
+    load 17
+    push 0
+    eq
+    jz else_math_LCM_21
+    push 0
+    swap
+    ret
+    jmp endif_math_LCM_22
+else_math_LCM_21:
+endif_math_LCM_22:
+    load 18
+    push 0
+    eq
+    jz else_math_LCM_23
+    push 0
+    swap
+    ret
+    jmp endif_math_LCM_24
+else_math_LCM_23:
+endif_math_LCM_24:
+    load 17
+    load 18
+    mul
+    store 19
+    load 19
+    load 18
+    load 17
+    call func_math_GCD
+    div
+    store 20
+    load 20
+    swap
+    ret
+    push 0
+    swap
+    ret
+
+func_math_Fact:
+    swap
+    store 21
+    load 21
+    push 1
+    gt
+    push 0
+    eq
+    jz else_math_Fact_25
+    push 1
+    swap
+    ret
+    jmp endif_math_Fact_26
+else_math_Fact_25:
+endif_math_Fact_26:
+    push 1
+    store 22
+    push 2
+    store 23
+for_start_math_Fact_27:
+    load 23
+    load 21
+    gt
+    push 0
+    eq
+    jz for_end_math_Fact_28
+    load 22
+    load 23
+    mul
+    store 22
+    load 23
+    push 1
+    add
+    store 23
+for_continue_math_Fact_29:
+    jmp for_start_math_Fact_27
+for_end_math_Fact_28:
+    load 22
+    swap
+    ret
+    push 0
+    swap
+    ret
+
+func_math_IsEven:
+    swap
+    store 24
+    load 24
+    push 2
+    mod
+    push 0
+    eq
+    jz else_math_IsEven_30
+    push 1
+    swap
+    ret
+    jmp endif_math_IsEven_31
+else_math_IsEven_30:
+endif_math_IsEven_31:
+    push 0
+    swap
+    ret
+    push 0
+    swap
+    ret
+
+func_math_IsOdd:
+    swap
+    store 25
+    load 25
+    push 2
+    mod
+    push 0
+    neq
+    jz else_math_IsOdd_32
+    push 1
+    swap
+    ret
+    jmp endif_math_IsOdd_33
+else_math_IsOdd_32:
+endif_math_IsOdd_33:
+    push 0
+    swap
+    ret
+    push 0
+    swap
+    ret
+
+func_math_Sign:
+    swap
+    store 26
+    load 26
+    push 0
+    gt
+    jz else_math_Sign_34
+    push 1
+    swap
+    ret
+    jmp endif_math_Sign_35
+else_math_Sign_34:
+endif_math_Sign_35:
+    push 0
+    swap
+    ret
+    push 0
+    swap
+    ret
+
+func_math_Clamp:
+    swap
+    store 27
+    swap
+    store 28
+    swap
+    store 29
+    load 27
+    load 28
+    lt
+    jz else_math_Clamp_36
+    load 28
+    swap
+    ret
+    jmp endif_math_Clamp_37
+else_math_Clamp_36:
+endif_math_Clamp_37:
+    load 27
+    load 29
+    gt
+    jz else_math_Clamp_38
+    load 29
+    swap
+    ret
+    jmp endif_math_Clamp_39
+else_math_Clamp_38:
+endif_math_Clamp_39:
+    load 27
+    swap
+    ret
+    push 0
+    swap
+    ret
+
+func_math_SumRange:
+    swap
+    store 30
+    load 30
+    load 30
+    push 1
+    add
+    mul
+    push 2
+    div
+    swap
+    ret
+    push 0
+    swap
+    ret
+
+func_math_IsPrime:
+    swap
+    store 31
+    load 31
+    push 1
+    gt
+    push 0
+    eq
+    jz else_math_IsPrime_40
+    push 0
+    swap
+    ret
+    jmp endif_math_IsPrime_41
+else_math_IsPrime_40:
+endif_math_IsPrime_41:
+    load 31
+    push 3
+    gt
+    push 0
+    eq
+    jz else_math_IsPrime_42
+    push 1
+    swap
+    ret
+    jmp endif_math_IsPrime_43
+else_math_IsPrime_42:
+endif_math_IsPrime_43:
+    load 31
+    push 2
+    mod
+    push 0
+    eq
+    jz else_math_IsPrime_44
+    push 0
+    swap
+    ret
+    jmp endif_math_IsPrime_45
+else_math_IsPrime_44:
+endif_math_IsPrime_45:
+    load 31
+    push 3
+    mod
+    push 0
+    eq
+    jz else_math_IsPrime_46
+    push 0
+    swap
+    ret
+    jmp endif_math_IsPrime_47
+else_math_IsPrime_46:
+endif_math_IsPrime_47:
+    load 31
+    call func_math_Sqrt
+    store 32
+    push 5
+    store 33
+for_start_math_IsPrime_48:
+    load 33
+    load 32
+    gt
+    push 0
+    eq
+    jz for_end_math_IsPrime_49
+    load 31
+    load 33
+    mod
+    push 0
+    eq
+    jz else_math_IsPrime_51
+    push 0
+    swap
+    ret
+    jmp endif_math_IsPrime_52
+else_math_IsPrime_51:
+endif_math_IsPrime_52:
+    load 31
+    load 33
+    push 2
+    add
+    mod
+    push 0
+    eq
+    jz else_math_IsPrime_53
+    push 0
+    swap
+    ret
+    jmp endif_math_IsPrime_54
+else_math_IsPrime_53:
+endif_math_IsPrime_54:
+    load 33
+    push 6
+    add
+    store 33
+for_continue_math_IsPrime_50:
+    jmp for_start_math_IsPrime_48
+for_end_math_IsPrime_49:
+    push 1
+    swap
+    ret
+    push 0
+    swap
+    ret
+
+func_math_Fib:
+    swap
+    store 34
+    load 34
+    push 0
+    eq
+    jz else_math_Fib_55
+    push 0
+    swap
+    ret
+    jmp endif_math_Fib_56
+else_math_Fib_55:
+endif_math_Fib_56:
+    load 34
+    push 1
+    eq
+    jz else_math_Fib_57
+    push 1
+    swap
+    ret
+    jmp endif_math_Fib_58
+else_math_Fib_57:
+endif_math_Fib_58:
+    push 0
+    store 35
+    push 1
+    store 36
+    push 2
+    store 37
+for_start_math_Fib_59:
+    load 37
+    load 34
+    gt
+    push 0
+    eq
+    jz for_end_math_Fib_60
+    load 35
+    load 36
+    add
+    store 38
+    load 36
+    store 35
+    load 38
+    store 36
+    load 37
+    push 1
+    add
+    store 37
+for_continue_math_Fib_61:
+    jmp for_start_math_Fib_59
+for_end_math_Fib_60:
+    load 36
+    swap
+    ret
+    push 0
+    swap
+    ret
+__print_int:
+    store 255
+    store 250
+    load 250
+    push 0
+    lt
+    jz not_negative_math_Fib_62
+    push 45
+    syscall print
+    push 0
+    load 250
+    sub
+    load 255
+    jmp __print_uint
+not_negative_math_Fib_62:
+    load 250
+    push 0
+    eq
+    jz not_zero_math_Fib_63
+    push 48
+    syscall print
+    load 255
+    ret
+not_zero_math_Fib_63:
+    push 1
+    store 251
+find_power_math_Fib_64:
+    load 250
+    push 10
+    div
+    load 251
+    lt
+    jnz find_power_done_math_Fib_65
+    load 251
+    push 10
+    mul
+    store 251
+    jmp find_power_math_Fib_64
+find_power_done_math_Fib_65:
+print_digit_loop_math_Fib_66:
+    load 251
+    push 0
+    gt
+    jz print_done_math_Fib_67
+    load 250
+    load 251
+    div
+    push 48
+    add
+    syscall print
+    load 250
+    load 251
+    mod
+    store 250
+    load 251
+    push 10
+    div
+    store 251
+    jmp print_digit_loop_math_Fib_66
+print_done_math_Fib_67:
+    load 255
+    ret
+__load8:
+    swap
+    load_abs
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    swap
+    ret
+__store8:
+    store 246
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    store 247
+    store 248
+    load 248
+    load 248
+    load_abs
+    store 249
+    load 249
+    load 249
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    sub
+    load 247
+    add
+    store_abs
+    load 246
+    ret
+__divmodu:
+    store 255
+    store 254
+    store 253
+    load 254
+    push 0
+    lt
+    jnz __divmodu_large_divisor
+    load 253
+    push 0
+    lt
+    jnz __divmodu_large_dividend
+    load 253
+    load 254
+    div
+    load 253
+    load 254
+    mod
+    load 255
+    ret
+__divmodu_large_divisor:
+    load 253
+    push -2147483648
+    add
+    load 254
+    push -2147483648
+    add
+    lt
+    push 0
+    eq
+    store 251
+    jmp __divmodu_remainder
+__divmodu_large_dividend:
+    load 253
+    push -2147483648
+    add
+    push 2
+    div
+    push 1073741824
+    add
+    load 254
+    div
+    push 2
+    mul
+    store 251
+    load 253
+    load 251
+    load 254
+    mul
+    sub
+    push -2147483648
+    add
+    load 254
+    push -2147483648
+    add
+    lt
+    jnz __divmodu_remainder
+    load 251
+    push 1
+    add
+    store 251
+__divmodu_remainder:
+    load 251
+    load 253
+    load 251
+    load 254
+    mul
+    sub
+    load 255
+    ret
+__print_uint:
+    swap
+    store 249
+    load 249
+    push 0
+    lt
+    jnz __print_uint_large
+    load 249
+    call __print_int
+    ret
+__print_uint_large:
+    load 249
+    push 10
+    call __divmodu
+    swap
+    call __print_int
+    push 48
+    add
+    syscall print
+    ret
+__print_fmt_signed:
+    push 1
+    jmp __print_fmt_args
+__print_fmt:
+    push 0
+__print_fmt_args:
+    store 247
+    store 240
+    store 241
+    store 242
+    store 243
+    store 244
+    store 245
+    load 247
+    jz __print_fmt_count
+    load 245
+    push 0
+    lt
+    store 247
+    load 247
+    jz __print_fmt_count
+    push 0
+    load 245
+    sub
+    store 245
+__print_fmt_count:
+    push 1
+    store 246
+    push 1
+    store 248
+    load 245
+    store 249
+__print_fmt_count_loop:
+    load 249
+    push -2147483648
+    add
+    load 244
+    push -2147483648
+    add
+    lt
+    jnz __print_fmt_pad
+    load 249
+    load 244
+    call __divmodu
+    pop
+    store 249
+    load 246
+    load 244
+    mul
+    store 246
+    load 248
+    push 1
+    add
+    store 248
+    jmp __print_fmt_count_loop
+__print_fmt_pad:
+    load 242
+    load 248
+    sub
+    load 247
+    sub
+    store 242
+    load 241
+    push 48
+    eq
+    jz __print_fmt_fill
+    load 247
+    jz __print_fmt_fill
+    push 45
+    syscall print
+    push 0
+    store 247
+__print_fmt_fill:
+    load 242
+    push 0
+    gt
+    jz __print_fmt_sign
+    load 241
+    syscall print
+    load 242
+    push 1
+    sub
+    store 242
+    jmp __print_fmt_fill
+__print_fmt_sign:
+    load 247
+    jz __print_fmt_digits
+    push 45
+    syscall print
+__print_fmt_digits:
+    load 246
+    jz __print_fmt_done
+    load 245
+    load 246
+    call __divmodu
+    store 245
+    store 249
+    load 249
+    push 10
+    lt
+    jz __print_fmt_letter
+    load 249
+    push 48
+    add
+    syscall print
+    jmp __print_fmt_next
+__print_fmt_letter:
+    load 249
+    load 243
+    add
+    syscall print
+__print_fmt_next:
+    load 246
+    load 244
+    call __divmodu
+    pop
+    store 246
+    jmp __print_fmt_digits
+__print_fmt_done:
+    load 240
+    ret
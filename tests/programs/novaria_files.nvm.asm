@@ -0,0 +1,594 @@
+.NVM0
+; Generated by Perano Language Compiler
+
+func_main:
+    push 0
+    store 0
+    push 0
+    store 1
+    push 0
+    store 2
+    push 0
+    store 3
+    push str_main_0
+    syscall open
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push 8
+    push str_main_1
+    push str_main_2
+    syscall create
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push str_main_3
+    syscall open
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push 16
+    push 0
+    syscall get_local_addr
+    push str_main_4
+    syscall read
+    store 4
+    push 0
+    store 5
+for_start_main_5:
+    load 5
+    load 4
+    lt
+    jz for_end_main_6
+    push 0
+    syscall get_local_addr
+    load 5
+    add
+    call __load8
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    syscall print
+    push 0
+    pop
+for_continue_main_7:
+    load 5
+    push 1
+    add
+    store 5
+    jmp for_start_main_5
+for_end_main_6:
+    push 10
+    syscall print
+    push 0
+    pop
+    push str_main_8
+    syscall delete
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push str_main_9
+    syscall open
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push 96
+    syscall port_in_byte
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push 15
+    push str_main_10
+    call func_novaria_Print
+    pop
+    push 64
+    call func_novaria_MemAlloc
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    load 4
+    syscall exit
+    push 0
+    pop
+    push 0
+    syscall exit
+    ret
+
+func_novaria_Print:
+    swap
+    store 6
+    swap
+    store 7
+    load 6
+    store 8
+    load 8
+    load_abs
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    store 9
+for_start_novaria_Print_11:
+    load 9
+    push 0
+    neq
+    jz for_end_novaria_Print_12
+    load 9
+    syscall print
+    push 0
+    pop
+    load 8
+    push 1
+    add
+    store 8
+for_continue_novaria_Print_13:
+    load 8
+    load_abs
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    store 9
+    jmp for_start_novaria_Print_11
+for_end_novaria_Print_12:
+    push 0
+    swap
+    ret
+
+func_novaria_ClearScreen:
+    push 0
+    swap
+    ret
+
+func_novaria_SetCursor:
+    swap
+    store 10
+    swap
+    store 11
+    push 0
+    swap
+    ret
+
+func_novaria_MemAlloc:
+    swap
+    store 12
+    push 0
+    swap
+    ret
+    push 0
+    swap
+    ret
+
+func_novaria_MemFree:
+    swap
+    store 13
+    push 0
+    swap
+    ret
+
+func_novaria_CAP_FS_READ:
+    push 1
+    swap
+    ret
+    push 0
+    swap
+    ret
+
+func_novaria_CAP_FS_WRITE:
+    push 2
+    swap
+    ret
+    push 0
+    swap
+    ret
+
+func_novaria_CAP_FS_CREATE:
+    push 4
+    swap
+    ret
+    push 0
+    swap
+    ret
+
+func_novaria_CAP_FS_DELETE:
+    push 8
+    swap
+    ret
+    push 0
+    swap
+    ret
+
+func_novaria_CAP_DRV_ACCESS:
+    push 16
+    swap
+    ret
+    push 0
+    swap
+    ret
+
+func_novaria_CAP_CAPS_MGMT:
+    push 32
+    swap
+    ret
+    push 0
+    swap
+    ret
+
+func_novaria_CAP_ALL:
+    push 65535
+    swap
+    ret
+    push 0
+    swap
+    ret
+
+func_novaria_GetPID:
+    push 0
+    swap
+    ret
+    push 0
+    swap
+    ret
+
+func_novaria_Yield:
+    push 0
+    swap
+    ret
+__print_int:
+    store 255
+    store 250
+    load 250
+    push 0
+    lt
+    jz not_negative_novaria_Yield_14
+    push 45
+    syscall print
+    push 0
+    load 250
+    sub
+    load 255
+    jmp __print_uint
+not_negative_novaria_Yield_14:
+    load 250
+    push 0
+    eq
+    jz not_zero_novaria_Yield_15
+    push 48
+    syscall print
+    load 255
+    ret
+not_zero_novaria_Yield_15:
+    push 1
+    store 251
+find_power_novaria_Yield_16:
+    load 250
+    push 10
+    div
+    load 251
+    lt
+    jnz find_power_done_novaria_Yield_17
+    load 251
+    push 10
+    mul
+    store 251
+    jmp find_power_novaria_Yield_16
+find_power_done_novaria_Yield_17:
+print_digit_loop_novaria_Yield_18:
+    load 251
+    push 0
+    gt
+    jz print_done_novaria_Yield_19
+    load 250
+    load 251
+    div
+    push 48
+    add
+    syscall print
+    load 250
+    load 251
+    mod
+    store 250
+    load 251
+    push 10
+    div
+    store 251
+    jmp print_digit_loop_novaria_Yield_18
+print_done_novaria_Yield_19:
+    load 255
+    ret
+__load8:
+    swap
+    load_abs
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    swap
+    ret
+__store8:
+    store 246
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    store 247
+    store 248
+    load 248
+    load 248
+    load_abs
+    store 249
+    load 249
+    load 249
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    sub
+    load 247
+    add
+    store_abs
+    load 246
+    ret
+__divmodu:
+    store 255
+    store 254
+    store 253
+    load 254
+    push 0
+    lt
+    jnz __divmodu_large_divisor
+    load 253
+    push 0
+    lt
+    jnz __divmodu_large_dividend
+    load 253
+    load 254
+    div
+    load 253
+    load 254
+    mod
+    load 255
+    ret
+__divmodu_large_divisor:
+    load 253
+    push -2147483648
+    add
+    load 254
+    push -2147483648
+    add
+    lt
+    push 0
+    eq
+    store 251
+    jmp __divmodu_remainder
+__divmodu_large_dividend:
+    load 253
+    push -2147483648
+    add
+    push 2
+    div
+    push 1073741824
+    add
+    load 254
+    div
+    push 2
+    mul
+    store 251
+    load 253
+    load 251
+    load 254
+    mul
+    sub
+    push -2147483648
+    add
+    load 254
+    push -2147483648
+    add
+    lt
+    jnz __divmodu_remainder
+    load 251
+    push 1
+    add
+    store 251
+__divmodu_remainder:
+    load 251
+    load 253
+    load 251
+    load 254
+    mul
+    sub
+    load 255
+    ret
+__print_uint:
+    swap
+    store 249
+    load 249
+    push 0
+    lt
+    jnz __print_uint_large
+    load 249
+    call __print_int
+    ret
+__print_uint_large:
+    load 249
+    push 10
+    call __divmodu
+    swap
+    call __print_int
+    push 48
+    add
+    syscall print
+    ret
+__print_fmt_signed:
+    push 1
+    jmp __print_fmt_args
+__print_fmt:
+    push 0
+__print_fmt_args:
+    store 247
+    store 240
+    store 241
+    store 242
+    store 243
+    store 244
+    store 245
+    load 247
+    jz __print_fmt_count
+    load 245
+    push 0
+    lt
+    store 247
+    load 247
+    jz __print_fmt_count
+    push 0
+    load 245
+    sub
+    store 245
+__print_fmt_count:
+    push 1
+    store 246
+    push 1
+    store 248
+    load 245
+    store 249
+__print_fmt_count_loop:
+    load 249
+    push -2147483648
+    add
+    load 244
+    push -2147483648
+    add
+    lt
+    jnz __print_fmt_pad
+    load 249
+    load 244
+    call __divmodu
+    pop
+    store 249
+    load 246
+    load 244
+    mul
+    store 246
+    load 248
+    push 1
+    add
+    store 248
+    jmp __print_fmt_count_loop
+__print_fmt_pad:
+    load 242
+    load 248
+    sub
+    load 247
+    sub
+    store 242
+    load 241
+    push 48
+    eq
+    jz __print_fmt_fill
+    load 247
+    jz __print_fmt_fill
+    push 45
+    syscall print
+    push 0
+    store 247
+__print_fmt_fill:
+    load 242
+    push 0
+    gt
+    jz __print_fmt_sign
+    load 241
+    syscall print
+    load 242
+    push 1
+    sub
+    store 242
+    jmp __print_fmt_fill
+__print_fmt_sign:
+    load 247
+    jz __print_fmt_digits
+    push 45
+    syscall print
+__print_fmt_digits:
+    load 246
+    jz __print_fmt_done
+    load 245
+    load 246
+    call __divmodu
+    store 245
+    store 249
+    load 249
+    push 10
+    lt
+    jz __print_fmt_letter
+    load 249
+    push 48
+    add
+    syscall print
+    jmp __print_fmt_next
+__print_fmt_letter:
+    load 249
+    load 243
+    add
+    syscall print
+__print_fmt_next:
+    load 246
+    load 244
+    call __divmodu
+    pop
+    store 246
+    jmp __print_fmt_digits
+__print_fmt_done:
+    load 240
+    ret
+
+    .word 8
+str_main_0:
+    .string "note.txt"
+    .word 8
+str_main_1:
+    .string "hi there"
+    .word 8
+str_main_2:
+    .string "note.txt"
+    .word 8
+str_main_3:
+    .string "note.txt"
+    .word 8
+str_main_4:
+    .string "note.txt"
+    .word 8
+str_main_8:
+    .string "note.txt"
+    .word 8
+str_main_9:
+    .string "note.txt"
+    .word 4
+str_main_10:
+    .string "bye\n"
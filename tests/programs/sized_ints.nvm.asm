@@ -0,0 +1,650 @@
+.NVM0
+; Generated by Perano Language Compiler
+
+func_main:
+    push 250
+    store 0
+    load 0
+    push 10
+    add
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    store 0
+    load 0
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push 127
+    store 1
+    load 1
+    push 1
+    add
+    push 128
+    add
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    push 128
+    sub
+    store 1
+    load 1
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push 300
+    store 2
+    load 2
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push 0
+    store 3
+    push 0
+    store 4
+    push 3
+    syscall get_local_addr
+    push 0
+    add
+    push 255
+    call __store8
+    push 3
+    syscall get_local_addr
+    push 1
+    add
+    push 7
+    call __store8
+    push 3
+    syscall get_local_addr
+    push 5
+    add
+    push 9
+    call __store8
+    push 3
+    syscall get_local_addr
+    push 0
+    add
+    call __load8
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    push 3
+    syscall get_local_addr
+    push 1
+    add
+    call __load8
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    add
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push 3
+    syscall get_local_addr
+    push 5
+    add
+    call __load8
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push 0
+    store 5
+    push 0
+    store 6
+    push 0
+    store 7
+    push 5
+    syscall get_local_addr
+    push 2
+    push 4
+    mul
+    add
+    push 5
+    push 0
+    swap
+    sub
+    store_abs
+    push 5
+    syscall get_local_addr
+    push 2
+    push 4
+    mul
+    add
+    load_abs
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push 0
+    store 8
+    load 8
+    push 1
+    sub
+    store 8
+    load 8
+    push 5
+    push -2147483648
+    add
+    swap
+    push -2147483648
+    add
+    swap
+    gt
+    jz else_main_0
+    load 8
+    push 2
+    call __divmodu
+    pop
+    call __print_uint
+    push 10
+    syscall print
+    push 0
+    pop
+    jmp endif_main_1
+else_main_0:
+endif_main_1:
+    push 0
+    store 9
+    push 9
+    syscall get_local_addr
+    push 1
+    add
+    push 3
+    push 0
+    swap
+    sub
+    call __store8
+    push 9
+    syscall get_local_addr
+    push 1
+    add
+    call __load8
+    push 128
+    add
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    push 128
+    sub
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push 1
+    push 0
+    swap
+    sub
+    call __print_uint
+    push 10
+    syscall print
+    push 0
+    pop
+    load 8
+    push 7
+    call __divmodu
+    pop
+    call __print_uint
+    push 10
+    syscall print
+    push 0
+    pop
+    load 8
+    push 7
+    call __divmodu
+    swap
+    pop
+    call __print_uint
+    push 10
+    syscall print
+    push 0
+    pop
+    load 8
+    push -1294967296
+    call __divmodu
+    pop
+    call __print_uint
+    push 10
+    syscall print
+    push 0
+    pop
+    load 8
+    push -294967296
+    push -2147483648
+    add
+    swap
+    push -2147483648
+    add
+    swap
+    lt
+    push 0
+    eq
+    jz else_main_2
+    load 8
+    push -1294967296
+    call __divmodu
+    swap
+    pop
+    call __print_uint
+    push 10
+    syscall print
+    push 0
+    pop
+    jmp endif_main_3
+else_main_2:
+endif_main_3:
+    push -2147483648
+    store 10
+    load 10
+    load 8
+    push -2147483648
+    add
+    swap
+    push -2147483648
+    add
+    swap
+    lt
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    load 10
+    push 2
+    call __divmodu
+    pop
+    call __print_uint
+    push 10
+    syscall print
+    push 0
+    pop
+    push 2147483647
+    store 11
+    load 11
+    push 1
+    add
+    store 11
+    load 11
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    load 11
+    push 5
+    add
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push 3
+    syscall get_local_addr
+    push 1
+    add
+    call __load8
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    syscall exit
+    ret
+__print_int:
+    store 255
+    store 250
+    load 250
+    push 0
+    lt
+    jz not_negative_main_4
+    push 45
+    syscall print
+    push 0
+    load 250
+    sub
+    load 255
+    jmp __print_uint
+not_negative_main_4:
+    load 250
+    push 0
+    eq
+    jz not_zero_main_5
+    push 48
+    syscall print
+    load 255
+    ret
+not_zero_main_5:
+    push 1
+    store 251
+find_power_main_6:
+    load 250
+    push 10
+    div
+    load 251
+    lt
+    jnz find_power_done_main_7
+    load 251
+    push 10
+    mul
+    store 251
+    jmp find_power_main_6
+find_power_done_main_7:
+print_digit_loop_main_8:
+    load 251
+    push 0
+    gt
+    jz print_done_main_9
+    load 250
+    load 251
+    div
+    push 48
+    add
+    syscall print
+    load 250
+    load 251
+    mod
+    store 250
+    load 251
+    push 10
+    div
+    store 251
+    jmp print_digit_loop_main_8
+print_done_main_9:
+    load 255
+    ret
+__load8:
+    swap
+    load_abs
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    swap
+    ret
+__store8:
+    store 246
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    store 247
+    store 248
+    load 248
+    load 248
+    load_abs
+    store 249
+    load 249
+    load 249
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    sub
+    load 247
+    add
+    store_abs
+    load 246
+    ret
+__divmodu:
+    store 255
+    store 254
+    store 253
+    load 254
+    push 0
+    lt
+    jnz __divmodu_large_divisor
+    load 253
+    push 0
+    lt
+    jnz __divmodu_large_dividend
+    load 253
+    load 254
+    div
+    load 253
+    load 254
+    mod
+    load 255
+    ret
+__divmodu_large_divisor:
+    load 253
+    push -2147483648
+    add
+    load 254
+    push -2147483648
+    add
+    lt
+    push 0
+    eq
+    store 251
+    jmp __divmodu_remainder
+__divmodu_large_dividend:
+    load 253
+    push -2147483648
+    add
+    push 2
+    div
+    push 1073741824
+    add
+    load 254
+    div
+    push 2
+    mul
+    store 251
+    load 253
+    load 251
+    load 254
+    mul
+    sub
+    push -2147483648
+    add
+    load 254
+    push -2147483648
+    add
+    lt
+    jnz __divmodu_remainder
+    load 251
+    push 1
+    add
+    store 251
+__divmodu_remainder:
+    load 251
+    load 253
+    load 251
+    load 254
+    mul
+    sub
+    load 255
+    ret
+__print_uint:
+    swap
+    store 249
+    load 249
+    push 0
+    lt
+    jnz __print_uint_large
+    load 249
+    call __print_int
+    ret
+__print_uint_large:
+    load 249
+    push 10
+    call __divmodu
+    swap
+    call __print_int
+    push 48
+    add
+    syscall print
+    ret
+__print_fmt_signed:
+    push 1
+    jmp __print_fmt_args
+__print_fmt:
+    push 0
+__print_fmt_args:
+    store 247
+    store 240
+    store 241
+    store 242
+    store 243
+    store 244
+    store 245
+    load 247
+    jz __print_fmt_count
+    load 245
+    push 0
+    lt
+    store 247
+    load 247
+    jz __print_fmt_count
+    push 0
+    load 245
+    sub
+    store 245
+__print_fmt_count:
+    push 1
+    store 246
+    push 1
+    store 248
+    load 245
+    store 249
+__print_fmt_count_loop:
+    load 249
+    push -2147483648
+    add
+    load 244
+    push -2147483648
+    add
+    lt
+    jnz __print_fmt_pad
+    load 249
+    load 244
+    call __divmodu
+    pop
+    store 249
+    load 246
+    load 244
+    mul
+    store 246
+    load 248
+    push 1
+    add
+    store 248
+    jmp __print_fmt_count_loop
+__print_fmt_pad:
+    load 242
+    load 248
+    sub
+    load 247
+    sub
+    store 242
+    load 241
+    push 48
+    eq
+    jz __print_fmt_fill
+    load 247
+    jz __print_fmt_fill
+    push 45
+    syscall print
+    push 0
+    store 247
+__print_fmt_fill:
+    load 242
+    push 0
+    gt
+    jz __print_fmt_sign
+    load 241
+    syscall print
+    load 242
+    push 1
+    sub
+    store 242
+    jmp __print_fmt_fill
+__print_fmt_sign:
+    load 247
+    jz __print_fmt_digits
+    push 45
+    syscall print
+__print_fmt_digits:
+    load 246
+    jz __print_fmt_done
+    load 245
+    load 246
+    call __divmodu
+    store 245
+    store 249
+    load 249
+    push 10
+    lt
+    jz __print_fmt_letter
+    load 249
+    push 48
+    add
+    syscall print
+    jmp __print_fmt_next
+__print_fmt_letter:
+    load 249
+    load 243
+    add
+    syscall print
+__print_fmt_next:
+    load 246
+    load 244
+    call __divmodu
+    pop
+    store 246
+    jmp __print_fmt_digits
+__print_fmt_done:
+    load 240
+    ret
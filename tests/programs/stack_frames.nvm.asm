@@ -0,0 +1,643 @@
+.NVM0
+; Generated by Perano Language Compiler
+
+func_main:
+    push 0
+    store 0
+    push 0
+    store 1
+    push 0
+    store 2
+    push 0
+    store 3
+    push 0
+    store 4
+    push 0
+    store 5
+    push 0
+    store 6
+    push 0
+    store 7
+    push 0
+    store 8
+    push 0
+    store 9
+    push 0
+    store 10
+    push 0
+    store 11
+    push 0
+    store 12
+    push 0
+    store 13
+    push 0
+    store 14
+    push 0
+    store 15
+    push 0
+    store 16
+for_start_main_0:
+    load 16
+    push 16
+    lt
+    jz for_end_main_1
+    push 0
+    syscall get_local_addr
+    load 16
+    push 4
+    mul
+    add
+    load 16
+    call func_Spread
+    store_abs
+for_continue_main_2:
+    load 16
+    push 1
+    add
+    store 16
+    jmp for_start_main_0
+for_end_main_1:
+    push 0
+    syscall get_local_addr
+    push 0
+    push 4
+    mul
+    add
+    load_abs
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push 0
+    syscall get_local_addr
+    push 15
+    push 4
+    mul
+    add
+    load_abs
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push 1
+    push 2
+    call func_Spread
+    add
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push 3
+    call func_Declared
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push 0
+    store 17
+for_start_main_3:
+    load 17
+    push 3
+    lt
+    jz for_end_main_4
+    push 115
+    syscall print
+    push 112
+    syscall print
+    push 114
+    syscall print
+    push 101
+    syscall print
+    push 97
+    syscall print
+    push 100
+    syscall print
+    push 32
+    syscall print
+    push 61
+    syscall print
+    push 32
+    syscall print
+    load 17
+    call func_Spread
+    call __print_int
+    push 0
+    push 10
+    syscall print
+    pop
+for_continue_main_5:
+    load 17
+    push 1
+    add
+    store 17
+    jmp for_start_main_3
+for_end_main_4:
+    push 0
+    syscall get_local_addr
+    push 1
+    push 4
+    mul
+    add
+    load_abs
+    push 0
+    syscall get_local_addr
+    push 0
+    push 4
+    mul
+    add
+    load_abs
+    sub
+    syscall exit
+    ret
+
+func_Spread:
+    swap
+    store 18
+    load 18
+    store 19
+    load 19
+    push 1
+    add
+    store 20
+    load 20
+    push 1
+    add
+    store 21
+    load 21
+    push 1
+    add
+    store 22
+    load 22
+    push 1
+    add
+    store 23
+    load 23
+    push 1
+    add
+    store 24
+    load 24
+    push 1
+    add
+    store 25
+    load 25
+    push 1
+    add
+    store 26
+    load 26
+    push 1
+    add
+    store 27
+    load 27
+    push 1
+    add
+    store 28
+    push 0
+    store 29
+    push 0
+    store 30
+    push 0
+    store 31
+    push 0
+    store 32
+    push 0
+    store 33
+    push 0
+    store 34
+    push 0
+    store 35
+    push 0
+    store 36
+    push 0
+    store 37
+    push 0
+    store 38
+    push 0
+    store 39
+for_start_Spread_6:
+    load 39
+    push 10
+    lt
+    jz for_end_Spread_7
+    push 29
+    syscall get_local_addr
+    load 39
+    push 4
+    mul
+    add
+    load 39
+    load 39
+    mul
+    store_abs
+for_continue_Spread_8:
+    load 39
+    push 1
+    add
+    store 39
+    jmp for_start_Spread_6
+for_end_Spread_7:
+    load 19
+    load 20
+    add
+    load 21
+    add
+    load 22
+    add
+    load 23
+    add
+    load 24
+    add
+    load 25
+    add
+    load 26
+    add
+    load 27
+    add
+    load 28
+    add
+    store 40
+    push 0
+    store 41
+for_start_Spread_9:
+    load 41
+    push 10
+    lt
+    jz for_end_Spread_10
+    load 40
+    push 29
+    syscall get_local_addr
+    load 41
+    push 4
+    mul
+    add
+    load_abs
+    add
+    store 40
+for_continue_Spread_11:
+    load 41
+    push 1
+    add
+    store 41
+    jmp for_start_Spread_9
+for_end_Spread_10:
+    load 40
+    swap
+    ret
+    push 0
+    swap
+    ret
+
+func_Declared:
+    swap
+    store 42
+    load 42
+    store 43
+    push 0
+    store 44
+    push 0
+    store 45
+    load 42
+    push 1
+    add
+    store 46
+    load 44
+    load 45
+    add
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    load 42
+    push 100
+    mul
+    store 44
+    push 7
+    store 45
+    load 43
+    load 44
+    add
+    load 45
+    add
+    load 46
+    add
+    swap
+    ret
+    push 0
+    swap
+    ret
+__print_int:
+    store 255
+    store 250
+    load 250
+    push 0
+    lt
+    jz not_negative_Declared_12
+    push 45
+    syscall print
+    push 0
+    load 250
+    sub
+    load 255
+    jmp __print_uint
+not_negative_Declared_12:
+    load 250
+    push 0
+    eq
+    jz not_zero_Declared_13
+    push 48
+    syscall print
+    load 255
+    ret
+not_zero_Declared_13:
+    push 1
+    store 251
+find_power_Declared_14:
+    load 250
+    push 10
+    div
+    load 251
+    lt
+    jnz find_power_done_Declared_15
+    load 251
+    push 10
+    mul
+    store 251
+    jmp find_power_Declared_14
+find_power_done_Declared_15:
+print_digit_loop_Declared_16:
+    load 251
+    push 0
+    gt
+    jz print_done_Declared_17
+    load 250
+    load 251
+    div
+    push 48
+    add
+    syscall print
+    load 250
+    load 251
+    mod
+    store 250
+    load 251
+    push 10
+    div
+    store 251
+    jmp print_digit_loop_Declared_16
+print_done_Declared_17:
+    load 255
+    ret
+__load8:
+    swap
+    load_abs
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    swap
+    ret
+__store8:
+    store 246
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    store 247
+    store 248
+    load 248
+    load 248
+    load_abs
+    store 249
+    load 249
+    load 249
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    sub
+    load 247
+    add
+    store_abs
+    load 246
+    ret
+__divmodu:
+    store 255
+    store 254
+    store 253
+    load 254
+    push 0
+    lt
+    jnz __divmodu_large_divisor
+    load 253
+    push 0
+    lt
+    jnz __divmodu_large_dividend
+    load 253
+    load 254
+    div
+    load 253
+    load 254
+    mod
+    load 255
+    ret
+__divmodu_large_divisor:
+    load 253
+    push -2147483648
+    add
+    load 254
+    push -2147483648
+    add
+    lt
+    push 0
+    eq
+    store 251
+    jmp __divmodu_remainder
+__divmodu_large_dividend:
+    load 253
+    push -2147483648
+    add
+    push 2
+    div
+    push 1073741824
+    add
+    load 254
+    div
+    push 2
+    mul
+    store 251
+    load 253
+    load 251
+    load 254
+    mul
+    sub
+    push -2147483648
+    add
+    load 254
+    push -2147483648
+    add
+    lt
+    jnz __divmodu_remainder
+    load 251
+    push 1
+    add
+    store 251
+__divmodu_remainder:
+    load 251
+    load 253
+    load 251
+    load 254
+    mul
+    sub
+    load 255
+    ret
+__print_uint:
+    swap
+    store 249
+    load 249
+    push 0
+    lt
+    jnz __print_uint_large
+    load 249
+    call __print_int
+    ret
+__print_uint_large:
+    load 249
+    push 10
+    call __divmodu
+    swap
+    call __print_int
+    push 48
+    add
+    syscall print
+    ret
+__print_fmt_signed:
+    push 1
+    jmp __print_fmt_args
+__print_fmt:
+    push 0
+__print_fmt_args:
+    store 247
+    store 240
+    store 241
+    store 242
+    store 243
+    store 244
+    store 245
+    load 247
+    jz __print_fmt_count
+    load 245
+    push 0
+    lt
+    store 247
+    load 247
+    jz __print_fmt_count
+    push 0
+    load 245
+    sub
+    store 245
+__print_fmt_count:
+    push 1
+    store 246
+    push 1
+    store 248
+    load 245
+    store 249
+__print_fmt_count_loop:
+    load 249
+    push -2147483648
+    add
+    load 244
+    push -2147483648
+    add
+    lt
+    jnz __print_fmt_pad
+    load 249
+    load 244
+    call __divmodu
+    pop
+    store 249
+    load 246
+    load 244
+    mul
+    store 246
+    load 248
+    push 1
+    add
+    store 248
+    jmp __print_fmt_count_loop
+__print_fmt_pad:
+    load 242
+    load 248
+    sub
+    load 247
+    sub
+    store 242
+    load 241
+    push 48
+    eq
+    jz __print_fmt_fill
+    load 247
+    jz __print_fmt_fill
+    push 45
+    syscall print
+    push 0
+    store 247
+__print_fmt_fill:
+    load 242
+    push 0
+    gt
+    jz __print_fmt_sign
+    load 241
+    syscall print
+    load 242
+    push 1
+    sub
+    store 242
+    jmp __print_fmt_fill
+__print_fmt_sign:
+    load 247
+    jz __print_fmt_digits
+    push 45
+    syscall print
+__print_fmt_digits:
+    load 246
+    jz __print_fmt_done
+    load 245
+    load 246
+    call __divmodu
+    store 245
+    store 249
+    load 249
+    push 10
+    lt
+    jz __print_fmt_letter
+    load 249
+    push 48
+    add
+    syscall print
+    jmp __print_fmt_next
+__print_fmt_letter:
+    load 249
+    load 243
+    add
+    syscall print
+__print_fmt_next:
+    load 246
+    load 244
+    call __divmodu
+    pop
+    store 246
+    jmp __print_fmt_digits
+__print_fmt_done:
+    load 240
+    ret
@@ -0,0 +1,847 @@
+.NVM0
+; Generated by Perano Language Compiler
+
+func_main:
+    push str_main_0
+    call func_Greet
+    store 0
+    load 0
+    call __print_str
+    push 0
+    push 10
+    syscall print
+    pop
+    load 0
+    push 4
+    sub
+    load_abs
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    load 0
+    push 7
+    push 12
+    call __str_slice
+    store 1
+    push str_main_1
+    load 1
+    call __str_concat
+    push str_main_2
+    call __str_concat
+    call __print_str
+    push 0
+    push 10
+    syscall print
+    pop
+    load 0
+    push 3
+    push 0
+    swap
+    sub
+    push 5
+    call __str_slice
+    push str_main_3
+    call __str_concat
+    load 0
+    push 10
+    push 99
+    call __str_slice
+    call __str_concat
+    call __print_str
+    push 0
+    push 10
+    syscall print
+    pop
+    load 1
+    push str_main_4
+    call __str_compare
+    push 0
+    eq
+    jz else_main_5
+    push 101
+    syscall print
+    push 113
+    syscall print
+    push 117
+    syscall print
+    push 97
+    syscall print
+    push 108
+    syscall print
+    push 0
+    push 10
+    syscall print
+    pop
+    jmp endif_main_6
+else_main_5:
+endif_main_6:
+    load 1
+    push str_main_7
+    call __str_compare
+    push 0
+    neq
+    jz else_main_8
+    push 110
+    syscall print
+    push 111
+    syscall print
+    push 116
+    syscall print
+    push 32
+    syscall print
+    push 101
+    syscall print
+    push 113
+    syscall print
+    push 117
+    syscall print
+    push 97
+    syscall print
+    push 108
+    syscall print
+    push 0
+    push 10
+    syscall print
+    pop
+    jmp endif_main_9
+else_main_8:
+endif_main_9:
+    push str_main_10
+    push str_main_11
+    call __str_compare
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push str_main_12
+    push str_main_13
+    call __str_compare
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    load 1
+    push str_main_14
+    call __str_compare
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push str_main_15
+    load 1
+    call __str_concat
+    store 2
+    push 0
+    store 3
+for_start_main_16:
+    load 3
+    push 3
+    lt
+    jz for_end_main_17
+    load 2
+    push str_main_19
+    call __str_concat
+    store 2
+for_continue_main_18:
+    load 3
+    push 1
+    add
+    store 3
+    jmp for_start_main_16
+for_end_main_17:
+    load 2
+    call __print_str
+    push 0
+    push 10
+    syscall print
+    pop
+    load 2
+    push 4
+    sub
+    load_abs
+    call __print_int
+    push 10
+    syscall print
+    push 0
+    pop
+    push 110
+    syscall print
+    push 32
+    syscall print
+    push 61
+    syscall print
+    push 32
+    syscall print
+    load 2
+    push 4
+    sub
+    load_abs
+    call __print_int
+    push 44
+    syscall print
+    push 32
+    syscall print
+    push 119
+    syscall print
+    push 111
+    syscall print
+    push 114
+    syscall print
+    push 100
+    syscall print
+    push 32
+    syscall print
+    push 61
+    syscall print
+    push 32
+    syscall print
+    load 1
+    call __print_str
+    push 0
+    push 10
+    syscall print
+    pop
+    load 2
+    load 1
+    call __str_concat
+    push 4
+    sub
+    load_abs
+    syscall exit
+    ret
+
+func_Greet:
+    swap
+    store 4
+    push str_Greet_20
+    load 4
+    call __str_concat
+    swap
+    ret
+    push 0
+    swap
+    ret
+
+func_string_is_empty:
+    swap
+    store 5
+    load 5
+    push 4
+    sub
+    load_abs
+    push 0
+    eq
+    swap
+    ret
+    push 0
+    swap
+    ret
+__print_int:
+    store 255
+    store 250
+    load 250
+    push 0
+    lt
+    jz not_negative_string_is_empty_21
+    push 45
+    syscall print
+    push 0
+    load 250
+    sub
+    load 255
+    jmp __print_uint
+not_negative_string_is_empty_21:
+    load 250
+    push 0
+    eq
+    jz not_zero_string_is_empty_22
+    push 48
+    syscall print
+    load 255
+    ret
+not_zero_string_is_empty_22:
+    push 1
+    store 251
+find_power_string_is_empty_23:
+    load 250
+    push 10
+    div
+    load 251
+    lt
+    jnz find_power_done_string_is_empty_24
+    load 251
+    push 10
+    mul
+    store 251
+    jmp find_power_string_is_empty_23
+find_power_done_string_is_empty_24:
+print_digit_loop_string_is_empty_25:
+    load 251
+    push 0
+    gt
+    jz print_done_string_is_empty_26
+    load 250
+    load 251
+    div
+    push 48
+    add
+    syscall print
+    load 250
+    load 251
+    mod
+    store 250
+    load 251
+    push 10
+    div
+    store 251
+    jmp print_digit_loop_string_is_empty_25
+print_done_string_is_empty_26:
+    load 255
+    ret
+__alloc:
+    swap
+    load 252
+    jnz __alloc_ready
+    push __heap
+    store 252
+__alloc_ready:
+    load 252
+    swap
+    load 252
+    add
+    push 3
+    add
+    push 4
+    div
+    push 4
+    mul
+    store 252
+    load 252
+    push __heap_limit
+    load_abs
+    gt
+    jnz __alloc_full
+    swap
+    ret
+__alloc_full:
+    push __out_of_memory
+    call __print_str
+    push 12
+    syscall exit
+__str_alloc:
+    swap
+    store 250
+    load 250
+    push 5
+    add
+    call __alloc
+    store 251
+    load 251
+    load 250
+    store_abs
+    load 251
+    push 4
+    add
+    store 251
+    load 251
+    load 250
+    add
+    push 0
+    call __store8
+    load 251
+    swap
+    ret
+__str_copy:
+    load 250
+    jz __str_copy_done
+    load 251
+    load 253
+    call __load8
+    call __store8
+    load 251
+    push 1
+    add
+    store 251
+    load 253
+    push 1
+    add
+    store 253
+    load 250
+    push 1
+    sub
+    store 250
+    jmp __str_copy
+__str_copy_done:
+    ret
+__str_concat:
+    store 254
+    store 253
+    store 255
+    load 255
+    push 4
+    sub
+    load_abs
+    load 253
+    push 4
+    sub
+    load_abs
+    add
+    call __str_alloc
+    load 253
+    load 255
+    store 253
+    load 253
+    push 4
+    sub
+    load_abs
+    store 250
+    call __str_copy
+    store 253
+    load 253
+    push 4
+    sub
+    load_abs
+    store 250
+    call __str_copy
+    load 254
+    ret
+__str_compare:
+    store 254
+    store 253
+    store 251
+    load 251
+    push 4
+    sub
+    load_abs
+    store 250
+    load 253
+    push 4
+    sub
+    load_abs
+    store 255
+__str_compare_loop:
+    load 250
+    jz __str_compare_end
+    load 255
+    jz __str_compare_greater
+    load 251
+    call __load8
+    load 253
+    call __load8
+    lt
+    jnz __str_compare_less
+    load 251
+    call __load8
+    load 253
+    call __load8
+    gt
+    jnz __str_compare_greater
+    load 251
+    push 1
+    add
+    store 251
+    load 253
+    push 1
+    add
+    store 253
+    load 250
+    push 1
+    sub
+    store 250
+    load 255
+    push 1
+    sub
+    store 255
+    jmp __str_compare_loop
+__str_compare_end:
+    load 255
+    jnz __str_compare_less
+    push 0
+    jmp __str_compare_done
+__str_compare_less:
+    push -1
+    jmp __str_compare_done
+__str_compare_greater:
+    push 1
+__str_compare_done:
+    load 254
+    ret
+__str_slice:
+    store 254
+    store 255
+    store 250
+    store 253
+    load 255
+    load 253
+    push 4
+    sub
+    load_abs
+    gt
+    jz __str_slice_end_low
+    load 253
+    push 4
+    sub
+    load_abs
+    store 255
+__str_slice_end_low:
+    load 255
+    push 0
+    lt
+    jz __str_slice_start
+    push 0
+    store 255
+__str_slice_start:
+    load 250
+    push 0
+    lt
+    jz __str_slice_start_high
+    push 0
+    store 250
+__str_slice_start_high:
+    load 250
+    load 255
+    gt
+    jz __str_slice_copy
+    load 255
+    store 250
+__str_slice_copy:
+    load 253
+    load 250
+    add
+    store 253
+    load 255
+    load 250
+    sub
+    call __str_alloc
+    call __str_copy
+    load 254
+    ret
+__print_str:
+    swap
+    store 253
+    load 253
+    push 4
+    sub
+    load_abs
+    store 250
+__print_str_loop:
+    load 250
+    jz __print_str_done
+    load 253
+    call __load8
+    syscall print
+    load 253
+    push 1
+    add
+    store 253
+    load 250
+    push 1
+    sub
+    store 250
+    jmp __print_str_loop
+__print_str_done:
+    ret
+__load8:
+    swap
+    load_abs
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    swap
+    ret
+__store8:
+    store 246
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    store 247
+    store 248
+    load 248
+    load 248
+    load_abs
+    store 249
+    load 249
+    load 249
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    sub
+    load 247
+    add
+    store_abs
+    load 246
+    ret
+__divmodu:
+    store 255
+    store 254
+    store 253
+    load 254
+    push 0
+    lt
+    jnz __divmodu_large_divisor
+    load 253
+    push 0
+    lt
+    jnz __divmodu_large_dividend
+    load 253
+    load 254
+    div
+    load 253
+    load 254
+    mod
+    load 255
+    ret
+__divmodu_large_divisor:
+    load 253
+    push -2147483648
+    add
+    load 254
+    push -2147483648
+    add
+    lt
+    push 0
+    eq
+    store 251
+    jmp __divmodu_remainder
+__divmodu_large_dividend:
+    load 253
+    push -2147483648
+    add
+    push 2
+    div
+    push 1073741824
+    add
+    load 254
+    div
+    push 2
+    mul
+    store 251
+    load 253
+    load 251
+    load 254
+    mul
+    sub
+    push -2147483648
+    add
+    load 254
+    push -2147483648
+    add
+    lt
+    jnz __divmodu_remainder
+    load 251
+    push 1
+    add
+    store 251
+__divmodu_remainder:
+    load 251
+    load 253
+    load 251
+    load 254
+    mul
+    sub
+    load 255
+    ret
+__print_uint:
+    swap
+    store 249
+    load 249
+    push 0
+    lt
+    jnz __print_uint_large
+    load 249
+    call __print_int
+    ret
+__print_uint_large:
+    load 249
+    push 10
+    call __divmodu
+    swap
+    call __print_int
+    push 48
+    add
+    syscall print
+    ret
+__print_fmt_signed:
+    push 1
+    jmp __print_fmt_args
+__print_fmt:
+    push 0
+__print_fmt_args:
+    store 247
+    store 240
+    store 241
+    store 242
+    store 243
+    store 244
+    store 245
+    load 247
+    jz __print_fmt_count
+    load 245
+    push 0
+    lt
+    store 247
+    load 247
+    jz __print_fmt_count
+    push 0
+    load 245
+    sub
+    store 245
+__print_fmt_count:
+    push 1
+    store 246
+    push 1
+    store 248
+    load 245
+    store 249
+__print_fmt_count_loop:
+    load 249
+    push -2147483648
+    add
+    load 244
+    push -2147483648
+    add
+    lt
+    jnz __print_fmt_pad
+    load 249
+    load 244
+    call __divmodu
+    pop
+    store 249
+    load 246
+    load 244
+    mul
+    store 246
+    load 248
+    push 1
+    add
+    store 248
+    jmp __print_fmt_count_loop
+__print_fmt_pad:
+    load 242
+    load 248
+    sub
+    load 247
+    sub
+    store 242
+    load 241
+    push 48
+    eq
+    jz __print_fmt_fill
+    load 247
+    jz __print_fmt_fill
+    push 45
+    syscall print
+    push 0
+    store 247
+__print_fmt_fill:
+    load 242
+    push 0
+    gt
+    jz __print_fmt_sign
+    load 241
+    syscall print
+    load 242
+    push 1
+    sub
+    store 242
+    jmp __print_fmt_fill
+__print_fmt_sign:
+    load 247
+    jz __print_fmt_digits
+    push 45
+    syscall print
+__print_fmt_digits:
+    load 246
+    jz __print_fmt_done
+    load 245
+    load 246
+    call __divmodu
+    store 245
+    store 249
+    load 249
+    push 10
+    lt
+    jz __print_fmt_letter
+    load 249
+    push 48
+    add
+    syscall print
+    jmp __print_fmt_next
+__print_fmt_letter:
+    load 249
+    load 243
+    add
+    syscall print
+__print_fmt_next:
+    load 246
+    load 244
+    call __divmodu
+    pop
+    store 246
+    jmp __print_fmt_digits
+__print_fmt_done:
+    load 240
+    ret
+
+    .word 5
+str_main_0:
+    .string "world"
+    .word 1
+str_main_1:
+    .string "["
+    .word 1
+str_main_2:
+    .string "]"
+    .word 1
+str_main_3:
+    .string "|"
+    .word 5
+str_main_4:
+    .string "world"
+    .word 5
+str_main_7:
+    .string "World"
+    .word 3
+str_main_10:
+    .string "abc"
+    .word 3
+str_main_11:
+    .string "abd"
+    .word 3
+str_main_12:
+    .string "abc"
+    .word 2
+str_main_13:
+    .string "ab"
+    .word 5
+str_main_14:
+    .string "world"
+    .word 0
+str_main_15:
+    .string ""
+    .word 1
+str_main_19:
+    .string "!"
+    .word 7
+str_Greet_20:
+    .string "hello, "
+    .word 14
+__out_of_memory:
+    .string "out of memory\n"
+__heap_limit:
+    .word 983040
+__heap:
@@ -0,0 +1,31 @@
+--- compile error
+error: expected expression, found Newline
+  --> syntax_error.per:6:19
+   6 |
+     |     return n / 2 +
+     |                   ^ parser error
+
+error: expected ')', found Newline
+  --> syntax_error.per:10:23
+  10 |
+     |     var x i64 = (1 + 2
+     |                       ^ parser error
+
+error: expected expression, found LeftBrace
+  --> syntax_error.per:12:12
+  12 |
+     |     if y > {
+     |            ^ parser error
+
+error: expected Semicolon, found Identifier("i")
+  --> syntax_error.per:15:26
+  15 |
+     |     for var i = 0; i < 3 i = i + 1 {
+     |                          ^ parser error
+
+error: expected parameter type
+  --> syntax_error.per:21:16
+  21 |
+     | func tail( i64 {
+     |                ^ parser error
+
@@ -0,0 +1,840 @@
+.NVM0
+; Generated by Perano Language Compiler
+
+func_main:
+    push 0
+    store 0
+for_start_main_0:
+    load 0
+    push 5
+    lt
+    jz for_end_main_1
+    push 114
+    syscall print
+    push 111
+    syscall print
+    push 119
+    syscall print
+    push 32
+    syscall print
+    load 0
+    push 10
+    push 0
+    push 2
+    push 48
+    call __print_fmt_signed
+    push 58
+    syscall print
+    push 32
+    syscall print
+    load 0
+    call func_Square
+    push 10
+    push 0
+    push 4
+    push 32
+    call __print_fmt_signed
+    push 32
+    syscall print
+    load 0
+    push 255
+    mul
+    push 16
+    push 87
+    push 0
+    push 32
+    call __print_fmt
+    push 32
+    syscall print
+    load 0
+    push 255
+    mul
+    push 16
+    push 55
+    push 0
+    push 32
+    call __print_fmt
+    push 0
+    push 10
+    syscall print
+    pop
+for_continue_main_2:
+    load 0
+    push 1
+    add
+    store 0
+    jmp for_start_main_0
+for_end_main_1:
+    push str_main_3
+    store 1
+    push 0
+    store 2
+for_start_main_4:
+    load 2
+    push 40
+    lt
+    jz for_end_main_5
+    load 1
+    push str_main_7
+    call __str_concat
+    store 1
+for_continue_main_6:
+    load 2
+    push 1
+    add
+    store 2
+    jmp for_start_main_4
+for_end_main_5:
+    push 91
+    syscall print
+    load 1
+    call __print_str
+    push 124
+    syscall print
+    load 1
+    call __print_str
+    push 124
+    syscall print
+    load 1
+    call __print_str
+    push 93
+    syscall print
+    push 0
+    push 10
+    syscall print
+    pop
+    push 200
+    store 3
+    push 7
+    push 0
+    swap
+    sub
+    store 4
+    push 117
+    syscall print
+    push 56
+    syscall print
+    push 32
+    syscall print
+    load 3
+    call __print_int
+    push 44
+    syscall print
+    push 32
+    syscall print
+    load 4
+    push 10
+    push 0
+    push 4
+    push 48
+    call __print_fmt_signed
+    push 44
+    syscall print
+    push 32
+    syscall print
+    load 4
+    call __print_int
+    push 0
+    push 10
+    syscall print
+    pop
+    push -294967296
+    store 5
+    push 117
+    syscall print
+    push 51
+    syscall print
+    push 50
+    syscall print
+    push 32
+    syscall print
+    load 5
+    push 10
+    push 0
+    push 0
+    push 32
+    call __print_fmt
+    push 44
+    syscall print
+    push 32
+    syscall print
+    push 91
+    syscall print
+    load 5
+    push 10
+    push 0
+    push 12
+    push 32
+    call __print_fmt
+    push 93
+    syscall print
+    push 44
+    syscall print
+    push 32
+    syscall print
+    load 5
+    push 16
+    push 55
+    push 0
+    push 32
+    call __print_fmt
+    push 44
+    syscall print
+    push 32
+    syscall print
+    push 91
+    syscall print
+    load 4
+    push 10
+    push 0
+    push 4
+    push 32
+    call __print_fmt_signed
+    push 93
+    syscall print
+    push 44
+    syscall print
+    push 32
+    syscall print
+    push 91
+    syscall print
+    load 3
+    push 16
+    push 87
+    push 6
+    push 32
+    call __print_fmt
+    push 93
+    syscall print
+    push 0
+    push 10
+    syscall print
+    pop
+    push 2147483647
+    push 0
+    swap
+    sub
+    push 1
+    sub
+    store 6
+    push 105
+    syscall print
+    push 51
+    syscall print
+    push 50
+    syscall print
+    push 32
+    syscall print
+    load 6
+    call __print_int
+    push 44
+    syscall print
+    push 32
+    syscall print
+    push 91
+    syscall print
+    load 6
+    push 10
+    push 0
+    push 12
+    push 32
+    call __print_fmt_signed
+    push 93
+    syscall print
+    push 44
+    syscall print
+    push 32
+    syscall print
+    load 6
+    push 10
+    push 0
+    push 12
+    push 48
+    call __print_fmt_signed
+    push 0
+    push 10
+    syscall print
+    pop
+    push 0
+    syscall exit
+    ret
+
+func_Square:
+    swap
+    store 7
+    load 7
+    load 7
+    mul
+    swap
+    ret
+    push 0
+    swap
+    ret
+__print_int:
+    store 255
+    store 250
+    load 250
+    push 0
+    lt
+    jz not_negative_Square_8
+    push 45
+    syscall print
+    push 0
+    load 250
+    sub
+    load 255
+    jmp __print_uint
+not_negative_Square_8:
+    load 250
+    push 0
+    eq
+    jz not_zero_Square_9
+    push 48
+    syscall print
+    load 255
+    ret
+not_zero_Square_9:
+    push 1
+    store 251
+find_power_Square_10:
+    load 250
+    push 10
+    div
+    load 251
+    lt
+    jnz find_power_done_Square_11
+    load 251
+    push 10
+    mul
+    store 251
+    jmp find_power_Square_10
+find_power_done_Square_11:
+print_digit_loop_Square_12:
+    load 251
+    push 0
+    gt
+    jz print_done_Square_13
+    load 250
+    load 251
+    div
+    push 48
+    add
+    syscall print
+    load 250
+    load 251
+    mod
+    store 250
+    load 251
+    push 10
+    div
+    store 251
+    jmp print_digit_loop_Square_12
+print_done_Square_13:
+    load 255
+    ret
+__alloc:
+    swap
+    load 252
+    jnz __alloc_ready
+    push __heap
+    store 252
+__alloc_ready:
+    load 252
+    swap
+    load 252
+    add
+    push 3
+    add
+    push 4
+    div
+    push 4
+    mul
+    store 252
+    load 252
+    push __heap_limit
+    load_abs
+    gt
+    jnz __alloc_full
+    swap
+    ret
+__alloc_full:
+    push __out_of_memory
+    call __print_str
+    push 12
+    syscall exit
+__str_alloc:
+    swap
+    store 250
+    load 250
+    push 5
+    add
+    call __alloc
+    store 251
+    load 251
+    load 250
+    store_abs
+    load 251
+    push 4
+    add
+    store 251
+    load 251
+    load 250
+    add
+    push 0
+    call __store8
+    load 251
+    swap
+    ret
+__str_copy:
+    load 250
+    jz __str_copy_done
+    load 251
+    load 253
+    call __load8
+    call __store8
+    load 251
+    push 1
+    add
+    store 251
+    load 253
+    push 1
+    add
+    store 253
+    load 250
+    push 1
+    sub
+    store 250
+    jmp __str_copy
+__str_copy_done:
+    ret
+__str_concat:
+    store 254
+    store 253
+    store 255
+    load 255
+    push 4
+    sub
+    load_abs
+    load 253
+    push 4
+    sub
+    load_abs
+    add
+    call __str_alloc
+    load 253
+    load 255
+    store 253
+    load 253
+    push 4
+    sub
+    load_abs
+    store 250
+    call __str_copy
+    store 253
+    load 253
+    push 4
+    sub
+    load_abs
+    store 250
+    call __str_copy
+    load 254
+    ret
+__str_compare:
+    store 254
+    store 253
+    store 251
+    load 251
+    push 4
+    sub
+    load_abs
+    store 250
+    load 253
+    push 4
+    sub
+    load_abs
+    store 255
+__str_compare_loop:
+    load 250
+    jz __str_compare_end
+    load 255
+    jz __str_compare_greater
+    load 251
+    call __load8
+    load 253
+    call __load8
+    lt
+    jnz __str_compare_less
+    load 251
+    call __load8
+    load 253
+    call __load8
+    gt
+    jnz __str_compare_greater
+    load 251
+    push 1
+    add
+    store 251
+    load 253
+    push 1
+    add
+    store 253
+    load 250
+    push 1
+    sub
+    store 250
+    load 255
+    push 1
+    sub
+    store 255
+    jmp __str_compare_loop
+__str_compare_end:
+    load 255
+    jnz __str_compare_less
+    push 0
+    jmp __str_compare_done
+__str_compare_less:
+    push -1
+    jmp __str_compare_done
+__str_compare_greater:
+    push 1
+__str_compare_done:
+    load 254
+    ret
+__str_slice:
+    store 254
+    store 255
+    store 250
+    store 253
+    load 255
+    load 253
+    push 4
+    sub
+    load_abs
+    gt
+    jz __str_slice_end_low
+    load 253
+    push 4
+    sub
+    load_abs
+    store 255
+__str_slice_end_low:
+    load 255
+    push 0
+    lt
+    jz __str_slice_start
+    push 0
+    store 255
+__str_slice_start:
+    load 250
+    push 0
+    lt
+    jz __str_slice_start_high
+    push 0
+    store 250
+__str_slice_start_high:
+    load 250
+    load 255
+    gt
+    jz __str_slice_copy
+    load 255
+    store 250
+__str_slice_copy:
+    load 253
+    load 250
+    add
+    store 253
+    load 255
+    load 250
+    sub
+    call __str_alloc
+    call __str_copy
+    load 254
+    ret
+__print_str:
+    swap
+    store 253
+    load 253
+    push 4
+    sub
+    load_abs
+    store 250
+__print_str_loop:
+    load 250
+    jz __print_str_done
+    load 253
+    call __load8
+    syscall print
+    load 253
+    push 1
+    add
+    store 253
+    load 250
+    push 1
+    sub
+    store 250
+    jmp __print_str_loop
+__print_str_done:
+    ret
+__load8:
+    swap
+    load_abs
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    swap
+    ret
+__store8:
+    store 246
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    store 247
+    store 248
+    load 248
+    load 248
+    load_abs
+    store 249
+    load 249
+    load 249
+    push 256
+    mod
+    push 256
+    add
+    push 256
+    mod
+    sub
+    load 247
+    add
+    store_abs
+    load 246
+    ret
+__divmodu:
+    store 255
+    store 254
+    store 253
+    load 254
+    push 0
+    lt
+    jnz __divmodu_large_divisor
+    load 253
+    push 0
+    lt
+    jnz __divmodu_large_dividend
+    load 253
+    load 254
+    div
+    load 253
+    load 254
+    mod
+    load 255
+    ret
+__divmodu_large_divisor:
+    load 253
+    push -2147483648
+    add
+    load 254
+    push -2147483648
+    add
+    lt
+    push 0
+    eq
+    store 251
+    jmp __divmodu_remainder
+__divmodu_large_dividend:
+    load 253
+    push -2147483648
+    add
+    push 2
+    div
+    push 1073741824
+    add
+    load 254
+    div
+    push 2
+    mul
+    store 251
+    load 253
+    load 251
+    load 254
+    mul
+    sub
+    push -2147483648
+    add
+    load 254
+    push -2147483648
+    add
+    lt
+    jnz __divmodu_remainder
+    load 251
+    push 1
+    add
+    store 251
+__divmodu_remainder:
+    load 251
+    load 253
+    load 251
+    load 254
+    mul
+    sub
+    load 255
+    ret
+__print_uint:
+    swap
+    store 249
+    load 249
+    push 0
+    lt
+    jnz __print_uint_large
+    load 249
+    call __print_int
+    ret
+__print_uint_large:
+    load 249
+    push 10
+    call __divmodu
+    swap
+    call __print_int
+    push 48
+    add
+    syscall print
+    ret
+__print_fmt_signed:
+    push 1
+    jmp __print_fmt_args
+__print_fmt:
+    push 0
+__print_fmt_args:
+    store 247
+    store 240
+    store 241
+    store 242
+    store 243
+    store 244
+    store 245
+    load 247
+    jz __print_fmt_count
+    load 245
+    push 0
+    lt
+    store 247
+    load 247
+    jz __print_fmt_count
+    push 0
+    load 245
+    sub
+    store 245
+__print_fmt_count:
+    push 1
+    store 246
+    push 1
+    store 248
+    load 245
+    store 249
+__print_fmt_count_loop:
+    load 249
+    push -2147483648
+    add
+    load 244
+    push -2147483648
+    add
+    lt
+    jnz __print_fmt_pad
+    load 249
+    load 244
+    call __divmodu
+    pop
+    store 249
+    load 246
+    load 244
+    mul
+    store 246
+    load 248
+    push 1
+    add
+    store 248
+    jmp __print_fmt_count_loop
+__print_fmt_pad:
+    load 242
+    load 248
+    sub
+    load 247
+    sub
+    store 242
+    load 241
+    push 48
+    eq
+    jz __print_fmt_fill
+    load 247
+    jz __print_fmt_fill
+    push 45
+    syscall print
+    push 0
+    store 247
+__print_fmt_fill:
+    load 242
+    push 0
+    gt
+    jz __print_fmt_sign
+    load 241
+    syscall print
+    load 242
+    push 1
+    sub
+    store 242
+    jmp __print_fmt_fill
+__print_fmt_sign:
+    load 247
+    jz __print_fmt_digits
+    push 45
+    syscall print
+__print_fmt_digits:
+    load 246
+    jz __print_fmt_done
+    load 245
+    load 246
+    call __divmodu
+    store 245
+    store 249
+    load 249
+    push 10
+    lt
+    jz __print_fmt_letter
+    load 249
+    push 48
+    add
+    syscall print
+    jmp __print_fmt_next
+__print_fmt_letter:
+    load 249
+    load 243
+    add
+    syscall print
+__print_fmt_next:
+    load 246
+    load 244
+    call __divmodu
+    pop
+    store 246
+    jmp __print_fmt_digits
+__print_fmt_done:
+    load 240
+    ret
+
+    .word 0
+str_main_3:
+    .string ""
+    .word 10
+str_main_7:
+    .string "0123456789"
+    .word 14
+__out_of_memory:
+    .string "out of memory\n"
+__heap_limit:
+    .word 983040
+__heap:
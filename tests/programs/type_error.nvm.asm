@@ -0,0 +1,25 @@
+--- compile error
+error: Type mismatch in assignment to 'small': expected U8, got I64; narrowing or sign changes need an explicit 'as' cast
+  --> type_error.per:6:5
+   6 |
+     |     small = wide
+     |     ^ type error
+
+error: Left operand of Add must be numeric, got Bool
+  --> type_error.per:8:12
+   8 |
+     |     return flag + "text"
+     |            ^ type error
+
+error: Right operand of Add must be numeric, got String
+  --> type_error.per:8:19
+   8 |
+     |     return flag + "text"
+     |                   ^ type error
+
+error: Operands of Add have incompatible types Bool and String; use an explicit 'as' cast
+  --> type_error.per:8:17
+   8 |
+     |     return flag + "text"
+     |                 ^ type error
+
@@ -0,0 +1,89 @@
+//! `perano-lang repl` fed from a pipe.
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn session(input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_perano-lang"))
+        .arg("repl")
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to start the REPL");
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// What the session printed, without prompts.
+fn printed(output: &Output) -> Vec<String> {
+    String::from_utf8_lossy(&output.stdout)
+        .replace("... ", "")
+        .split("> ")
+        .map(|s| s.trim_end_matches('\n').to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+#[test]
+fn state_persists_between_inputs() {
+    let output = session(
+        "var x: u8 = 250
+x = x + 10
+x
+fn square(n: i64) -> i64 {
+    return n * n
+}
+stdio.Println(square(x))
+import \"math\" as m
+m.Max(x, 3) == 4
+var s = \"$(x:03d)\" ++ \"!\"
+s
+",
+    );
+    assert!(output.status.success());
+    assert_eq!(printed(&output), ["4", "16", "true", "\"004!\""]);
+}
+
+#[test]
+fn errors_keep_the_session_going() {
+    let output = session(
+        "var a: i64 = 1
+var b: string = a
+a / (a - 1)
+@
+:type a > 0
+:type a as u8
+:asm a + 1
+a + 1
+",
+    );
+    assert!(output.status.success());
+    let printed = printed(&output);
+    assert_eq!(printed[..2], ["bool", "u8"]);
+    assert!(printed[2].contains("main:") && !printed[2].contains("stdio_Println:"), "{}", printed[2]);
+    assert_eq!(printed[3], "2");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("<repl>:1:1"), "{}", stderr);
+    assert!(stderr.contains("Type mismatch in variable 'b'"), "{}", stderr);
+    assert!(stderr.contains("division by zero"), "{}", stderr);
+    assert!(stderr.contains("unexpected character: '@'"), "{}", stderr);
+}
+
+#[test]
+fn runaway_recursion_is_a_runtime_error() {
+    let output = session(
+        "fn f(n: i64) -> i64 {
+    return f(n + 1)
+}
+f(1)
+1 + 1
+",
+    );
+    assert!(output.status.success(), "{:?}", output.status);
+    assert_eq!(printed(&output), ["2"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("call depth exceeded 1000 in 'f'"), "{}", stderr);
+}